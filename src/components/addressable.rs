@@ -0,0 +1,21 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+/// A device that can be read and written at numbered addresses, without going through its
+/// pins.
+///
+/// Pin-level emulations are the most faithful, but they're also slow and cumbersome for
+/// chips with many internal registers. Those chips can instead be emulated at the register
+/// level by implementing this trait, and pin-level wrappers (or a memory map) can then
+/// translate bus accesses into calls to `read` and `write`.
+pub trait Addressable {
+    /// Reads the value at an address. This takes `&mut self` because reads can have side
+    /// effects on real hardware (reading a 6526's interrupt control register clears it, for
+    /// example).
+    fn read(&mut self, addr: u16) -> u8;
+
+    /// Writes a value to an address.
+    fn write(&mut self, addr: u16, value: u8);
+}
@@ -0,0 +1,269 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::fmt::{Display, Error, Formatter};
+
+use crate::{
+    components::{
+        pin::PinRef,
+        trace::{Trace, TraceRef},
+    },
+    vectors::RefVec,
+};
+
+/// An error from connecting a device's pins to a bus.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BusError {
+    /// The number of pins doesn't match the width of the bus. The first value is the bus's
+    /// width and the second is the number of pins.
+    WidthMismatch(usize, usize),
+
+    /// The pin at this index is already connected to a trace. A pin can only ever be
+    /// connected to a single trace.
+    AlreadyConnected(usize),
+}
+
+impl Display for BusError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            BusError::WidthMismatch(width, pins) => {
+                write!(f, "cannot connect {} pins to a {}-line bus", pins, width)
+            }
+            BusError::AlreadyConnected(index) => {
+                write!(f, "pin at index {} is already connected to a trace", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BusError {}
+
+/// An ordered group of traces that together carry a single value, such as an address or a
+/// data bus.
+///
+/// The traces are held LSB-first: the first trace is bit 0 of the bus's value, the second
+/// is bit 1, and so on. Each trace is named with the bus's prefix followed by its bit
+/// number, so a 16-bit bus with the prefix "A" has traces named A0 through A15.
+///
+/// A bus starts out with no pins connected to it. Devices are attached with `connect`,
+/// which connects each of a list of pins to the trace in the same position.
+pub struct Bus {
+    /// The traces of the bus, in bit order.
+    traces: RefVec<Trace>,
+
+    /// The names of the traces, in the same order as the traces themselves.
+    names: Vec<String>,
+}
+
+impl Bus {
+    /// Creates a new bus with `width` traces, named with `prefix` followed by each trace's
+    /// bit number.
+    pub fn new(width: usize, prefix: &str) -> Bus {
+        Bus {
+            traces: RefVec::with_vec((0..width).map(|_| Trace::new(vec![])).collect()),
+            names: (0..width).map(|i| format!("{}{}", prefix, i)).collect(),
+        }
+    }
+
+    /// Returns the number of traces in the bus.
+    pub fn width(&self) -> usize {
+        self.traces.len()
+    }
+
+    /// Returns the traces of the bus, in bit order.
+    pub fn traces(&self) -> RefVec<Trace> {
+        self.traces.clone()
+    }
+
+    /// Returns the trace for a single bit of the bus.
+    pub fn trace(&self, index: usize) -> TraceRef {
+        self.traces.get_ref(index)
+    }
+
+    /// Returns the name of the trace for a single bit of the bus.
+    pub fn name(&self, index: usize) -> &str {
+        self.names[index].as_str()
+    }
+
+    /// Connects a device's pins to the bus. The pins must be in bit order (LSB first) and
+    /// there must be exactly as many of them as there are traces in the bus. Nothing is
+    /// connected if the widths don't match or if any of the pins is already connected to a
+    /// trace.
+    pub fn connect(&mut self, pins: &[PinRef]) -> Result<(), BusError> {
+        if pins.len() != self.width() {
+            return Err(BusError::WidthMismatch(self.width(), pins.len()));
+        }
+        if let Some(index) = pins.iter().position(|pin| pin.borrow().connected()) {
+            return Err(BusError::AlreadyConnected(index));
+        }
+
+        for (trace, pin) in self.traces.iter().zip(pins.iter()) {
+            trace.borrow_mut().add_pin(clone_ref!(pin));
+            pin.borrow_mut().set_trace(clone_ref!(trace));
+        }
+        Ok(())
+    }
+
+    /// Reads the value of the bus. If any of the bus's traces are floating, there is no
+    /// meaningful value and `None` is returned.
+    pub fn read(&self) -> Option<usize> {
+        if self.traces.iter().any(|trace| floating!(trace)) {
+            None
+        } else {
+            Some(self.value())
+        }
+    }
+
+    /// Reads the value of the bus, treating floating traces as low.
+    pub fn value(&self) -> usize {
+        self.traces
+            .iter()
+            .enumerate()
+            .fold(0, |value, (i, trace)| value | (high!(trace) as usize) << i)
+    }
+
+    /// Sets the levels of the bus's traces to represent a value. This sets the traces
+    /// directly, so the usual rules apply: a trace being driven by an output pin keeps that
+    /// pin's level.
+    pub fn set_value(&mut self, value: usize) {
+        debug_assert!(
+            self.width() >= usize::BITS as usize || value >> self.width() == 0,
+            "value {:#x} does not fit in a {}-line bus",
+            value,
+            self.width()
+        );
+        for (i, trace) in self.traces.iter().enumerate() {
+            set_level!(trace, Some(((value >> i) & 1) as f64));
+        }
+    }
+
+    /// Sets all of the bus's traces to floating. Traces being driven by output pins keep
+    /// those pins' levels, and pulled-up or pulled-down traces take their pulled levels.
+    pub fn float(&mut self) {
+        for trace in self.traces.iter() {
+            float!(trace);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::components::pin::{
+        Mode::{self, Input, Output},
+        Pin,
+    };
+
+    use super::*;
+
+    fn make_pins(width: usize, mode: Mode) -> RefVec<Pin> {
+        RefVec::with_vec((0..width).map(|i| pin!(i + 1, "P", mode)).collect())
+    }
+
+    #[test]
+    fn names() {
+        let bus = Bus::new(16, "A");
+        assert_eq!(bus.width(), 16);
+        assert_eq!(bus.name(0), "A0");
+        assert_eq!(bus.name(15), "A15");
+    }
+
+    #[test]
+    fn value_unconnected() {
+        let mut bus = Bus::new(8, "D");
+        assert_eq!(bus.read(), None);
+
+        bus.set_value(0x5a);
+        assert_eq!(bus.read(), Some(0x5a));
+        assert_eq!(bus.value(), 0x5a);
+        assert!(high!(bus.trace(1)));
+        assert!(low!(bus.trace(0)));
+
+        bus.float();
+        assert_eq!(bus.read(), None);
+        assert_eq!(bus.value(), 0);
+    }
+
+    #[test]
+    fn read_partial_float() {
+        let mut bus = Bus::new(4, "D");
+        bus.set_value(0xf);
+        float!(bus.trace(1));
+
+        assert_eq!(bus.read(), None, "read should be None if any line floats");
+        assert_eq!(bus.value(), 0xd, "value should treat floating lines as low");
+    }
+
+    #[test]
+    fn read_pulled_up() {
+        let mut bus = Bus::new(4, "D");
+        bus.trace(3).borrow_mut().pull_up();
+        bus.float();
+
+        assert_eq!(bus.read(), None);
+        assert_eq!(bus.value(), 0x8, "pulled-up line should read high");
+    }
+
+    #[test]
+    fn connect_inputs() {
+        let mut bus = Bus::new(8, "D");
+        let pins = make_pins(8, Input);
+        bus.connect(&pins).unwrap();
+
+        bus.set_value(0xc3);
+        for (i, pin) in pins.iter().enumerate() {
+            assert_eq!(
+                high!(pin),
+                (0xc3 >> i) & 1 == 1,
+                "pin {} has wrong level",
+                i
+            );
+        }
+    }
+
+    #[test]
+    fn connect_outputs() {
+        let mut bus = Bus::new(4, "D");
+        let outputs = make_pins(4, Output);
+        let inputs = make_pins(4, Input);
+        bus.connect(&outputs).unwrap();
+        bus.connect(&inputs).unwrap();
+
+        for (i, pin) in outputs.iter().enumerate() {
+            set_level!(pin, Some(((0x6 >> i) & 1) as f64));
+        }
+        assert_eq!(bus.read(), Some(0x6));
+        assert!(high!(inputs[1]));
+        assert!(high!(inputs[2]));
+
+        bus.set_value(0x9);
+        assert_eq!(
+            bus.read(),
+            Some(0x6),
+            "output pins should override set_value"
+        );
+    }
+
+    #[test]
+    fn connect_width_mismatch() {
+        let mut bus = Bus::new(8, "D");
+        let pins = make_pins(4, Input);
+
+        assert_eq!(bus.connect(&pins), Err(BusError::WidthMismatch(8, 4)));
+        assert!(
+            pins.iter().all(|p| !p.borrow().connected()),
+            "nothing should be connected after a failed connect"
+        );
+    }
+
+    #[test]
+    fn connect_already_connected() {
+        let mut bus = Bus::new(2, "D");
+        let pins = make_pins(2, Input);
+        let _t = trace!(pins[1]);
+
+        assert_eq!(bus.connect(&pins), Err(BusError::AlreadyConnected(1)));
+        assert!(!pins[0].borrow().connected());
+    }
+}
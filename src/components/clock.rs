@@ -0,0 +1,361 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{cell::RefCell, rc::Rc};
+
+/// A device that does work once per clock cycle.
+///
+/// Most chips in the emulation react only to level changes on their pins, but some (like
+/// the CPU and the VIC) have internal state that advances with the system clock whether
+/// their pins change or not. Those devices implement this trait and are registered with a
+/// `Scheduler`, which clocks them in a fixed order.
+pub trait Clocked {
+    /// Advances the device by one clock cycle. `cycle` is the number of the cycle being
+    /// run, counting from 0.
+    fn clock(&mut self, cycle: u64);
+}
+
+/// A convenience alias for a shared internally-mutable reference to a clocked device.
+pub type ClockedRef = Rc<RefCell<dyn Clocked>>;
+
+/// The phases that the C64's devices are registered in with a `Scheduler`, which set the
+/// order they're clocked in within each cycle.
+///
+/// The VIC has the bus during the first half of a cycle (φ1) and the CPU during the second
+/// (φ2), so the VIC is clocked first. The CIAs, the SID, and the other devices that respond
+/// to the CPU come after it, so that they see what the CPU did in the same cycle.
+pub mod phase {
+    /// Devices that only watch the others (like a `Probe`). They're clocked first so that
+    /// they know the cycle number before anything changes in it.
+    pub const OBSERVERS: usize = 0;
+
+    /// The VIC.
+    pub const VIC: usize = 1;
+
+    /// The CPU.
+    pub const CPU: usize = 2;
+
+    /// The CIAs, the SID, and other devices that react to the CPU.
+    pub const PERIPHERALS: usize = 3;
+}
+
+/// A callback scheduled to run at a particular cycle. The callback is passed the number of
+/// the cycle in which it's run.
+pub type CycleCallback = Box<dyn FnMut(u64)>;
+
+/// A callback waiting for its cycle to come around.
+struct Event {
+    /// The cycle in which the callback will next run.
+    cycle: u64,
+
+    /// The number of cycles between runs, for a periodic callback. One-shot callbacks have
+    /// `None` here and are dropped after they run.
+    period: Option<u64>,
+
+    /// The callback itself.
+    callback: CycleCallback,
+}
+
+/// Advances a set of clocked devices together, one cycle at a time, in a defined order.
+///
+/// Each device is registered with a phase number. In each cycle, devices are clocked in
+/// ascending order of phase, and devices with the same phase are clocked in the order that
+/// they were registered. The phases used for the C64's devices are in `phase`; with them,
+/// each device in a cycle sees the work of those that come before it.
+///
+/// The scheduler also counts cycles and can run callbacks at particular cycles, either
+/// once or periodically. Callbacks for a cycle run after all of the devices have been
+/// clocked for that cycle.
+pub struct Scheduler {
+    /// The registered devices and their phases, kept sorted by phase.
+    devices: Vec<(usize, ClockedRef)>,
+
+    /// Callbacks that have yet to run (or, for periodic callbacks, to run again).
+    events: Vec<Event>,
+
+    /// The number of the next cycle to be run. This is also the number of cycles that have
+    /// been run so far.
+    cycle: u64,
+}
+
+impl Scheduler {
+    /// Creates a new scheduler with no devices, starting at cycle 0.
+    pub fn new() -> Scheduler {
+        Scheduler {
+            devices: vec![],
+            events: vec![],
+            cycle: 0,
+        }
+    }
+
+    /// Returns the number of cycles that have been run.
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    /// Registers a device to be clocked in the given phase.
+    pub fn register(&mut self, phase: usize, device: ClockedRef) {
+        // Inserting after every device with the same or an earlier phase keeps devices of
+        // the same phase in registration order
+        let index = self.devices.partition_point(|(p, _)| *p <= phase);
+        self.devices.insert(index, (phase, device));
+    }
+
+    /// Schedules a callback to run once, in the given cycle. The cycle must not already
+    /// have been run.
+    pub fn at(&mut self, cycle: u64, callback: CycleCallback) {
+        debug_assert!(
+            cycle >= self.cycle,
+            "cannot schedule a callback for past cycle {}",
+            cycle
+        );
+        self.events.push(Event {
+            cycle,
+            period: None,
+            callback,
+        });
+    }
+
+    /// Schedules a callback to run in the given cycle and then again every `period` cycles
+    /// after that. The cycle must not already have been run, and the period must not be 0.
+    pub fn every(&mut self, cycle: u64, period: u64, callback: CycleCallback) {
+        debug_assert!(
+            cycle >= self.cycle,
+            "cannot schedule a callback for past cycle {}",
+            cycle
+        );
+        assert!(period > 0, "period of a periodic callback must not be 0");
+        self.events.push(Event {
+            cycle,
+            period: Some(period),
+            callback,
+        });
+    }
+
+    /// Runs a single clock cycle. Every registered device is clocked in phase order, and
+    /// then any callbacks scheduled for the cycle are run.
+    pub fn tick(&mut self) {
+        let cycle = self.cycle;
+        for (_, device) in self.devices.iter() {
+            device.borrow_mut().clock(cycle);
+        }
+
+        for event in self.events.iter_mut().filter(|e| e.cycle == cycle) {
+            (event.callback)(cycle);
+            if let Some(period) = event.period {
+                event.cycle += period;
+            }
+        }
+        self.events.retain(|e| e.cycle > cycle);
+
+        self.cycle += 1;
+    }
+
+    /// Runs `n` clock cycles.
+    pub fn tick_n(&mut self, n: u64) {
+        for _ in 0..n {
+            self.tick();
+        }
+    }
+
+    /// Runs clock cycles until `predicate` returns `true`. The predicate is passed the
+    /// number of the next cycle to be run and is checked before each cycle, so if it's
+    /// true to begin with, no cycles are run. Returns the number of cycles that were run.
+    pub fn run_until<F>(&mut self, mut predicate: F) -> u64
+    where
+        F: FnMut(u64) -> bool,
+    {
+        let start = self.cycle;
+        while !predicate(self.cycle) {
+            self.tick();
+        }
+        self.cycle - start
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Scheduler::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    type Log = Rc<RefCell<Vec<(&'static str, u64)>>>;
+
+    struct Mock {
+        name: &'static str,
+        log: Log,
+    }
+
+    impl Clocked for Mock {
+        fn clock(&mut self, cycle: u64) {
+            self.log.borrow_mut().push((self.name, cycle));
+        }
+    }
+
+    fn mock(name: &'static str, log: &Log) -> ClockedRef {
+        new_ref!(Mock {
+            name,
+            log: Rc::clone(log),
+        })
+    }
+
+    #[test]
+    fn phase_order() {
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+
+        // Registered out of order on purpose
+        scheduler.register(2, mock("cpu", &log));
+        scheduler.register(1, mock("vic", &log));
+
+        scheduler.tick_n(3);
+        assert_eq!(scheduler.cycle(), 3);
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                ("vic", 0),
+                ("cpu", 0),
+                ("vic", 1),
+                ("cpu", 1),
+                ("vic", 2),
+                ("cpu", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn same_phase_registration_order() {
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+
+        scheduler.register(3, mock("cia1", &log));
+        scheduler.register(1, mock("vic", &log));
+        scheduler.register(3, mock("cia2", &log));
+
+        scheduler.tick();
+        assert_eq!(*log.borrow(), vec![("vic", 0), ("cia1", 0), ("cia2", 0)]);
+    }
+
+    #[test]
+    fn one_shot_callback() {
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        let fired = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.register(1, mock("cpu", &log));
+
+        scheduler.tick_n(5);
+        let f = Rc::clone(&fired);
+        scheduler.at(
+            scheduler.cycle() + 1000,
+            Box::new(move |cycle| f.borrow_mut().push(cycle)),
+        );
+
+        // Cycles 5 through 1004
+        scheduler.tick_n(1000);
+        assert!(fired.borrow().is_empty(), "callback should not fire early");
+        scheduler.tick();
+        assert_eq!(*fired.borrow(), vec![1005]);
+        scheduler.tick_n(2000);
+        assert_eq!(
+            *fired.borrow(),
+            vec![1005],
+            "callback should fire only once"
+        );
+    }
+
+    #[test]
+    fn callback_after_devices() {
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.register(1, mock("cpu", &log));
+
+        let l = Rc::clone(&log);
+        scheduler.at(1, Box::new(move |cycle| l.borrow_mut().push(("cb", cycle))));
+
+        scheduler.tick_n(2);
+        assert_eq!(*log.borrow(), vec![("cpu", 0), ("cpu", 1), ("cb", 1)]);
+    }
+
+    #[test]
+    fn periodic_callback() {
+        let fired = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+
+        let f = Rc::clone(&fired);
+        scheduler.every(10, 63, Box::new(move |cycle| f.borrow_mut().push(cycle)));
+
+        scheduler.tick_n(200);
+        assert_eq!(*fired.borrow(), vec![10, 73, 136, 199]);
+    }
+
+    #[test]
+    fn run_until() {
+        let log: Log = Rc::new(RefCell::new(vec![]));
+        let mut scheduler = Scheduler::new();
+        scheduler.register(1, mock("cpu", &log));
+
+        let l = Rc::clone(&log);
+        let run = scheduler.run_until(move |_| l.borrow().len() == 50);
+        assert_eq!(run, 50);
+        assert_eq!(scheduler.cycle(), 50);
+
+        let run = scheduler.run_until(|cycle| cycle >= 10);
+        assert_eq!(
+            run, 0,
+            "no cycles should run if the predicate is already true"
+        );
+    }
+
+    /// A device that counts its cycles and checks that another device's count is already
+    /// `ahead` of its own when it's clocked.
+    struct Counter {
+        count: Rc<RefCell<u64>>,
+        other: Rc<RefCell<u64>>,
+        ahead: u64,
+    }
+
+    impl Clocked for Counter {
+        fn clock(&mut self, cycle: u64) {
+            let mut count = self.count.borrow_mut();
+            assert_eq!(*count, cycle);
+            assert_eq!(*self.other.borrow(), cycle + self.ahead);
+            *count += 1;
+        }
+    }
+
+    #[test]
+    fn lock_step() {
+        let vic_count = Rc::new(RefCell::new(0));
+        let cpu_count = Rc::new(RefCell::new(0));
+        let mut scheduler = Scheduler::new();
+
+        // In each cycle, the VIC is clocked before the CPU, so the CPU sees the VIC one
+        // count ahead and the VIC sees the CPU even with it
+        scheduler.register(
+            phase::CPU,
+            new_ref!(Counter {
+                count: Rc::clone(&cpu_count),
+                other: Rc::clone(&vic_count),
+                ahead: 1,
+            }),
+        );
+        scheduler.register(
+            phase::VIC,
+            new_ref!(Counter {
+                count: Rc::clone(&vic_count),
+                other: Rc::clone(&cpu_count),
+                ahead: 0,
+            }),
+        );
+
+        scheduler.tick_n(1000);
+        assert_eq!(*vic_count.borrow(), 1000);
+        assert_eq!(*cpu_count.borrow(), 1000);
+    }
+}
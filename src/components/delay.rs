@@ -0,0 +1,194 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use crate::components::{
+    clock::{Clocked, ClockedRef},
+    level::Level,
+    pin::PinRef,
+};
+
+/// A convenience alias for a shared reference to a delay. Delays are internally mutable on
+/// their own, so they don't need a `RefCell`.
+pub type DelayRef = Rc<Delay>;
+
+/// An output level waiting for its cycle to come around.
+struct Transition {
+    /// The cycle in which the pin will be driven.
+    cycle: u64,
+
+    /// The pin to drive.
+    pin: PinRef,
+
+    /// The level to drive it to.
+    level: Level,
+}
+
+/// A propagation delay for the outputs of simple logic chips.
+///
+/// Without a delay, a gate sets its outputs in the same `update` call that its inputs
+/// change in, so every change goes all the way through a circuit at once. Real chips take
+/// some time to respond, and a chip created with a delay (see `Ic7406::with_delay`, for
+/// example) hands its output changes to the delay instead, which drives them `ticks`
+/// cycles later.
+///
+/// A delay doesn't count cycles itself; it takes them from a `Scheduler`, with which it has
+/// to be registered (using `clocked`). Changes that come due in a cycle are driven when the
+/// delay is clocked in that cycle, so its phase decides where in the cycle they happen.
+///
+/// Only the latest change to an output counts. When a new change is scheduled for a pin
+/// that already has one waiting, the waiting one is dropped, just as a gate's output can't
+/// follow an input pulse shorter than its propagation delay. A pulse like that never makes
+/// it to the output at all.
+///
+/// One delay can be shared by any number of chips, and chips that share one all have the
+/// same propagation delay. It can be changed at any time with `set_propagation_delay`,
+/// which affects changes scheduled after that. A delay of 0 drives changes immediately,
+/// the same as having no delay.
+pub struct Delay {
+    /// The number of cycles between a change being scheduled and its pin being driven.
+    ticks: Cell<usize>,
+
+    /// The cycle passed to the last `clock`.
+    cycle: Cell<u64>,
+
+    /// The changes that have been scheduled but not yet driven.
+    pending: RefCell<Vec<Transition>>,
+}
+
+/// The `Clocked` side of a delay, registered with a `Scheduler`. This is separate from
+/// the delay so that the delay itself isn't borrowed while it drives pins, since driving a
+/// pin can cause more changes to be scheduled.
+struct DelayClock(DelayRef);
+
+impl Delay {
+    /// Creates a new delay of `ticks` cycles.
+    pub fn new(ticks: usize) -> DelayRef {
+        Rc::new(Delay {
+            ticks: Cell::new(ticks),
+            cycle: Cell::new(0),
+            pending: RefCell::new(vec![]),
+        })
+    }
+
+    /// Returns a reference that can be registered with a `Scheduler` to clock this delay.
+    pub fn clocked(self: &Rc<Self>) -> ClockedRef {
+        new_ref!(DelayClock(Rc::clone(self)))
+    }
+
+    /// Returns the number of cycles that changes are delayed.
+    pub fn propagation_delay(&self) -> usize {
+        self.ticks.get()
+    }
+
+    /// Sets the number of cycles that changes are delayed.
+    pub fn set_propagation_delay(&self, ticks: usize) {
+        self.ticks.set(ticks);
+    }
+
+    /// Returns the number of changes waiting to be driven.
+    pub fn pending(&self) -> usize {
+        self.pending.borrow().len()
+    }
+
+    /// Schedules a pin to be driven to a level after the delay, replacing any change that's
+    /// already waiting for it. With a delay of 0, the pin is driven right away.
+    pub fn schedule(&self, pin: &PinRef, level: Level) {
+        let mut pending = self.pending.borrow_mut();
+        pending.retain(|t| !Rc::ptr_eq(&t.pin, pin));
+        match self.ticks.get() {
+            0 => {
+                drop(pending);
+                pin.borrow_mut().drive(level);
+            }
+            ticks => pending.push(Transition {
+                cycle: self.cycle.get() + ticks as u64,
+                pin: clone_ref!(pin),
+                level,
+            }),
+        }
+    }
+
+    /// Drives the pins whose changes are due in a cycle.
+    fn clock(&self, cycle: u64) {
+        self.cycle.set(cycle);
+        let due: Vec<Transition> = {
+            let mut pending = self.pending.borrow_mut();
+            let (due, waiting) = pending.drain(..).partition(|t| t.cycle <= cycle);
+            *pending = waiting;
+            due
+        };
+        for t in due {
+            t.pin.borrow_mut().drive(t.level);
+        }
+    }
+}
+
+impl Clocked for DelayClock {
+    fn clock(&mut self, cycle: u64) {
+        self.0.clock(cycle);
+    }
+}
+
+/// Drives an output pin of a chip, through the chip's delay if it has one.
+pub fn drive(delay: &Option<DelayRef>, pin: &PinRef, level: Level) {
+    match delay {
+        Some(delay) => delay.schedule(pin, level),
+        None => pin.borrow_mut().drive(level),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::components::{clock::Scheduler, pin::Mode::Output};
+
+    #[test]
+    fn latest_change_wins() {
+        let pin = pin!(1, "Y", Output);
+        let trace = trace!(pin);
+        let delay = Delay::new(3);
+
+        delay.schedule(&pin, Level::High);
+        delay.schedule(&pin, Level::Low);
+        assert_eq!(delay.pending(), 1);
+
+        delay.clock(2);
+        assert!(floating!(trace), "change should not be driven early");
+        delay.clock(3);
+        assert!(low!(trace));
+        assert_eq!(delay.pending(), 0);
+    }
+
+    #[test]
+    fn zero_delay() {
+        let pin = pin!(1, "Y", Output);
+        let trace = trace!(pin);
+        let delay = Delay::new(0);
+        delay.schedule(&pin, Level::High);
+        assert!(high!(trace));
+        assert_eq!(delay.pending(), 0);
+    }
+
+    #[test]
+    fn clocked_by_scheduler() {
+        let pin = pin!(1, "Y", Output);
+        let trace = trace!(pin);
+        let delay = Delay::new(5);
+        let mut scheduler = Scheduler::new();
+        scheduler.register(0, delay.clocked());
+
+        let (d, p) = (Rc::clone(&delay), clone_ref!(pin));
+        scheduler.at(10, Box::new(move |_| d.schedule(&p, Level::High)));
+        scheduler.tick_n(15);
+        assert!(floating!(trace));
+        scheduler.tick();
+        assert!(high!(trace), "change should be driven in cycle 15");
+    }
+}
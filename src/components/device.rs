@@ -7,12 +7,16 @@ use std::{
     cell::RefCell,
     fmt::{Debug, Formatter, Result},
     rc::Rc,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use crate::{
-    components::pin::{
-        Mode::{Bidirectional, Input, Output, Unconnected},
-        Pin,
+    components::{
+        level::Level,
+        pin::{
+            Mode::{Bidirectional, Input, Output, Unconnected},
+            Pin, PinRef,
+        },
     },
     vectors::RefVec,
 };
@@ -21,7 +25,20 @@ pub type DeviceRef = Rc<RefCell<dyn Device>>;
 
 pub const DUMMY: &str = "__DUMMY__";
 
+/// The identifier that will be given to the next device created.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a new device identifier, different from every other one returned so far. Each
+/// device calls this once, when it's constructed, and returns the result from `id`.
+pub fn next_id() -> usize {
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
 pub trait Device {
+    /// Returns the device's unique identifier. This should be obtained from `next_id` when
+    /// the device is constructed and never change afterwards.
+    fn id(&self) -> usize;
+
     // I would like to use an array here instead of a Vec - the array is set at creation
     // time and never changes, so the mutability of a Vec is not necessary. Unfortunately,
     // const generics are necessary to do this, and while they now exist, they do not allow
@@ -36,9 +53,27 @@ pub trait Device {
     // different const generics, and we can't yet express that.
     fn pins(&self) -> RefVec<Pin>;
     // Also would like to use an array here, but same const generic problem.
+    /// Returns a snapshot of the device's internal state, for debuggers and tests. The
+    /// format is specific to each device and is documented with the device. Devices with no
+    /// state worth inspecting return an empty vector.
     fn registers(&self) -> Vec<u8>;
     fn update(&mut self, event: &LevelChange);
 
+    /// Returns the pin with the given pin number, or `None` if the device has no such pin.
+    fn pin(&self, number: usize) -> Option<PinRef> {
+        self.pins().get(number).map(Rc::clone)
+    }
+
+    /// Returns the first pin with the given name, or `None` if the device has no pin by
+    /// that name. This lets pins be looked up without importing the device's pin
+    /// constants, which is handy for generic wiring and debugging code.
+    fn pin_by_name(&self, name: &str) -> Option<PinRef> {
+        self.pins()
+            .iter()
+            .find(|pin| name!(pin) == name)
+            .map(Rc::clone)
+    }
+
     fn debug_fmt(&self, f: &mut Formatter) -> Result {
         let alt = f.alternate();
         let mut str = String::from("Device {");
@@ -112,5 +147,28 @@ impl Debug for dyn Device {
     }
 }
 
+/// The event passed to a device's `update` when one of its pins changes level. It carries
+/// the pin that changed and its new level, so that a device can match on the level without
+/// borrowing the pin again.
 #[derive(Clone, Debug)]
-pub struct LevelChange<'a>(pub Rc<RefCell<&'a Pin>>);
+pub struct LevelChange<'a>(pub Rc<RefCell<&'a Pin>>, pub Level);
+
+impl LevelChange<'_> {
+    /// Returns the new level of the pin that changed.
+    pub fn level(&self) -> Level {
+        self.1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::devices::chips::Ic7408;
+
+    #[test]
+    fn distinct_ids() {
+        let a = Ic7408::new();
+        let b = Ic7408::new();
+        assert_ne!(a.borrow().id(), b.borrow().id());
+        assert_eq!(a.borrow().id(), a.borrow().id(), "id should not change");
+    }
+}
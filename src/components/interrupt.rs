@@ -0,0 +1,282 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+use crate::components::{
+    clock::{Clocked, ClockedRef},
+    level::Level,
+    pin::{Mode::Output, PinRef},
+};
+
+/// A convenience alias for a shared reference to an interrupt controller. Controllers are
+/// internally mutable on their own, so they don't need a `RefCell`.
+pub type InterruptControllerRef = Rc<InterruptController>;
+
+/// The two interrupt lines of the 6510.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interrupt {
+    /// The maskable interrupt. It's level-sensitive: the CPU is interrupted for as long as
+    /// any device holds the line low (and interrupts aren't disabled).
+    Irq,
+
+    /// The non-maskable interrupt. It's edge-sensitive: the CPU is interrupted once when
+    /// the line goes low, and not again until it's gone high and then low again.
+    Nmi,
+}
+
+/// A device that can pull one of the interrupt lines low.
+struct Source {
+    /// The name of the source, for debugging.
+    name: &'static str,
+
+    /// The line that the source pulls low.
+    line: Interrupt,
+
+    /// Whether the source is currently pulling its line low.
+    asserted: bool,
+}
+
+/// The combined IRQ and NMI lines of the C64.
+///
+/// Several devices share each of the 6510's interrupt lines. Both CIA1 and the VIC (and the
+/// cartridge port) can pull IRQ low, and CIA2, the RESTORE key, and the cartridge port can
+/// all pull NMI low. In the real machine, those outputs are open-collector and are simply
+/// wired together, so the line is low whenever any of them is. The controller does the same
+/// thing and also keeps track of who's doing it, which is the first question to ask when
+/// the CPU is stuck in an interrupt handler.
+///
+/// Each device gets an `InterruptSource` from `add_source`, which it asserts and releases.
+/// Those changes reach the controller's `irq` and `nmi` output pins (which are connected
+/// to the CPU's pins like any other) when the controller is clocked, so it has to be
+/// registered with a `Scheduler` using `clocked`.
+///
+/// NMI is edge-sensitive, and the controller handles that itself: every time the combined
+/// NMI line goes from high to low, one NMI is made pending, and `take_nmi` reports it
+/// exactly once. A source that holds NMI low doesn't cause any more, and neither does a
+/// second source asserting NMI while the first is still holding it (which is why RESTORE
+/// doesn't work while a CIA2 NMI hasn't been acknowledged).
+pub struct InterruptController {
+    /// Every source that has been added, in the order they were added.
+    sources: RefCell<Vec<Source>>,
+
+    /// The IRQ output pin, active low.
+    irq: PinRef,
+
+    /// The NMI output pin, active low.
+    nmi: PinRef,
+
+    /// Whether the combined NMI line was low the last time the controller was clocked.
+    nmi_low: Cell<bool>,
+
+    /// Whether an NMI has been triggered but not yet taken.
+    nmi_pending: Cell<bool>,
+}
+
+/// The `Clocked` side of an interrupt controller, registered with a `Scheduler`.
+struct InterruptClock(InterruptControllerRef);
+
+/// A handle that a device uses to pull one of the interrupt lines low.
+pub struct InterruptSource {
+    /// The controller that the source belongs to.
+    controller: InterruptControllerRef,
+
+    /// The index of the source in the controller's list.
+    index: usize,
+}
+
+impl InterruptController {
+    /// Creates a new controller with no sources, so that both of its outputs are high.
+    pub fn new() -> InterruptControllerRef {
+        let irq = pin!(1, "IRQ", Output);
+        let nmi = pin!(2, "NMI", Output);
+        set!(irq, nmi);
+
+        Rc::new(InterruptController {
+            sources: RefCell::new(vec![]),
+            irq,
+            nmi,
+            nmi_low: Cell::new(false),
+            nmi_pending: Cell::new(false),
+        })
+    }
+
+    /// Returns a reference that can be registered with a `Scheduler` to clock this
+    /// controller.
+    pub fn clocked(self: &Rc<Self>) -> ClockedRef {
+        new_ref!(InterruptClock(Rc::clone(self)))
+    }
+
+    /// Adds a new source that pulls `line` low when it's asserted. It starts out released.
+    pub fn add_source(self: &Rc<Self>, name: &'static str, line: Interrupt) -> InterruptSource {
+        let mut sources = self.sources.borrow_mut();
+        sources.push(Source {
+            name,
+            line,
+            asserted: false,
+        });
+        InterruptSource {
+            controller: Rc::clone(self),
+            index: sources.len() - 1,
+        }
+    }
+
+    /// Returns the IRQ output pin.
+    pub fn irq(&self) -> PinRef {
+        clone_ref!(self.irq)
+    }
+
+    /// Returns the NMI output pin.
+    pub fn nmi(&self) -> PinRef {
+        clone_ref!(self.nmi)
+    }
+
+    /// Returns the names of the sources that are asserted, in the order they were added.
+    pub fn active_sources(&self) -> Vec<&'static str> {
+        self.sources
+            .borrow()
+            .iter()
+            .filter(|source| source.asserted)
+            .map(|source| source.name)
+            .collect()
+    }
+
+    /// Determines whether an NMI is pending and clears it if it is. This is for the CPU,
+    /// which calls it when it's ready to handle an NMI.
+    pub fn take_nmi(&self) -> bool {
+        self.nmi_pending.replace(false)
+    }
+
+    /// Determines whether any source of a line is asserted.
+    fn low(&self, line: Interrupt) -> bool {
+        self.sources
+            .borrow()
+            .iter()
+            .any(|source| source.line == line && source.asserted)
+    }
+
+    /// Drives the outputs from the current state of the sources.
+    fn clock(&self) {
+        let irq_low = self.low(Interrupt::Irq);
+        let nmi_low = self.low(Interrupt::Nmi);
+
+        if nmi_low && !self.nmi_low.get() {
+            self.nmi_pending.set(true);
+        }
+        self.nmi_low.set(nmi_low);
+
+        set_level!(self.irq, Level::from(!irq_low));
+        set_level!(self.nmi, Level::from(!nmi_low));
+    }
+}
+
+impl Clocked for InterruptClock {
+    fn clock(&mut self, _cycle: u64) {
+        self.0.clock();
+    }
+}
+
+impl InterruptSource {
+    /// Pulls the source's line low. This has no effect if the source is already asserted.
+    pub fn assert(&self) {
+        self.set(true);
+    }
+
+    /// Stops pulling the source's line low. The line only goes high if no other source is
+    /// asserting it.
+    pub fn release(&self) {
+        self.set(false);
+    }
+
+    /// Determines whether the source is asserted.
+    pub fn asserted(&self) -> bool {
+        self.controller.sources.borrow()[self.index].asserted
+    }
+
+    /// Sets whether the source is asserted.
+    fn set(&self, asserted: bool) {
+        self.controller.sources.borrow_mut()[self.index].asserted = asserted;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::components::clock::{phase, Scheduler};
+
+    fn before_each() -> (InterruptControllerRef, Scheduler) {
+        let controller = InterruptController::new();
+        let mut scheduler = Scheduler::new();
+        scheduler.register(phase::PERIPHERALS, controller.clocked());
+        (controller, scheduler)
+    }
+
+    #[test]
+    fn shared_irq() {
+        let (controller, mut scheduler) = before_each();
+        let irq = trace!(controller.irq());
+        let cia1 = controller.add_source("CIA1", Interrupt::Irq);
+        let vic = controller.add_source("VIC", Interrupt::Irq);
+
+        scheduler.tick();
+        assert!(high!(irq));
+
+        vic.assert();
+        cia1.assert();
+        assert!(
+            high!(irq),
+            "change should wait for the controller to be clocked"
+        );
+        scheduler.tick();
+        assert!(low!(irq));
+        assert_eq!(controller.active_sources(), vec!["CIA1", "VIC"]);
+
+        cia1.release();
+        scheduler.tick();
+        assert!(low!(irq), "VIC should still be holding IRQ low");
+        assert_eq!(controller.active_sources(), vec!["VIC"]);
+
+        vic.release();
+        scheduler.tick();
+        assert!(high!(irq));
+        assert!(controller.active_sources().is_empty());
+        assert!(!controller.take_nmi(), "IRQs should not cause NMIs");
+    }
+
+    #[test]
+    fn nmi_edge() {
+        let (controller, mut scheduler) = before_each();
+        let nmi = trace!(controller.nmi());
+        let cia2 = controller.add_source("CIA2", Interrupt::Nmi);
+        let restore = controller.add_source("RESTORE", Interrupt::Nmi);
+
+        cia2.assert();
+        let mut taken = 0;
+        for _ in 0..10 {
+            scheduler.tick();
+            taken += controller.take_nmi() as usize;
+        }
+        assert_eq!(taken, 1, "NMI held low should trigger only once");
+        assert!(low!(nmi));
+
+        restore.assert();
+        scheduler.tick();
+        assert!(!controller.take_nmi(), "NMI was already low");
+
+        cia2.release();
+        restore.release();
+        scheduler.tick();
+        assert!(high!(nmi));
+        restore.assert();
+        scheduler.tick();
+        assert!(
+            controller.take_nmi(),
+            "new falling edge should trigger an NMI"
+        );
+    }
+}
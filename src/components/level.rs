@@ -0,0 +1,172 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::fmt::{self, Display, Formatter};
+
+/// The level below which a signal is low and at or above which it's high.
+const THRESHOLD: f64 = 0.5;
+
+/// The signal on a pin or a trace.
+///
+/// Nearly every signal in the C64 is digital, so a level is usually `High`, `Low`, or
+/// `Floating` (not driven at all, the high-impedance state). A few parts of the machine
+/// work with analog signals, though, like the paddle inputs that pass through a 4066 to the
+/// SID, and those levels are `Analog`. An analog level is still high or low to anything
+/// that reads it as a digital signal: it's high if it's 0.5 or more and low otherwise.
+///
+/// A level of exactly 0.0 or 1.0 is always `Low` or `High`, never `Analog`, so that two
+/// levels that mean the same thing always compare equal. Levels made with `analog` or
+/// converted from an `Option<f64>` are normalized this way.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Level {
+    /// A digital low, 0.0.
+    Low,
+
+    /// A digital high, 1.0.
+    High,
+
+    /// No level at all.
+    #[default]
+    Floating,
+
+    /// An analog level other than 0.0 or 1.0.
+    Analog(f64),
+}
+
+impl Level {
+    /// Returns the level for an analog value, which is `Low` or `High` if the value is
+    /// exactly 0.0 or 1.0.
+    pub fn analog(value: f64) -> Level {
+        if value == 0.0 {
+            Level::Low
+        } else if value == 1.0 {
+            Level::High
+        } else {
+            Level::Analog(value)
+        }
+    }
+
+    /// Returns the numeric value of the level, or `None` if it's floating.
+    pub fn value(self) -> Option<f64> {
+        match self {
+            Level::Low => Some(0.0),
+            Level::High => Some(1.0),
+            Level::Floating => None,
+            Level::Analog(value) => Some(value),
+        }
+    }
+
+    /// Determines whether the level is high. `High` is, and so is any analog level of 0.5
+    /// or more.
+    pub fn high(self) -> bool {
+        match self {
+            Level::High => true,
+            Level::Analog(value) => value >= THRESHOLD,
+            _ => false,
+        }
+    }
+
+    /// Determines whether the level is low. `Low` is, and so is any analog level of less
+    /// than 0.5.
+    pub fn low(self) -> bool {
+        match self {
+            Level::Low => true,
+            Level::Analog(value) => value < THRESHOLD,
+            _ => false,
+        }
+    }
+
+    /// Determines whether the level is floating.
+    pub fn floating(self) -> bool {
+        self == Level::Floating
+    }
+
+    /// Returns the level as a digital device would see it, with analog levels turned into
+    /// `High` or `Low`. This lets digital devices match on a level without worrying about
+    /// analog ones.
+    pub fn digital(self) -> Level {
+        match self {
+            Level::Analog(value) if value >= THRESHOLD => Level::High,
+            Level::Analog(_) => Level::Low,
+            _ => self,
+        }
+    }
+
+    /// Returns this level, or `float` if this level is floating.
+    pub fn or(self, float: Level) -> Level {
+        match self {
+            Level::Floating => float,
+            _ => self,
+        }
+    }
+}
+
+impl From<Option<f64>> for Level {
+    fn from(level: Option<f64>) -> Self {
+        match level {
+            None => Level::Floating,
+            Some(value) => Level::analog(value),
+        }
+    }
+}
+
+impl From<Level> for Option<f64> {
+    fn from(level: Level) -> Self {
+        level.value()
+    }
+}
+
+impl From<bool> for Level {
+    fn from(high: bool) -> Self {
+        if high {
+            Level::High
+        } else {
+            Level::Low
+        }
+    }
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Level::Low => write!(f, "low"),
+            Level::High => write!(f, "high"),
+            Level::Floating => write!(f, "floating"),
+            Level::Analog(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn normalized() {
+        assert_eq!(Level::from(Some(1.0)), Level::High);
+        assert_eq!(Level::from(Some(0.0)), Level::Low);
+        assert_eq!(Level::from(None), Level::Floating);
+        assert_eq!(Level::from(Some(0.25)), Level::Analog(0.25));
+        assert_eq!(Level::analog(1.0), Level::High);
+    }
+
+    #[test]
+    fn threshold() {
+        assert!(Level::Analog(0.5).high());
+        assert!(!Level::Analog(0.5).low());
+        assert!(Level::Analog(0.49).low());
+        assert!(!Level::Floating.high() && !Level::Floating.low());
+        assert_eq!(Level::Analog(0.75).digital(), Level::High);
+        assert_eq!(Level::Analog(0.25).digital(), Level::Low);
+        assert_eq!(Level::Floating.digital(), Level::Floating);
+    }
+
+    #[test]
+    fn round_trip() {
+        for level in [None, Some(0.0), Some(0.3), Some(1.0)] {
+            assert_eq!(Option::<f64>::from(Level::from(level)), level);
+        }
+    }
+}
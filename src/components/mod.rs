@@ -3,6 +3,14 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+pub mod addressable;
+pub mod bus;
+pub mod clock;
+pub mod delay;
 pub mod device;
+pub mod interrupt;
+pub mod level;
 pub mod pin;
+pub mod port;
+pub mod probe;
 pub mod trace;
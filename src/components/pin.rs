@@ -4,13 +4,14 @@
 // https://opensource.org/licenses/MIT
 
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     fmt::{Debug, Error, Formatter},
-    rc::Rc,
+    rc::{Rc, Weak},
 };
 
 use super::{
     device::{DeviceRef, LevelChange},
+    level::Level,
     trace::TraceRef,
 };
 
@@ -18,6 +19,74 @@ use super::{
 /// to type all those angle brackets.
 pub type PinRef = Rc<RefCell<Pin>>;
 
+/// A callback that can be registered with a pin to be called when that pin sees a rising or
+/// falling edge.
+pub type EdgeCallback = Box<dyn FnMut()>;
+
+thread_local! {
+    /// How many calls to `with_batch` are in progress.
+    static BATCH_DEPTH: Cell<usize> = const { Cell::new(0) };
+
+    /// The pins whose observers are waiting to be notified when the outermost batch ends,
+    /// in the order they first changed.
+    static BATCH_PENDING: RefCell<Vec<Weak<RefCell<Pin>>>> = const { RefCell::new(vec![]) };
+}
+
+/// Runs a function with pin notifications batched, returning what the function returns.
+///
+/// Normally a pin notifies its observers as soon as its level changes, so setting 8 pins of
+/// a data bus one at a time updates a device watching that bus 8 times, each time with a
+/// value that's only partly there. Inside a batch, pins still take their new levels right
+/// away, but their observers aren't notified until the batch ends. Then each pin that
+/// changed notifies its observers once, with its final level, in the order in which the
+/// pins first changed. A pin that ends the batch with the level it started with doesn't
+/// notify at all.
+///
+/// Edge callbacks (`on_rising` and `on_falling`) aren't batched; they're still called for
+/// every edge as it happens.
+///
+/// Batches can be nested, in which case nothing is notified until the outermost one ends.
+/// Notifications made when a batch ends aren't part of any batch, so changes that observers
+/// make in response to them propagate immediately as usual.
+pub fn with_batch<R>(f: impl FnOnce() -> R) -> R {
+    /// Ends a batch when dropped, so that a panic inside the batch doesn't leave the rest of
+    /// the thread batching forever. If the outermost batch ends in a panic, its pending
+    /// notifications are dropped rather than sent, and the pins are cleared so that they
+    /// notify as usual in later batches.
+    struct Guard;
+
+    impl Drop for Guard {
+        fn drop(&mut self) {
+            let depth = BATCH_DEPTH.with(|d| {
+                d.set(d.get() - 1);
+                d.get()
+            });
+            if depth == 0 {
+                let pending = BATCH_PENDING.with(|p| p.take());
+                let panicking = std::thread::panicking();
+                for pin in pending.iter().filter_map(Weak::upgrade) {
+                    if panicking {
+                        if let Ok(mut pin) = pin.try_borrow_mut() {
+                            pin.deferred = None;
+                        }
+                    } else {
+                        pin.borrow_mut().flush();
+                    }
+                }
+            }
+        }
+    }
+
+    BATCH_DEPTH.with(|d| d.set(d.get() + 1));
+    let _guard = Guard;
+    f()
+}
+
+/// Determines whether a batch is in progress.
+fn batching() -> bool {
+    BATCH_DEPTH.with(|d| d.get() > 0)
+}
+
 /// The direction through which data can flow through a pin.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Mode {
@@ -33,7 +102,7 @@ pub enum Mode {
 
     /// Indicates that the pin is used for output from a chip or port to a trace. Setting
     /// the level of this pin will also set the level of the trace, and a change to the
-    /// traces level will have no effect unless it's `None`.
+    /// traces level will have no effect unless it's floating.
     Output,
 
     /// Indicates that the pin is used for both input to *and* output from a chip or a port.
@@ -58,7 +127,7 @@ pub enum Mode {
 /// Pins may also be pulled up or down, which defines what level they have if a level isn't
 /// given to them. This emulates the internal pull-ups and pull-downs that some chips have
 /// (such as the port pins on a 6526 CIA). If no level is given to them and they have no
-/// pull-up or pull-down, then their level will be `None`. This can be used to represent,
+/// pull-up or pull-down, then they'll float (`Level::Floating`). This can be used to represent,
 /// e.g., a high-impedance state that cuts the pin off from its circuit.
 ///
 /// A pin maintains a list of observers that will be notified each time the pin's value
@@ -71,13 +140,13 @@ pub struct Pin {
     /// The pin name. Again, thjis is normally defined in chip or port literature.
     name: &'static str,
 
-    /// The level that the pin will take if its level is set to `None`. This value is set
-    /// by `pull_up`, `pull_down`, and `pull_off`.
-    float: Option<f64>,
+    /// The level that the pin will take if it's set to float. This value is set by
+    /// `pull_up`, `pull_down`, and `pull_off`.
+    float: Level,
 
     /// The level of the pin. If the pin has no level (i.e., it's disconnected or in a hi-Z
-    /// state), this will be `None`.
-    level: Option<f64>,
+    /// state), this will be `Level::Floating`.
+    level: Level,
 
     /// The trace to which this pin is connected. Will be `None` if the pin has not been
     /// connected to a trace. Once a trace has been connected, there is no way to disconnect
@@ -87,33 +156,50 @@ pub struct Pin {
     /// The mode of the pin, a description of which direction data is flowing through it.
     mode: Mode,
 
+    /// Whether the pin is an open-collector output. An open-collector pin can only pull its
+    /// trace low; setting it high instead floats it, leaving the level to be determined by
+    /// other output pins or by a pull-up on the trace.
+    open_collector: bool,
+
     /// A list of observers that will have their `update` methods called when this pin
     /// changes level.
-    device: Option<DeviceRef>,
-}
+    devices: Vec<DeviceRef>,
 
-/// Normalizes a level, returning that level unless it is `None`. If it *is* `None`, the
-/// `float` parameter will be returned instead.
-fn normalize(level: Option<f64>, float: Option<f64>) -> Option<f64> {
-    match level {
-        None => float,
-        _ => level,
-    }
+    /// Callbacks that will be called when this pin's level rises from low to high.
+    rising: Vec<EdgeCallback>,
+
+    /// Callbacks that will be called when this pin's level falls from high to low.
+    falling: Vec<EdgeCallback>,
+
+    /// A reference to this pin itself, which lets it add itself to the pending list of a
+    /// batch.
+    me: Weak<RefCell<Pin>>,
+
+    /// The level the pin had before its first change in the current batch, or `None` if it
+    /// hasn't changed in the current batch.
+    deferred: Option<Level>,
 }
 
 impl Pin {
     /// Creates a new pin and returns a shared, internally mutable reference to it. The pin
-    /// will be in the supplied state with a level and float level of `None`.
+    /// will be in the supplied state and floating, with no pull-up or pull-down.
     pub fn new(number: usize, name: &'static str, mode: Mode) -> PinRef {
-        Rc::new(RefCell::new(Pin {
-            number,
-            name,
-            mode,
-            float: None,
-            level: None,
-            trace: None,
-            device: None,
-        }))
+        Rc::new_cyclic(|me| {
+            RefCell::new(Pin {
+                number,
+                name,
+                mode,
+                float: Level::Floating,
+                level: Level::Floating,
+                trace: None,
+                devices: vec![],
+                open_collector: false,
+                rising: vec![],
+                falling: vec![],
+                me: Weak::clone(me),
+                deferred: None,
+            })
+        })
     }
 
     /// Sets the pin's connected trace. This trace must be wrapped in an `Rc`'d `RefCell`
@@ -123,83 +209,96 @@ impl Pin {
         self.trace = Some(trace);
     }
 
+    /// Returns the trace that this pin is connected to, if any.
+    pub fn trace(&self) -> Option<TraceRef> {
+        self.trace.as_ref().map(Rc::clone)
+    }
+
     /// Returns the pin number.
     pub fn number(&self) -> usize {
         self.number
     }
 
     /// Returns the pin name.
-    pub fn name(&self) -> &str {
+    pub fn name(&self) -> &'static str {
         self.name
     }
 
-    /// Returns the level of the pin. This can be `None` if the pin is in a hi-Z state.
-    pub fn level(&self) -> Option<f64> {
+    /// Returns the level of the pin.
+    pub fn signal(&self) -> Level {
         self.level
     }
 
-    /// Sets the level of the pin. The supplied value does not automatically become the
-    /// pin's level; a pin in `Input` mode will ignore a level set by this function.
-    pub fn set_level(&mut self, level: Option<f64>) {
+    /// Sets the level of the pin. The supplied level does not automatically become the
+    /// pin's level; a pin in `Input` mode will ignore a level set by this function, and an
+    /// open-collector pin will float instead of taking a high level.
+    pub fn drive(&mut self, level: Level) {
+        let level = if self.open_collector && level.high() {
+            Level::Floating
+        } else {
+            level
+        };
         self.level = match &self.trace {
-            None => normalize(level, self.float),
+            None => level.or(self.float),
             Some(trace) => match self.mode {
-                Mode::Unconnected => normalize(level, self.float),
+                Mode::Unconnected => level.or(self.float),
                 Mode::Input => self.level,
                 Mode::Output | Mode::Bidirectional => {
-                    let normalized = normalize(level, self.float);
-                    trace.borrow_mut().update(normalized);
+                    let normalized = level.or(self.float);
+                    trace.borrow_mut().update(normalized, self.source());
                     normalized
                 }
             },
         }
     }
 
+    /// Returns the level of the pin as a number, or `None` if the pin is in a hi-Z state.
+    pub fn level(&self) -> Option<f64> {
+        self.level.value()
+    }
+
+    /// Sets the level of the pin from a number, or to floating if it's `None`. This is the
+    /// same as `drive`, which it's been replaced by.
+    #[deprecated(note = "use `drive` with a `Level` instead")]
+    pub fn set_level(&mut self, level: Option<f64>) {
+        self.drive(Level::from(level));
+    }
+
     /// Determines whether the pin's level is high. This conventionally means a level of
     /// `1.0`, but any value of `0.5` or higher will register as high.
     pub fn high(&self) -> bool {
-        match self.level {
-            None => false,
-            Some(n) => n >= 0.5,
-        }
+        self.level.high()
     }
 
     /// Determines whether the pin's level is low. This conventionally means a level of
     /// `0.0`, but any value less than `0.5` will register as low.
     pub fn low(&self) -> bool {
-        match self.level {
-            None => false,
-            Some(n) => n < 0.5,
-        }
+        self.level.low()
     }
 
     /// Determines whether the pin's level is floating. This means it has no level at all,
     /// normally indicative of a disconnected or hi-Z state.
     pub fn floating(&self) -> bool {
-        match self.level {
-            None => true,
-            _ => false,
-        }
+        self.level.floating()
     }
 
-    /// Sets the pin's level to high (`Some(1.0)`).
+    /// Sets the pin's level to high.
     pub fn set(&mut self) {
-        self.set_level(Some(1.0));
+        self.drive(Level::High);
     }
 
-    /// Sets the pin's level to low (`Some(0.0)`).
+    /// Sets the pin's level to low.
     pub fn clear(&mut self) {
-        self.set_level(Some(0.0));
+        self.drive(Level::Low);
     }
 
-    /// Sets the pin's level to floating (`None`).
+    /// Sets the pin's level to floating.
     pub fn float(&mut self) {
-        self.set_level(None);
+        self.drive(Level::Floating);
     }
 
     /// Toggles the pin's value. If the pin was high (`0.5` or higher), its new level will
-    /// become `Some(0.0)`, and vice versa. This function has no effect on pins with a level
-    /// of `None`.
+    /// become low, and vice versa. This function has no effect on pins that are floating.
     pub fn toggle(&mut self) {
         if self.high() {
             self.clear();
@@ -210,16 +309,41 @@ impl Pin {
 
     /// Updates the pin's value if it is an input pin (mode `Input` or `Bidirectional`).
     /// This will notify observers of the pin if its level actually changes (it's not being
-    /// set to the same level it aleady had).
+    /// set to the same level it aleady had), either immediately or, if a batch is in
+    /// progress, when it ends.
     ///
     /// This method should only be called by a connected trace, so its visibility is limited
     /// to the components module.
-    pub(super) fn update(&mut self, level: Option<f64>) {
+    pub(super) fn update(&mut self, level: Level) {
         let old_level = self.level;
-        let new_level = normalize(level, self.float);
+        let new_level = level.or(self.float);
         if self.input() && new_level != old_level {
             self.level = new_level;
-            self.notify();
+            if batching() {
+                self.defer(old_level);
+            } else {
+                self.notify();
+            }
+            self.fire_edges(old_level, new_level);
+        }
+    }
+
+    /// Puts off notifying observers until the current batch ends. `old_level` is the level
+    /// the pin had before this change.
+    fn defer(&mut self, old_level: Level) {
+        if self.deferred.is_none() {
+            self.deferred = Some(old_level);
+            BATCH_PENDING.with(|p| p.borrow_mut().push(Weak::clone(&self.me)));
+        }
+    }
+
+    /// Notifies observers of a change that was put off by a batch, unless the pin's level
+    /// has come back around to where it was before the batch.
+    fn flush(&mut self) {
+        if let Some(old_level) = self.deferred.take() {
+            if old_level != self.level {
+                self.notify();
+            }
         }
     }
 
@@ -241,93 +365,144 @@ impl Pin {
 
         if let Some(trace) = &self.trace {
             match mode {
-                Mode::Output | Mode::Bidirectional => trace.borrow_mut().update(self.level),
+                Mode::Output | Mode::Bidirectional => {
+                    trace.borrow_mut().update(self.level, self.source())
+                }
                 Mode::Input | Mode::Unconnected => {
                     if mode == Mode::Input {
-                        self.level = normalize(trace.borrow().level(), self.float);
+                        self.level = trace.borrow().signal().or(self.float);
                     }
-                    if old_level.is_some()
+                    if !old_level.floating()
                         && (old_mode == Mode::Output || old_mode == Mode::Bidirectional)
                     {
-                        trace.borrow_mut().update(None);
+                        trace.borrow_mut().update(Level::Floating, None);
                     }
                 }
             }
         }
     }
 
-    /// Determines whether the pin is an input pin (mode `Input` or `Bidirectional`).
-    pub fn input(&self) -> bool {
+    /// Returns whether the pin is an open-collector output.
+    pub fn open_collector(&self) -> bool {
+        self.open_collector
+    }
+
+    /// Sets whether the pin is an open-collector output. Open-collector pins never drive a
+    /// high level; setting them high floats them instead, so a trace connected to several
+    /// of them (and pulled up) is high only when none of them are pulling it low. This is
+    /// how wired-AND lines like the C64's IRQ and NMI lines work.
+    ///
+    /// The pin's current level is not changed by this method; it will take effect the next
+    /// time the pin's level is set.
+    pub fn set_open_collector(&mut self, open_collector: bool) {
+        self.open_collector = open_collector;
+    }
+
+    /// Identifies this pin to its trace as a driver, if it's in `Output` mode. This is
+    /// passed along when the pin sets its trace's level so that the trace can report
+    /// contention involving this pin.
+    fn source(&self) -> Option<(usize, &'static str)> {
         match self.mode {
-            Mode::Input | Mode::Bidirectional => true,
-            _ => false,
+            Mode::Output => Some((self.number, self.name)),
+            _ => None,
         }
     }
 
+    /// Determines whether the pin is an input pin (mode `Input` or `Bidirectional`).
+    pub fn input(&self) -> bool {
+        matches!(self.mode, Mode::Input | Mode::Bidirectional)
+    }
+
     /// Determines whether the pin is an output pin (mode `Output` or `Bidirectional`).
     pub fn output(&self) -> bool {
-        match self.mode {
-            Mode::Output | Mode::Bidirectional => true,
-            _ => false,
-        }
+        matches!(self.mode, Mode::Output | Mode::Bidirectional)
     }
 
-    /// Sets the pin to be pulled up. If a pin is pulled up, setting it to a level of `None`
-    /// will cause it to instead be set to `Some(1.0)`. This emulates pins that are
+    /// Sets the pin to be pulled up. If a pin is pulled up, setting it to float will cause
+    /// it to instead be set high. This emulates pins that are
     /// internally pulled up, like the parallel port pins on the 6526 CIA.
     pub fn pull_up(&mut self) {
-        self.float = Some(1.0);
-        self.level = normalize(self.level, self.float);
+        self.float = Level::High;
+        self.level = self.level.or(self.float);
     }
 
-    /// Sets the pin to be pulled down. If a pin is pulled down, setting it to a level of
-    /// `None` will cause it instead to be set to `Some(0.0)`. This emulates pins that are
-    /// internally pulled down.
+    /// Sets the pin to be pulled down. If a pin is pulled down, setting it to float will
+    /// cause it instead to be set low. This emulates pins that are internally pulled down.
     pub fn pull_down(&mut self) {
-        self.float = Some(0.0);
-        self.level = normalize(self.level, self.float);
+        self.float = Level::Low;
+        self.level = self.level.or(self.float);
     }
 
     /// Removes any pull-up or pull-down status for the pin. The pin will take levels
-    /// normally, taking on the level `None` if it is set to `None`.
+    /// normally, floating if it is set to float.
     pub fn pull_off(&mut self) {
-        self.float = None;
-        self.level = normalize(self.level, self.float);
+        self.float = Level::Floating;
     }
 
     /// Determines whether the pin has a connected trace. This is a convenience function
     /// used by `Trace` to ensure that it can only connect to a pin that doesn't already
     /// have a trace connected.
     pub fn connected(&self) -> bool {
-        match self.trace {
-            None => false,
-            _ => true,
-        }
+        self.trace.is_some()
     }
 
-    /// Attaches an observer to this pin. In reality every pin should have one observer
-    /// because each pin belongs to only one device, but this will allow a pin to be
-    /// observed in testing or debugging as well.
+    /// Attaches an observer to this pin. Observers are added to any that are already
+    /// attached, and all of them are notified when the pin's level changes. Normally a pin
+    /// has one observer, the device it belongs to, but this allows a pin to be observed in
+    /// testing or debugging as well.
     pub fn attach(&mut self, device: DeviceRef) {
-        self.device = Some(device);
+        self.devices.push(device);
     }
 
-    /// Detaches an observer from this pin. The observer is found by its `id` method and the
-    /// first one with the same id as the supplied observer is removed.
+    /// Detaches an observer from this pin. The observer is identified by its device id (the
+    /// value returned by its `id` method), and the first observer with that id is removed.
+    /// Detaching an observer that isn't attached does nothing.
+    ///
+    /// Each attached observer is borrowed to read its id, so this must not be called from
+    /// within an observer's own `update` method.
     ///
-    /// Each pin should have one observer (the device it belongs to) and that observer
-    /// should have an id of 0. Moreover, it should not ever have to be detached. This
-    /// method allows there to be temporary debugging/testing observers that can be attached
-    /// and detached at will.
-    pub fn detach(&mut self) {
-        self.device = None;
+    /// A pin's own device should never have to be detached. This method allows there to be
+    /// temporary debugging/testing observers that can be attached and detached at will.
+    pub fn detach(&mut self, id: usize) {
+        if let Some(index) = self.devices.iter().position(|d| d.borrow().id() == id) {
+            self.devices.remove(index);
+        }
+    }
+
+    /// Registers a callback to be called whenever this pin's level rises from low to high
+    /// (crosses the `0.5` threshold upwards). As with observers, callbacks are only called
+    /// for level changes that come from the pin's trace while it's in `Input` or
+    /// `Bidirectional` mode. Changes to or from a floating level are not edges and do not
+    /// call any callbacks.
+    ///
+    /// Callbacks are called while the pin is borrowed, so they must not try to borrow the
+    /// pin themselves.
+    pub fn on_rising(&mut self, callback: EdgeCallback) {
+        self.rising.push(callback);
+    }
+
+    /// Registers a callback to be called whenever this pin's level falls from high to low
+    /// (crosses the `0.5` threshold downwards). The same restrictions apply as for
+    /// `on_rising`.
+    pub fn on_falling(&mut self, callback: EdgeCallback) {
+        self.falling.push(callback);
+    }
+
+    /// Calls the rising or falling edge callbacks if a level change crossed the high/low
+    /// threshold.
+    fn fire_edges(&mut self, old_level: Level, new_level: Level) {
+        if old_level.low() && new_level.high() {
+            self.rising.iter_mut().for_each(|cb| cb());
+        } else if old_level.high() && new_level.low() {
+            self.falling.iter_mut().for_each(|cb| cb());
+        }
     }
 
-    /// Notifies this pin's observers of a change to its
+    /// Notifies this pin's observers of a change to its level.
     fn notify(&self) {
         let pin = Rc::new(RefCell::new(self));
-        let event = &LevelChange(pin);
-        for ob in self.device.iter() {
+        let event = &LevelChange(pin, self.signal());
+        for ob in self.devices.iter() {
             ob.borrow_mut().update(event);
         }
     }
@@ -371,7 +546,7 @@ impl Debug for Pin {
 
 #[cfg(test)]
 mod test {
-    use crate::components::device::Device;
+    use crate::components::device::{next_id, Device};
     use crate::vectors::RefVec;
 
     use super::Mode::{Bidirectional, Input, Output, Unconnected};
@@ -496,7 +671,10 @@ mod test {
         assert!(high!(p));
     }
 
+    // This and `edge_callbacks_threshold` also check that the deprecated `set_level` still
+    // works the way it always has
     #[test]
+    #[allow(deprecated)]
     fn level_no_trace() {
         let p = Pin::new(1, "A", Input);
         assert!(p.borrow().level().is_none());
@@ -527,7 +705,7 @@ mod test {
     fn level_update_no_trace() {
         let p = Pin::new(1, "A", Input);
         p.borrow_mut().set();
-        p.borrow_mut().update(None);
+        p.borrow_mut().update(Level::Floating);
         assert!(p.borrow().level().is_none());
     }
 
@@ -814,6 +992,54 @@ mod test {
         assert!(floating!(p));
     }
 
+    #[test]
+    fn open_collector_output() {
+        let p = pin!(1, "A", Output);
+        open_collector!(p);
+        let t = trace!(p);
+        pull_up!(t);
+
+        clear!(p);
+        assert!(low!(p));
+        assert!(low!(t));
+
+        set!(p);
+        assert!(floating!(p));
+        assert!(high!(t));
+    }
+
+    #[test]
+    fn open_collector_wired_and() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        open_collector!(p1, p2);
+        let t = trace!(p1, p2);
+        pull_up!(t);
+
+        set!(p1);
+        set!(p2);
+        assert!(
+            high!(t),
+            "trace should be pulled high when no pin pulls it low"
+        );
+
+        clear!(p1);
+        assert!(low!(t), "trace should be low when one pin pulls it low");
+
+        clear!(p2);
+        set!(p1);
+        assert!(
+            low!(t),
+            "trace should stay low while the other pin pulls it low"
+        );
+
+        set!(p2);
+        assert!(
+            high!(t),
+            "trace should return high when all pins release it"
+        );
+    }
+
     // Device testing
     //
     // This is a bit weird because we need to be able to see into the Device from the
@@ -851,6 +1077,7 @@ mod test {
     // done this way in actual code.
 
     struct TestDevice {
+        id: usize,
         count: usize,
         level: Option<f64>,
     }
@@ -858,6 +1085,7 @@ mod test {
     impl TestDevice {
         fn new() -> TestDevice {
             TestDevice {
+                id: next_id(),
                 count: 0,
                 level: None,
             }
@@ -865,6 +1093,10 @@ mod test {
     }
 
     impl Device for TestDevice {
+        fn id(&self) -> usize {
+            self.id
+        }
+
         fn update(&mut self, event: &LevelChange) {
             self.count += 1;
             self.level = level!(event.0);
@@ -954,13 +1186,14 @@ mod test {
 
         let d = Rc::new(RefCell::new(TestDevice::new()));
         let tested = Rc::clone(&d);
+        let id = d.borrow().id();
 
         attach!(p, d);
 
         set!(t);
         assert_eq!(tested.borrow().count, 1);
 
-        detach!(p);
+        detach!(p, id);
 
         clear!(t);
         assert_eq!(tested.borrow().count, 1);
@@ -973,13 +1206,280 @@ mod test {
 
         let d = Rc::new(RefCell::new(TestDevice::new()));
         let tested = Rc::clone(&d);
+        let id = d.borrow().id();
 
         set!(t);
         assert_eq!(tested.borrow().count, 0);
 
-        detach!(p);
+        detach!(p, id);
+
+        clear!(t);
+        assert_eq!(tested.borrow().count, 0);
+    }
+
+    #[test]
+    fn observer_multiple() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+
+        let d1 = Rc::new(RefCell::new(TestDevice::new()));
+        let d2 = Rc::new(RefCell::new(TestDevice::new()));
+        let tested1 = Rc::clone(&d1);
+        let tested2 = Rc::clone(&d2);
+        let id = d1.borrow().id();
+
+        attach!(p, d1);
+        attach!(p, d2);
+
+        set!(t);
+        assert_eq!(tested1.borrow().count, 1);
+        assert_eq!(tested1.borrow().level.unwrap(), 1.0);
+        assert_eq!(tested2.borrow().count, 1);
+        assert_eq!(tested2.borrow().level.unwrap(), 1.0);
+
+        detach!(p, id);
 
         clear!(t);
+        assert_eq!(tested1.borrow().count, 1);
+        assert_eq!(tested2.borrow().count, 2);
+        assert_eq!(tested2.borrow().level.unwrap(), 0.0);
+    }
+
+    fn counter() -> (Rc<RefCell<usize>>, EdgeCallback) {
+        let count = Rc::new(RefCell::new(0));
+        let c = Rc::clone(&count);
+        (count, Box::new(move || *c.borrow_mut() += 1))
+    }
+
+    #[test]
+    fn edge_callbacks() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+        clear!(t);
+
+        let (rises, on_rise) = counter();
+        let (falls, on_fall) = counter();
+        p.borrow_mut().on_rising(on_rise);
+        p.borrow_mut().on_falling(on_fall);
+
+        set!(t);
+        assert_eq!(*rises.borrow(), 1);
+        assert_eq!(*falls.borrow(), 0);
+
+        set!(t);
+        assert_eq!(*rises.borrow(), 1, "no edge without a level change");
+
+        clear!(t);
+        assert_eq!(*rises.borrow(), 1);
+        assert_eq!(*falls.borrow(), 1);
+
+        for _ in 0..3 {
+            set!(t);
+            clear!(t);
+        }
+        assert_eq!(*rises.borrow(), 4);
+        assert_eq!(*falls.borrow(), 4);
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn edge_callbacks_threshold() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+        t.borrow_mut().set_level(Some(0.2));
+
+        let (rises, on_rise) = counter();
+        let (falls, on_fall) = counter();
+        p.borrow_mut().on_rising(on_rise);
+        p.borrow_mut().on_falling(on_fall);
+
+        t.borrow_mut().set_level(Some(0.4));
+        assert_eq!(*rises.borrow(), 0, "level stayed below the threshold");
+        t.borrow_mut().set_level(Some(0.5));
+        assert_eq!(*rises.borrow(), 1);
+        t.borrow_mut().set_level(Some(0.9));
+        assert_eq!(*rises.borrow(), 1, "level stayed above the threshold");
+        t.borrow_mut().set_level(Some(0.49));
+        assert_eq!(*falls.borrow(), 1);
+    }
+
+    #[test]
+    fn edge_callbacks_float() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+
+        let (rises, on_rise) = counter();
+        let (falls, on_fall) = counter();
+        p.borrow_mut().on_rising(on_rise);
+        p.borrow_mut().on_falling(on_fall);
+
+        set!(t);
+        float!(t);
+        clear!(t);
+        float!(t);
+        set!(t);
+        assert_eq!(
+            *rises.borrow(),
+            0,
+            "floating transitions are not rising edges"
+        );
+        assert_eq!(
+            *falls.borrow(),
+            0,
+            "floating transitions are not falling edges"
+        );
+    }
+
+    #[test]
+    fn edge_callbacks_output() {
+        let p = pin!(1, "A", Output);
+        let t = trace!(p);
+
+        let (rises, on_rise) = counter();
+        p.borrow_mut().on_rising(on_rise);
+
+        clear!(p);
+        set!(p);
+        set!(t);
+        assert_eq!(*rises.borrow(), 0, "output pins do not see edges");
+    }
+
+    #[test]
+    fn observer_detach_by_id() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+
+        let d1 = Rc::new(RefCell::new(TestDevice::new()));
+        let d2 = Rc::new(RefCell::new(TestDevice::new()));
+        let tested1 = Rc::clone(&d1);
+        let tested2 = Rc::clone(&d2);
+        let id2 = d2.borrow().id();
+        assert_ne!(d1.borrow().id(), id2, "devices should have distinct ids");
+
+        attach!(p, d1);
+        attach!(p, d2);
+
+        // Detaching the second observer must leave the first in place
+        detach!(p, id2);
+        set!(t);
+        assert_eq!(tested1.borrow().count, 1);
+        assert_eq!(tested2.borrow().count, 0);
+    }
+
+    #[test]
+    fn batch_notifies_once_per_pin() {
+        let pins = RefVec::with_vec(
+            (0..8)
+                .map(|i| pin!(i + 1, "D", Input))
+                .collect::<Vec<PinRef>>(),
+        );
+        let traces = pins.iter().map(|p| trace!(p)).collect::<Vec<TraceRef>>();
+
+        let d = Rc::new(RefCell::new(TestDevice::new()));
+        let tested = Rc::clone(&d);
+        for p in pins.iter() {
+            let observer: DeviceRef = Rc::clone(&d) as DeviceRef;
+            attach!(p, observer);
+        }
+
+        // Unbatched, every pin notifies as soon as it changes
+        for t in traces.iter() {
+            set!(t);
+        }
+        assert_eq!(tested.borrow().count, 8);
+
+        // Batched, the pins that changed notify once each, and only after all have changed
+        tested.borrow_mut().count = 0;
+        with_batch(|| {
+            for t in traces.iter().take(4) {
+                clear!(t);
+                set!(t);
+                clear!(t);
+            }
+            assert_eq!(tested.borrow().count, 0, "no notifications inside a batch");
+        });
+        assert_eq!(tested.borrow().count, 4);
+        assert_eq!(crate::utils::pins_to_value(&pins), 0xf0);
+    }
+
+    #[test]
+    fn batch_skips_restored_level() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+        clear!(t);
+
+        let d = Rc::new(RefCell::new(TestDevice::new()));
+        let tested = Rc::clone(&d);
+        attach!(p, d);
+
+        with_batch(|| {
+            set!(t);
+            clear!(t);
+        });
         assert_eq!(tested.borrow().count, 0);
     }
+
+    #[test]
+    fn batch_nested() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+
+        let d = Rc::new(RefCell::new(TestDevice::new()));
+        let tested = Rc::clone(&d);
+        attach!(p, d);
+
+        with_batch(|| {
+            with_batch(|| {
+                set!(t);
+            });
+            assert_eq!(tested.borrow().count, 0, "inner batch should not notify");
+        });
+        assert_eq!(tested.borrow().count, 1);
+        assert_eq!(tested.borrow().level, Some(1.0));
+    }
+
+    #[test]
+    fn batch_edges_not_deferred() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+        clear!(t);
+
+        let (rises, on_rise) = counter();
+        p.borrow_mut().on_rising(on_rise);
+
+        with_batch(|| {
+            set!(t);
+            assert_eq!(*rises.borrow(), 1);
+            clear!(t);
+            set!(t);
+        });
+        assert_eq!(*rises.borrow(), 2);
+    }
+
+    #[test]
+    fn batch_recovers_from_panic() {
+        let p = pin!(1, "A", Input);
+        let t = trace!(p);
+        clear!(t);
+
+        let d = Rc::new(RefCell::new(TestDevice::new()));
+        let tested = Rc::clone(&d);
+        attach!(p, d);
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            with_batch(|| {
+                set!(t);
+                panic!("panic inside a batch");
+            })
+        }));
+        assert!(result.is_err());
+        assert!(!batching(), "batch should have ended");
+        assert_eq!(tested.borrow().count, 0, "panicked batch should not notify");
+
+        with_batch(|| {
+            clear!(t);
+        });
+        assert_eq!(tested.borrow().count, 1, "later batch should notify");
+        assert_eq!(tested.borrow().level, Some(0.0));
+    }
 }
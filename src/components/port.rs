@@ -0,0 +1,330 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    fmt::{Display, Error, Formatter},
+    rc::Rc,
+};
+
+use crate::{
+    components::{
+        pin::{Mode, Pin},
+        trace::{Trace, TraceRef},
+    },
+    utils::{mode_to_pins, none_to_pins, pins_to_value, value_to_pins},
+    vectors::RefVec,
+};
+
+/// An error from connecting one port to another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PortError {
+    /// The two ports have different numbers of pins. The first value is this port's width
+    /// and the second is the other port's width.
+    WidthMismatch(usize, usize),
+
+    /// The pins at this index in both ports are already connected to different traces.
+    /// Traces can't be merged, so these pins can't be connected to each other.
+    AlreadyConnected(usize),
+}
+
+impl Display for PortError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            PortError::WidthMismatch(this, other) => write!(
+                f,
+                "cannot connect a {}-pin port to a {}-pin port",
+                this, other
+            ),
+            PortError::AlreadyConnected(index) => write!(
+                f,
+                "pins at index {} are already connected to different traces",
+                index
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PortError {}
+
+/// An ordered group of pins that are read and written together as a single value, such as
+/// a chip's address or data pins.
+///
+/// The pins are held LSB-first: the first pin is bit 0 of the port's value, the second is
+/// bit 1, and so on. The pins themselves are shared with the device that owns them, so a
+/// port is just a different view of pins that are also in that device's `pins` vector.
+#[derive(Clone)]
+pub struct Port {
+    /// The pins in the port, in bit order.
+    pins: RefVec<Pin>,
+}
+
+impl Port {
+    /// Creates a new port from a vector of pins, which must be in bit order (LSB first).
+    pub fn new(pins: RefVec<Pin>) -> Port {
+        Port { pins }
+    }
+
+    /// Creates a new port from some of a device's pins. `pins` is the full pin vector of
+    /// the device (indexed by pin number), and `numbers` lists the pin numbers that make up
+    /// the port in bit order (LSB first).
+    pub fn from_pins(pins: &RefVec<Pin>, numbers: &[usize]) -> Port {
+        Port::new(RefVec::with_vec(
+            numbers.iter().map(|&n| clone_ref!(pins[n])).collect(),
+        ))
+    }
+
+    /// Returns the number of pins in the port.
+    pub fn width(&self) -> usize {
+        self.pins.len()
+    }
+
+    /// Returns the pins of the port, in bit order.
+    pub fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    /// Reads the value of the port. If any of the port's pins are floating, there is no
+    /// meaningful value and `None` is returned.
+    pub fn read(&self) -> Option<usize> {
+        if self.pins.iter().any(|pin| floating!(pin)) {
+            None
+        } else {
+            Some(pins_to_value(&self.pins))
+        }
+    }
+
+    /// Reads the value of the port, treating floating pins as low. This is how chips
+    /// generally see their input pins, since a floating input doesn't stop a chip from
+    /// latching a value.
+    pub fn value(&self) -> usize {
+        pins_to_value(&self.pins)
+    }
+
+    /// Sets the levels of the port's pins to represent a value. The value must fit in the
+    /// port's width.
+    pub fn write(&mut self, value: usize) {
+        value_to_pins(value, &self.pins);
+    }
+
+    /// Sets the mode of all of the port's pins.
+    pub fn set_mode(&mut self, mode: Mode) {
+        mode_to_pins(mode, &self.pins);
+    }
+
+    /// Sets all of the port's pins to floating.
+    pub fn float(&mut self) {
+        none_to_pins(&self.pins);
+    }
+
+    /// Connects each of this port's pins to the pin in the same position of another port,
+    /// returning the traces that connect them. A new trace is created for each pair of
+    /// pins that aren't yet connected to anything; if one pin of a pair is already
+    /// connected to a trace, the other pin is added to that trace.
+    ///
+    /// The ports must be the same width. Nothing is connected if they aren't, or if any
+    /// pair of pins are already connected to two different traces.
+    pub fn connect(&self, other: &Port) -> Result<RefVec<Trace>, PortError> {
+        if self.width() != other.width() {
+            return Err(PortError::WidthMismatch(self.width(), other.width()));
+        }
+        for (i, (a, b)) in self.pins.iter().zip(other.pins.iter()).enumerate() {
+            if let (Some(ta), Some(tb)) = (a.borrow().trace(), b.borrow().trace()) {
+                if !Rc::ptr_eq(&ta, &tb) {
+                    return Err(PortError::AlreadyConnected(i));
+                }
+            }
+        }
+
+        let traces = self
+            .pins
+            .iter()
+            .zip(other.pins.iter())
+            .map(|(a, b)| {
+                let existing = a.borrow().trace().or_else(|| b.borrow().trace());
+                match existing {
+                    Some(trace) => {
+                        for pin in [a, b] {
+                            if !pin.borrow().connected() {
+                                trace.borrow_mut().add_pin(clone_ref!(pin));
+                                pin.borrow_mut().set_trace(clone_ref!(trace));
+                            }
+                        }
+                        trace
+                    }
+                    None => trace!(a, b),
+                }
+            })
+            .collect::<Vec<TraceRef>>();
+
+        Ok(RefVec::with_vec(traces))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        components::pin::Mode::{Input, Output},
+        test_utils::{traces_to_value, value_to_traces},
+    };
+
+    use super::*;
+
+    fn make_port(width: usize, mode: Mode) -> Port {
+        Port::new(RefVec::with_vec(
+            (0..width).map(|i| pin!(i + 1, "P", mode)).collect(),
+        ))
+    }
+
+    fn make_traces(port: &Port) -> RefVec<Trace> {
+        RefVec::with_vec(port.pins().iter().map(|p| trace!(p)).collect())
+    }
+
+    #[test]
+    fn width() {
+        assert_eq!(make_port(8, Input).width(), 8);
+        assert_eq!(make_port(10, Input).width(), 10);
+    }
+
+    #[test]
+    fn from_pins() {
+        let pins = refvec![
+            pin!(0, "DUMMY", Input),
+            pin!(1, "A1", Input),
+            pin!(2, "A0", Input),
+        ];
+        let port = Port::from_pins(&pins, &[2, 1]);
+        assert_eq!(port.width(), 2);
+        assert_eq!(name!(port.pins()[0]), "A0");
+        assert_eq!(name!(port.pins()[1]), "A1");
+    }
+
+    #[test]
+    fn read_lsb_first() {
+        let port = make_port(8, Input);
+        let tr = make_traces(&port);
+
+        value_to_traces(0x81, &tr);
+        assert_eq!(port.read(), Some(0x81));
+        assert!(high!(port.pins()[0]));
+        assert!(high!(port.pins()[7]));
+        assert!(low!(port.pins()[1]));
+    }
+
+    #[test]
+    fn read_partial_float() {
+        let port = make_port(4, Input);
+        let tr = make_traces(&port);
+
+        value_to_traces(0xf, &tr);
+        assert_eq!(port.read(), Some(0xf));
+
+        float!(tr[2]);
+        assert_eq!(port.read(), None, "read should be None if any pin floats");
+        assert_eq!(
+            port.value(),
+            0xb,
+            "value should treat the floating pin as low"
+        );
+    }
+
+    #[test]
+    fn read_all_float() {
+        let port = make_port(4, Input);
+        assert_eq!(port.read(), None);
+        assert_eq!(port.value(), 0);
+    }
+
+    #[test]
+    fn write() {
+        let mut port = make_port(8, Output);
+        let tr = make_traces(&port);
+
+        port.write(0xa5);
+        assert_eq!(traces_to_value(&tr), 0xa5);
+    }
+
+    #[test]
+    fn set_mode() {
+        let mut port = make_port(4, Input);
+        let tr = make_traces(&port);
+
+        port.write(0xf);
+        assert_eq!(port.read(), None, "input pins should ignore writes");
+
+        port.set_mode(Output);
+        port.write(0xf);
+        assert_eq!(traces_to_value(&tr), 0xf);
+        assert!(port.pins().iter().all(|p| mode!(p) == Output));
+    }
+
+    #[test]
+    fn float() {
+        let mut port = make_port(4, Output);
+        let tr = make_traces(&port);
+
+        port.write(0xf);
+        port.float();
+        assert!(tr.iter().all(|t| floating!(t)));
+        assert_eq!(port.read(), None);
+    }
+
+    #[test]
+    fn connect() {
+        let mut out = make_port(8, Output);
+        let inp = make_port(8, Input);
+
+        let tr = out.connect(&inp).unwrap();
+        assert_eq!(tr.len(), 8);
+
+        out.write(0x3c);
+        assert_eq!(inp.read(), Some(0x3c));
+        assert_eq!(traces_to_value(&tr), 0x3c);
+    }
+
+    #[test]
+    fn connect_existing_trace() {
+        let mut out = make_port(4, Output);
+        let inp1 = make_port(4, Input);
+        let inp2 = make_port(4, Input);
+
+        let tr1 = out.connect(&inp1).unwrap();
+        let tr2 = inp2.connect(&out).unwrap();
+        for (t1, t2) in tr1.iter().zip(tr2.iter()) {
+            assert!(
+                std::rc::Rc::ptr_eq(t1, t2),
+                "existing traces should be reused"
+            );
+        }
+
+        out.write(0x9);
+        assert_eq!(inp1.read(), Some(0x9));
+        assert_eq!(inp2.read(), Some(0x9));
+    }
+
+    #[test]
+    fn connect_width_mismatch() {
+        let out = make_port(8, Output);
+        let inp = make_port(4, Input);
+
+        assert_eq!(
+            out.connect(&inp).err(),
+            Some(PortError::WidthMismatch(8, 4))
+        );
+        assert!(
+            inp.pins().iter().all(|p| !p.borrow().connected()),
+            "nothing should be connected after a failed connect"
+        );
+    }
+
+    #[test]
+    fn connect_different_traces() {
+        let a = make_port(2, Output);
+        let b = make_port(2, Input);
+        let _ta = make_traces(&a);
+        let _tb = make_traces(&b);
+
+        assert_eq!(a.connect(&b).err(), Some(PortError::AlreadyConnected(0)));
+    }
+}
@@ -0,0 +1,537 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    rc::{Rc, Weak},
+};
+
+use crate::{
+    components::{
+        clock::Clocked,
+        device::{next_id, Device, DeviceRef, LevelChange, DUMMY},
+        level::Level,
+        pin::{
+            Mode::{Input, Unconnected},
+            Pin, PinRef,
+        },
+        trace::TraceRef,
+    },
+    diagnostics::BoundedLog,
+    vectors::RefVec,
+};
+
+/// The number of changes that a probe keeps by default.
+pub const PROBE_LOG_CAPACITY: usize = 65536;
+
+/// A convenience alias for a shared internally-mutable reference to a probe.
+pub type ProbeRef = Rc<RefCell<Probe>>;
+
+/// A recorded change in the level of a watched signal.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Change {
+    /// The cycle in which the change happened, as passed to the probe's last `clock`.
+    pub cycle: u64,
+
+    /// The name the signal was given when it was watched.
+    pub signal: &'static str,
+
+    /// The level the signal changed to.
+    pub level: Level,
+}
+
+/// A signal that the probe is watching.
+struct Signal {
+    /// The name of the signal, which is also the name of the probe's pin on its trace.
+    name: &'static str,
+
+    /// The level the signal had when it started being watched.
+    initial: Level,
+
+    /// Whether changes to the signal are being recorded.
+    enabled: bool,
+}
+
+/// A logic analyzer that records changes in the levels of traces over time.
+///
+/// A probe watches a trace by connecting a pin of its own to it, an input pin that's named
+/// after the signal. Every time the trace's level changes, the probe records a `Change`
+/// with the signal's name, its new level, and the current cycle. The probe doesn't count
+/// cycles itself; it implements `Clocked` and takes the cycle number from its last
+/// `clock`, so it should be registered with the same `Scheduler` as the devices it's
+/// watching (in `phase::OBSERVERS`, so that changes in a cycle are recorded with that
+/// cycle's number).
+///
+/// By default, a probe keeps only the most recent `PROBE_LOG_CAPACITY` changes, so it can be
+/// left running for a long time. A different limit can be set with `with_capacity`, and a
+/// probe created with `unbounded` keeps every change it records. Recording can be turned
+/// off and on for each signal with `set_enabled`.
+///
+/// Changes can be looked at directly (`changes` and `changes_of` are handy in test
+/// assertions) or written out as a Value Change Dump with `write_vcd`, which can be opened
+/// in waveform viewers like GTKWave or PulseView.
+pub struct Probe {
+    /// The unique identifier of this device.
+    id: usize,
+
+    /// A reference to the probe itself, which its pins need in order to notify it.
+    me: Weak<RefCell<Probe>>,
+
+    /// The probe's pins, one for each watched signal, along with a dummy pin (at index 0)
+    /// so that each signal's index in `signals` is its pin number minus 1.
+    pins: RefVec<Pin>,
+
+    /// The watched signals, in the order they were added.
+    signals: Vec<Signal>,
+
+    /// The recorded changes, oldest first.
+    log: BoundedLog<Change>,
+
+    /// The cycle passed to the last `clock`.
+    cycle: u64,
+}
+
+impl Probe {
+    /// Creates a new probe that keeps only the most recent `PROBE_LOG_CAPACITY` changes.
+    pub fn new() -> ProbeRef {
+        Probe::with_capacity(PROBE_LOG_CAPACITY)
+    }
+
+    /// Creates a new probe that keeps every change it records. Its memory use grows for as
+    /// long as it's watching signals that change.
+    pub fn unbounded() -> ProbeRef {
+        Probe::with_capacity(usize::MAX)
+    }
+
+    /// Creates a new probe that keeps only the most recent `capacity` changes.
+    pub fn with_capacity(capacity: usize) -> ProbeRef {
+        Rc::new_cyclic(|me| {
+            RefCell::new(Probe {
+                id: next_id(),
+                me: Weak::clone(me),
+                pins: RefVec::with_vec(vec![pin!(0, DUMMY, Unconnected)]),
+                signals: vec![],
+                log: BoundedLog::new(capacity),
+                cycle: 0,
+            })
+        })
+    }
+
+    /// Starts watching a trace, recording its changes under the signal name `name`. Names
+    /// must be unique within a probe.
+    pub fn watch(&mut self, name: &'static str, trace: &TraceRef) {
+        assert!(
+            self.signal(name).is_none(),
+            "probe is already watching a signal named {}",
+            name
+        );
+        let pin = pin!(self.signals.len() + 1, name, Input);
+        trace.borrow_mut().add_pin(clone_ref!(pin));
+        pin.borrow_mut().set_trace(clone_ref!(trace));
+
+        let initial = pin.borrow().signal();
+        let device: DeviceRef = self.me.upgrade().unwrap();
+        attach!(pin, device);
+
+        self.pins.push(pin);
+        self.signals.push(Signal {
+            name,
+            initial,
+            enabled: true,
+        });
+    }
+
+    /// Starts watching the trace that a pin is connected to, recording its changes under
+    /// the signal name `name`. If the pin isn't connected to a trace, it's given one of its
+    /// own so that it can be watched.
+    pub fn watch_pin(&mut self, name: &'static str, pin: &PinRef) {
+        let trace = pin.borrow().trace();
+        match trace {
+            Some(trace) => self.watch(name, &trace),
+            None => self.watch(name, &trace!(pin)),
+        }
+    }
+
+    /// Turns recording of a signal's changes on or off. Signals are recorded when they're
+    /// first watched.
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) {
+        if let Some(i) = self.signal(name) {
+            self.signals[i].enabled = enabled;
+        }
+    }
+
+    /// Returns the recorded changes of every signal, oldest first.
+    pub fn changes(&self) -> Vec<Change> {
+        self.log.iter().copied().collect()
+    }
+
+    /// Returns the recorded changes of one signal, oldest first.
+    pub fn changes_of(&self, name: &str) -> Vec<Change> {
+        self.log
+            .iter()
+            .filter(|change| change.signal == name)
+            .copied()
+            .collect()
+    }
+
+    /// Returns the number of changes that have been dropped to make room for newer ones.
+    pub fn evicted(&self) -> usize {
+        self.log.evicted()
+    }
+
+    /// Removes all of the recorded changes.
+    pub fn clear(&mut self) {
+        self.log.clear();
+    }
+
+    /// Writes the recorded changes as a Value Change Dump.
+    ///
+    /// Each signal is a 1-bit wire, and each cycle is one unit of time (1 µs, which is close
+    /// enough to a C64 cycle for viewing). Analog levels are written as the digital levels
+    /// they'd be read as, and floating levels as `z`. The initial value of each signal is
+    /// its level when it started being watched, unless changes have been evicted, in which
+    /// case it's unknown (`x`).
+    pub fn write_vcd(&self, w: &mut impl Write) -> io::Result<()> {
+        writeln!(w, "$timescale 1 us $end")?;
+        writeln!(w, "$scope module probe $end")?;
+        for (i, signal) in self.signals.iter().enumerate() {
+            writeln!(w, "$var wire 1 {} {} $end", vcd_id(i), signal.name)?;
+        }
+        writeln!(w, "$upscope $end")?;
+        writeln!(w, "$enddefinitions $end")?;
+
+        writeln!(w, "$dumpvars")?;
+        for (i, signal) in self.signals.iter().enumerate() {
+            let value = if self.log.evicted() > 0 {
+                'x'
+            } else {
+                vcd_value(signal.initial)
+            };
+            writeln!(w, "{}{}", value, vcd_id(i))?;
+        }
+        writeln!(w, "$end")?;
+
+        let mut time = None;
+        for change in self.log.iter() {
+            if time != Some(change.cycle) {
+                writeln!(w, "#{}", change.cycle)?;
+                time = Some(change.cycle);
+            }
+            let i = self.signal(change.signal).unwrap();
+            writeln!(w, "{}{}", vcd_value(change.level), vcd_id(i))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the index of the signal with the given name.
+    fn signal(&self, name: &str) -> Option<usize> {
+        self.signals.iter().position(|signal| signal.name == name)
+    }
+}
+
+/// Returns the VCD identifier code of the signal at an index. Codes are made of the
+/// printable ASCII characters from `!` to `~`, one character for the first 94 signals and
+/// more after that.
+fn vcd_id(index: usize) -> String {
+    let mut id = String::new();
+    let mut n = index;
+    loop {
+        id.push((b'!' + (n % 94) as u8) as char);
+        n /= 94;
+        if n == 0 {
+            break id;
+        }
+        n -= 1;
+    }
+}
+
+/// Returns the VCD value of a level.
+fn vcd_value(level: Level) -> char {
+    match level.digital() {
+        Level::High => '1',
+        Level::Low => '0',
+        _ => 'z',
+    }
+}
+
+impl Device for Probe {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn update(&mut self, event: &LevelChange) {
+        let signal = &self.signals[number!(event.0) - 1];
+        if signal.enabled {
+            self.log.push(Change {
+                cycle: self.cycle,
+                signal: signal.name,
+                level: event.level(),
+            });
+        }
+    }
+}
+
+impl Clocked for Probe {
+    fn clock(&mut self, cycle: u64) {
+        self.cycle = cycle;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::{
+        components::clock::{phase, ClockedRef, Scheduler},
+        devices::chips::Ic4164,
+        test_utils::make_traces,
+    };
+
+    /// Connects a probe to the RAS, CAS, WE, D, and Q pins of a 4164.
+    fn probed_4164() -> (ProbeRef, DeviceRef) {
+        let chip = Ic4164::new();
+        make_traces(&chip);
+        let probe = Probe::new();
+        for name in ["RAS", "CAS", "WE", "D", "Q"] {
+            let pin = chip.borrow().pin_by_name(name).unwrap();
+            probe.borrow_mut().watch_pin(name, &pin);
+        }
+        (probe, chip)
+    }
+
+    /// Writes a 1 to address 0 of a 4164 in write mode, clocking the probe with a new
+    /// cycle (starting at 10) before each step.
+    fn write_cycle(probe: &ProbeRef, chip: &DeviceRef) {
+        let trace = |name| {
+            let pin = chip.borrow().pin_by_name(name).unwrap();
+            let trace = pin.borrow().trace().unwrap();
+            trace
+        };
+        let (ras, cas, we, d) = (trace("RAS"), trace("CAS"), trace("WE"), trace("D"));
+        set!(ras, cas, we);
+        for name in ["A0", "A1", "A2", "A3", "A4", "A5", "A6", "A7"] {
+            clear!(trace(name));
+        }
+
+        let steps: [&dyn Fn(); 7] = [
+            &|| {
+                clear!(ras);
+            },
+            &|| {
+                set!(d);
+            },
+            &|| {
+                clear!(we);
+            },
+            &|| {
+                clear!(cas);
+            },
+            &|| {
+                set!(cas);
+            },
+            &|| {
+                set!(we);
+            },
+            &|| {
+                set!(ras);
+            },
+        ];
+        for (cycle, step) in steps.iter().enumerate() {
+            probe.borrow_mut().clock(cycle as u64 + 10);
+            step();
+        }
+    }
+
+    #[test]
+    fn write_edge_order() {
+        let (probe, chip) = probed_4164();
+        write_cycle(&probe, &chip);
+        probe.borrow_mut().clear();
+        write_cycle(&probe, &chip);
+
+        let probe = probe.borrow();
+        let edges: Vec<(u64, &str, Level)> = probe
+            .changes()
+            .iter()
+            .filter(|c| c.signal != "Q" && c.signal != "D")
+            .map(|c| (c.cycle, c.signal, c.level))
+            .collect();
+        assert_eq!(
+            edges,
+            vec![
+                (10, "RAS", Level::Low),
+                (12, "WE", Level::Low),
+                (13, "CAS", Level::Low),
+                (14, "CAS", Level::High),
+                (15, "WE", Level::High),
+                (16, "RAS", Level::High),
+            ]
+        );
+        assert_eq!(
+            probe.changes_of("CAS"),
+            vec![
+                Change {
+                    cycle: 13,
+                    signal: "CAS",
+                    level: Level::Low
+                },
+                Change {
+                    cycle: 14,
+                    signal: "CAS",
+                    level: Level::High
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn disabled_signal() {
+        let (probe, chip) = probed_4164();
+        probe.borrow_mut().set_enabled("CAS", false);
+        write_cycle(&probe, &chip);
+        assert!(probe.borrow().changes_of("CAS").is_empty());
+        assert!(!probe.borrow().changes_of("RAS").is_empty());
+    }
+
+    #[test]
+    fn ring_buffer() {
+        let trace = trace!(pin!(1, "OUT", Unconnected));
+        clear!(trace);
+        let probe = Probe::with_capacity(4);
+        probe.borrow_mut().watch("T", &trace);
+        for cycle in 0..10 {
+            probe.borrow_mut().clock(cycle);
+            trace.borrow_mut().toggle();
+        }
+        let probe = probe.borrow();
+        assert_eq!(probe.evicted(), 6);
+        let cycles: Vec<u64> = probe.changes().iter().map(|c| c.cycle).collect();
+        assert_eq!(cycles, vec![6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn default_capacity() {
+        let trace = trace!(pin!(1, "OUT", Unconnected));
+        clear!(trace);
+        let bounded = Probe::new();
+        let unbounded = Probe::unbounded();
+        bounded.borrow_mut().watch("T", &trace);
+        unbounded.borrow_mut().watch("T", &trace);
+        for _ in 0..PROBE_LOG_CAPACITY + 2 {
+            trace.borrow_mut().toggle();
+        }
+        assert_eq!(bounded.borrow().changes().len(), PROBE_LOG_CAPACITY);
+        assert_eq!(bounded.borrow().evicted(), 2);
+        assert_eq!(unbounded.borrow().changes().len(), PROBE_LOG_CAPACITY + 2);
+        assert_eq!(unbounded.borrow().evicted(), 0);
+    }
+
+    #[test]
+    fn scheduler_cycles() {
+        let trace = trace!(pin!(1, "OUT", Unconnected));
+        clear!(trace);
+        let probe = Probe::new();
+        probe.borrow_mut().watch("T", &trace);
+
+        let mut scheduler = Scheduler::new();
+        scheduler.register(phase::OBSERVERS, Rc::clone(&probe) as ClockedRef);
+        let t = clone_ref!(trace);
+        scheduler.every(2, 3, Box::new(move |_| t.borrow_mut().toggle()));
+        scheduler.tick_n(9);
+
+        let cycles: Vec<u64> = probe.borrow().changes().iter().map(|c| c.cycle).collect();
+        assert_eq!(cycles, vec![2, 5, 8]);
+    }
+
+    /// The parts of a VCD file that the tests check: the signal name of each identifier
+    /// code, and a list of (time, code, value) for the initial values (at time `None`) and
+    /// each change.
+    struct Vcd {
+        names: HashMap<String, String>,
+        values: Vec<(Option<u64>, String, char)>,
+    }
+
+    fn parse_vcd(text: &str) -> Vcd {
+        let mut names = HashMap::new();
+        let mut values = vec![];
+        let mut time = None;
+        let mut tokens = text.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token {
+                "$var" => {
+                    let fields: Vec<&str> = tokens.by_ref().take_while(|t| *t != "$end").collect();
+                    assert_eq!(fields[..2], ["wire", "1"]);
+                    names.insert(fields[2].to_string(), fields[3].to_string());
+                }
+                "$timescale" | "$scope" | "$upscope" | "$enddefinitions" => {
+                    tokens.by_ref().find(|t| *t == "$end");
+                }
+                "$dumpvars" | "$end" => {}
+                t if t.starts_with('#') => time = Some(t[1..].parse().unwrap()),
+                t => {
+                    let value = t.chars().next().unwrap();
+                    assert!("01xz".contains(value), "bad value {}", t);
+                    let code = &t[1..];
+                    assert!(names.contains_key(code), "undeclared code {}", code);
+                    values.push((time, code.to_string(), value));
+                }
+            }
+        }
+        Vcd { names, values }
+    }
+
+    #[test]
+    fn vcd_round_trip() {
+        let (probe, chip) = probed_4164();
+        write_cycle(&probe, &chip);
+
+        let mut out = vec![];
+        probe.borrow().write_vcd(&mut out).unwrap();
+        let vcd = parse_vcd(&String::from_utf8(out).unwrap());
+
+        let mut names: Vec<&str> = vcd.names.values().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(names, ["CAS", "D", "Q", "RAS", "WE"]);
+
+        let initial: Vec<char> = vcd
+            .values
+            .iter()
+            .filter(|(time, _, _)| time.is_none())
+            .map(|(_, _, value)| *value)
+            .collect();
+        assert_eq!(initial, ['z'; 5], "traces should float before the write");
+
+        let changes: Vec<(u64, &str, char)> = vcd
+            .values
+            .iter()
+            .filter_map(|(time, code, value)| time.map(|t| (t, vcd.names[code].as_str(), *value)))
+            .collect();
+        let expected: Vec<(u64, &str, char)> = probe
+            .borrow()
+            .changes()
+            .iter()
+            .map(|c| (c.cycle, c.signal, vcd_value(c.level)))
+            .collect();
+        assert_eq!(changes, expected);
+    }
+
+    #[test]
+    fn vcd_ids() {
+        assert_eq!(vcd_id(0), "!");
+        assert_eq!(vcd_id(93), "~");
+        assert_eq!(vcd_id(94), "!!");
+        assert_eq!(vcd_id(95), "\"!");
+    }
+}
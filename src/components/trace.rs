@@ -3,14 +3,79 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
-use std::{cell::RefCell, cmp::Ordering, fmt::Debug, rc::Rc};
+use std::{
+    cell::RefCell,
+    fmt::{Debug, Display, Formatter},
+    rc::Rc,
+};
 
-use super::pin::{Mode, PinRef};
+use crate::diagnostics::BoundedLog;
+
+use super::{
+    device::DeviceRef,
+    level::Level,
+    pin::{Mode, PinRef},
+};
+
+/// The number of contention events that a trace keeps by default.
+pub const CONTENTION_LOG_CAPACITY: usize = 256;
 
 /// A convenience alias for a shared internally-mutable reference to a Trace, so we don't
 /// have to type all those angle brackets.
 pub type TraceRef = Rc<RefCell<Trace>>;
 
+/// The strategy that a trace uses to resolve the levels of multiple output pins that are
+/// driving it at the same time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Resolution {
+    /// The trace takes the maximum level of its driving output pins. This models ordinary
+    /// totem-pole outputs where a high output wins, and it is the default.
+    Max,
+
+    /// The trace takes the minimum level of its driving output pins, so any output pulling
+    /// it low makes it low. This models open-collector lines like the C64's IRQ and NMI
+    /// lines, where several devices share a pulled-up trace and any of them can pull it
+    /// low. When all of the outputs release the trace (float), it takes its pull-up level.
+    WiredAnd,
+}
+
+impl Resolution {
+    /// Combines two driven levels according to this resolution strategy. Neither level may
+    /// be floating.
+    fn combine(self, a: Level, b: Level) -> Level {
+        let (a, b) = (a.value().unwrap_or(0.0), b.value().unwrap_or(0.0));
+        Level::analog(match self {
+            Resolution::Max => a.max(b),
+            Resolution::WiredAnd => a.min(b),
+        })
+    }
+}
+
+/// What a trace does when two or more of its output pins drive it to conflicting levels
+/// (one low, one high).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Contention {
+    /// Contention is not checked for, and the trace's resolution strategy picks the level.
+    /// This is the default.
+    MaxWins,
+
+    /// Contention is recorded as a `ContentionEvent` that can be retrieved with
+    /// `contention_events`. The trace's level is still picked by its resolution strategy.
+    Warn,
+
+    /// Contention causes a panic identifying the pins involved. This is useful when
+    /// assembling a circuit to find wiring mistakes as soon as they happen.
+    Panic,
+}
+
+/// A record of two or more output pins driving a trace to conflicting levels.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContentionEvent {
+    /// The number, name, and level of each output pin that was driving the trace when the
+    /// contention was detected.
+    pub drivers: Vec<(usize, &'static str, f64)>,
+}
+
 /// A printed-circuit board trace that connects two or more pins.
 ///
 /// A trace is designed primarily to have its level modified by a connected output pin.
@@ -19,14 +84,15 @@ pub type TraceRef = Rc<RefCell<Trace>>;
 /// to the following rules:
 ///
 /// 1. If the trace has at least one output pin connected to it that has a level, the trace
-///    takes on the maximum level among all of its connected output pins.
-/// 2. If the value being set is `None`: a. If the trace has been pulled up, its value is
-///    1.0. b. If the trace has been pulled down, its value is 0.0. c. Its value is `None`.
+///    takes on the maximum level among all of its connected output pins (or the minimum
+///    level, if its resolution has been set to `Resolution::WiredAnd`).
+/// 2. If the value being set is floating: a. If the trace has been pulled up, it's high.
+///    b. If the trace has been pulled down, it's low. c. It floats.
 /// 3. The trace takes on the set value.
 ///
 /// If a trace is set by a pin (either by an output pin changing values or by an unconnected
 /// pin mode-changing into an output pin), then the value is simply set *unless* the value
-/// it's being set to is `None`. In that case the same rules as direct setting apply.
+/// it's being set to is floating. In that case the same rules as direct setting apply.
 ///
 /// A change in the level of the trace will be propagated to any input pins connected to the
 /// trace. When this happens, the observers of all of those input pins are notified of the
@@ -35,26 +101,40 @@ pub struct Trace {
     /// A list of all of the pins that are connected to this trace.
     pins: Vec<PinRef>,
 
-    /// The level that the trace will take if its level is set to `None` and there are no
-    /// output pins with levels that will override this. This value is set by `pull_up`,
-    /// `pull_down`, and `pull_off`.
-    float: Option<f64>,
+    /// The level that the trace will take if it's set to float and there are no output pins
+    /// with levels that will override this. This value is set by `pull_up`, `pull_down`,
+    /// and `pull_off`.
+    float: Level,
 
     /// The level of the trace. If the trace has no level (i.e., it has no output pins with
-    /// levels and has had its own level set to `None`), this will be `None`.
-    level: Option<f64>,
+    /// levels and has been set to float itself), this will be `Level::Floating`.
+    level: Level,
+
+    /// How the levels of multiple output pins driving the trace are combined.
+    resolution: Resolution,
+
+    /// What the trace does when its output pins drive it to conflicting levels.
+    contention: Contention,
+
+    /// The contention events that have been recorded while the contention policy was
+    /// `Contention::Warn`. Only the most recent `CONTENTION_LOG_CAPACITY` are kept unless
+    /// the capacity is changed with `set_contention_log_capacity`.
+    events: BoundedLog<ContentionEvent>,
 }
 
 impl Trace {
     /// Creates a new trace from a vector of pins that are connected to it and returns a
     /// shared, internally mutable reference to it. Its initial level will depend on the
     /// levels of the output pins in that vector (if there are none, the trace's level will
-    /// be `None`). It's initial float value will be `None` (i.e., not pulled up or down).
+    /// float). It's not pulled up or down to begin with.
     pub fn new(pins: Vec<PinRef>) -> TraceRef {
         Rc::new(RefCell::new(Trace {
             pins,
-            float: None,
-            level: None,
+            float: Level::Floating,
+            level: Level::Floating,
+            resolution: Resolution::Max,
+            contention: Contention::MaxWins,
+            events: BoundedLog::new(CONTENTION_LOG_CAPACITY),
         }))
     }
 
@@ -64,9 +144,10 @@ impl Trace {
     ///
     /// Essentially, if there is an output pin that has a level, then the new level this
     /// method returns will be equal to the maximum level of all of its output pins (plus
-    /// the passed-in level, if `from_pin` is `true`). If there are no output pins with
-    /// levels, the passed-in level will be returned, unless that level is `None`, in which
-    /// case this traces float value will be returned.
+    /// the passed-in level, if `from_pin` is `true`), or the minimum level if the trace's
+    /// resolution is `Resolution::WiredAnd`. If there are no output pins with levels, the
+    /// passed-in level will be returned, unless that level is floating, in which case this
+    /// traces float value will be returned.
     ///
     /// A reasonable question would be "why pass in the level when it's just coming from an
     /// output pin anyway?" The answer is that this method is often called as a consequence
@@ -76,122 +157,93 @@ impl Trace {
     /// reference to a value that has already been borrowed mutable, and that's a no-no.
     /// Since this is a private method only used internally, this doesn't create any real
     /// complexity issues.
-    fn calculate(&self, level: Option<f64>, from_pin: bool) -> Option<f64> {
+    fn calculate(&self, level: Level, from_pin: bool) -> Level {
+        let resolution = self.resolution;
         match self
             .pins
             .iter()
-            .filter(|&pin| match pin.try_borrow() {
-                Ok(p) => p.mode() == Mode::Output && !p.floating(),
-                Err(_) => false,
+            .filter_map(|pin| match pin.try_borrow() {
+                Ok(p) if p.mode() == Mode::Output && !p.floating() => Some(p.signal()),
+                _ => None,
             })
-            .max_by(|x, y| {
-                // `unwrap` is fine here because anything with a `None` level has already
-                // been filtered out
-                match x
-                    .borrow()
-                    .level()
-                    .unwrap()
-                    .partial_cmp(&y.borrow().level().unwrap())
-                {
-                    Some(order) => order,
-                    // This isn't actually a possibility - all `None` values have already
-                    // been filtered out - but we have to keep the compiler happy.
-                    None => Ordering::Less,
-                }
-            }) {
-            Some(maxpin) => {
-                // `unwrap` is fine here because anything with a `None` level has already
-                // been filtered out
-                let plevel = maxpin.borrow().level().unwrap();
-                if from_pin {
-                    match level {
-                        Some(ilevel) => {
-                            if ilevel > plevel {
-                                Some(ilevel)
-                            } else {
-                                Some(plevel)
-                            }
-                        }
-                        None => Some(plevel),
-                    }
-                } else {
-                    Some(plevel)
-                }
-            }
-            None => match level {
-                Some(_) => level,
-                None => self.float,
-            },
+            .reduce(|a, b| resolution.combine(a, b))
+        {
+            Some(plevel) if from_pin && !level.floating() => resolution.combine(level, plevel),
+            Some(plevel) => plevel,
+            None => level.or(self.float),
         }
     }
 
-    /// Returns the level of the trace. This can be `None` if no output pins are driving the
-    /// trace.
-    pub fn level(&self) -> Option<f64> {
+    /// Returns the level of the trace.
+    pub fn signal(&self) -> Level {
         self.level
     }
 
     /// Sets a new level for the trace. This is a direct setting of the trace and is not
     /// considered to have come from a pin (pins use `update` instead). It will be
-    /// overridden if there is an output pin connected to the trace that has a non-`None`
-    /// level.
-    pub fn set_level(&mut self, level: Option<f64>) {
+    /// overridden if there is an output pin connected to the trace that isn't floating.
+    pub fn drive(&mut self, level: Level) {
         self.level = self.calculate(level, false);
+        self.check_contention(None);
         for pin in self.pins.iter_mut() {
             pin.borrow_mut().update(self.level);
         }
     }
 
+    /// Returns the level of the trace as a number. This can be `None` if no output pins are
+    /// driving the trace.
+    pub fn level(&self) -> Option<f64> {
+        self.level.value()
+    }
+
+    /// Sets a new level for the trace from a number, or floats it if it's `None`. This is
+    /// the same as `drive`, which it's been replaced by.
+    #[deprecated(note = "use `drive` with a `Level` instead")]
+    pub fn set_level(&mut self, level: Option<f64>) {
+        self.drive(Level::from(level));
+    }
+
     /// Determines whether the trace's level is high. This conventionally means a level of
     /// `1.0`, but any value of `0.5` or higher will register as high.
     pub fn high(&self) -> bool {
-        match self.level {
-            None => false,
-            Some(n) => n >= 0.5,
-        }
+        self.level.high()
     }
 
     /// Determines whether the trace's level is low. This conventionally means a level of
     /// `0.0`, but any value less than `0.5` will register as low.
     pub fn low(&self) -> bool {
-        match self.level {
-            None => false,
-            Some(n) => n < 0.5,
-        }
+        self.level.low()
     }
 
     /// Determines whether the trace's level is floating. This means it has no level at all,
     /// normally indicative of a trace with no output pins with levels.
     pub fn floating(&self) -> bool {
-        match self.level {
-            None => true,
-            _ => false,
-        }
+        self.level.floating()
     }
 
-    /// Sets the traces's level to high (`Some(1.0)`). This will have no effect if the trace
-    /// has an output pin connected to it with a non-`None` level.
+    /// Sets the traces's level to high. This will have no effect if the trace has an output
+    /// pin connected to it that isn't floating.
     pub fn set(&mut self) {
-        self.set_level(Some(1.0));
+        self.drive(Level::High);
     }
 
-    /// Sets the traces's level to low (`Some(0.0)`). This will have no effect if the trace
-    /// has an output pin connected to it with a non-`None` level.
+    /// Sets the traces's level to low. This will have no effect if the trace has an output
+    /// pin connected to it that isn't floating.
     pub fn clear(&mut self) {
-        self.set_level(Some(0.0));
+        self.drive(Level::Low);
     }
 
-    /// Sets the traces's level to floating (`None`). This will have no effect if the trace
-    /// has an output pin connected to it with a non-`None` level. If that's not the case
-    /// but the trace is being pulled up or down, that will override this `None` value.
+    /// Sets the traces's level to floating. This will have no effect if the trace has an
+    /// output pin connected to it that isn't floating. If that's not the case but the trace
+    /// is being pulled up or down, that will override the floating level.
     pub fn float(&mut self) {
-        self.set_level(None);
+        self.drive(Level::Floating);
     }
 
     /// Toggles the pin's value. If the pin was high (`0.5` or higher), its new level will
-    /// become `Some(0.0)`, and vice versa. This function has no effect on pins with a level
-    /// of `None`. It also has no effect if the trace has non-`None` levelled output pins
-    /// connected to it.
+    /// become low, and vice versa. This function has no effect on traces that are
+    /// floating. It also has no effect if the trace has output pins connected to it that
+    /// aren't floating.
     pub fn toggle(&mut self) {
         if self.high() {
             self.clear();
@@ -204,38 +256,163 @@ impl Trace {
     /// so its visibilty is limited to the components module. It *will* factor into level
     /// calculations alongside other connected output pins, and it will notify observers of
     /// input pins that it connects to.
-    pub(super) fn update(&mut self, level: Option<f64>) {
+    ///
+    /// If the pin calling this method is an output pin, `source` should be its number and
+    /// name. The pin is borrowed while it calls this method, so this is the only way that
+    /// the trace can identify it when reporting contention.
+    pub(super) fn update(&mut self, level: Level, source: Option<(usize, &'static str)>) {
         self.level = self.calculate(level, true);
+        if let (Some((number, name)), Some(l)) = (source, level.value()) {
+            self.check_contention(Some((number, name, l)));
+        } else {
+            self.check_contention(None);
+        }
         for pin in self.pins.iter() {
             if let Ok(mut p) = pin.try_borrow_mut() {
-                p.update(level);
+                p.update(self.level);
             }
         }
     }
 
-    /// Sets the trace to be pulled up. If a trace is pulled up, setting it to a level of
-    /// `None` will cause it to instead be set to `Some(1.0)`. This emulates traces that are
+    /// Sets the trace to be pulled up. If a trace is pulled up, setting it to float will
+    /// cause it to instead be set high. This emulates traces that are
     /// connected to pull-up resistors connected to the power supply that are intended to
     /// make the trace level high unless another output pin is driving it.
     pub fn pull_up(&mut self) {
-        self.float = Some(1.0);
-        self.set_level(self.level);
+        self.float = Level::High;
+        self.drive(self.level);
     }
 
-    /// Sets the trace to be pulled down. If a trace is pulled down, setting it to a level
-    /// of `None` will cause it to instead be set to `Some(0.0)`. This emulates traces that
+    /// Sets the trace to be pulled down. If a trace is pulled down, setting it to float
+    /// will cause it to instead be set low. This emulates traces that
     /// are connected to pull-down resistors connected to ground that are intended to make
     /// the trace level high unless another output pin is driving it.
     pub fn pull_down(&mut self) {
-        self.float = Some(0.0);
-        self.set_level(self.level);
+        self.float = Level::Low;
+        self.drive(self.level);
     }
 
     /// Removes any pull-up or pull-down status for the trace. The trace will take levels
-    /// normally, taking on the level `None` if it is set to `None`.
+    /// normally, floating if it is set to float.
     pub fn pull_off(&mut self) {
-        self.float = None;
-        self.set_level(self.level);
+        self.float = Level::Floating;
+        self.drive(self.level);
+    }
+
+    /// Returns the strategy the trace uses to combine the levels of multiple output pins.
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Sets the strategy the trace uses to combine the levels of multiple output pins. The
+    /// trace's level is recalculated under the new strategy.
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.drive(self.level);
+    }
+
+    /// Returns what the trace does when its output pins drive it to conflicting levels.
+    pub fn contention_policy(&self) -> Contention {
+        self.contention
+    }
+
+    /// Sets what the trace does when its output pins drive it to conflicting levels.
+    pub fn set_contention_policy(&mut self, contention: Contention) {
+        self.contention = contention;
+    }
+
+    /// Returns the log of contention events recorded while the contention policy was
+    /// `Contention::Warn`.
+    pub fn contention_events(&self) -> &BoundedLog<ContentionEvent> {
+        &self.events
+    }
+
+    /// Removes and returns the recorded contention events, oldest first.
+    pub fn take_contention_events(&mut self) -> Vec<ContentionEvent> {
+        self.events.take()
+    }
+
+    /// Sets the number of contention events that the trace keeps. Once there are more than
+    /// this, the oldest are evicted.
+    pub fn set_contention_log_capacity(&mut self, capacity: usize) {
+        self.events.set_capacity(capacity);
+    }
+
+    /// Returns the number, name, and level of each output pin that is currently driving
+    /// the trace (i.e., that has a level). Pins that are borrowed, which happens when they
+    /// are in the middle of setting the trace's level, are skipped.
+    fn drivers(&self) -> impl Iterator<Item = (usize, &'static str, f64)> + '_ {
+        self.pins.iter().filter_map(|pin| match pin.try_borrow() {
+            Ok(p) if p.mode() == Mode::Output => {
+                p.level().map(|level| (p.number(), p.name(), level))
+            }
+            _ => None,
+        })
+    }
+
+    /// Determines whether two or more of the trace's output pins are driving it to
+    /// different non-floating levels. This reports any difference at all, so two analog
+    /// outputs at 0.75 and 1.0 are in contention here, while the contention policy (see
+    /// `check_contention`) only reacts to levels on opposite sides of the 0.5 threshold.
+    /// This check is made on demand and works regardless of the contention policy or
+    /// resolution strategy.
+    pub fn contention(&self) -> bool {
+        let mut levels = self.drivers().map(|(_, _, level)| level);
+        match levels.next() {
+            Some(first) => levels.any(|level| level != first),
+            None => false,
+        }
+    }
+
+    /// Determines whether two or more of the trace's output pins are driving it to
+    /// different levels. This is another name for `contention`, for callers that want to
+    /// make clear that they aren't asking about the contention policy's threshold.
+    pub fn drivers_disagree(&self) -> bool {
+        self.contention()
+    }
+
+    /// Checks the trace's output pins for contention and records or panics according to the
+    /// contention policy. `source` is the pin currently setting the trace's level, if any,
+    /// since that pin is borrowed and can't be examined through the pin list.
+    ///
+    /// Contention is two or more output pins with levels on opposite sides of the 0.5
+    /// threshold. Traces using `Resolution::WiredAnd` are never in contention, since
+    /// conflicting levels are exactly how they're meant to be used. Output pins whose
+    /// levels differ without crossing the threshold aren't in contention here, though
+    /// `contention` reports them.
+    fn check_contention(&mut self, source: Option<(usize, &'static str, f64)>) {
+        if self.contention == Contention::MaxWins || self.resolution == Resolution::WiredAnd {
+            return;
+        }
+
+        let drivers: Vec<(usize, &'static str, f64)> =
+            source.into_iter().chain(self.drivers()).collect();
+
+        let high = drivers.iter().any(|&(_, _, level)| level >= 0.5);
+        let low = drivers.iter().any(|&(_, _, level)| level < 0.5);
+        if !(high && low) {
+            return;
+        }
+
+        let event = ContentionEvent { drivers };
+        match self.contention {
+            Contention::Panic => panic!(
+                "Bus contention: {}",
+                event
+                    .drivers
+                    .iter()
+                    .map(|(number, name, level)| format!("{} (pin {}) = {}", name, number, level))
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ),
+            _ => {
+                // Don't record the same conflict over and over if it persists through
+                // several level changes
+                if self.events.last() != Some(&event) {
+                    self.events.push(event);
+                }
+            }
+        }
     }
 
     /// Connects a pin to this trace. This will only actually happen if the pin is not
@@ -244,7 +421,7 @@ impl Trace {
     pub fn add_pin(&mut self, pin: PinRef) {
         if !pin.borrow().connected() {
             self.pins.push(pin);
-            self.set_level(self.level);
+            self.drive(self.level);
         }
     }
 
@@ -258,6 +435,61 @@ impl Trace {
     }
 }
 
+/// An error from connecting pins by name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectError {
+    /// The device (the first value) has no pin with the name (the second value).
+    NoSuchPin(String, String),
+
+    /// The device's (the first value) pin with the name (the second value) is already
+    /// connected to a trace.
+    AlreadyConnected(String, String),
+}
+
+impl Display for ConnectError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
+        match self {
+            ConnectError::NoSuchPin(device, name) => {
+                write!(f, "device {} has no pin named {}", device, name)
+            }
+            ConnectError::AlreadyConnected(device, name) => {
+                write!(f, "pin {} of device {} is already connected", name, device)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectError {}
+
+/// Creates a trace that connects pins looked up by name. Each entry is a label for the
+/// device (used only in errors), the device, and the name of the pin. This is what the
+/// `connect!` macro calls, and it's usually easier to use that.
+///
+/// Nothing is connected if any of the pins doesn't exist or is already connected to a
+/// trace.
+pub fn connect(pins: &[(&str, &DeviceRef, &str)]) -> Result<TraceRef, ConnectError> {
+    let mut found = vec![];
+    for &(label, device, name) in pins {
+        let pin = device
+            .borrow()
+            .pin_by_name(name)
+            .ok_or_else(|| ConnectError::NoSuchPin(label.to_string(), name.to_string()))?;
+        if pin.borrow().connected() {
+            return Err(ConnectError::AlreadyConnected(
+                label.to_string(),
+                name.to_string(),
+            ));
+        }
+        found.push(pin);
+    }
+
+    let trace = Trace::new(found.clone());
+    for pin in found {
+        pin.borrow_mut().set_trace(Rc::clone(&trace));
+    }
+    Ok(trace)
+}
+
 impl Debug for Trace {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
         let alt = f.alternate();
@@ -284,7 +516,7 @@ impl Debug for Trace {
 mod test {
     use crate::{
         components::{
-            device::{Device, LevelChange},
+            device::{next_id, Device, LevelChange},
             pin::Pin,
         },
         vectors::RefVec,
@@ -296,6 +528,7 @@ mod test {
     // Testing (as opposed to using) Devices is weird; see the long-winded explanation
     // in pin.rs for why.
     struct TestDevice {
+        id: usize,
         count: usize,
         level: Option<f64>,
     }
@@ -303,6 +536,7 @@ mod test {
     impl TestDevice {
         fn new() -> TestDevice {
             TestDevice {
+                id: next_id(),
                 count: 0,
                 level: None,
             }
@@ -310,6 +544,10 @@ mod test {
     }
 
     impl Device for TestDevice {
+        fn id(&self) -> usize {
+            self.id
+        }
+
         fn update(&mut self, event: &LevelChange) {
             self.count += 1;
             self.level = level!(event.0);
@@ -687,4 +925,237 @@ mod test {
         pull_off!(t);
         assert!(floating!(t));
     }
+
+    #[test]
+    fn resolution_default_max() {
+        let t = trace!();
+        assert_eq!(t.borrow().resolution(), Resolution::Max);
+    }
+
+    #[test]
+    fn resolution_max_mixed() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+
+        clear!(p1);
+        set!(p2);
+        assert!(high!(t), "high output should win under Max");
+
+        set!(p1);
+        clear!(p2);
+        assert!(high!(t), "high output should win under Max");
+    }
+
+    #[test]
+    fn resolution_wired_and_mixed() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        t.borrow_mut().set_resolution(Resolution::WiredAnd);
+
+        clear!(p1);
+        set!(p2);
+        assert!(low!(t), "low output should win under WiredAnd");
+
+        set!(p1);
+        clear!(p2);
+        assert!(low!(t), "low output should win under WiredAnd");
+
+        set!(p2);
+        assert!(high!(t), "trace should be high when all outputs are high");
+    }
+
+    #[test]
+    fn resolution_change_recalculates() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+
+        clear!(p1);
+        set!(p2);
+        assert!(high!(t));
+
+        t.borrow_mut().set_resolution(Resolution::WiredAnd);
+        assert!(low!(t));
+
+        t.borrow_mut().set_resolution(Resolution::Max);
+        assert!(high!(t));
+    }
+
+    #[test]
+    fn wired_and_interrupt_sources() {
+        // Two interrupt sources sharing a pulled-up /IRQ line. Each pulls the line low to
+        // signal an interrupt and floats it to release.
+        let cia = pin!(1, "IRQ", Output);
+        let vic = pin!(2, "IRQ", Output);
+        let cpu = pin!(3, "IRQ", Input);
+        let irq = trace!(cia, vic, cpu);
+        irq.borrow_mut().set_resolution(Resolution::WiredAnd);
+        pull_up!(irq);
+
+        float!(cia);
+        float!(vic);
+        assert!(high!(irq), "line should be pulled up with no interrupts");
+        assert!(high!(cpu));
+
+        clear!(cia);
+        assert!(low!(irq), "line should be low while the CIA interrupts");
+        assert!(low!(cpu));
+
+        clear!(vic);
+        float!(cia);
+        assert!(
+            low!(irq),
+            "line should stay low while the VIC still interrupts"
+        );
+        assert!(low!(cpu));
+
+        float!(vic);
+        assert!(high!(irq), "line should return high when both release it");
+        assert!(high!(cpu));
+    }
+
+    #[test]
+    fn contention_default_max_wins() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        assert_eq!(t.borrow().contention_policy(), Contention::MaxWins);
+
+        clear!(p1);
+        set!(p2);
+        assert!(high!(t));
+        assert!(t.borrow().contention_events().is_empty());
+    }
+
+    #[test]
+    fn contention_warn() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        t.borrow_mut().set_contention_policy(Contention::Warn);
+
+        clear!(p1);
+        assert!(t.borrow().contention_events().is_empty());
+        set!(p2);
+        assert!(high!(t), "level should still be resolved while warning");
+
+        let events = t.borrow_mut().take_contention_events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].drivers.len(), 2);
+        assert!(events[0].drivers.contains(&(1, "A", 0.0)));
+        assert!(events[0].drivers.contains(&(2, "B", 1.0)));
+        assert!(t.borrow().contention_events().is_empty());
+    }
+
+    #[test]
+    fn contention_warn_same_side() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        t.borrow_mut().set_contention_policy(Contention::Warn);
+
+        set_level!(p1, Some(0.75));
+        set!(p2);
+        float!(p2);
+        clear!(p1);
+        clear!(p2);
+        assert!(t.borrow().contention_events().is_empty());
+    }
+
+    #[test]
+    fn contention_warn_direct_set() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        clear!(p1);
+        set!(p2);
+        let t = trace!(p1, p2);
+        t.borrow_mut().set_contention_policy(Contention::Warn);
+
+        set!(t);
+        assert_eq!(t.borrow().contention_events().len(), 1);
+    }
+
+    #[test]
+    fn contention_log_bounded() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        t.borrow_mut().set_contention_policy(Contention::Warn);
+        t.borrow_mut().set_contention_log_capacity(2);
+
+        clear!(p1);
+        for level in [0.6, 0.7, 0.8, 0.9] {
+            set_level!(p2, Some(level));
+            assert_eq!(
+                level!(t),
+                Some(level),
+                "Eviction should not affect the level"
+            );
+        }
+
+        let trace = t.borrow();
+        let events = trace.contention_events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events.evicted(), 2);
+        assert!(events.first().unwrap().drivers.contains(&(2, "B", 0.8)));
+        assert!(events.last().unwrap().drivers.contains(&(2, "B", 0.9)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Bus contention")]
+    fn contention_panic() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        t.borrow_mut().set_contention_policy(Contention::Panic);
+
+        clear!(p1);
+        set!(p2);
+    }
+
+    #[test]
+    fn contention_ignored_wired_and() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        t.borrow_mut().set_resolution(Resolution::WiredAnd);
+        t.borrow_mut().set_contention_policy(Contention::Panic);
+
+        clear!(p1);
+        set!(p2);
+        assert!(low!(t));
+    }
+
+    #[test]
+    fn contention_query() {
+        let p1 = pin!(1, "A", Output);
+        let p2 = pin!(2, "B", Output);
+        let t = trace!(p1, p2);
+        assert!(!t.borrow().contention(), "no drivers is not contention");
+
+        clear!(p1);
+        assert!(!t.borrow().contention(), "one driver is not contention");
+
+        set!(p2);
+        assert!(t.borrow().contention(), "0.0 and 1.0 are in contention");
+
+        clear!(p2);
+        assert!(!t.borrow().contention(), "two drivers at 0.0 agree");
+
+        set_level!(p1, Some(0.75));
+        set!(p2);
+        assert!(t.borrow().contention(), "0.75 and 1.0 are in contention");
+        assert!(
+            t.borrow().drivers_disagree(),
+            "drivers_disagree is another name for contention"
+        );
+
+        float!(p1);
+        assert!(
+            !t.borrow().contention(),
+            "floating pins don't drive the trace"
+        );
+    }
 }
@@ -0,0 +1,280 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Answers the question "which device responds to this access?" for the C64's memory map.
+//!
+//! Bank switching in the C64 is done entirely by the 82S100 PLA, which turns the state of
+//! the 6510's I/O port, the cartridge port, and the address bus into chip select signals.
+//! `resolve` and `Resolver` run an access through an emulated `Ic82S100` and translate the
+//! output it selects into a `Selected` value, so that code which needs to know the memory
+//! map (a memory map device, a debugger, tests) doesn't need to have its own copy of the
+//! PLA's logic.
+
+use crate::{
+    components::trace::TraceRef,
+    devices::chips::{ic82s100::constants::*, Ic82S100},
+};
+
+/// The five signals that select the C64's banking mode: LORAM, HIRAM, and CHAREN from the
+/// 6510's I/O port, and GAME and EXROM from the cartridge port. All of them are
+/// active-low in the sense that `true` (high) is the state of a C64 with no cartridge and
+/// the default I/O port value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BankMode {
+    /// The LORAM line (bit 0 of the 6510's I/O port).
+    pub loram: bool,
+
+    /// The HIRAM line (bit 1 of the 6510's I/O port).
+    pub hiram: bool,
+
+    /// The CHAREN line (bit 2 of the 6510's I/O port).
+    pub charen: bool,
+
+    /// The GAME line from the cartridge port.
+    pub game: bool,
+
+    /// The EXROM line from the cartridge port.
+    pub exrom: bool,
+}
+
+impl BankMode {
+    /// Creates a banking mode from its number in the usual mode table (as found at
+    /// https://www.c64-wiki.com/wiki/Bank_Switching#Mode_Table, for instance). Bits 0-4
+    /// of the mode number are LORAM, HIRAM, CHAREN, GAME, and EXROM, so that mode 31 is
+    /// the default mode with no cartridge.
+    pub fn from_mode(mode: u8) -> BankMode {
+        BankMode {
+            loram: mode & 0x01 != 0,
+            hiram: mode & 0x02 != 0,
+            charen: mode & 0x04 != 0,
+            game: mode & 0x08 != 0,
+            exrom: mode & 0x10 != 0,
+        }
+    }
+}
+
+impl Default for BankMode {
+    fn default() -> Self {
+        BankMode::from_mode(31)
+    }
+}
+
+/// The device that the PLA selects for a memory access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selected {
+    /// The 64k of system RAM.
+    Ram,
+
+    /// The BASIC ROM.
+    Basic,
+
+    /// The KERNAL ROM.
+    Kernal,
+
+    /// The character ROM.
+    CharRom,
+
+    /// The I/O block. Which I/O device (VIC, SID, color RAM, one of the CIAs, or one of
+    /// the cartridge I/O areas) is selected from A8-A11 by the 74139, not by the PLA.
+    Io,
+
+    /// The cartridge's low ROM, selected through the ROML line.
+    RomL,
+
+    /// The cartridge's high ROM, selected through the ROMH line.
+    RomH,
+
+    /// Nothing. This only happens with an Ultimax cartridge, which leaves large parts of
+    /// the CPU's address space unmapped, so that reads return whatever is floating on the
+    /// data bus.
+    Unmapped,
+}
+
+/// An 82S100 wired up so that accesses can be run through it one after another.
+///
+/// `resolve` builds a new PLA every time it's called, which is fine for occasional
+/// questions but too slow to do for every memory access. A `Resolver` builds its PLA once
+/// and keeps it, so it's what a memory map should use.
+pub struct Resolver {
+    /// Traces connected to each of the PLA's pins, indexed by pin number. The PLA itself is
+    /// kept alive by its pins.
+    traces: Vec<TraceRef>,
+}
+
+impl Resolver {
+    /// Creates a new resolver with its own PLA.
+    pub fn new() -> Resolver {
+        let pla = Ic82S100::new();
+        let traces = pla
+            .borrow()
+            .pins()
+            .iter()
+            .map(|pin| trace!(clone_ref!(pin)))
+            .collect();
+        Resolver { traces }
+    }
+
+    /// Determines which device answers a memory access in the given banking mode. See the
+    /// free function `resolve` for the meanings of the arguments.
+    pub fn resolve(&self, mode: BankMode, addr: u16, cpu_active: bool, read: bool) -> Selected {
+        let traces = &self.traces;
+        let bit = |n: u16| addr & (1 << n) != 0;
+        let inputs = [
+            (OE, false),
+            (CAS, false),
+            (LORAM, mode.loram),
+            (HIRAM, mode.hiram),
+            (CHAREN, mode.charen),
+            (GAME, mode.game),
+            (EXROM, mode.exrom),
+            (A15, bit(15)),
+            (A14, bit(14)),
+            (A13, bit(13)),
+            (A12, bit(12)),
+            // VA14 comes from CIA 2 inverted, so it's high for VIC banks 0 and 2
+            (VA14, !bit(14)),
+            (VA13, bit(13)),
+            (VA12, bit(12)),
+            (BA, true),
+            // AEC is inverted on its way to the PLA, so it's low for CPU accesses
+            (AEC, !cpu_active),
+            (R_W, read || !cpu_active),
+        ];
+        for (pin, level) in inputs {
+            if level {
+                set!(traces[pin]);
+            } else {
+                clear!(traces[pin]);
+            }
+        }
+
+        // Outputs are active-low. CASRAM is checked last because it's deselected whenever
+        // any of the others is selected anyway.
+        let outputs = [
+            (BASIC, Selected::Basic),
+            (KERNAL, Selected::Kernal),
+            (CHAROM, Selected::CharRom),
+            (IO, Selected::Io),
+            (ROML, Selected::RomL),
+            (ROMH, Selected::RomH),
+            (CASRAM, Selected::Ram),
+        ];
+        outputs
+            .iter()
+            .find(|(pin, _)| low!(traces[*pin]))
+            .map_or(Selected::Unmapped, |(_, selected)| *selected)
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Resolver::new()
+    }
+}
+
+/// Determines which device answers a memory access in the given banking mode.
+///
+/// If `cpu_active` is `true`, this is a 6510 access to `addr`, and `read` tells whether
+/// it's a read or a write. (Writes to ROM addresses go to the RAM underneath.) If it's
+/// `false`, this is a VIC access, and `addr` is the full 16-bit address the VIC is reading,
+/// including the two bits of bank selection that come from CIA 2. The VIC only reads, so
+/// `read` is ignored in that case.
+///
+/// This builds a new PLA for every call, which keeps it simple and free of shared state
+/// but makes it too slow to call for every emulated memory access. Use a `Resolver` for
+/// that.
+pub fn resolve(mode: BankMode, addr: u16, cpu_active: bool, read: bool) -> Selected {
+    Resolver::new().resolve(mode, addr, cpu_active, read)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use Selected::*;
+
+    const DEFAULT: u8 = 31;
+
+    fn cpu_read(mode: u8, addr: u16) -> Selected {
+        resolve(BankMode::from_mode(mode), addr, true, true)
+    }
+
+    fn cpu_write(mode: u8, addr: u16) -> Selected {
+        resolve(BankMode::from_mode(mode), addr, true, false)
+    }
+
+    fn vic_read(mode: u8, addr: u16) -> Selected {
+        resolve(BankMode::from_mode(mode), addr, false, true)
+    }
+
+    /// Checks the CPU reads at the start of each of the 4k blocks that differ between
+    /// modes: $1000, $8000, $A000, $C000, $D000, and $E000.
+    fn assert_map(mode: u8, expected: [Selected; 6]) {
+        let addrs = [0x1000, 0x8000, 0xa000, 0xc000, 0xd000, 0xe000];
+        for (addr, expected) in addrs.iter().zip(expected.iter()) {
+            assert_eq!(
+                cpu_read(mode, *addr),
+                *expected,
+                "Incorrect device in mode {} at ${:04X}",
+                mode,
+                addr
+            );
+        }
+    }
+
+    #[test]
+    fn default_mode() {
+        assert_eq!(BankMode::default(), BankMode::from_mode(DEFAULT));
+        assert_map(DEFAULT, [Ram, Ram, Basic, Ram, Io, Kernal]);
+        assert_eq!(cpu_read(DEFAULT, 0x0000), Ram);
+        assert_eq!(cpu_read(DEFAULT, 0xdfff), Io);
+        assert_eq!(cpu_read(DEFAULT, 0xffff), Kernal);
+    }
+
+    #[test]
+    fn no_cartridge_modes() {
+        assert_map(30, [Ram, Ram, Ram, Ram, Io, Kernal]);
+        assert_map(29, [Ram, Ram, Ram, Ram, Io, Ram]);
+        assert_map(28, [Ram, Ram, Ram, Ram, Ram, Ram]);
+        assert_map(27, [Ram, Ram, Basic, Ram, CharRom, Kernal]);
+        assert_map(26, [Ram, Ram, Ram, Ram, CharRom, Kernal]);
+        assert_map(25, [Ram, Ram, Ram, Ram, CharRom, Ram]);
+        assert_map(24, [Ram, Ram, Ram, Ram, Ram, Ram]);
+    }
+
+    #[test]
+    fn cartridge_modes() {
+        // 8k cartridge
+        assert_map(15, [Ram, RomL, Basic, Ram, Io, Kernal]);
+        // 16k cartridge
+        assert_map(7, [Ram, RomL, RomH, Ram, Io, Kernal]);
+        assert_map(6, [Ram, Ram, RomH, Ram, Io, Kernal]);
+        // Ultimax
+        assert_map(23, [Unmapped, RomL, Unmapped, Unmapped, Io, RomH]);
+        assert_eq!(cpu_read(23, 0x0fff), Ram);
+    }
+
+    #[test]
+    fn writes_go_to_ram() {
+        assert_eq!(cpu_write(DEFAULT, 0xa000), Ram);
+        assert_eq!(cpu_write(DEFAULT, 0xe000), Ram);
+        assert_eq!(cpu_write(DEFAULT, 0xd020), Io);
+        assert_eq!(cpu_write(27, 0xd000), Ram);
+    }
+
+    #[test]
+    fn vic_reads() {
+        // Character ROM appears at $1000-$1FFF in VIC banks 0 and 2 only
+        assert_eq!(vic_read(DEFAULT, 0x1000), CharRom);
+        assert_eq!(vic_read(DEFAULT, 0x9800), CharRom);
+        assert_eq!(vic_read(DEFAULT, 0x5000), Ram);
+        assert_eq!(vic_read(DEFAULT, 0xd000), Ram, "the VIC never sees I/O");
+        assert_eq!(vic_read(DEFAULT, 0xa000), Ram, "the VIC never sees BASIC");
+
+        // Ultimax cartridges replace the top 4k of each VIC bank with ROMH
+        assert_eq!(vic_read(23, 0x3000), RomH);
+        assert_eq!(vic_read(23, 0x0000), Ram);
+    }
+}
@@ -0,0 +1,143 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::loaders::crt::{self, Bank, Configuration};
+
+use super::{Cartridge, Cartridge16k, Cartridge8k, CartridgeError, UltimaxCartridge, WINDOW_SIZE};
+
+/// Loads a cartridge from the contents of a .CRT file.
+///
+/// The file is parsed with `loaders::crt::parse`, so only hardware type 0 (a normal
+/// cartridge with no banking) is supported. A ROM smaller than its window is mirrored
+/// through it, the same way that a real ROM with fewer address lines would be.
+pub fn load_crt(bytes: &[u8]) -> Result<Box<dyn Cartridge>, CartridgeError> {
+    let image = crt::parse(bytes)?;
+    let configuration = image.configuration();
+    let roml = image.roml.map(mirror);
+    let romh = image.romh.map(mirror);
+
+    match configuration {
+        Some(Configuration::Normal8k) => Ok(Box::new(Cartridge8k::new(&required(roml)?)?)),
+        Some(Configuration::Normal16k) => Ok(Box::new(Cartridge16k::from_halves(
+            &required(roml)?,
+            &required(romh)?,
+        )?)),
+        Some(Configuration::Ultimax) => Ok(Box::new(UltimaxCartridge::new(
+            roml.as_deref(),
+            &required(romh)?,
+        )?)),
+        None => Err(CartridgeError::Lines(image.exrom, image.game)),
+    }
+}
+
+/// Repeats a bank's ROM image to fill its 8k window.
+fn mirror(bank: Bank) -> Vec<u8> {
+    bank.data
+        .iter()
+        .copied()
+        .cycle()
+        .take(WINDOW_SIZE)
+        .collect()
+}
+
+/// Returns the image of a window that the cartridge's configuration needs.
+fn required(window: Option<Vec<u8>>) -> Result<Vec<u8>, CartridgeError> {
+    window.ok_or(CartridgeError::MissingRom)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::test_utils::make_crt as crt;
+
+    use super::*;
+
+    /// A ROM image whose bytes are their own offsets, XORed with `seed`.
+    fn rom(size: usize, seed: u8) -> Vec<u8> {
+        (0..size).map(|i| (i as u8) ^ seed).collect()
+    }
+
+    #[test]
+    fn load_8k() {
+        let mut cart = load_crt(&crt(0, 0, 1, &[(0x8000, rom(0x2000, 0))])).unwrap();
+        assert!(!cart.exrom(), "EXROM should be low");
+        assert!(cart.game(), "GAME should be high");
+        for addr in [0x8000, 0x8001, 0x80ff, 0x8100, 0x9abc, 0x9fff] {
+            assert_eq!(cart.roml_read(addr), addr as u8, "ROML ${:04X}", addr);
+        }
+    }
+
+    #[test]
+    fn load_16k_single_chip() {
+        let mut data = rom(0x2000, 0x00);
+        data.extend(rom(0x2000, 0xff));
+        let mut cart = load_crt(&crt(0, 0, 0, &[(0x8000, data)])).unwrap();
+        assert!(!cart.exrom());
+        assert!(!cart.game());
+        assert_eq!(cart.roml_read(0x8042), 0x42);
+        assert_eq!(cart.romh_read(0xa042), 0xbd);
+    }
+
+    #[test]
+    fn load_16k_two_chips() {
+        let chips = [(0xa000, rom(0x2000, 0xff)), (0x8000, rom(0x2000, 0x00))];
+        let mut cart = load_crt(&crt(0, 0, 0, &chips)).unwrap();
+        assert_eq!(cart.roml_read(0x8042), 0x42);
+        assert_eq!(cart.romh_read(0xa042), 0xbd);
+    }
+
+    #[test]
+    fn load_ultimax_mirrored() {
+        let mut cart = load_crt(&crt(0, 1, 0, &[(0xf000, rom(0x1000, 0x80))])).unwrap();
+        assert!(cart.exrom());
+        assert!(!cart.game());
+        assert_eq!(cart.romh_read(0xfffc), 0x7c);
+        assert_eq!(
+            cart.romh_read(0xeffc),
+            0x7c,
+            "4k ROMH should be mirrored at $E000"
+        );
+        assert_eq!(cart.roml_read(0x8000), 0x00);
+    }
+
+    #[test]
+    fn unsupported_hardware() {
+        let bytes = crt(1, 0, 0, &[(0x8000, rom(0x2000, 0))]);
+        let err = load_crt(&bytes).err().unwrap();
+        assert_eq!(err, CartridgeError::Hardware(1));
+        assert_eq!(
+            err.to_string(),
+            "hardware type 1 is not supported (only type 0, a normal cartridge, is)"
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        let mut bytes = crt(0, 0, 1, &[(0x8000, rom(0x2000, 0))]);
+        assert_eq!(
+            load_crt(&bytes[..0x2000]).err(),
+            Some(CartridgeError::Truncated(0x40))
+        );
+        assert_eq!(
+            load_crt(&bytes[..0x20]).err(),
+            Some(CartridgeError::Truncated(0))
+        );
+        assert_eq!(
+            load_crt(&crt(0, 1, 1, &[])).err(),
+            Some(CartridgeError::Lines(true, true))
+        );
+        assert_eq!(
+            load_crt(&crt(0, 0, 1, &[])).err(),
+            Some(CartridgeError::MissingRom)
+        );
+        assert_eq!(
+            load_crt(&crt(0, 0, 1, &[(0xa000, rom(0x2000, 0))])).err(),
+            Some(CartridgeError::LoadAddress(0x40))
+        );
+        bytes[0x40] = b'X';
+        assert_eq!(load_crt(&bytes).err(), Some(CartridgeError::Chip(0x40)));
+        bytes[0] = b'X';
+        assert_eq!(load_crt(&bytes).err(), Some(CartridgeError::Signature));
+    }
+}
@@ -0,0 +1,326 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Cartridges that plug into the C64's expansion port.
+
+mod crt;
+
+pub use self::crt::load_crt;
+
+use std::fmt::{self, Display, Formatter};
+
+/// The size of each of the ROML and ROMH windows.
+const WINDOW_SIZE: usize = 0x2000;
+
+/// An error in building a cartridge from an image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum CartridgeError {
+    /// A ROM image was the wrong size. The first value is the size that was expected and
+    /// the second is the size of the image.
+    ImageSize(usize, usize),
+
+    /// The image doesn't start with the .CRT signature.
+    Signature,
+
+    /// The image ended in the middle of the header or of the CHIP packet starting at this
+    /// offset.
+    Truncated(usize),
+
+    /// The CHIP packet at this offset is malformed (its signature or length is wrong).
+    Chip(usize),
+
+    /// The CHIP packet at this offset loads to an address that doesn't fit the cartridge's
+    /// configuration.
+    LoadAddress(usize),
+
+    /// The hardware type in the .CRT header isn't one that's emulated.
+    Hardware(u16),
+
+    /// The EXROM and GAME levels in the .CRT header (in that order) don't describe a
+    /// cartridge configuration. Both lines high means that no cartridge is plugged in.
+    Lines(bool, bool),
+
+    /// The image has no CHIP packet for a ROM that its configuration needs.
+    MissingRom,
+}
+
+impl Display for CartridgeError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::ImageSize(expected, actual) => write!(
+                f,
+                "ROM image is {} bytes, but it must be {} bytes",
+                actual, expected
+            ),
+            CartridgeError::Signature => write!(f, "image is not a .CRT file"),
+            CartridgeError::Truncated(offset) => {
+                write!(f, "image ends in the middle of data at offset {}", offset)
+            }
+            CartridgeError::Chip(offset) => {
+                write!(f, "CHIP packet at offset {} is malformed", offset)
+            }
+            CartridgeError::LoadAddress(offset) => write!(
+                f,
+                "CHIP packet at offset {} has a load address that doesn't fit the cartridge",
+                offset
+            ),
+            CartridgeError::Hardware(kind) => write!(
+                f,
+                "hardware type {} is not supported (only type 0, a normal cartridge, is)",
+                kind
+            ),
+            CartridgeError::Lines(exrom, game) => write!(
+                f,
+                "EXROM {} and GAME {} is not a cartridge configuration",
+                if *exrom { "high" } else { "low" },
+                if *game { "high" } else { "low" }
+            ),
+            CartridgeError::MissingRom => write!(f, "image is missing a required ROM"),
+        }
+    }
+}
+
+impl std::error::Error for CartridgeError {}
+
+/// A cartridge plugged into the expansion port.
+///
+/// A cartridge tells the PLA how to map it into memory with the levels of its EXROM and
+/// GAME lines (both active low). The PLA then selects the cartridge's ROM with its ROML
+/// output (normally $8000-$9FFF) and ROMH output ($A000-$BFFF or $E000-$FFFF, depending on
+/// the configuration), and the 74139 selects its I/O with IO1 ($DE00-$DEFF) and IO2
+/// ($DF00-$DFFF). Addresses passed to these methods are full CPU addresses; a cartridge
+/// uses as many of the low bits as it needs.
+///
+/// Reads take `&mut self` because bank-switching cartridges can change state on any access.
+/// A cartridge that doesn't respond to an I/O area returns `None` from reads of it, leaving
+/// the data bus undriven.
+pub trait Cartridge {
+    /// Returns the level of the EXROM line.
+    fn exrom(&self) -> bool;
+
+    /// Returns the level of the GAME line.
+    fn game(&self) -> bool;
+
+    /// Reads from the cartridge while ROML is selected.
+    fn roml_read(&mut self, addr: u16) -> u8;
+
+    /// Reads from the cartridge while ROMH is selected.
+    fn romh_read(&mut self, addr: u16) -> u8;
+
+    /// Reads from the cartridge while IO1 is selected.
+    fn io1_read(&mut self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Writes to the cartridge while IO1 is selected.
+    fn io1_write(&mut self, _addr: u16, _value: u8) {}
+
+    /// Reads from the cartridge while IO2 is selected.
+    fn io2_read(&mut self, _addr: u16) -> Option<u8> {
+        None
+    }
+
+    /// Writes to the cartridge while IO2 is selected.
+    fn io2_write(&mut self, _addr: u16, _value: u8) {}
+}
+
+/// Copies a ROM image into a new 8k window, checking that it's exactly 8k.
+fn window(image: &[u8]) -> Result<Box<[u8; WINDOW_SIZE]>, CartridgeError> {
+    if image.len() != WINDOW_SIZE {
+        return Err(CartridgeError::ImageSize(WINDOW_SIZE, image.len()));
+    }
+    let mut rom = Box::new([0; WINDOW_SIZE]);
+    rom.copy_from_slice(image);
+    Ok(rom)
+}
+
+/// Reads a byte from an 8k window.
+fn window_read(rom: &[u8; WINDOW_SIZE], addr: u16) -> u8 {
+    rom[addr as usize & (WINDOW_SIZE - 1)]
+}
+
+/// A cartridge with 8k of ROM at $8000-$9FFF.
+///
+/// EXROM is low and GAME is high, so the ROM replaces RAM at $8000-$9FFF while BASIC and
+/// the KERNAL stay banked in. ROMH is never selected in this configuration.
+pub struct Cartridge8k {
+    /// The ROM read through ROML.
+    roml: Box<[u8; WINDOW_SIZE]>,
+}
+
+impl Cartridge8k {
+    /// Creates a new 8k cartridge from an 8192-byte ROM image.
+    pub fn new(roml: &[u8]) -> Result<Cartridge8k, CartridgeError> {
+        Ok(Cartridge8k {
+            roml: window(roml)?,
+        })
+    }
+}
+
+impl Cartridge for Cartridge8k {
+    fn exrom(&self) -> bool {
+        false
+    }
+
+    fn game(&self) -> bool {
+        true
+    }
+
+    fn roml_read(&mut self, addr: u16) -> u8 {
+        window_read(&self.roml, addr)
+    }
+
+    fn romh_read(&mut self, _addr: u16) -> u8 {
+        0
+    }
+}
+
+/// A cartridge with 16k of ROM at $8000-$BFFF.
+///
+/// EXROM and GAME are both low. The first 8k of the ROM is read through ROML at
+/// $8000-$9FFF, and the second through ROMH at $A000-$BFFF in place of BASIC.
+pub struct Cartridge16k {
+    /// The ROM read through ROML.
+    roml: Box<[u8; WINDOW_SIZE]>,
+
+    /// The ROM read through ROMH.
+    romh: Box<[u8; WINDOW_SIZE]>,
+}
+
+impl Cartridge16k {
+    /// Creates a new 16k cartridge from a 16384-byte ROM image.
+    pub fn new(rom: &[u8]) -> Result<Cartridge16k, CartridgeError> {
+        if rom.len() != WINDOW_SIZE * 2 {
+            return Err(CartridgeError::ImageSize(WINDOW_SIZE * 2, rom.len()));
+        }
+        Cartridge16k::from_halves(&rom[..WINDOW_SIZE], &rom[WINDOW_SIZE..])
+    }
+
+    /// Creates a new 16k cartridge from separate 8192-byte ROML and ROMH images.
+    pub fn from_halves(roml: &[u8], romh: &[u8]) -> Result<Cartridge16k, CartridgeError> {
+        Ok(Cartridge16k {
+            roml: window(roml)?,
+            romh: window(romh)?,
+        })
+    }
+}
+
+impl Cartridge for Cartridge16k {
+    fn exrom(&self) -> bool {
+        false
+    }
+
+    fn game(&self) -> bool {
+        false
+    }
+
+    fn roml_read(&mut self, addr: u16) -> u8 {
+        window_read(&self.roml, addr)
+    }
+
+    fn romh_read(&mut self, addr: u16) -> u8 {
+        window_read(&self.romh, addr)
+    }
+}
+
+/// An Ultimax cartridge, which takes over the top of memory in place of the KERNAL.
+///
+/// EXROM is high and GAME is low. This puts the C64 into the mode of the MAX Machine that
+/// it was designed alongside: ROMH is read at $E000-$FFFF (so the cartridge supplies the
+/// reset and interrupt vectors), ROML at $8000-$9FFF, and RAM above $0FFF disappears except
+/// for the I/O area. Many Ultimax cartridges only have ROMH, so ROML is optional and reads
+/// as 0 when it's missing.
+pub struct UltimaxCartridge {
+    /// The ROM read through ROML, if there is one.
+    roml: Option<Box<[u8; WINDOW_SIZE]>>,
+
+    /// The ROM read through ROMH.
+    romh: Box<[u8; WINDOW_SIZE]>,
+}
+
+impl UltimaxCartridge {
+    /// Creates a new Ultimax cartridge from an 8192-byte ROMH image and an optional
+    /// 8192-byte ROML image.
+    pub fn new(roml: Option<&[u8]>, romh: &[u8]) -> Result<UltimaxCartridge, CartridgeError> {
+        Ok(UltimaxCartridge {
+            roml: roml.map(window).transpose()?,
+            romh: window(romh)?,
+        })
+    }
+}
+
+impl Cartridge for UltimaxCartridge {
+    fn exrom(&self) -> bool {
+        true
+    }
+
+    fn game(&self) -> bool {
+        false
+    }
+
+    fn roml_read(&mut self, addr: u16) -> u8 {
+        match &self.roml {
+            Some(rom) => window_read(rom, addr),
+            None => 0,
+        }
+    }
+
+    fn romh_read(&mut self, addr: u16) -> u8 {
+        window_read(&self.romh, addr)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn image(seed: u8) -> Vec<u8> {
+        (0..WINDOW_SIZE).map(|i| (i as u8) ^ seed).collect()
+    }
+
+    #[test]
+    fn cartridge_8k() {
+        let mut cart = Cartridge8k::new(&image(0x00)).unwrap();
+        assert!(!cart.exrom());
+        assert!(cart.game());
+        assert_eq!(cart.roml_read(0x8000), 0x00);
+        assert_eq!(cart.roml_read(0x9fff), 0xff);
+        assert_eq!(cart.roml_read(0x8123), 0x23);
+        assert_eq!(cart.io1_read(0xde00), None);
+    }
+
+    #[test]
+    fn cartridge_16k() {
+        let mut rom = image(0x00);
+        rom.extend(image(0xff));
+        let mut cart = Cartridge16k::new(&rom).unwrap();
+        assert!(!cart.exrom());
+        assert!(!cart.game());
+        assert_eq!(cart.roml_read(0x8001), 0x01);
+        assert_eq!(cart.romh_read(0xa001), 0xfe);
+    }
+
+    #[test]
+    fn ultimax() {
+        let mut cart = UltimaxCartridge::new(None, &image(0x80)).unwrap();
+        assert!(cart.exrom());
+        assert!(!cart.game());
+        assert_eq!(cart.romh_read(0xfffc), 0x7c);
+        assert_eq!(cart.roml_read(0x8000), 0x00);
+    }
+
+    #[test]
+    fn wrong_size() {
+        assert_eq!(
+            Cartridge8k::new(&[0; 4096]).err(),
+            Some(CartridgeError::ImageSize(8192, 4096))
+        );
+        assert_eq!(
+            Cartridge16k::new(&image(0)).err(),
+            Some(CartridgeError::ImageSize(16384, 8192))
+        );
+    }
+}
@@ -47,13 +47,13 @@ pub mod constants {
 
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
-            Pin, PinRef,
+            Pin,
         },
+        port::Port,
     },
-    utils::{mode_to_pins, pins_to_value, value_to_pins},
     vectors::RefVec,
 };
 
@@ -141,16 +141,25 @@ const PA_DATA: [usize; 4] = [D0, D1, D2, D3];
 ///
 /// In the Commodore 64, U6 is a 2114. As explained above, it was used strictly as RAM for
 /// storing graphics colors.
+///
+/// Being static RAM, the 2114 latches nothing, so `registers` returns a snapshot of what
+/// the chip is doing at the moment: a status byte, the low and high bytes of the address on
+/// the address pins, and the 4-bit value stored at that address. Bit 0 of the status byte
+/// is set if the chip is selected (CS is low), and bit 1 is set if it's being written to
+/// (WE is low).
 pub struct Ic2114 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 2114, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
 
-    /// Separate references to the A0-A9 pins in the `pins` vector.
-    addr_pins: RefVec<Pin>,
+    /// The A0-A9 pins from the `pins` vector, as a port.
+    addr: Port,
 
-    /// Separate references to the D0-D3 pins in the `pins` vector.
-    data_pins: RefVec<Pin>,
+    /// The D0-D3 pins from the `pins` vector, as a port.
+    data: Port,
 
     /// The place where the data is actually stored. The 2114 is 4-bit memory, and there is
     /// not a u4 type in Rust, so we use a u8 along with an address resolution function.
@@ -195,22 +204,15 @@ impl Ic2114 {
         let gnd = pin!(GND, "GND", Unconnected);
 
         let pins = pins![a0, a1, a2, a3, a4, a5, a6, a7, a8, a9, d0, d1, d2, d3, cs, we, vcc, gnd];
-        let addr_pins = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|pa| clone_ref!(pins[pa]))
-                .collect::<Vec<PinRef>>(),
-        );
-        let data_pins = RefVec::with_vec(
-            IntoIterator::into_iter(PA_DATA)
-                .map(|pa| clone_ref!(pins[pa]))
-                .collect::<Vec<PinRef>>(),
-        );
+        let addr = Port::from_pins(&pins, &PA_ADDRESS);
+        let data = Port::from_pins(&pins, &PA_DATA);
         let memory = [0; 512];
 
         let device: DeviceRef = new_ref!(Ic2114 {
+            id: next_id(),
             pins,
-            addr_pins,
-            data_pins,
+            addr,
+            data,
             memory
         });
         attach_to!(device, a0, a1, a2, a3, a4, a5, a6, a7, a8, a9, d0, d1, d2, d3, cs, we);
@@ -230,6 +232,19 @@ impl Ic2114 {
         let current = self.memory[index] & !(0x0f << shift);
         self.memory[index] = current | (value << shift);
     }
+
+    /// Returns the address on the address pins while the pin numbered `number` is changing
+    /// to the given level. The levels of the other address pins are read as usual.
+    fn address_with(&self, number: usize, level: bool) -> u16 {
+        PA_ADDRESS.iter().enumerate().fold(0, |addr, (bit, &n)| {
+            let high = if n == number {
+                level
+            } else {
+                high!(self.pins[n])
+            };
+            addr | (high as u16) << bit
+        })
+    }
 }
 
 /// Resolves an address to the actual indices within the memory array where that address
@@ -240,58 +255,70 @@ fn resolve(addr: u16) -> (usize, usize) {
 }
 
 impl Device for Ic2114 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
 
     fn registers(&self) -> Vec<u8> {
-        vec![]
+        let addr = self.addr.value() as u16;
+        let status = !high!(self.pins[CS]) as u8 | (!high!(self.pins[WE]) as u8) << 1;
+        vec![status, addr as u8, (addr >> 8) as u8, self.read(addr)]
     }
 
     fn update(&mut self, event: &LevelChange) {
         macro_rules! read {
             () => {
-                mode_to_pins(Output, &self.data_pins);
-                let addr = pins_to_value(&self.addr_pins) as u16;
-                let value = self.read(addr) as usize;
-                value_to_pins(value, &self.data_pins);
+                read!(self.addr.value() as u16)
+            };
+            ($addr:expr) => {
+                self.data.set_mode(Output);
+                let value = self.read($addr) as usize;
+                self.data.write(value);
             };
         }
         macro_rules! write {
             () => {
-                mode_to_pins(Input, &self.data_pins);
-                let addr = pins_to_value(&self.addr_pins) as u16;
-                let value = pins_to_value(&self.data_pins) as u8;
-                self.write(addr, value);
+                write!(self.addr.value() as u16)
+            };
+            ($addr:expr) => {
+                self.data.set_mode(Input);
+                let value = self.data.value() as u8;
+                self.write($addr, value);
             };
         }
 
         match event {
-            LevelChange(pin) if number!(pin) == CS => {
+            LevelChange(pin, _) if number!(pin) == CS => {
                 if high!(pin) {
-                    mode_to_pins(Input, &self.data_pins);
+                    self.data.set_mode(Input);
                 } else if high!(self.pins[WE]) {
                     read!();
                 } else {
                     write!();
                 }
             }
-            LevelChange(pin) if number!(pin) == WE => {
-                if !high!(self.pins[CS]) {
-                    if high!(pin) {
-                        read!();
-                    } else {
-                        write!();
-                    }
+            LevelChange(pin, _) if number!(pin) == WE && !high!(self.pins[CS]) => {
+                if high!(pin) {
+                    read!();
+                } else {
+                    write!();
                 }
             }
-            LevelChange(pin) if PA_ADDRESS.contains(&number!(pin)) => {
-                if !high!(self.pins[CS]) {
-                    if high!(self.pins[WE]) {
-                        read!();
-                    } else {
-                        write!();
-                    }
+            LevelChange(pin, _) if PA_ADDRESS.contains(&number!(pin)) && !high!(self.pins[CS]) => {
+                // Every address line change is handled on its own, so a multi-line address
+                // change while WE is low writes the data pins to each address along the way.
+                // This is what the hardware does, and it's deliberate. The changing pin can't
+                // be read through the address port while it's being updated, so its new level
+                // comes from the event instead.
+                let addr = self.address_with(number!(pin), high!(pin));
+                if high!(self.pins[WE]) {
+                    read!(addr);
+                } else {
+                    write!(addr);
                 }
             }
             _ => {}
@@ -302,7 +329,7 @@ impl Device for Ic2114 {
 #[cfg(test)]
 mod test {
     use crate::{
-        components::trace::{Trace, TraceRef},
+        components::trace::Trace,
         test_utils::{make_traces, traces_to_value, value_to_traces},
     };
 
@@ -315,16 +342,8 @@ mod test {
         set!(tr[CS]);
         set!(tr[WE]);
 
-        let addr_tr = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
-        let data_tr = RefVec::with_vec(
-            IntoIterator::into_iter(PA_DATA)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
+        let addr_tr = tr.map_indices(&PA_ADDRESS);
+        let data_tr = tr.map_indices(&PA_DATA);
 
         (device, tr, addr_tr, data_tr)
     }
@@ -357,4 +376,131 @@ mod test {
             );
         }
     }
+
+    // With CS and WE both low, each address line change is a new write, so changing lines
+    // one at a time writes the held data value to every intermediate address.
+    #[test]
+    fn address_change_during_write() {
+        let (_, tr, addr_tr, data_tr) = before_each();
+
+        value_to_traces(0x000, &addr_tr);
+        value_to_traces(0x5, &data_tr);
+        clear!(tr[WE]);
+        clear!(tr[CS]);
+
+        // $000 -> $001 -> $003 -> $007 -> $00f, then back down via $00e and $00c to $008
+        for line in [A0, A1, A2, A3] {
+            set!(tr[line]);
+        }
+        for line in [A0, A1, A2] {
+            clear!(tr[line]);
+        }
+
+        set!(tr[CS]);
+        set!(tr[WE]);
+        float!(tr[D0], tr[D1], tr[D2], tr[D3]);
+
+        let written = [0x000, 0x001, 0x003, 0x007, 0x00f, 0x00e, 0x00c, 0x008];
+        for addr in 0..0x010 {
+            value_to_traces(addr, &addr_tr);
+            clear!(tr[CS]);
+            let value = traces_to_value(&data_tr);
+            set!(tr[CS]);
+
+            let expected = if written.contains(&addr) { 0x5 } else { 0 };
+            assert_eq!(
+                value, expected,
+                "Incorrect value at address ${:03x}: expected ${:1x}, actual ${:1x}",
+                addr, expected, value
+            );
+        }
+    }
+
+    // Address and data pins as given in the datasheet, as pairs of (physical pin number,
+    // bit). These are deliberately not taken from the constants so that they can catch
+    // mistakes in them or in PA_ADDRESS and PA_DATA.
+    const DATASHEET_ADDRESS: [(usize, usize); 10] = [
+        (5, 0),
+        (6, 1),
+        (7, 2),
+        (4, 3),
+        (3, 4),
+        (2, 5),
+        (1, 6),
+        (17, 7),
+        (16, 8),
+        (15, 9),
+    ];
+    const DATASHEET_DATA: [(usize, usize); 4] = [(14, 0), (13, 1), (12, 2), (11, 3)];
+
+    // Addresses are stored two to a byte, with the even address in the low nibble.
+    #[test]
+    fn cell_layout() {
+        for addr in 0..0x400 {
+            let (index, shift) = resolve(addr);
+            assert_eq!(
+                index,
+                addr as usize / 2,
+                "Wrong byte for address ${:03X}",
+                addr
+            );
+            assert_eq!(
+                shift,
+                (addr as usize % 2) * 4,
+                "Wrong nibble for address ${:03X}",
+                addr
+            );
+        }
+    }
+
+    // Writing through a single physical address pin and a single physical data pin must
+    // land in the same cell and bit as the corresponding single-bit values written through
+    // PA_ADDRESS and PA_DATA.
+    #[test]
+    fn pins_match_datasheet() {
+        let (_, tr, addr_tr, data_tr) = before_each();
+
+        for (i, (apin, abit)) in IntoIterator::into_iter(DATASHEET_ADDRESS).enumerate() {
+            let (dpin, dbit) = DATASHEET_DATA[i % 4];
+
+            value_to_traces(0, &addr_tr);
+            set!(tr[apin]);
+            value_to_traces(0, &data_tr);
+            set!(tr[dpin]);
+            clear!(tr[WE]);
+            clear!(tr[CS]);
+            set!(tr[CS]);
+            set!(tr[WE]);
+
+            value_to_traces(1 << abit, &addr_tr);
+            clear!(tr[CS]);
+            let value = traces_to_value(&data_tr);
+            set!(tr[CS]);
+
+            assert_eq!(
+                value,
+                1 << dbit,
+                "Pin {} should address bit {} and pin {} should hold data bit {}",
+                apin,
+                abit,
+                dpin,
+                dbit
+            );
+        }
+    }
+
+    #[test]
+    fn registers_snapshot() {
+        let (device, tr, addr_tr, data_tr) = before_each();
+        value_to_traces(0x2a5, &addr_tr);
+        assert_eq!(device.borrow().registers(), vec![0b00, 0xa5, 0x02, 0]);
+
+        value_to_traces(0x9, &data_tr);
+        clear!(tr[WE]);
+        clear!(tr[CS]);
+        assert_eq!(device.borrow().registers(), vec![0b11, 0xa5, 0x02, 0x9]);
+
+        set!(tr[WE]);
+        assert_eq!(device.borrow().registers(), vec![0b01, 0xa5, 0x02, 0x9]);
+    }
 }
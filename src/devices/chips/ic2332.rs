@@ -57,14 +57,17 @@ pub mod constants {
     pub const GND: usize = 12;
 }
 
+use std::{convert::TryInto, path::Path};
+
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
-            Pin, PinRef,
+            Pin,
         },
     },
+    roms::{load_rom_file, RomError},
     utils::{none_to_pins, pins_to_value, value_to_pins},
     vectors::RefVec,
 };
@@ -139,6 +142,9 @@ const PA_DATA: [usize; 8] = [D0, D1, D2, D3, D4, D5, D6, D7];
 /// In the Commodore 64, U5 is a 2332A (a variant with slightly faster data access). It's
 /// used to store information on how to display characters to the screen.
 pub struct Ic2332 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 2332, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
@@ -197,19 +203,12 @@ impl Ic2332 {
             a0, a1, a2, a3, a4, a5, a6, a7, a8, a9, a10, a11, d0, d1, d2, d3, d4, d5, d6, d7, cs1,
             cs2, vcc, gnd
         ];
-        let addr_pins = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|pa| clone_ref!(pins[pa]))
-                .collect::<Vec<PinRef>>(),
-        );
-        let data_pins = RefVec::with_vec(
-            IntoIterator::into_iter(PA_DATA)
-                .map(|pa| clone_ref!(pins[pa]))
-                .collect::<Vec<PinRef>>(),
-        );
-        let memory = bytes.clone();
+        let addr_pins = pins.map_indices(&PA_ADDRESS);
+        let data_pins = pins.map_indices(&PA_DATA);
+        let memory = *bytes;
 
         let device: DeviceRef = new_ref!(Ic2332 {
+            id: next_id(),
             pins,
             addr_pins,
             data_pins,
@@ -220,6 +219,16 @@ impl Ic2332 {
 
         device
     }
+
+    /// Creates a new 2332 emulation with the contents of a ROM image file and returns a
+    /// shared, internally mutable reference to it. The file must be exactly 4096 bytes
+    /// long; any other length is a `RomError::Size`.
+    pub fn from_file(path: &Path) -> Result<DeviceRef, RomError> {
+        let bytes: [u8; 4096] = load_rom_file(path, 4096)?
+            .try_into()
+            .map_err(|bytes: Vec<u8>| RomError::Size(4096, bytes.len()))?;
+        Ok(Ic2332::new(&bytes))
+    }
 }
 
 fn cs_for(cs: usize) -> usize {
@@ -231,6 +240,10 @@ fn cs_for(cs: usize) -> usize {
 }
 
 impl Device for Ic2332 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -241,7 +254,7 @@ impl Device for Ic2332 {
 
     fn update(&mut self, event: &LevelChange) {
         match event {
-            LevelChange(pin) => {
+            LevelChange(pin, _) => {
                 let cs = cs_for(number!(pin));
                 if low!(self.pins[cs]) && low!(pin) {
                     let value = self.memory[pins_to_value(&self.addr_pins)];
@@ -256,50 +269,69 @@ impl Device for Ic2332 {
 
 #[cfg(test)]
 mod test {
+    use std::{env, fs};
+
     use crate::{
-        components::trace::{Trace, TraceRef},
-        roms::ROM_CHARACTER,
+        components::trace::Trace,
         test_utils::{make_traces, traces_to_value, value_to_traces},
     };
 
     use super::*;
 
-    fn before_each() -> (DeviceRef, RefVec<Trace>, RefVec<Trace>, RefVec<Trace>) {
-        let device = Ic2332::new(&ROM_CHARACTER);
+    fn before_each(device: DeviceRef) -> (DeviceRef, RefVec<Trace>, RefVec<Trace>, RefVec<Trace>) {
         let tr = make_traces(&device);
 
         clear!(tr[CS2]);
         set!(tr[CS1]);
 
-        let addr_tr = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
-        let data_tr = RefVec::with_vec(
-            IntoIterator::into_iter(PA_DATA)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
+        let addr_tr = tr.map_indices(&PA_ADDRESS);
+        let data_tr = tr.map_indices(&PA_DATA);
 
         (device, tr, addr_tr, data_tr)
     }
 
     #[test]
     fn read_full() {
-        let (_, tr, addr_tr, data_tr) = before_each();
+        let roms = test_roms!();
+        let (_, tr, addr_tr, data_tr) = before_each(Ic2332::new(&roms.character));
 
-        for addr in 0..=0x0fff {
+        for (addr, &expected) in roms.character.iter().enumerate() {
             value_to_traces(addr, &addr_tr);
             clear!(tr[CS1]);
             let value = traces_to_value(&data_tr);
             set!(tr[CS1]);
 
             assert_eq!(
-                value as u8, ROM_CHARACTER[addr],
+                value as u8, expected,
                 "Incorrect value at address ${:04X}: expected ${:X}, actual ${:X}",
-                addr, ROM_CHARACTER[addr], value
+                addr, expected, value
             );
         }
     }
+
+    #[test]
+    fn load_from_file() {
+        let path = env::temp_dir().join(format!("c64-ic2332-{}.bin", std::process::id()));
+        let bytes = (0..4096).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        fs::write(&path, &bytes).unwrap();
+        let device = Ic2332::from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        let (_, tr, addr_tr, data_tr) = before_each(device.unwrap());
+        for addr in [0x000, 0x001, 0x7ff, 0xfff] {
+            value_to_traces(addr, &addr_tr);
+            clear!(tr[CS1]);
+            assert_eq!(traces_to_value(&data_tr) as u8, bytes[addr]);
+            set!(tr[CS1]);
+        }
+    }
+
+    #[test]
+    fn reject_wrong_size() {
+        let path = env::temp_dir().join(format!("c64-ic2332-short-{}.bin", std::process::id()));
+        fs::write(&path, [0; 2048]).unwrap();
+        let result = Ic2332::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result.err(), Some(RomError::Size(4096, 2048))));
+    }
 }
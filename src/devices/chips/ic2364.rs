@@ -57,14 +57,17 @@ pub mod constants {
     pub const GND: usize = 12;
 }
 
+use std::{convert::TryInto, path::Path};
+
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
-            Pin, PinRef,
+            Pin,
         },
     },
+    roms::{load_rom_file, RomError},
     utils::{none_to_pins, pins_to_value, value_to_pins},
     vectors::RefVec,
 };
@@ -137,7 +140,14 @@ const PA_DATA: [usize; 8] = [D0, D1, D2, D3, D4, D5, D6, D7];
 ///
 /// In the Commodore 64, U3 and U4 are both 2364A's (a variant with slightly faster data
 /// access). U3 stores the BASIC interpreter and U4 stores the kernal.
+///
+/// `registers` returns a snapshot of what the chip is doing at the moment: a status byte,
+/// the low and high bytes of the address on the address pins, and the byte stored at that
+/// address. Bit 0 of the status byte is set if the chip is selected (CS is low).
 pub struct Ic2364 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 2364, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
@@ -196,19 +206,12 @@ impl Ic2364 {
             a0, a1, a2, a3, a4, a5, a6, a7, a8, a9, a10, a11, a12, d0, d1, d2, d3, d4, d5, d6, d7,
             cs, vcc, gnd
         ];
-        let addr_pins = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|pa| clone_ref!(pins[pa]))
-                .collect::<Vec<PinRef>>(),
-        );
-        let data_pins = RefVec::with_vec(
-            IntoIterator::into_iter(PA_DATA)
-                .map(|pa| clone_ref!(pins[pa]))
-                .collect::<Vec<PinRef>>(),
-        );
-        let memory = bytes.clone();
+        let addr_pins = pins.map_indices(&PA_ADDRESS);
+        let data_pins = pins.map_indices(&PA_DATA);
+        let memory = *bytes;
 
         let device: DeviceRef = new_ref!(Ic2364 {
+            id: next_id(),
             pins,
             addr_pins,
             data_pins,
@@ -219,20 +222,48 @@ impl Ic2364 {
 
         device
     }
+
+    /// Creates a new 2364 emulation with the contents of a ROM image file and returns a
+    /// shared, internally mutable reference to it. The file must be exactly 8192 bytes
+    /// long; any other length is a `RomError::Size`.
+    pub fn from_file(path: &Path) -> Result<DeviceRef, RomError> {
+        Ic2364::new_from_vec(load_rom_file(path, 8192)?)
+    }
+
+    /// Creates a new 2364 emulation with the contents of a ROM image that's only known at
+    /// run time (one loaded with `roms::load_rom_file` and changed with
+    /// `roms::apply_patches`, say) and returns a shared, internally mutable reference to
+    /// it. The image must be exactly 8192 bytes long.
+    pub fn new_from_vec(bytes: Vec<u8>) -> Result<DeviceRef, RomError> {
+        let bytes: [u8; 8192] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| RomError::Size(8192, bytes.len()))?;
+        Ok(Ic2364::new(&bytes))
+    }
 }
 
 impl Device for Ic2364 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
 
     fn registers(&self) -> Vec<u8> {
-        vec![]
+        let addr = pins_to_value(&self.addr_pins);
+        vec![
+            low!(self.pins[CS]) as u8,
+            addr as u8,
+            (addr >> 8) as u8,
+            self.memory[addr],
+        ]
     }
 
     fn update(&mut self, event: &LevelChange) {
         match event {
-            LevelChange(pin) => {
+            LevelChange(pin, _) => {
                 if low!(pin) {
                     let value = self.memory[pins_to_value(&self.addr_pins)];
                     value_to_pins(value as usize, &self.data_pins);
@@ -246,9 +277,10 @@ impl Device for Ic2364 {
 
 #[cfg(test)]
 mod test {
+    use std::{env, fs};
+
     use crate::{
-        components::trace::{Trace, TraceRef},
-        roms::{ROM_BASIC, ROM_KERNAL},
+        components::trace::Trace,
         test_utils::{make_traces, traces_to_value, value_to_traces},
     };
 
@@ -260,53 +292,107 @@ mod test {
 
         set!(tr[CS]);
 
-        let addr_tr = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
-        let data_tr = RefVec::with_vec(
-            IntoIterator::into_iter(PA_DATA)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
+        let addr_tr = tr.map_indices(&PA_ADDRESS);
+        let data_tr = tr.map_indices(&PA_DATA);
 
         (device, tr, addr_tr, data_tr)
     }
 
     #[test]
     fn read_full_basic() {
-        let (_, tr, addr_tr, data_tr) = before_each(&ROM_BASIC);
+        let roms = test_roms!();
+        let (_, tr, addr_tr, data_tr) = before_each(&roms.basic);
 
-        for addr in 0..=0x1fff {
+        for (addr, &expected) in roms.basic.iter().enumerate() {
             value_to_traces(addr, &addr_tr);
             clear!(tr[CS]);
             let value = traces_to_value(&data_tr);
             set!(tr[CS]);
 
             assert_eq!(
-                value as u8, ROM_BASIC[addr],
+                value as u8, expected,
                 "Incorrect value at address ${:04X}: expected ${:X}, actual ${:X}",
-                addr, ROM_BASIC[addr], value
+                addr, expected, value
             );
         }
     }
 
     #[test]
     fn read_full_kernal() {
-        let (_, tr, addr_tr, data_tr) = before_each(&ROM_KERNAL);
+        let roms = test_roms!();
+        let (_, tr, addr_tr, data_tr) = before_each(&roms.kernal);
 
-        for addr in 0..=0x1fff {
+        for (addr, &expected) in roms.kernal.iter().enumerate() {
             value_to_traces(addr, &addr_tr);
             clear!(tr[CS]);
             let value = traces_to_value(&data_tr);
             set!(tr[CS]);
 
             assert_eq!(
-                value as u8, ROM_KERNAL[addr],
+                value as u8, expected,
                 "Incorrect value at address ${:04X}: expected ${:X}, actual ${:X}",
-                addr, ROM_KERNAL[addr], value
+                addr, expected, value
             );
         }
     }
+
+    #[test]
+    fn registers_snapshot() {
+        let roms = test_roms!();
+        let (device, tr, addr_tr, _) = before_each(&roms.kernal);
+        value_to_traces(0x1ffc, &addr_tr);
+        assert_eq!(
+            device.borrow().registers(),
+            vec![0, 0xfc, 0x1f, roms.kernal[0x1ffc]]
+        );
+
+        clear!(tr[CS]);
+        assert_eq!(device.borrow().registers()[0], 1);
+    }
+
+    #[test]
+    fn load_from_file() {
+        let path = env::temp_dir().join(format!("c64-ic2364-{}.bin", std::process::id()));
+        let bytes = (0..8192).map(|i| (i * 7) as u8).collect::<Vec<u8>>();
+        fs::write(&path, &bytes).unwrap();
+        let device = Ic2364::from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        let device = device.unwrap();
+        let tr = make_traces(&device);
+        let addr_tr = tr.map_indices(&PA_ADDRESS);
+        for addr in [0x0000, 0x0001, 0x1000, 0x1fff] {
+            value_to_traces(addr, &addr_tr);
+            assert_eq!(device.borrow().registers()[3], bytes[addr]);
+        }
+    }
+
+    #[test]
+    fn from_vec() {
+        let mut bytes = vec![0xea; 8192];
+        bytes[0x1ffc] = 0x34;
+        let device = Ic2364::new_from_vec(bytes).unwrap();
+        let tr = make_traces(&device);
+        let addr_tr = tr.map_indices(&PA_ADDRESS);
+        value_to_traces(0x1ffc, &addr_tr);
+        assert_eq!(device.borrow().registers()[3], 0x34);
+
+        let err = Ic2364::new_from_vec(vec![0; 4096]).err().unwrap();
+        assert!(matches!(err, RomError::Size(8192, 4096)));
+    }
+
+    #[test]
+    fn reject_wrong_size() {
+        let path = env::temp_dir().join(format!("c64-ic2364-short-{}.bin", std::process::id()));
+        fs::write(&path, [0; 8193]).unwrap();
+        let result = Ic2364::from_file(&path);
+        fs::remove_file(&path).unwrap();
+
+        let err = result.err().unwrap();
+        assert!(matches!(err, RomError::Size(8192, 8193)));
+        assert_eq!(
+            err.to_string(),
+            "ROM image is 8193 bytes, but it must be 8192 bytes"
+        );
+    }
 }
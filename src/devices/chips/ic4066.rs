@@ -40,7 +40,7 @@ pub mod constants {
 
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Bidirectional, Input, Unconnected},
             Pin,
@@ -65,7 +65,8 @@ const CONTROLS: [usize; 4] = [X1, X2, X3, X4];
 /// them to be set*. This is a bit of a compromise necessitated by the fact that this is a
 /// digital simulation of an analog circuit, but it should be the most natural. Most use
 /// cases do not involve switching the direction that data flows through the switch
-/// regularly.
+/// regularly. If neither data pin has been set, whatever level is already present on one
+/// of them is passed to the other; if neither has a level, both are left floating.
 ///
 /// There is no high-impedance state for the pins of this device. When the control pin his
 /// high, the data pins simply take on the level of whatever circuits they're connected to.
@@ -103,6 +104,9 @@ const CONTROLS: [usize; 4] = [X1, X2, X3, X4];
 /// control which processor has access to the color RAM's data pins, while the other is used
 /// as an analog switch to control which game port is providing paddle data to the 6581 SID.
 pub struct Ic4066 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 4066, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
@@ -144,6 +148,7 @@ impl Ic4066 {
         let last = vec![None, None, None, None];
 
         let device: DeviceRef = new_ref!(Ic4066 {
+            id: next_id(),
             pins: pins![a1, a2, a3, a4, b1, b2, b3, b4, x1, x2, x3, x4, vdd, vss],
             last,
         });
@@ -193,6 +198,10 @@ fn switch(control: usize) -> usize {
 }
 
 impl Device for Ic4066 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -204,7 +213,7 @@ impl Device for Ic4066 {
     fn update(&mut self, event: &LevelChange) {
         match event {
             // Control pin change
-            LevelChange(pin) if CONTROLS.contains(&number!(pin)) => {
+            LevelChange(pin, _) if CONTROLS.contains(&number!(pin)) => {
                 let (a, b) = ios_for(number!(pin));
                 let apin = clone_ref!(self.pins[a]);
                 let bpin = clone_ref!(self.pins[b]);
@@ -226,15 +235,27 @@ impl Device for Ic4066 {
                         Some(num) if num == a => set_level!(bpin, level!(apin)),
                         Some(num) if num == b => set_level!(apin, level!(bpin)),
                         _ => {
-                            clear!(apin);
-                            clear!(bpin);
+                            // Nothing has passed through the switch yet, so rather than
+                            // driving both pins to an arbitrary level, pass along whatever
+                            // level is already present on one side and otherwise leave
+                            // them both floating
+                            let alevel = level!(apin);
+                            let blevel = level!(bpin);
+                            if alevel.is_some() {
+                                set_level!(bpin, alevel);
+                            } else if blevel.is_some() {
+                                set_level!(apin, blevel);
+                            } else {
+                                float!(apin);
+                                float!(bpin);
+                            }
                         }
                     }
                 }
             }
             // I/O pin change: remember the index of the pin being changed, and if the
             // control pin is low, set the level of the associated I/O pin to the new level
-            LevelChange(pin) if IOS.contains(&number!(pin)) => {
+            LevelChange(pin, _) if IOS.contains(&number!(pin)) => {
                 let (out, x) = io_control_for(number!(pin));
                 let index = switch(x);
 
@@ -250,7 +271,15 @@ impl Device for Ic4066 {
 
 #[cfg(test)]
 mod test {
-    use crate::{components::trace::Trace, test_utils::make_traces};
+    use crate::{
+        components::{
+            level::Level,
+            pin::{with_batch, Mode::Output},
+            trace::{Trace, TraceRef},
+        },
+        devices::chips::Ic7408,
+        test_utils::make_traces,
+    };
 
     use super::*;
 
@@ -473,58 +502,104 @@ mod test {
 
     #[test]
     fn unset_before_high_x() {
-        let (_, tr) = before_each();
+        let (chip, tr) = before_each();
+        let pins = chip.borrow().pins();
+
+        for &(x, a, b) in &[(X1, A1, B1), (X2, A2, B2), (X3, A3, B3), (X4, A4, B4)] {
+            set!(tr[x]);
+            clear!(tr[x]);
+            assert!(
+                floating!(pins[a]) && floating!(pins[b]),
+                "{} and {} should both be floating since nothing was last set",
+                pins[a].borrow().name(),
+                pins[b].borrow().name(),
+            );
+            assert!(
+                floating!(tr[a]) && floating!(tr[b]),
+                "{} and {} should not drive their traces since nothing was last set",
+                pins[a].borrow().name(),
+                pins[b].borrow().name(),
+            );
+        }
+    }
 
-        set!(tr[X1]);
-        clear!(tr[X1]);
-        assert_eq!(
-            level!(tr[A1]).unwrap(),
-            0.0,
-            "A1 should be low since nothing was last set"
-        );
-        assert_eq!(
-            level!(tr[B1]).unwrap(),
-            0.0,
-            "B1 should be low since nothing was last set"
-        );
+    /// Closes switch 1 while an external output pin drives `driven` (A1 or B1) to 0.75,
+    /// with both changes made in the same batch. The switch is notified first, so it closes
+    /// before it has seen any I/O pin change and has to pick up the level that's already
+    /// on the driven side. Returns the traces of A1 and B1.
+    fn close_with_external_level(driven: usize) -> (TraceRef, TraceRef) {
+        let chip = Ic4066::new();
+        let pins = chip.borrow().pins();
+        let ext = pin!(1, "EXT", Output);
+        let x1 = trace!(pins[X1]);
+        let (a1, b1) = if driven == A1 {
+            (trace!(pins[A1], ext), trace!(pins[B1]))
+        } else {
+            (trace!(pins[A1]), trace!(pins[B1], ext))
+        };
+
+        set!(x1);
+        with_batch(|| {
+            clear!(x1);
+            set_level!(ext, Some(0.75));
+        });
+        (a1, b1)
+    }
 
-        set!(tr[X2]);
-        clear!(tr[X2]);
+    #[test]
+    fn external_level_on_a_passed_on_low_x() {
+        let (a1, b1) = close_with_external_level(A1);
         assert_eq!(
-            level!(tr[A2]).unwrap(),
-            0.0,
-            "A2 should be low since nothing was last set"
+            level!(b1),
+            Some(0.75),
+            "B1 should take on the level already on A1"
         );
         assert_eq!(
-            level!(tr[B2]).unwrap(),
-            0.0,
-            "B2 should be low since nothing was last set"
+            level!(a1),
+            Some(0.75),
+            "A1 should still be driven by the external pin"
         );
+    }
 
-        set!(tr[X3]);
-        clear!(tr[X3]);
+    #[test]
+    fn external_level_on_b_passed_on_low_x() {
+        let (a1, b1) = close_with_external_level(B1);
         assert_eq!(
-            level!(tr[A3]).unwrap(),
-            0.0,
-            "A3 should be low since nothing was last set"
+            level!(a1),
+            Some(0.75),
+            "A1 should take on the level already on B1"
         );
         assert_eq!(
-            level!(tr[B3]).unwrap(),
-            0.0,
-            "B3 should be low since nothing was last set"
+            level!(b1),
+            Some(0.75),
+            "B1 should still be driven by the external pin"
         );
+    }
 
-        set!(tr[X4]);
-        clear!(tr[X4]);
-        assert_eq!(
-            level!(tr[A4]).unwrap(),
-            0.0,
-            "A4 should be low since nothing was last set"
-        );
+    #[test]
+    fn analog_read_as_digital() {
+        let (_, tr) = before_each();
+        let and = Ic7408::new();
+        let pin = |name| and.borrow().pin_by_name(name).unwrap();
+
+        // The 7408's A1 input shares the 4066's B1 trace, and its B1 input is held high
+        let a = pin("A1");
+        tr[B1].borrow_mut().add_pin(clone_ref!(a));
+        a.borrow_mut().set_trace(clone_ref!(tr[B1]));
+        let b = trace!(pin("B1"));
+        let y = trace!(pin("Y1"));
+        set!(b);
+
+        clear!(tr[X1]);
+        set_level!(tr[A1], Some(0.7));
         assert_eq!(
-            level!(tr[B4]).unwrap(),
-            0.0,
-            "B4 should be low since nothing was last set"
+            tr[B1].borrow().signal(),
+            Level::Analog(0.7),
+            "B1 should carry A1's analog level"
         );
+        assert!(high!(y), "the 7408 should read 0.7 as high");
+
+        set_level!(tr[A1], Some(0.3));
+        assert!(low!(y), "the 7408 should read 0.3 as low");
     }
 }
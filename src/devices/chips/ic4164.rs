@@ -43,14 +43,14 @@ pub mod constants {
 
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
-            Pin, PinRef,
+            Pin,
         },
+        port::Port,
     },
     vectors::RefVec,
-    utils::pins_to_value,
 };
 
 use self::constants::*;
@@ -97,6 +97,14 @@ const PA_ADDRESS: [usize; 8] = [A0, A1, A2, A3, A4, A5, A6, A7];
 /// incurring the cost of resetting the row address. This doesn't happen in the C64; the
 /// 6567 VIC cycles the RAS line once every clock cycle.
 ///
+/// Being dynamic RAM, the 4164 loses the contents of a row of its memory array if that row
+/// isn't refreshed often enough (every 2ms or so). Any access that brings RAS low refreshes
+/// the row whose address is latched, including a "RAS-only" refresh cycle in which CAS
+/// never goes low at all. This emulation does not model refresh by default, but a chip
+/// created with `new_with_decay` will scramble the contents of any row that goes too long
+/// without a refresh. Time is measured in RAS cycles (the number of times that RAS has gone
+/// low), which in the C64 is the same as clock cycles.
+///
 /// Unlike most other non-logic chips in the system, there is no dedicated chip-select pin.
 /// The combination of RAS and CAS can be regarded as such a pin, and it is used that way in
 /// the Commodore 64.
@@ -153,13 +161,21 @@ const PA_ADDRESS: [usize; 8] = [A0, A1, A2, A3, A4, A5, A6, A7];
 ///
 /// In the Commodore 64, U9, U10, U11, U12, U21, U22, U23, and U24 are 4164s, one for each
 /// of the 8 bits on the data bus.
+///
+/// `registers` returns four bytes describing the chip's latched state: a status byte, the
+/// latched row address, the latched column address, and the latched input data bit. Bits 0,
+/// 1, and 2 of the status byte are set if the row, column, and data (respectively) are
+/// latched; any of the other three bytes that isn't latched is 0.
 pub struct Ic4164 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 4164, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
 
-    /// Separate references to the A0-A7 pins in the `pins` vector.
-    addr_pins: RefVec<Pin>,
+    /// The A0-A7 pins from the `pins` vector, as a port.
+    addr: Port,
 
     /// The place where the data is actually stored. The 4164 is 1-bit memory that is stored
     /// in a 256x256 matrix internally, but we don't have either u1 or u256 types (bools
@@ -182,12 +198,36 @@ pub struct Ic4164 {
     /// easily. If no data has been latched (either WE or CAS is not low), this will be
     /// `None`.
     data: Option<u8>,
+
+    /// The number of RAS cycles that a row can go without being refreshed before its
+    /// contents decay. If this is `None`, decay is disabled and rows never lose their
+    /// contents.
+    decay: Option<usize>,
+
+    /// The number of times that RAS has gone low. This is the clock against which refresh
+    /// is measured.
+    ras_count: usize,
+
+    /// The value of `ras_count` when each row was last refreshed.
+    refreshed: [usize; 256],
 }
 
 impl Ic4164 {
     /// Creates a new 4164 64k x 1 dynamic RAM emulation and returns a shared, internally
-    /// mutable reference to it.
+    /// mutable reference to it. The emulation does not model refresh; its memory never
+    /// decays.
     pub fn new() -> DeviceRef {
+        Ic4164::build(None)
+    }
+
+    /// Creates a new 4164 64k x 1 dynamic RAM emulation whose rows decay if they go more
+    /// than `cycles` RAS cycles without being refreshed, and returns a shared, internally
+    /// mutable reference to it.
+    pub fn new_with_decay(cycles: usize) -> DeviceRef {
+        Ic4164::build(Some(cycles))
+    }
+
+    fn build(decay: Option<usize>) -> DeviceRef {
         // Address pins 0-7.
         let a0 = pin!(A0, "A0", Input);
         let a1 = pin!(A1, "A1", Input);
@@ -227,20 +267,24 @@ impl Ic4164 {
         let vss = pin!(VSS, "VSS", Unconnected);
 
         let pins = pins![a0, a1, a2, a3, a4, a5, a6, a7, d, q, ras, cas, we, nc, vcc, vss];
-        let addr_pins = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|pa| clone_ref!(pins[pa]))
-                .collect::<Vec<PinRef>>(),
-        );
+        let addr = Port::from_pins(&pins, &PA_ADDRESS);
 
-        let device: DeviceRef = new_ref!(Ic4164 {
+        let mut chip = Ic4164 {
+            id: next_id(),
             pins,
-            addr_pins,
+            addr,
             memory: [0; 2048],
             row: None,
             col: None,
             data: None,
-        });
+            decay: None,
+            ras_count: 0,
+            refreshed: [0; 256],
+        };
+        if let Some(cycles) = decay {
+            chip.enable_decay(cycles);
+        }
+        let device: DeviceRef = new_ref!(chip);
 
         float!(q);
         attach_to!(device, ras, cas, we);
@@ -248,6 +292,40 @@ impl Ic4164 {
         device
     }
 
+    /// Enables decay, so that any row that goes more than `cycles` RAS cycles without being
+    /// refreshed loses its contents. Every row is treated as having just been refreshed.
+    pub fn enable_decay(&mut self, cycles: usize) {
+        self.decay = Some(cycles);
+        self.refreshed = [self.ras_count; 256];
+    }
+
+    /// Refreshes a row, which happens whenever RAS goes low. If decay is enabled and the
+    /// row has gone too long since its last refresh, its contents are lost first; the
+    /// refresh then restores the (now indeterminate) values that it finds.
+    fn refresh(&mut self, row: u8) {
+        self.ras_count += 1;
+        if let Some(cycles) = self.decay {
+            if self.ras_count - self.refreshed[row as usize] > cycles {
+                self.decay_row(row);
+            }
+        }
+        self.refreshed[row as usize] = self.ras_count;
+    }
+
+    /// Replaces the contents of a row with indeterminate values. Real cells leak toward
+    /// whichever level their construction favors, so what's left is not predictable; here
+    /// the row is filled from a simple xorshift generator seeded by the row and the time.
+    fn decay_row(&mut self, row: u8) {
+        let mut x = (self.ras_count as u32 ^ (row as u32) << 16) | 1;
+        let (start, _) = resolve(row, 0);
+        for word in &mut self.memory[start..start + 8] {
+            x ^= x << 13;
+            x ^= x >> 17;
+            x ^= x << 5;
+            *word = x;
+        }
+    }
+
     /// Reads the row and col and calculates the specific bit in the memory array to which
     /// this row/col combination refers. The first element of the return value is the index
     /// of the 32-bit number in the memory array where that bit resides; the second element
@@ -256,14 +334,7 @@ impl Ic4164 {
         // Unless there's a bug in this program, this method should never be called while
         // either `self.row` or `self.col` are `None`. So we actually *want* it to panic if
         // `unwrap()` fails.
-        let row = self.row.unwrap() as usize;
-        let col = self.col.unwrap() as usize;
-
-        let row_index = row << 3;
-        let col_index = (col & 0b1110_0000) >> 5;
-        let bit_index = col & 0b0001_1111;
-
-        (row_index | col_index, bit_index)
+        resolve(self.row.unwrap(), self.col.unwrap())
     }
 
     /// Retrieves a single bit from the memory array and sets the level of the Q pin to the
@@ -292,18 +363,42 @@ impl Ic4164 {
     }
 }
 
+/// Resolves a row and column into the indices within the memory array where that cell is
+/// stored. Cells are laid out row-major, so the cell at a row and column is bit
+/// `(row << 8) | col` of the array as a whole. The returned tuple contains the index of the
+/// 32-bit number in the array holding that bit and the index of the bit within that number.
+fn resolve(row: u8, col: u8) -> (usize, usize) {
+    let row_index = (row as usize) << 3;
+    let col_index = (col as usize & 0b1110_0000) >> 5;
+    let bit_index = col as usize & 0b0001_1111;
+
+    (row_index | col_index, bit_index)
+}
+
 impl Device for Ic4164 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
 
     fn registers(&self) -> Vec<u8> {
-        vec![]
+        let status = self.row.is_some() as u8
+            | (self.col.is_some() as u8) << 1
+            | (self.data.is_some() as u8) << 2;
+        vec![
+            status,
+            self.row.unwrap_or(0),
+            self.col.unwrap_or(0),
+            self.data.unwrap_or(0),
+        ]
     }
 
     fn update(&mut self, event: &LevelChange) {
         match event {
-            LevelChange(pin) if number!(pin) == RAS => {
+            LevelChange(pin, _) if number!(pin) == RAS => {
                 // Invoked when the RAS pin changes level. When it goes low, the current
                 // states of the A0-A7 pins are latched. The address is released when the
                 // RAS pin goes high.
@@ -313,13 +408,18 @@ impl Device for Ic4164 {
                 // those accesses. This can speed up reads and writes within the same page
                 // by reducing the amount of setup needed for those reads and writes. (This
                 // does not happen in the C64.)
+                //
+                // Bringing RAS low also refreshes the latched row, whether or not CAS
+                // follows.
                 if high!(pin) {
                     self.row = None;
                 } else {
-                    self.row = Some(pins_to_value(&self.addr_pins) as u8);
+                    let row = self.addr.value() as u8;
+                    self.refresh(row);
+                    self.row = Some(row);
                 }
             }
-            LevelChange(pin) if number!(pin) == CAS => {
+            LevelChange(pin, _) if number!(pin) == CAS => {
                 // Invoked when the CAS pin changes level.
                 //
                 // When CAS goes low, the current states of the A0-A7 pins are latched in a
@@ -338,7 +438,7 @@ impl Device for Ic4164 {
                     self.col = None;
                     self.data = None;
                 } else {
-                    self.col = Some(pins_to_value(&self.addr_pins) as u8);
+                    self.col = Some(self.addr.value() as u8);
                     if high!(self.pins[WE]) {
                         self.read();
                     } else {
@@ -347,7 +447,7 @@ impl Device for Ic4164 {
                     }
                 }
             }
-            LevelChange(pin) if number!(pin) == WE => {
+            LevelChange(pin, _) if number!(pin) == WE => {
                 // Invoked when the WE pin changes level.
                 //
                 // When WE is high, read mode is enabled (though the actual read will not be
@@ -387,7 +487,7 @@ impl Device for Ic4164 {
 #[cfg(test)]
 mod test {
     use crate::{
-        components::trace::{Trace, TraceRef},
+        components::trace::Trace,
         test_utils::{make_traces, value_to_traces},
     };
 
@@ -401,11 +501,7 @@ mod test {
         set!(tr[RAS]);
         set!(tr[CAS]);
 
-        let addr_tr = RefVec::with_vec(
-            IntoIterator::into_iter(PA_ADDRESS)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
+        let addr_tr = tr.map_indices(&PA_ADDRESS);
 
         (device, tr, addr_tr)
     }
@@ -656,4 +752,220 @@ mod test {
         }
         set!(tr[RAS]);
     }
+
+    // Address pins as given in the datasheet, as pairs of (physical pin number, address
+    // bit). These are deliberately not taken from the constants so that they can catch
+    // mistakes in them or in PA_ADDRESS.
+    const DATASHEET_ADDRESS: [(usize, usize); 8] = [
+        (5, 0),
+        (7, 1),
+        (6, 2),
+        (12, 3),
+        (11, 4),
+        (10, 5),
+        (13, 6),
+        (9, 7),
+    ];
+
+    fn read_bit(tr: &RefVec<Trace>, addr_tr: &RefVec<Trace>, row: usize, col: usize) -> bool {
+        value_to_traces(row, addr_tr);
+        clear!(tr[RAS]);
+        value_to_traces(col, addr_tr);
+        clear!(tr[CAS]);
+        let value = high!(tr[Q]);
+        set!(tr[CAS]);
+        set!(tr[RAS]);
+        value
+    }
+
+    // Each cell lives at bit `(row << 8) | col` of the memory array.
+    #[test]
+    fn cell_layout() {
+        for row in 0..=0xff {
+            for col in 0..=0xff {
+                let (index, bit) = resolve(row, col);
+                assert_eq!(
+                    index * 32 + bit,
+                    (row as usize) << 8 | col as usize,
+                    "Row ${:02X}, column ${:02X} resolved to the wrong cell",
+                    row,
+                    col
+                );
+            }
+        }
+    }
+
+    // Raising a single physical address pin while latching the row or column must address
+    // the same cell as the corresponding single-bit value written through PA_ADDRESS.
+    #[test]
+    fn address_pins_match_datasheet() {
+        let (_, tr, addr_tr) = before_each();
+
+        for (pin, bit) in DATASHEET_ADDRESS {
+            for is_row in [true, false] {
+                let (row, col) = if is_row { (1 << bit, 0) } else { (0, 1 << bit) };
+                assert!(
+                    !read_bit(&tr, &addr_tr, row, col),
+                    "Cell at row ${:02X}, column ${:02X} should not have been written yet",
+                    row,
+                    col
+                );
+
+                value_to_traces(0, &addr_tr);
+                if is_row {
+                    set!(tr[pin]);
+                }
+                clear!(tr[RAS]);
+                value_to_traces(0, &addr_tr);
+                if !is_row {
+                    set!(tr[pin]);
+                }
+                set!(tr[D]);
+                clear!(tr[WE]);
+                clear!(tr[CAS]);
+                set!(tr[CAS]);
+                set!(tr[WE]);
+                set!(tr[RAS]);
+
+                assert!(
+                    read_bit(&tr, &addr_tr, row, col),
+                    "Pin {} should address {} bit {}",
+                    pin,
+                    if is_row { "row" } else { "column" },
+                    bit
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn pin_lookup() {
+        let device = Ic4164::new();
+        let q = device.borrow().pin_by_name("Q").unwrap();
+        assert_eq!(number!(q), Q);
+        assert_eq!(mode!(q), Output);
+
+        let ras = device.borrow().pin_by_name("RAS").unwrap();
+        assert!(std::rc::Rc::ptr_eq(&ras, &device.borrow().pins()[RAS]));
+        assert!(device.borrow().pin_by_name("XYZ").is_none());
+
+        assert_eq!(name!(device.borrow().pin(CAS).unwrap()), "CAS");
+        assert!(device.borrow().pin(17).is_none());
+    }
+
+    #[test]
+    fn registers_latched() {
+        let (device, tr, addr_tr) = before_each();
+        assert_eq!(device.borrow().registers(), vec![0, 0, 0, 0]);
+
+        value_to_traces(0x3c, &addr_tr);
+        clear!(tr[RAS]);
+        assert_eq!(device.borrow().registers(), vec![0b001, 0x3c, 0, 0]);
+
+        value_to_traces(0xa7, &addr_tr);
+        clear!(tr[CAS]);
+        assert_eq!(device.borrow().registers(), vec![0b011, 0x3c, 0xa7, 0]);
+
+        // Read-modify-write latches the data bit as well
+        set!(tr[D]);
+        clear!(tr[WE]);
+        assert_eq!(device.borrow().registers(), vec![0b111, 0x3c, 0xa7, 1]);
+
+        set!(tr[WE]);
+        set!(tr[CAS]);
+        set!(tr[RAS]);
+        assert_eq!(device.borrow().registers(), vec![0, 0, 0, 0]);
+    }
+
+    /// Writes a value to every cell in a row, leaving RAS and CAS high afterwards.
+    fn write_row(tr: &RefVec<Trace>, addr_tr: &RefVec<Trace>, row: usize, value: bool) {
+        set_level!(tr[D], Some(value as u8 as f64));
+        for col in 0..256 {
+            value_to_traces(row, addr_tr);
+            clear!(tr[RAS]);
+            value_to_traces(col, addr_tr);
+            clear!(tr[WE]);
+            clear!(tr[CAS]);
+            set!(tr[CAS]);
+            set!(tr[WE]);
+            set!(tr[RAS]);
+        }
+    }
+
+    /// Reads every cell in a row, returning the number of cells that were high.
+    fn count_row(tr: &RefVec<Trace>, addr_tr: &RefVec<Trace>, row: usize) -> usize {
+        let mut count = 0;
+        for col in 0..256 {
+            value_to_traces(row, addr_tr);
+            clear!(tr[RAS]);
+            value_to_traces(col, addr_tr);
+            clear!(tr[CAS]);
+            count += high!(tr[Q]) as usize;
+            set!(tr[CAS]);
+            set!(tr[RAS]);
+        }
+        count
+    }
+
+    /// Performs RAS-only refresh cycles on a row.
+    fn ras_only(tr: &RefVec<Trace>, addr_tr: &RefVec<Trace>, row: usize, times: usize) {
+        value_to_traces(row, addr_tr);
+        for _ in 0..times {
+            clear!(tr[RAS]);
+            set!(tr[RAS]);
+        }
+    }
+
+    fn before_each_decay(cycles: usize) -> (DeviceRef, RefVec<Trace>, RefVec<Trace>) {
+        let device = Ic4164::new_with_decay(cycles);
+        let tr = make_traces(&device);
+
+        set!(tr[WE]);
+        set!(tr[RAS]);
+        set!(tr[CAS]);
+
+        let addr_tr = tr.map_indices(&PA_ADDRESS);
+
+        (device, tr, addr_tr)
+    }
+
+    #[test]
+    fn no_decay_by_default() {
+        let (_, tr, addr_tr) = before_each();
+        write_row(&tr, &addr_tr, 0x12, true);
+        ras_only(&tr, &addr_tr, 0x34, 10_000);
+        assert_eq!(count_row(&tr, &addr_tr, 0x12), 256);
+    }
+
+    #[test]
+    fn row_decays_without_refresh() {
+        // The window has to cover the 256 RAS cycles used to write or read the row itself
+        let (_, tr, addr_tr) = before_each_decay(1000);
+        write_row(&tr, &addr_tr, 0x12, true);
+        write_row(&tr, &addr_tr, 0x13, false);
+
+        ras_only(&tr, &addr_tr, 0x34, 1001);
+
+        let ones = count_row(&tr, &addr_tr, 0x12);
+        let zeros = 256 - count_row(&tr, &addr_tr, 0x13);
+        assert!(ones < 256, "Row of 1s should have decayed");
+        assert!(zeros < 256, "Row of 0s should have decayed");
+    }
+
+    #[test]
+    fn ras_only_refresh_retains_row() {
+        let (_, tr, addr_tr) = before_each_decay(1000);
+        write_row(&tr, &addr_tr, 0x12, true);
+
+        for _ in 0..4 {
+            ras_only(&tr, &addr_tr, 0x34, 900);
+            ras_only(&tr, &addr_tr, 0x12, 1);
+        }
+
+        assert_eq!(
+            count_row(&tr, &addr_tr, 0x12),
+            256,
+            "Refreshed row should keep its contents"
+        );
+    }
 }
@@ -0,0 +1,1089 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+mod model;
+mod pins;
+mod serial;
+mod timer;
+mod tod;
+
+pub mod constants {
+    /// Register address of peripheral data register A.
+    pub const PRA: u16 = 0x0;
+    /// Register address of peripheral data register B.
+    pub const PRB: u16 = 0x1;
+    /// Register address of data direction register A.
+    pub const DDRA: u16 = 0x2;
+    /// Register address of data direction register B.
+    pub const DDRB: u16 = 0x3;
+    /// Register address of the low byte of timer A.
+    pub const TALO: u16 = 0x4;
+    /// Register address of the high byte of timer A.
+    pub const TAHI: u16 = 0x5;
+    /// Register address of the low byte of timer B.
+    pub const TBLO: u16 = 0x6;
+    /// Register address of the high byte of timer B.
+    pub const TBHI: u16 = 0x7;
+    /// Register address of the time-of-day clock's tenths of seconds.
+    pub const TOD10TH: u16 = 0x8;
+    /// Register address of the time-of-day clock's seconds.
+    pub const TODSEC: u16 = 0x9;
+    /// Register address of the time-of-day clock's minutes.
+    pub const TODMIN: u16 = 0xa;
+    /// Register address of the time-of-day clock's hours.
+    pub const TODHR: u16 = 0xb;
+    /// Register address of the serial data register.
+    pub const SDR: u16 = 0xc;
+    /// Register address of the interrupt control register.
+    pub const ICR: u16 = 0xd;
+    /// Register address of control register A.
+    pub const CRA: u16 = 0xe;
+    /// Register address of control register B.
+    pub const CRB: u16 = 0xf;
+
+    /// Interrupt control register bit for timer A underflow.
+    pub const ICR_TA: u8 = 0x01;
+    /// Interrupt control register bit for timer B underflow.
+    pub const ICR_TB: u8 = 0x02;
+    /// Interrupt control register bit for the time-of-day clock reaching the alarm time.
+    pub const ICR_ALARM: u8 = 0x04;
+    /// Interrupt control register bit for the serial port completing a byte.
+    pub const ICR_SP: u8 = 0x08;
+    /// Interrupt control register bit for a negative transition on the FLAG input.
+    pub const ICR_FLAG: u8 = 0x10;
+    /// Interrupt control register bit that is set on read if an interrupt has occurred, or
+    /// that selects setting (1) or clearing (0) mask bits on write.
+    pub const ICR_IR: u8 = 0x80;
+
+    /// Control register bit that starts (1) or stops (0) a timer.
+    pub const CR_START: u8 = 0x01;
+    /// Control register bit that selects one-shot (1) or continuous (0) mode.
+    pub const CR_RUNMODE: u8 = 0x08;
+    /// Control register strobe bit that forces a timer's latch into its counter.
+    pub const CR_LOAD: u8 = 0x10;
+    /// Control register A bit that makes timer A count CNT transitions instead of clock
+    /// cycles.
+    pub const CRA_INMODE: u8 = 0x20;
+    /// Control register A bit that puts the serial port in output (1) or input (0) mode.
+    pub const CRA_SPMODE: u8 = 0x40;
+    /// Control register A bit that selects a 50Hz (1) or 60Hz (0) time-of-day clock input.
+    pub const CRA_TODIN: u8 = 0x80;
+    /// Control register B bits that select what timer B counts: clock cycles (`00`), CNT
+    /// transitions (`01`), timer A underflows (`10`), or timer A underflows while CNT is
+    /// high (`11`).
+    pub const CRB_INMODE: u8 = 0x60;
+    /// Control register B bit that makes writes to the time-of-day registers set the alarm
+    /// (1) instead of the clock (0).
+    pub const CRB_ALARM: u8 = 0x80;
+}
+
+use crate::components::{addressable::Addressable, clock::Clocked};
+
+pub use self::{
+    model::{CiaConfig, CiaConfigError, CiaModel, ModelWarning, Quirk},
+    pins::Ic6526Pins,
+};
+
+use self::{constants::*, serial::SerialPort, timer::Timer, tod::Tod};
+
+/// An emulation of the 6526 Complex Interface Adapter, at the register level.
+///
+/// The 6526 is a peripheral chip with two 8-bit parallel ports, two 16-bit interval timers,
+/// a time-of-day clock, an 8-bit serial shift register, and an interrupt controller that
+/// ties them together. The C64 has two of them: CIA 1 (U1) scans the keyboard and
+/// joysticks and generates the system IRQ, and CIA 2 (U2) handles the serial bus, the
+/// user port, and VIC bank selection, and generates NMIs.
+///
+/// Unlike the other chips in this module, this is not a pin-level emulation. The chip's 16
+/// registers are accessed through `Addressable` (only the low 4 bits of the address are
+/// decoded, as on the real chip), and the timers are advanced by `Clocked`, once per φ2
+/// cycle. The IRQ output is available from `irq_asserted`. The time-of-day clock has its
+/// own input, which is ticked by `tod_tick`.
+///
+/// | Address | Name    | Description                                                    |
+/// | ------- | ------- | -------------------------------------------------------------- |
+/// | $0      | PRA     | Port A data. Input bits read the levels set by `set_port_a`.   |
+/// | $1      | PRB     | Port B data. Input bits read the levels set by `set_port_b`.   |
+/// | $2      | DDRA    | Port A data direction. A 1 bit makes that port pin an output.  |
+/// | $3      | DDRB    | Port B data direction.                                         |
+/// | $4      | TALO    | Timer A. Reads return the counter; writes go to the latch.     |
+/// | $5      | TAHI    |                                                                |
+/// | $6      | TBLO    | Timer B. Reads return the counter; writes go to the latch.     |
+/// | $7      | TBHI    |                                                                |
+/// | $8      | TOD10TH | Time-of-day clock tenths of seconds, in BCD.                   |
+/// | $9      | TODSEC  | Time-of-day clock seconds, in BCD.                             |
+/// | $A      | TODMIN  | Time-of-day clock minutes, in BCD.                             |
+/// | $B      | TODHR   | Time-of-day clock hours, in BCD, with bit 7 set for PM.        |
+/// | $C      | SDR     | Serial data register.                                          |
+/// | $D      | ICR     | Interrupt control. Reads return and clear the interrupt flags; |
+/// |         |         | writes set or clear bits of the interrupt mask.                |
+/// | $E      | CRA     | Control register A.                                            |
+/// | $F      | CRB     | Control register B.                                            |
+///
+/// ### Timers
+///
+/// Each timer counts down from its latched value and underflows on the count after it
+/// reaches 0, so with a latch value of N it underflows every N + 1 counts. Writing the high
+/// byte of a stopped timer loads the latch into the counter, and setting the LOAD bit in a
+/// control register does so at any time. An underflow reloads the counter from the latch,
+/// sets the timer's bit in the interrupt flags, and in one-shot mode stops the timer.
+///
+/// Timer A counts φ2 cycles or, if bit 5 of CRA is set, positive CNT transitions. Timer B
+/// can count φ2 cycles, positive CNT transitions, timer A underflows, or timer A underflows
+/// while CNT is high, depending on bits 5 and 6 of CRB. The KERNAL doesn't chain the
+/// timers, but plenty of other software does so to make a 32-bit timer. CNT is set with
+/// `set_cnt`; it's pulled high in the C64.
+///
+/// The timers' PB6/PB7 outputs (CRA/CRB bits 1 and 2) are not yet emulated.
+///
+/// ### Time-of-day clock
+///
+/// The TOD clock counts 12-hour time in BCD, from the 50Hz or 60Hz power line signal (bit 7
+/// of CRA selects which). Reading TODHR freezes all four registers until TOD10TH is read,
+/// so that the time can be read without it changing part-way through; the clock keeps
+/// running meanwhile. Writing TODHR stops the clock until TOD10TH is written. If bit 7 of
+/// CRB is set, writes set the alarm time instead of the clock, and the clock reaching the
+/// alarm time raises an interrupt. Writing 12 to TODHR flips the AM/PM bit, as it does on
+/// the real chip. After a reset the clock is stopped at 1:00:00.0 AM.
+///
+/// ### Serial port
+///
+/// SDR is shifted MSB first over SP, one bit per CNT cycle. In input mode (bit 6 of CRA
+/// clear), bits are shifted in from SP on rising edges of CNT, and each 8th raises an
+/// interrupt with the received byte in SDR. In output mode, writing SDR shifts the byte out
+/// on SP with CNT toggled by each underflow of timer A, so one bit takes two underflows, and
+/// an interrupt is raised when the byte is done. SP is set with `set_sp`, and the levels
+/// driven in output mode are available from `sp_out` and `cnt_out`.
+///
+/// ### Ports
+///
+/// Each bit of a port is an output if its bit in the data direction register is set and an
+/// input if it's clear. Reading a port returns the data register's bits for outputs and the
+/// levels of the pins for inputs. Since this emulation has no pins, those levels are set
+/// with `set_port_a` and `set_port_b`; they're high until they're set, as the port pins
+/// have internal pull-ups. The levels the chip drives on its ports are available from
+/// `port_a` and `port_b`. `Ic6526Pins` wraps this emulation with real pins, including the
+/// PC handshaking output.
+///
+/// ### Interrupts
+///
+/// Each interrupt source sets a bit in the interrupt flags whether or not it's enabled in
+/// the mask. If a flag is set whose mask bit is also set, the IRQ output is asserted and
+/// bit 7 of ICR reads as 1. Reading ICR returns the flags and then clears them, which also
+/// releases IRQ. Besides the timers, TOD alarm, and serial port, a negative transition on
+/// the FLAG input is an interrupt source; it's signaled by calling `flag`.
+///
+/// ### Models
+///
+/// The 6526 asserts IRQ one cycle later than the 6526A and 8521 do, and some software tells
+/// them apart by that. `new` emulates the 6526A; `with_config` can create any model, or a
+/// chip with a particular set of `Quirk`s, and `quirks` reports which ones a chip has. The
+/// first time software does something that a quirk decides the result of (like reading ICR
+/// in the same cycle that an interrupt happens), a `ModelWarning` is recorded, and these
+/// can be retrieved with `model_warnings`.
+pub struct Ic6526 {
+    /// Port A's data register.
+    pra: u8,
+
+    /// Port B's data register.
+    prb: u8,
+
+    /// Port A's data direction register.
+    ddra: u8,
+
+    /// Port B's data direction register.
+    ddrb: u8,
+
+    /// The levels of port A's pins, which are read for its input bits.
+    pa_in: u8,
+
+    /// The levels of port B's pins, which are read for its input bits.
+    pb_in: u8,
+
+    /// Timer A.
+    timer_a: Timer,
+
+    /// Timer B.
+    timer_b: Timer,
+
+    /// The interrupt flags that have been set since ICR was last read.
+    icr_flags: u8,
+
+    /// The interrupt mask. Only flags that have their mask bit set assert IRQ.
+    icr_mask: u8,
+
+    /// The time-of-day clock.
+    tod: Tod,
+
+    /// The serial port.
+    serial: SerialPort,
+
+    /// The level of the CNT input.
+    cnt: bool,
+
+    /// The level of the SP input.
+    sp: bool,
+
+    /// The settings the chip was created with.
+    config: CiaConfig,
+
+    /// The interrupt flags that have been set in the current cycle (and weren't set
+    /// already). With `Quirk::DelayedIrq`, these don't assert IRQ until the next cycle.
+    new_flags: u8,
+
+    /// The cycle passed to the last call to `clock`.
+    cycle: u64,
+
+    /// The model-dependent behaviors that software has touched, one for each quirk at most.
+    warnings: Vec<ModelWarning>,
+}
+
+impl Ic6526 {
+    /// Creates a new 6526 in the state it has after a reset: all registers are 0 (making
+    /// all port pins inputs), both timers and the TOD clock are stopped, and no interrupts
+    /// are enabled. The chip is a 6526A.
+    pub fn new() -> Ic6526 {
+        Ic6526::with_valid_config(CiaConfig::new(CiaModel::Mos6526A))
+    }
+
+    /// Creates a new chip like `new` does, but with the given model and quirks. This fails
+    /// if the configuration doesn't describe a chip that could exist.
+    pub fn with_config(config: CiaConfig) -> Result<Ic6526, CiaConfigError> {
+        config.validate()?;
+        Ok(Ic6526::with_valid_config(config))
+    }
+
+    /// Creates a new chip with a configuration that's already been validated.
+    fn with_valid_config(config: CiaConfig) -> Ic6526 {
+        Ic6526 {
+            pra: 0,
+            prb: 0,
+            ddra: 0,
+            ddrb: 0,
+            pa_in: 0xff,
+            pb_in: 0xff,
+            timer_a: Timer::default(),
+            timer_b: Timer::default(),
+            icr_flags: 0,
+            icr_mask: 0,
+            tod: Tod::new(),
+            serial: SerialPort::default(),
+            cnt: true,
+            sp: true,
+            config,
+            new_flags: 0,
+            cycle: 0,
+            warnings: vec![],
+        }
+    }
+
+    /// Puts the chip back in the state it has after a reset. Its model and quirks don't
+    /// change, and neither do the model warnings that have been recorded.
+    pub fn reset(&mut self) {
+        let warnings = std::mem::take(&mut self.warnings);
+        *self = Ic6526::with_valid_config(self.config.clone());
+        self.warnings = warnings;
+    }
+
+    /// Returns the model of CIA being emulated.
+    pub fn model(&self) -> CiaModel {
+        self.config.model
+    }
+
+    /// Returns the quirks that the chip has.
+    pub fn quirks(&self) -> &[Quirk] {
+        self.config.quirks()
+    }
+
+    /// Returns a warning for each model-dependent behavior that software has touched, in
+    /// the order they were first touched.
+    pub fn model_warnings(&self) -> &[ModelWarning] {
+        &self.warnings
+    }
+
+    /// Determines whether the chip is asserting its IRQ output, which happens while any
+    /// interrupt flag is set whose mask bit is also set.
+    pub fn irq_asserted(&self) -> bool {
+        let flags = if self.has(Quirk::DelayedIrq) {
+            self.icr_flags & !self.new_flags
+        } else {
+            self.icr_flags
+        };
+        flags & self.icr_mask != 0
+    }
+
+    /// Determines whether the chip has a quirk.
+    fn has(&self, quirk: Quirk) -> bool {
+        self.quirks().contains(&quirk)
+    }
+
+    /// Records that software has touched the behavior controlled by a quirk, if it hasn't
+    /// already.
+    fn warn(&mut self, quirk: Quirk) {
+        if self.warnings.iter().all(|w| w.quirk != quirk) {
+            self.warnings.push(ModelWarning {
+                quirk,
+                active: self.has(quirk),
+                cycle: self.cycle,
+            });
+        }
+    }
+
+    /// Sets interrupt flags.
+    fn raise(&mut self, flags: u8) {
+        self.new_flags |= flags & !self.icr_flags;
+        self.icr_flags |= flags;
+    }
+
+    /// Sets the level of the CNT input. A timer that counts CNT transitions counts once for
+    /// each low-to-high transition, as does the serial port in input mode.
+    pub fn set_cnt(&mut self, level: bool) {
+        let rising = level && !self.cnt;
+        self.cnt = level;
+
+        if rising {
+            if self.timer_a.control() & CRA_SPMODE == 0 && self.serial.shift_in(self.sp) {
+                self.raise(ICR_SP);
+            }
+
+            let a_underflow = self.timer_a.control() & CRA_INMODE != 0 && self.timer_a.count();
+            if a_underflow {
+                self.raise(ICR_TA);
+            }
+            if self.timer_b.control() & CRB_INMODE == 0x20 && self.timer_b.count() {
+                self.raise(ICR_TB);
+            }
+            if a_underflow {
+                self.after_a_underflow();
+            }
+        }
+    }
+
+    /// Sets the level of the SP input, which is shifted in by the serial port in input mode.
+    pub fn set_sp(&mut self, level: bool) {
+        self.sp = level;
+    }
+
+    /// Returns the level the serial port drives on SP. This is high unless the port is in
+    /// output mode and shifting out a 0 bit.
+    pub fn sp_out(&self) -> bool {
+        self.serial.sp()
+    }
+
+    /// Returns the level the serial port drives on CNT. This is high unless the port is in
+    /// output mode and in the first half of a bit.
+    pub fn cnt_out(&self) -> bool {
+        self.serial.cnt()
+    }
+
+    /// Sets the levels of port A's pins. Only the bits that are inputs are used.
+    pub fn set_port_a(&mut self, value: u8) {
+        self.pa_in = value;
+    }
+
+    /// Sets the levels of port B's pins. Only the bits that are inputs are used.
+    pub fn set_port_b(&mut self, value: u8) {
+        self.pb_in = value;
+    }
+
+    /// Returns the levels the chip drives on port A. Bits that are inputs are 1, since the
+    /// chip pulls them up.
+    pub fn port_a(&self) -> u8 {
+        self.pra | !self.ddra
+    }
+
+    /// Returns the levels the chip drives on port B. Bits that are inputs are 1, since the
+    /// chip pulls them up.
+    pub fn port_b(&self) -> u8 {
+        self.prb | !self.ddrb
+    }
+
+    /// Signals a negative transition on the FLAG input, which sets its interrupt flag.
+    pub fn flag(&mut self) {
+        self.raise(ICR_FLAG);
+    }
+
+    /// Handles one tick of the time-of-day clock's 50Hz or 60Hz input.
+    pub fn tod_tick(&mut self) {
+        if self.tod.tick(self.timer_a.control() & CRA_TODIN != 0) {
+            self.raise(ICR_ALARM);
+        }
+    }
+
+    /// Handles the effects of an underflow of timer A on the rest of the chip: timer B
+    /// counts it if it's set to, and the serial port shifts if it's in output mode.
+    fn after_a_underflow(&mut self) {
+        if self.timer_a.control() & CRA_SPMODE != 0 && self.serial.underflow() {
+            self.raise(ICR_SP);
+        }
+
+        let counts = match self.timer_b.control() & CRB_INMODE {
+            0x40 => true,
+            0x60 => self.cnt,
+            _ => false,
+        };
+        if counts && self.timer_b.count() {
+            self.raise(ICR_TB);
+        }
+    }
+
+    /// Reads ICR, returning the interrupt flags (with bit 7 set if IRQ is asserted) and
+    /// clearing them.
+    fn read_icr(&mut self) -> u8 {
+        if self.new_flags & self.icr_mask != 0 {
+            self.warn(Quirk::DelayedIrq);
+        }
+        if self.new_flags & ICR_TB != 0 {
+            self.warn(Quirk::TimerBBug);
+        }
+
+        let mut value = self.icr_flags | if self.irq_asserted() { ICR_IR } else { 0 };
+        if self.has(Quirk::TimerBBug) {
+            value &= !(self.new_flags & ICR_TB);
+        }
+        self.icr_flags = 0;
+        self.new_flags = 0;
+        value
+    }
+
+    /// Writes ICR. If bit 7 of the value is set, each other 1 bit sets the corresponding
+    /// mask bit; if bit 7 is clear, each other 1 bit clears the corresponding mask bit. 0
+    /// bits leave their mask bits alone.
+    fn write_icr(&mut self, value: u8) {
+        if value & ICR_IR != 0 {
+            self.icr_mask |= value & !ICR_IR;
+        } else {
+            self.icr_mask &= !value;
+        }
+    }
+}
+
+impl Default for Ic6526 {
+    fn default() -> Self {
+        Ic6526::new()
+    }
+}
+
+impl Addressable for Ic6526 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr & 0xf {
+            PRA => (self.pra & self.ddra) | (self.pa_in & !self.ddra),
+            PRB => (self.prb & self.ddrb) | (self.pb_in & !self.ddrb),
+            DDRA => self.ddra,
+            DDRB => self.ddrb,
+            TALO => self.timer_a.counter() as u8,
+            TAHI => (self.timer_a.counter() >> 8) as u8,
+            TBLO => self.timer_b.counter() as u8,
+            TBHI => (self.timer_b.counter() >> 8) as u8,
+            ICR => self.read_icr(),
+            CRA => self.timer_a.control(),
+            CRB => self.timer_b.control(),
+            TOD10TH | TODSEC | TODMIN | TODHR => self.tod.read(((addr & 0xf) - TOD10TH) as usize),
+            SDR => self.serial.read(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr & 0xf {
+            PRA => self.pra = value,
+            PRB => self.prb = value,
+            DDRA => self.ddra = value,
+            DDRB => self.ddrb = value,
+            TALO => self.timer_a.write_lo(value),
+            TAHI => self.timer_a.write_hi(value),
+            TBLO => self.timer_b.write_lo(value),
+            TBHI => self.timer_b.write_hi(value),
+            ICR => self.write_icr(value),
+            CRA => {
+                if (self.timer_a.control() ^ value) & CRA_SPMODE != 0 {
+                    self.serial.reset();
+                }
+                self.timer_a.write_control(value);
+            }
+            CRB => self.timer_b.write_control(value),
+            TOD10TH | TODSEC | TODMIN | TODHR => {
+                let reg = ((addr & 0xf) - TOD10TH) as usize;
+                if self.timer_b.control() & CRB_ALARM != 0 {
+                    self.tod.write_alarm(reg, value);
+                } else if self.tod.write_time(reg, value) {
+                    self.raise(ICR_ALARM);
+                }
+            }
+            SDR => self
+                .serial
+                .write(value, self.timer_a.control() & CRA_SPMODE != 0),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Clocked for Ic6526 {
+    fn clock(&mut self, cycle: u64) {
+        self.cycle = cycle;
+        self.new_flags = 0;
+
+        let a_underflow = self.timer_a.control() & CRA_INMODE == 0 && self.timer_a.count();
+        if a_underflow {
+            self.raise(ICR_TA);
+        }
+        if self.timer_b.control() & CRB_INMODE == 0 && self.timer_b.count() {
+            self.raise(ICR_TB);
+        }
+        if a_underflow {
+            self.after_a_underflow();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::testing::{check_addressable, AddressableSpec};
+
+    fn clock_n(cia: &mut Ic6526, n: usize) {
+        for _ in 0..n {
+            cia.clock(0);
+        }
+    }
+
+    fn set_timer_a(cia: &mut Ic6526, value: u16) {
+        cia.write(TALO, value as u8);
+        cia.write(TAHI, (value >> 8) as u8);
+    }
+
+    fn set_timer_b(cia: &mut Ic6526, value: u16) {
+        cia.write(TBLO, value as u8);
+        cia.write(TBHI, (value >> 8) as u8);
+    }
+
+    fn timer_a(cia: &mut Ic6526) -> u16 {
+        cia.read(TALO) as u16 | (cia.read(TAHI) as u16) << 8
+    }
+
+    #[test]
+    fn reset_state() {
+        let mut cia = Ic6526::new();
+        for addr in 0..16 {
+            if addr != PRA && addr != PRB && addr != TODHR {
+                assert_eq!(cia.read(addr), 0, "Register ${:X} should be 0", addr);
+            }
+        }
+        assert_eq!(cia.read(TODHR), 0x01, "TOD should reset to 1 AM");
+        assert!(!cia.irq_asserted());
+    }
+
+    #[test]
+    fn ports_inputs_read_high() {
+        let mut cia = Ic6526::new();
+        cia.write(PRA, 0x00);
+        assert_eq!(cia.read(PRA), 0xff, "input bits should read high");
+
+        cia.write(DDRA, 0x0f);
+        assert_eq!(cia.read(PRA), 0xf0);
+        cia.write(PRA, 0x05);
+        assert_eq!(cia.read(PRA), 0xf5);
+    }
+
+    #[test]
+    fn address_mirroring() {
+        let mut cia = Ic6526::new();
+        cia.write(0xdc02, 0x5a);
+        assert_eq!(cia.read(DDRA), 0x5a);
+        assert_eq!(cia.read(0xdd12), 0x5a);
+    }
+
+    #[test]
+    fn write_hi_loads_stopped_timer() {
+        let mut cia = Ic6526::new();
+        cia.write(TALO, 0x34);
+        assert_eq!(
+            timer_a(&mut cia),
+            0,
+            "low byte alone should only set the latch"
+        );
+        cia.write(TAHI, 0x12);
+        assert_eq!(timer_a(&mut cia), 0x1234);
+    }
+
+    #[test]
+    fn one_shot_exact_cycle() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 5);
+        cia.write(ICR, ICR_IR | ICR_TA);
+        cia.write(CRA, CR_START | CR_RUNMODE);
+
+        // The counter goes 4, 3, 2, 1, 0 over the first five cycles
+        for expected in (0..5).rev() {
+            cia.clock(0);
+            assert_eq!(timer_a(&mut cia), expected);
+            assert!(!cia.irq_asserted(), "IRQ should not fire before underflow");
+        }
+
+        // ...and underflows on the sixth
+        cia.clock(0);
+        assert!(cia.irq_asserted(), "IRQ should fire on cycle 6");
+        assert_eq!(timer_a(&mut cia), 5, "counter should reload from the latch");
+        assert_eq!(cia.read(CRA) & CR_START, 0, "one-shot timer should stop");
+
+        cia.read(ICR);
+        clock_n(&mut cia, 20);
+        assert!(!cia.irq_asserted(), "stopped timer should not fire again");
+        assert_eq!(timer_a(&mut cia), 5);
+    }
+
+    #[test]
+    fn continuous_reload() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 9);
+        cia.write(CRA, CR_START);
+
+        let mut underflows = vec![];
+        for cycle in 1..=50 {
+            cia.clock(0);
+            if cia.read(ICR) & ICR_TA != 0 {
+                underflows.push(cycle);
+            }
+        }
+        assert_eq!(underflows, vec![10, 20, 30, 40, 50]);
+        assert_eq!(cia.read(CRA) & CR_START, CR_START);
+    }
+
+    #[test]
+    fn icr_read_clears() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 0);
+        cia.write(ICR, ICR_IR | ICR_TA);
+        cia.write(CRA, CR_START | CR_RUNMODE);
+        cia.clock(0);
+
+        assert!(cia.irq_asserted());
+        assert_eq!(cia.read(ICR), ICR_IR | ICR_TA);
+        assert!(!cia.irq_asserted(), "reading ICR should release IRQ");
+        assert_eq!(cia.read(ICR), 0, "reading ICR should clear the flags");
+    }
+
+    #[test]
+    fn icr_masked_flag() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 0);
+        cia.write(CRA, CR_START | CR_RUNMODE);
+        cia.clock(0);
+
+        assert!(!cia.irq_asserted(), "masked source should not assert IRQ");
+        assert_eq!(cia.read(ICR), ICR_TA, "flag should be set without bit 7");
+    }
+
+    #[test]
+    fn icr_mask_set_and_clear() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 0);
+        cia.write(ICR, ICR_IR | ICR_TA | ICR_TB);
+        cia.write(ICR, ICR_TB);
+        cia.write(CRA, CR_START | CR_RUNMODE);
+        cia.clock(0);
+        assert!(
+            cia.irq_asserted(),
+            "clearing TB's mask bit should leave TA's"
+        );
+
+        cia.read(ICR);
+        cia.write(ICR, ICR_TA);
+        cia.write(CRA, CR_START | CR_RUNMODE);
+        cia.clock(0);
+        assert!(!cia.irq_asserted());
+    }
+
+    #[test]
+    fn force_load() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 100);
+        cia.write(CRA, CR_START);
+        clock_n(&mut cia, 30);
+        assert_eq!(timer_a(&mut cia), 70);
+
+        // Writing the latch of a running timer doesn't touch the counter...
+        set_timer_a(&mut cia, 1000);
+        assert_eq!(timer_a(&mut cia), 70);
+
+        // ...until it's forced
+        cia.write(CRA, CR_START | CR_LOAD);
+        assert_eq!(timer_a(&mut cia), 1000);
+        assert_eq!(cia.read(CRA) & CR_LOAD, 0, "LOAD is a strobe and reads 0");
+
+        cia.clock(0);
+        assert_eq!(timer_a(&mut cia), 999);
+    }
+
+    #[test]
+    fn one_shot_hi_write_starts() {
+        let mut cia = Ic6526::new();
+        cia.write(CRA, CR_RUNMODE);
+        set_timer_a(&mut cia, 3);
+        assert_eq!(cia.read(CRA) & CR_START, CR_START);
+        clock_n(&mut cia, 4);
+        assert_eq!(cia.read(ICR), ICR_TA);
+    }
+
+    #[test]
+    fn timer_b_counts_a_underflows() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 9);
+        set_timer_b(&mut cia, 2);
+        cia.write(ICR, ICR_IR | ICR_TB);
+        cia.write(CRB, CR_START | 0x40);
+        cia.write(CRA, CR_START);
+
+        // B underflows on A's third underflow, 30 cycles in
+        clock_n(&mut cia, 29);
+        assert!(!cia.irq_asserted());
+        cia.clock(0);
+        assert!(cia.irq_asserted());
+        assert_eq!(cia.read(ICR), ICR_IR | ICR_TA | ICR_TB);
+    }
+
+    #[test]
+    fn timer_b_counts_a_underflows_gated_by_cnt() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 0);
+        set_timer_b(&mut cia, 10);
+        cia.write(CRB, CR_START | 0x60);
+        cia.write(CRA, CR_START);
+
+        clock_n(&mut cia, 4);
+        assert_eq!(cia.read(TBLO), 6, "CNT high: B should count A's underflows");
+
+        cia.set_cnt(false);
+        clock_n(&mut cia, 4);
+        assert_eq!(cia.read(TBLO), 6, "CNT low: B should not count");
+    }
+
+    #[test]
+    fn timer_counts_cnt() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 2);
+        cia.write(CRA, CR_START | CRA_INMODE);
+
+        clock_n(&mut cia, 10);
+        assert_eq!(timer_a(&mut cia), 2, "timer should ignore φ2 in CNT mode");
+
+        for _ in 0..3 {
+            cia.set_cnt(false);
+            cia.set_cnt(true);
+        }
+        assert_eq!(cia.read(ICR), ICR_TA);
+        assert_eq!(timer_a(&mut cia), 2);
+    }
+
+    fn tod_ticks(cia: &mut Ic6526, n: usize) {
+        for _ in 0..n {
+            cia.tod_tick();
+        }
+    }
+
+    fn set_tod(cia: &mut Ic6526, hr: u8, min: u8, sec: u8, tenths: u8) {
+        cia.write(TODHR, hr);
+        cia.write(TODMIN, min);
+        cia.write(TODSEC, sec);
+        cia.write(TOD10TH, tenths);
+    }
+
+    #[test]
+    fn tod_latch_on_hours_read() {
+        let mut cia = Ic6526::new();
+        set_tod(&mut cia, 0x01, 0x59, 0x59, 0x9);
+
+        assert_eq!(cia.read(TODHR), 0x01);
+        // 60Hz, so this rolls the clock over to 2:00:00.0
+        tod_ticks(&mut cia, 6);
+        assert_eq!(cia.read(TODMIN), 0x59, "minutes should be latched");
+        assert_eq!(cia.read(TODSEC), 0x59, "seconds should be latched");
+        assert_eq!(cia.read(TODHR), 0x01, "hours should stay latched");
+        assert_eq!(cia.read(TOD10TH), 0x9, "tenths should be latched");
+
+        // Reading tenths released the latch; the clock kept running
+        assert_eq!(cia.read(TODMIN), 0x00);
+        assert_eq!(cia.read(TODHR), 0x02);
+        assert_eq!(cia.read(TOD10TH), 0x0);
+    }
+
+    #[test]
+    fn tod_50hz() {
+        let mut cia = Ic6526::new();
+        cia.write(CRA, CRA_TODIN);
+        set_tod(&mut cia, 0x01, 0, 0, 0);
+        tod_ticks(&mut cia, 50);
+        assert_eq!(cia.read(TODSEC), 0x01);
+    }
+
+    #[test]
+    fn tod_crb7_selects_alarm() {
+        let mut cia = Ic6526::new();
+        set_tod(&mut cia, 0x01, 0, 0, 0);
+
+        cia.write(CRB, CRB_ALARM);
+        cia.write(TODHR, 0x03);
+        cia.write(TODMIN, 0x15);
+        assert_eq!(cia.read(TODMIN), 0, "alarm writes should not set the clock");
+        assert_eq!(
+            cia.read(TODHR),
+            0x01,
+            "alarm writes should not set the clock"
+        );
+        cia.read(TOD10TH);
+
+        tod_ticks(&mut cia, 6);
+        assert_eq!(
+            cia.read(TOD10TH),
+            0x1,
+            "alarm writes should not stop the clock"
+        );
+
+        cia.write(CRB, 0);
+        cia.write(TODHR, 0x03);
+        assert_eq!(cia.read(TODHR), 0x03, "clock writes should set the clock");
+    }
+
+    #[test]
+    fn tod_alarm_irq() {
+        let mut cia = Ic6526::new();
+        cia.write(ICR, ICR_IR | ICR_ALARM);
+        cia.write(CRB, CRB_ALARM);
+        set_tod(&mut cia, 0x01, 0, 0x01, 0);
+        cia.write(CRB, 0);
+        set_tod(&mut cia, 0x01, 0, 0, 0x8);
+
+        tod_ticks(&mut cia, 6);
+        assert!(!cia.irq_asserted(), "alarm should not fire at 1:00:00.9");
+        tod_ticks(&mut cia, 6);
+        assert!(cia.irq_asserted(), "alarm should fire at 1:00:01.0");
+        assert_eq!(cia.read(ICR), ICR_IR | ICR_ALARM);
+    }
+
+    #[test]
+    fn sdr_input() {
+        let mut cia = Ic6526::new();
+        cia.write(ICR, ICR_IR | ICR_SP);
+
+        for (i, bit) in [1, 0, 1, 1, 0, 0, 1, 0].iter().enumerate() {
+            assert!(!cia.irq_asserted(), "no interrupt before bit {}", i + 1);
+            cia.set_sp(*bit == 1);
+            cia.set_cnt(false);
+            cia.set_cnt(true);
+        }
+        assert!(cia.irq_asserted(), "8 bits should raise an interrupt");
+        assert_eq!(cia.read(ICR), ICR_IR | ICR_SP);
+        assert_eq!(cia.read(SDR), 0b1011_0010);
+    }
+
+    #[test]
+    fn sdr_output() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 3);
+        cia.write(ICR, ICR_IR | ICR_SP);
+        cia.write(CRA, CR_START | CRA_SPMODE);
+        cia.write(SDR, 0b1100_1010);
+
+        // One bit takes two underflows of timer A, or eight cycles
+        let mut bits = vec![];
+        for _ in 0..8 {
+            clock_n(&mut cia, 4);
+            assert!(
+                !cia.cnt_out(),
+                "CNT should be low in the first half of a bit"
+            );
+            bits.push(cia.sp_out() as u8);
+            assert!(!cia.irq_asserted());
+            clock_n(&mut cia, 4);
+            assert!(
+                cia.cnt_out(),
+                "CNT should be high in the second half of a bit"
+            );
+        }
+        assert_eq!(bits, vec![1, 1, 0, 0, 1, 0, 1, 0]);
+        assert!(
+            cia.irq_asserted(),
+            "shifting out 8 bits should raise an interrupt"
+        );
+        cia.read(ICR);
+
+        clock_n(&mut cia, 64);
+        assert!(cia.cnt_out(), "CNT should idle high");
+        assert!(!cia.irq_asserted(), "nothing more should be shifted out");
+    }
+
+    #[test]
+    fn sdr_output_back_to_back() {
+        let mut cia = Ic6526::new();
+        set_timer_a(&mut cia, 0);
+        cia.write(CRA, CR_START | CRA_SPMODE);
+        cia.write(SDR, 0xff);
+        clock_n(&mut cia, 3);
+        cia.write(SDR, 0x00);
+
+        let mut underflows = vec![];
+        for cycle in 1..=40 {
+            cia.clock(0);
+            if cia.read(ICR) & ICR_SP != 0 {
+                underflows.push(cycle);
+            }
+        }
+        assert_eq!(
+            underflows,
+            vec![13, 29],
+            "the second byte should follow the first without a gap"
+        );
+    }
+
+    /// Runs an interrupt through timer A and reads ICR in the cycle it happens, which is
+    /// how software tells a 6526 from a 6526A. Returns the value read from ICR and whether
+    /// IRQ was asserted afterwards.
+    fn icr_probe(cia: &mut Ic6526) -> (u8, bool) {
+        set_timer_a(cia, 0);
+        cia.write(ICR, ICR_IR | ICR_TA);
+        cia.write(CRA, CR_START | CR_RUNMODE);
+        cia.clock(0);
+        let value = cia.read(ICR);
+        cia.clock(0);
+        (value, cia.irq_asserted())
+    }
+
+    fn with_model(model: CiaModel) -> Ic6526 {
+        Ic6526::with_config(CiaConfig::new(model)).unwrap()
+    }
+
+    #[test]
+    fn model_quirks() {
+        assert_eq!(Ic6526::new().model(), CiaModel::Mos6526A);
+        assert!(Ic6526::new().quirks().is_empty());
+        assert!(with_model(CiaModel::Mos8521).quirks().is_empty());
+        assert_eq!(
+            with_model(CiaModel::Mos6526).quirks(),
+            &[Quirk::DelayedIrq, Quirk::TimerBBug]
+        );
+    }
+
+    #[test]
+    fn model_changes_icr_probe() {
+        let mut cia = with_model(CiaModel::Mos6526A);
+        assert_eq!(icr_probe(&mut cia), (ICR_IR | ICR_TA, false));
+
+        let mut cia = with_model(CiaModel::Mos6526);
+        assert_eq!(
+            icr_probe(&mut cia),
+            (ICR_TA, false),
+            "6526 should not have asserted IRQ yet, and the read should lose the interrupt"
+        );
+    }
+
+    #[test]
+    fn delayed_irq() {
+        let mut cia = with_model(CiaModel::Mos6526);
+        set_timer_a(&mut cia, 0);
+        cia.write(ICR, ICR_IR | ICR_TA);
+        cia.write(CRA, CR_START | CR_RUNMODE);
+        cia.clock(0);
+        assert!(!cia.irq_asserted(), "IRQ should wait a cycle");
+        cia.clock(0);
+        assert!(cia.irq_asserted());
+        assert_eq!(cia.read(ICR), ICR_IR | ICR_TA);
+    }
+
+    #[test]
+    fn timer_b_bug() {
+        let mut cia = with_model(CiaModel::Mos6526);
+        set_timer_b(&mut cia, 0);
+        cia.write(CRB, CR_START | CR_RUNMODE);
+        cia.clock(0);
+        assert_eq!(cia.read(ICR), 0, "timer B's flag should be lost");
+
+        let mut cia = with_model(CiaModel::Mos6526A);
+        set_timer_b(&mut cia, 0);
+        cia.write(CRB, CR_START | CR_RUNMODE);
+        cia.clock(0);
+        assert_eq!(cia.read(ICR), ICR_TB);
+    }
+
+    #[test]
+    fn model_warning_once() {
+        for model in [CiaModel::Mos6526, CiaModel::Mos6526A] {
+            let mut cia = with_model(model);
+            assert!(cia.model_warnings().is_empty());
+
+            cia.clock(7);
+            icr_probe(&mut cia);
+            icr_probe(&mut cia);
+            assert_eq!(
+                cia.model_warnings(),
+                &[ModelWarning {
+                    quirk: Quirk::DelayedIrq,
+                    active: model == CiaModel::Mos6526,
+                    cycle: 0,
+                }],
+                "the warning should be recorded once for {:?}",
+                model
+            );
+        }
+    }
+
+    #[test]
+    fn no_warning_for_ordinary_reads() {
+        let mut cia = with_model(CiaModel::Mos6526);
+        set_timer_a(&mut cia, 0);
+        cia.write(ICR, ICR_IR | ICR_TA);
+        cia.write(CRA, CR_START);
+        for _ in 0..10 {
+            cia.clock(0);
+            cia.clock(0);
+            cia.read(ICR);
+        }
+        assert!(cia.model_warnings().is_empty());
+    }
+
+    #[test]
+    fn config_validation() {
+        let config = CiaConfig {
+            model: CiaModel::Mos6526A,
+            quirks: Some(vec![Quirk::TimerBBug]),
+        };
+        assert_eq!(
+            Ic6526::with_config(config).err(),
+            Some(CiaConfigError::Requires(
+                Quirk::TimerBBug,
+                Quirk::DelayedIrq
+            ))
+        );
+
+        let config = CiaConfig {
+            model: CiaModel::Mos6526A,
+            quirks: Some(vec![Quirk::DelayedIrq]),
+        };
+        let mut cia = Ic6526::with_config(config).unwrap();
+        assert_eq!(cia.quirks(), &[Quirk::DelayedIrq]);
+        assert_eq!(icr_probe(&mut cia), (ICR_TA, false));
+    }
+
+    #[test]
+    fn reset_keeps_model() {
+        let mut cia = with_model(CiaModel::Mos6526);
+        icr_probe(&mut cia);
+        cia.write(DDRA, 0xff);
+        cia.reset();
+        assert_eq!(cia.read(DDRA), 0);
+        assert_eq!(cia.model(), CiaModel::Mos6526);
+        assert_eq!(cia.model_warnings().len(), 1);
+    }
+
+    #[test]
+    fn addressable_contract() {
+        // Most registers don't read back what was written to them (the timers read their
+        // counters, ICR reads and clears the flags, and so on), but the DDRs and SDR do
+        let spec = AddressableSpec {
+            stored: vec![DDRA..=DDRB, SDR..=SDR],
+            mirror: Some(16),
+        };
+        check_addressable(|| Box::new(Ic6526::new()), spec);
+    }
+}
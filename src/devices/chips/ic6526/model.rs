@@ -0,0 +1,138 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::fmt::{self, Display, Formatter};
+
+/// The revisions of the CIA that were put into C64s (and C128s).
+///
+/// The original 6526 was used in early C64s. The 6526A replaced it in later C64s, and the
+/// 8521, which is the same chip made in HMOS, was used in the C64C and the C128. The later
+/// two behave identically, but they differ from the 6526 in when an interrupt reaches the
+/// IRQ output. See `Quirk` for the differences that are emulated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CiaModel {
+    /// The original 6526.
+    Mos6526,
+
+    /// The 6526A.
+    Mos6526A,
+
+    /// The 8521, which behaves like the 6526A.
+    Mos8521,
+}
+
+impl CiaModel {
+    /// Returns the quirks that this model has.
+    pub fn quirks(self) -> &'static [Quirk] {
+        match self {
+            CiaModel::Mos6526 => &[Quirk::DelayedIrq, Quirk::TimerBBug],
+            CiaModel::Mos6526A | CiaModel::Mos8521 => &[],
+        }
+    }
+}
+
+/// A behavior that differs between CIA models.
+///
+/// Software that depends on one of these (usually to find out which model it's running on)
+/// gets different results from different models, so the first time software does something
+/// whose result depends on a quirk, the chip records a `ModelWarning`. That happens whether
+/// or not the quirk is active, since the point is to know that the choice of model mattered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Quirk {
+    /// IRQ is asserted one cycle after the interrupt flag that causes it is set, rather
+    /// than in the same cycle. Reading ICR in the cycle in between returns the flag without
+    /// bit 7 and clears it, so the interrupt never happens at all.
+    DelayedIrq,
+
+    /// Reading ICR in the cycle that timer B underflows returns its flag as 0 (and clears
+    /// it anyway), so the underflow is lost. This comes from the same delay as
+    /// `DelayedIrq`, so a chip can't have it without also having `DelayedIrq`.
+    TimerBBug,
+}
+
+impl Display for Quirk {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Quirk::DelayedIrq => write!(f, "IRQ delayed by one cycle"),
+            Quirk::TimerBBug => write!(f, "timer B flag lost when ICR is read on underflow"),
+        }
+    }
+}
+
+/// The settings that a CIA is created with.
+///
+/// By default, a chip has the quirks of its model. `quirks` can replace those with any set
+/// of quirks, as long as it's one that a real chip could have.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CiaConfig {
+    /// The model of CIA to emulate.
+    pub model: CiaModel,
+
+    /// The quirks to emulate instead of the model's, if any.
+    pub quirks: Option<Vec<Quirk>>,
+}
+
+impl CiaConfig {
+    /// Creates a configuration for a model with its usual quirks.
+    pub fn new(model: CiaModel) -> CiaConfig {
+        CiaConfig {
+            model,
+            quirks: None,
+        }
+    }
+
+    /// Returns the quirks that a chip with this configuration has.
+    pub fn quirks(&self) -> &[Quirk] {
+        match &self.quirks {
+            Some(quirks) => quirks,
+            None => self.model.quirks(),
+        }
+    }
+
+    /// Checks that the configuration describes a chip that could exist, returning an error
+    /// if the quirks contradict each other.
+    pub fn validate(&self) -> Result<(), CiaConfigError> {
+        let quirks = self.quirks();
+        if quirks.contains(&Quirk::TimerBBug) && !quirks.contains(&Quirk::DelayedIrq) {
+            return Err(CiaConfigError::Requires(
+                Quirk::TimerBBug,
+                Quirk::DelayedIrq,
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// An error in a `CiaConfig`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CiaConfigError {
+    /// The first quirk can only happen on a chip that also has the second.
+    Requires(Quirk, Quirk),
+}
+
+impl Display for CiaConfigError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            CiaConfigError::Requires(quirk, required) => {
+                write!(f, "quirk \"{}\" requires quirk \"{}\"", quirk, required)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CiaConfigError {}
+
+/// A record of software doing something whose result depends on the CIA model.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ModelWarning {
+    /// The quirk that decided the result.
+    pub quirk: Quirk,
+
+    /// Whether the chip has that quirk, which tells which way the result went.
+    pub active: bool,
+
+    /// The cycle in which it happened, as passed to the chip's last `clock`.
+    pub cycle: u64,
+}
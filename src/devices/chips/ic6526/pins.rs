@@ -0,0 +1,719 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+pub mod constants {
+    /// Pin assignment for port A pin 0.
+    pub const PA0: usize = 2;
+    /// Pin assignment for port A pin 1.
+    pub const PA1: usize = 3;
+    /// Pin assignment for port A pin 2.
+    pub const PA2: usize = 4;
+    /// Pin assignment for port A pin 3.
+    pub const PA3: usize = 5;
+    /// Pin assignment for port A pin 4.
+    pub const PA4: usize = 6;
+    /// Pin assignment for port A pin 5.
+    pub const PA5: usize = 7;
+    /// Pin assignment for port A pin 6.
+    pub const PA6: usize = 8;
+    /// Pin assignment for port A pin 7.
+    pub const PA7: usize = 9;
+
+    /// Pin assignment for port B pin 0.
+    pub const PB0: usize = 10;
+    /// Pin assignment for port B pin 1.
+    pub const PB1: usize = 11;
+    /// Pin assignment for port B pin 2.
+    pub const PB2: usize = 12;
+    /// Pin assignment for port B pin 3.
+    pub const PB3: usize = 13;
+    /// Pin assignment for port B pin 4.
+    pub const PB4: usize = 14;
+    /// Pin assignment for port B pin 5.
+    pub const PB5: usize = 15;
+    /// Pin assignment for port B pin 6.
+    pub const PB6: usize = 16;
+    /// Pin assignment for port B pin 7.
+    pub const PB7: usize = 17;
+
+    /// Pin assignment for data pin 0.
+    pub const D0: usize = 33;
+    /// Pin assignment for data pin 1.
+    pub const D1: usize = 32;
+    /// Pin assignment for data pin 2.
+    pub const D2: usize = 31;
+    /// Pin assignment for data pin 3.
+    pub const D3: usize = 30;
+    /// Pin assignment for data pin 4.
+    pub const D4: usize = 29;
+    /// Pin assignment for data pin 5.
+    pub const D5: usize = 28;
+    /// Pin assignment for data pin 6.
+    pub const D6: usize = 27;
+    /// Pin assignment for data pin 7.
+    pub const D7: usize = 26;
+
+    /// Pin assignment for register select pin 0.
+    pub const RS0: usize = 38;
+    /// Pin assignment for register select pin 1.
+    pub const RS1: usize = 37;
+    /// Pin assignment for register select pin 2.
+    pub const RS2: usize = 36;
+    /// Pin assignment for register select pin 3.
+    pub const RS3: usize = 35;
+
+    /// Pin assignment for the handshaking output pin.
+    pub const PC: usize = 18;
+    /// Pin assignment for the time-of-day clock input pin.
+    pub const TOD: usize = 19;
+    /// Pin assignment for the interrupt request output pin.
+    pub const IRQ: usize = 21;
+    /// Pin assignment for the read/write pin.
+    pub const R_W: usize = 22;
+    /// Pin assignment for the chip select pin.
+    pub const CS: usize = 23;
+    /// Pin assignment for the handshaking input pin.
+    pub const FLAG: usize = 24;
+    /// Pin assignment for the φ2 clock input pin.
+    pub const PHI2: usize = 25;
+    /// Pin assignment for the reset pin.
+    pub const RES: usize = 34;
+    /// Pin assignment for the serial port pin.
+    pub const SP: usize = 39;
+    /// Pin assignment for the count pin.
+    pub const CNT: usize = 40;
+
+    /// Pin assignment for the +5V power supply pin.
+    pub const VCC: usize = 20;
+    /// Pin assignment for the ground pin.
+    pub const VSS: usize = 1;
+}
+
+use crate::{
+    components::{
+        addressable::Addressable,
+        clock::Clocked,
+        device::{next_id, Device, DeviceRef, LevelChange},
+        pin::{
+            Mode::{Input, Output, Unconnected},
+            Pin,
+        },
+        port::Port,
+    },
+    vectors::RefVec,
+};
+
+use self::constants::*;
+use super::{
+    constants::{CRA, CRA_SPMODE, DDRA, DDRB, PRB},
+    Ic6526,
+};
+
+const PA_PORT_A: [usize; 8] = [PA0, PA1, PA2, PA3, PA4, PA5, PA6, PA7];
+const PA_PORT_B: [usize; 8] = [PB0, PB1, PB2, PB3, PB4, PB5, PB6, PB7];
+const PA_DATA: [usize; 8] = [D0, D1, D2, D3, D4, D5, D6, D7];
+const PA_REGISTER: [usize; 4] = [RS0, RS1, RS2, RS3];
+
+/// A pin-level emulation of the 6526 Complex Interface Adapter.
+///
+/// This wraps the register-level `Ic6526` with the chip's 40 pins so that it can be wired
+/// into a board with traces like the other chips. All of the chip's actual behavior comes
+/// from `Ic6526`; this device only translates pin levels into calls to it and its state
+/// back into pin levels. See `Ic6526` for a description of the registers and what they do.
+///
+/// A register access happens while CS is low and φ2 is high. If R/W is high, the register
+/// selected by RS0-RS3 is read as soon as that's true and driven onto D0-D7 until it stops
+/// being true. (It's only read once per access, which matters for registers like ICR that
+/// change when they're read.) If R/W is low, the value on D0-D7 is written to the selected
+/// register when φ2 falls. The falling edge of φ2 also ends each clock cycle of the chip,
+/// so the CIA's timers count φ2 cycles without needing a `Scheduler`.
+///
+/// Each pin of ports A and B is an output or an input depending on its bit in the data
+/// direction register. Input pins are read when the port's register is read; pins that
+/// aren't connected to anything read high because of the chip's internal pull-ups. The PC
+/// output goes low for one cycle after every read or write of port B, which lets another
+/// device know that the port has been used.
+///
+/// The IRQ output is open-drain: it pulls its trace low while an interrupt is asserted and
+/// floats otherwise, so that it can share the C64's IRQ line with other chips (the trace
+/// needs to be pulled up). A falling edge on FLAG, a rising edge on TOD, and changes to CNT
+/// and SP are passed on to the register-level chip, and SP and CNT become outputs while
+/// the serial port is in output mode. A low level on RES resets the chip.
+///
+/// The chip comes in a 40-pin dual in-line package with the following pin assignments.
+/// ```text
+///         +-----+--+-----+
+///     Vss |1    +--+   40| CNT
+///     PA0 |2           39| SP
+///     PA1 |3           38| RS0
+///     PA2 |4           37| RS1
+///     PA3 |5           36| RS2
+///     PA4 |6           35| RS3
+///     PA5 |7           34| RES
+///     PA6 |8           33| D0
+///     PA7 |9           32| D1
+///     PB0 |10   6526   31| D2
+///     PB1 |11          30| D3
+///     PB2 |12          29| D4
+///     PB3 |13          28| D5
+///     PB4 |14          27| D6
+///     PB5 |15          26| D7
+///     PB6 |16          25| φ2
+///     PB7 |17          24| FLAG
+///      PC |18          23| CS
+///     TOD |19          22| R/W
+///     Vcc |20          21| IRQ
+///         +--------------+
+/// ```
+/// These pin assignments are explained below.
+///
+/// | Pin | Name  | Description                                                            |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 1   | Vss   | Electrical ground. Not emulated.                                       |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 2   | PA0   | Port A pins. Each is an input or an output depending on its bit in     |
+/// | 3   | PA1   | DDRA.                                                                  |
+/// | 4   | PA2   |                                                                        |
+/// | 5   | PA3   |                                                                        |
+/// | 6   | PA4   |                                                                        |
+/// | 7   | PA5   |                                                                        |
+/// | 8   | PA6   |                                                                        |
+/// | 9   | PA7   |                                                                        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 10  | PB0   | Port B pins. Each is an input or an output depending on its bit in     |
+/// | 11  | PB1   | DDRB.                                                                  |
+/// | 12  | PB2   |                                                                        |
+/// | 13  | PB3   |                                                                        |
+/// | 14  | PB4   |                                                                        |
+/// | 15  | PB5   |                                                                        |
+/// | 16  | PB6   |                                                                        |
+/// | 17  | PB7   |                                                                        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 18  | PC    | Handshaking output. Goes low for one cycle after a port B access.      |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 19  | TOD   | Time-of-day clock input, 50Hz or 60Hz. Counted on rising edges.        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 20  | Vcc   | +5V power supply. Not emulated.                                        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 21  | IRQ   | Active-low, open-drain interrupt request output.                       |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 22  | R/W   | Read/write. High for a register read, low for a register write.        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 23  | CS    | Active-low chip select. Registers can only be accessed while it's low. |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 24  | FLAG  | Active-low handshaking input. A falling edge sets ICR bit 4.           |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 25  | φ2    | System clock. Accesses happen while it's high, and each falling edge   |
+/// |     |       | ends one of the chip's clock cycles.                                   |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 26  | D7    | Data pins. Register values are read from and written to these pins.    |
+/// | 27  | D6    |                                                                        |
+/// | 28  | D5    |                                                                        |
+/// | 29  | D4    |                                                                        |
+/// | 30  | D3    |                                                                        |
+/// | 31  | D2    |                                                                        |
+/// | 32  | D1    |                                                                        |
+/// | 33  | D0    |                                                                        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 34  | RES   | Active-low reset.                                                      |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 35  | RS3   | Register select pins. These select which of the 16 registers is read   |
+/// | 36  | RS2   | or written.                                                            |
+/// | 37  | RS1   |                                                                        |
+/// | 38  | RS0   |                                                                        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 39  | SP    | Serial port data. An input or output depending on bit 6 of CRA.        |
+/// | --- | ----- | ---------------------------------------------------------------------- |
+/// | 40  | CNT   | Serial port clock and timer count input. An output while the serial    |
+/// |     |       | port is in output mode.                                                |
+///
+/// In the Commodore 64, U1 and U2 are 6526s. Their CS pins are driven by the 74139 I/O
+/// decoder, and their RS pins are connected to A0-A3.
+pub struct Ic6526Pins {
+    /// The unique identifier of this device.
+    id: usize,
+
+    /// The pins of the 6526, along with a dummy pin (at index 0) to ensure that the vector
+    /// index of the others matches the 1-based pin assignments.
+    pins: RefVec<Pin>,
+
+    /// The PA0-PA7 pins from the `pins` vector, as a port.
+    port_a: Port,
+
+    /// The PB0-PB7 pins from the `pins` vector, as a port.
+    port_b: Port,
+
+    /// The D0-D7 pins from the `pins` vector, as a port.
+    data: Port,
+
+    /// The RS0-RS3 pins from the `pins` vector, as a port.
+    register: Port,
+
+    /// The register-level chip that does the actual work.
+    core: Ic6526,
+
+    /// Whether a register is being read. This keeps a register from being read (and
+    /// possibly changed by the read) more than once per access.
+    reading: bool,
+
+    /// Whether port B has been read or written in the current cycle.
+    pb_accessed: bool,
+}
+
+impl Ic6526Pins {
+    /// Creates a new pin-level 6526 emulation and returns a shared, internally mutable
+    /// reference to it.
+    pub fn new() -> DeviceRef {
+        // Port pins. These all start as inputs, since a reset clears both data direction
+        // registers.
+        let pa0 = pin!(PA0, "PA0", Input);
+        let pa1 = pin!(PA1, "PA1", Input);
+        let pa2 = pin!(PA2, "PA2", Input);
+        let pa3 = pin!(PA3, "PA3", Input);
+        let pa4 = pin!(PA4, "PA4", Input);
+        let pa5 = pin!(PA5, "PA5", Input);
+        let pa6 = pin!(PA6, "PA6", Input);
+        let pa7 = pin!(PA7, "PA7", Input);
+        let pb0 = pin!(PB0, "PB0", Input);
+        let pb1 = pin!(PB1, "PB1", Input);
+        let pb2 = pin!(PB2, "PB2", Input);
+        let pb3 = pin!(PB3, "PB3", Input);
+        let pb4 = pin!(PB4, "PB4", Input);
+        let pb5 = pin!(PB5, "PB5", Input);
+        let pb6 = pin!(PB6, "PB6", Input);
+        let pb7 = pin!(PB7, "PB7", Input);
+
+        // Data pins. These are outputs only while a register is being read.
+        let d0 = pin!(D0, "D0", Input);
+        let d1 = pin!(D1, "D1", Input);
+        let d2 = pin!(D2, "D2", Input);
+        let d3 = pin!(D3, "D3", Input);
+        let d4 = pin!(D4, "D4", Input);
+        let d5 = pin!(D5, "D5", Input);
+        let d6 = pin!(D6, "D6", Input);
+        let d7 = pin!(D7, "D7", Input);
+
+        // Register select pins.
+        let rs0 = pin!(RS0, "RS0", Input);
+        let rs1 = pin!(RS1, "RS1", Input);
+        let rs2 = pin!(RS2, "RS2", Input);
+        let rs3 = pin!(RS3, "RS3", Input);
+
+        // Bus control pins.
+        let phi2 = pin!(PHI2, "φ2", Input);
+        let cs = pin!(CS, "CS", Input);
+        let r_w = pin!(R_W, "R_W", Input);
+        let res = pin!(RES, "RES", Input);
+
+        // Handshaking, interrupt, and clock pins. IRQ is open-drain so that it can be
+        // shared with other interrupt sources.
+        let pc = pin!(PC, "PC", Output);
+        let flag = pin!(FLAG, "FLAG", Input);
+        let irq = pin!(IRQ, "IRQ", Output);
+        let tod = pin!(TOD, "TOD", Input);
+
+        // Serial port pins. These are inputs unless the serial port is in output mode.
+        let sp = pin!(SP, "SP", Input);
+        let cnt = pin!(CNT, "CNT", Input);
+
+        // Power supply and ground pins, not emulated
+        let vcc = pin!(VCC, "VCC", Unconnected);
+        let vss = pin!(VSS, "VSS", Unconnected);
+
+        open_collector!(irq);
+        set!(pc, irq);
+
+        let pins = pins![
+            pa0, pa1, pa2, pa3, pa4, pa5, pa6, pa7, pb0, pb1, pb2, pb3, pb4, pb5, pb6, pb7, d0, d1,
+            d2, d3, d4, d5, d6, d7, rs0, rs1, rs2, rs3, phi2, cs, r_w, res, pc, flag, irq, tod, sp,
+            cnt, vcc, vss
+        ];
+        let port_a = Port::from_pins(&pins, &PA_PORT_A);
+        let port_b = Port::from_pins(&pins, &PA_PORT_B);
+        let data = Port::from_pins(&pins, &PA_DATA);
+        let register = Port::from_pins(&pins, &PA_REGISTER);
+
+        let device: DeviceRef = new_ref!(Ic6526Pins {
+            id: next_id(),
+            pins,
+            port_a,
+            port_b,
+            data,
+            register,
+            core: Ic6526::new(),
+            reading: false,
+            pb_accessed: false,
+        });
+
+        attach_to!(device, phi2, cs, r_w, res, flag, tod, sp, cnt);
+
+        device
+    }
+
+    /// Starts or ends a register read, depending on the levels of φ2, CS, and R/W. These
+    /// are passed in because one of them may be the pin whose level change is being
+    /// handled.
+    fn bus(&mut self, phi2: bool, cs: bool, r_w: bool) {
+        if phi2 && !cs && r_w {
+            if !self.reading {
+                self.reading = true;
+                let addr = self.register.value() as u16;
+                self.core.set_port_a(read_port(&self.port_a));
+                self.core.set_port_b(read_port(&self.port_b));
+                let value = self.core.read(addr);
+                self.pb_accessed |= addr == PRB;
+                self.data.set_mode(Output);
+                self.data.write(value as usize);
+            }
+        } else if self.reading {
+            self.reading = false;
+            self.data.set_mode(Input);
+        }
+    }
+
+    /// Ends a clock cycle on the falling edge of φ2. A pending register write is done,
+    /// the handshaking output is updated, and the register-level chip is clocked.
+    fn end_cycle(&mut self) {
+        if !high!(self.pins[CS]) && !high!(self.pins[R_W]) {
+            let addr = self.register.value() as u16;
+            self.core.write(addr, self.data.value() as u8);
+            self.pb_accessed |= addr == PRB;
+        }
+        self.bus(false, true, true);
+
+        if self.pb_accessed {
+            clear!(self.pins[PC]);
+        } else {
+            set!(self.pins[PC]);
+        }
+        self.pb_accessed = false;
+
+        self.core.clock(0);
+        self.update_ports();
+        self.update_serial();
+        self.update_irq();
+    }
+
+    /// Sets each port pin to be an input or output according to its data direction
+    /// register, and sets the levels of the outputs.
+    fn update_ports(&mut self) {
+        let ports = [
+            (&self.port_a, self.core.read(DDRA), self.core.port_a()),
+            (&self.port_b, self.core.read(DDRB), self.core.port_b()),
+        ];
+        for (port, ddr, value) in ports {
+            for (bit, pin) in port.pins().iter().enumerate() {
+                if ddr & (1 << bit) == 0 {
+                    set_mode!(pin, Input);
+                } else {
+                    set_mode!(pin, Output);
+                    if value & (1 << bit) == 0 {
+                        clear!(pin);
+                    } else {
+                        set!(pin);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Makes SP and CNT outputs with the serial port's levels if it's in output mode, or
+    /// inputs if it's not.
+    fn update_serial(&mut self) {
+        if self.core.read(CRA) & CRA_SPMODE == 0 {
+            set_mode!(self.pins[SP], Input);
+            set_mode!(self.pins[CNT], Input);
+        } else {
+            set_mode!(self.pins[SP], Output);
+            set_mode!(self.pins[CNT], Output);
+            let levels = [(SP, self.core.sp_out()), (CNT, self.core.cnt_out())];
+            for (pin, level) in levels {
+                if level {
+                    set!(self.pins[pin]);
+                } else {
+                    clear!(self.pins[pin]);
+                }
+            }
+        }
+    }
+
+    /// Pulls IRQ low if the chip is asserting an interrupt, or releases it if not.
+    fn update_irq(&mut self) {
+        if self.core.irq_asserted() {
+            clear!(self.pins[IRQ]);
+        } else {
+            set!(self.pins[IRQ]);
+        }
+    }
+}
+
+/// Reads the levels of a port's pins. Floating pins read high because of the port's
+/// internal pull-ups.
+fn read_port(port: &Port) -> u8 {
+    port.pins()
+        .iter()
+        .enumerate()
+        .fold(0, |value, (bit, pin)| value | ((!low!(pin) as u8) << bit))
+}
+
+impl Device for Ic6526Pins {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn update(&mut self, event: &LevelChange) {
+        macro_rules! value_in {
+            ($pin:expr, $target:expr) => {
+                if number!($pin) == $target {
+                    high!($pin)
+                } else {
+                    high!(self.pins[$target])
+                }
+            };
+        }
+
+        match event {
+            LevelChange(pin, _) if number!(pin) == RES && !high!(pin) => {
+                self.core.reset();
+                self.pb_accessed = false;
+                self.bus(false, true, true);
+                set!(self.pins[PC]);
+                self.update_ports();
+                self.update_serial();
+                self.update_irq();
+            }
+            LevelChange(pin, _) if number!(pin) == PHI2 && !high!(pin) => self.end_cycle(),
+            LevelChange(pin, _) if [PHI2, CS, R_W].contains(&number!(pin)) => {
+                let phi2 = value_in!(pin, PHI2);
+                let cs = value_in!(pin, CS);
+                let r_w = value_in!(pin, R_W);
+                self.bus(phi2, cs, r_w);
+                // Reading ICR can release IRQ
+                self.update_irq();
+            }
+            LevelChange(pin, _) if number!(pin) == FLAG && !high!(pin) => {
+                self.core.flag();
+                self.update_irq();
+            }
+            LevelChange(pin, _) if number!(pin) == TOD && high!(pin) => {
+                self.core.tod_tick();
+                self.update_irq();
+            }
+            LevelChange(pin, _) if number!(pin) == CNT => {
+                self.core.set_cnt(high!(pin));
+                self.update_irq();
+            }
+            LevelChange(pin, _) if number!(pin) == SP => self.core.set_sp(high!(pin)),
+            _ => {}
+        }
+    }
+
+    fn debug_fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}, {:?}", self.reading, self.pb_accessed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        components::trace::Trace,
+        devices::chips::ic6526::constants::{
+            CR_START, ICR, ICR_FLAG, ICR_IR, ICR_TA, PRA, TAHI, TALO,
+        },
+        test_utils::{make_traces, traces_to_value, value_to_traces},
+    };
+
+    use super::*;
+
+    fn before_each() -> (DeviceRef, RefVec<Trace>, RefVec<Trace>, RefVec<Trace>) {
+        let device = Ic6526Pins::new();
+        let tr = make_traces(&device);
+
+        set!(tr[CS], tr[R_W], tr[RES], tr[FLAG]);
+        clear!(tr[PHI2]);
+        pull_up!(tr[IRQ]);
+
+        let data_tr = tr.map_indices(&PA_DATA);
+        let rs_tr = tr.map_indices(&PA_REGISTER);
+
+        (device, tr, data_tr, rs_tr)
+    }
+
+    /// Runs one φ2 cycle without accessing the chip.
+    fn cycle(tr: &RefVec<Trace>) {
+        set!(tr[PHI2]);
+        clear!(tr[PHI2]);
+    }
+
+    /// Writes a register over the pins, taking one φ2 cycle.
+    fn write(
+        tr: &RefVec<Trace>,
+        data_tr: &RefVec<Trace>,
+        rs_tr: &RefVec<Trace>,
+        reg: u16,
+        value: u8,
+    ) {
+        value_to_traces(reg as usize, rs_tr);
+        value_to_traces(value as usize, data_tr);
+        clear!(tr[R_W]);
+        clear!(tr[CS]);
+        cycle(tr);
+        set!(tr[CS]);
+        set!(tr[R_W]);
+        for t in data_tr.iter() {
+            float!(t);
+        }
+    }
+
+    /// Reads a register over the pins, taking one φ2 cycle.
+    fn read(tr: &RefVec<Trace>, data_tr: &RefVec<Trace>, rs_tr: &RefVec<Trace>, reg: u16) -> u8 {
+        value_to_traces(reg as usize, rs_tr);
+        clear!(tr[CS]);
+        set!(tr[PHI2]);
+        let value = traces_to_value(data_tr) as u8;
+        clear!(tr[PHI2]);
+        set!(tr[CS]);
+        value
+    }
+
+    #[test]
+    fn register_write_and_read() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+
+        write(&tr, &data_tr, &rs_tr, DDRA, 0x5a);
+        assert!(
+            data_tr.iter().all(|t| floating!(t)),
+            "data pins should not be driven after a write"
+        );
+        assert_eq!(read(&tr, &data_tr, &rs_tr, DDRA), 0x5a);
+        assert!(
+            data_tr.iter().all(|t| floating!(t)),
+            "data pins should be released after a read"
+        );
+    }
+
+    #[test]
+    fn unselected_ignores_bus() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+
+        value_to_traces(DDRA as usize, &rs_tr);
+        value_to_traces(0xff, &data_tr);
+        clear!(tr[R_W]);
+        cycle(&tr);
+        set!(tr[R_W]);
+        for t in data_tr.iter() {
+            float!(t);
+        }
+
+        assert_eq!(read(&tr, &data_tr, &rs_tr, DDRA), 0x00);
+    }
+
+    #[test]
+    fn port_a_follows_register() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+        let pa_tr = tr.map_indices(&PA_PORT_A);
+
+        write(&tr, &data_tr, &rs_tr, PRA, 0xa5);
+        assert!(
+            pa_tr.iter().all(|t| floating!(t)),
+            "port pins should not be driven while they're inputs"
+        );
+
+        write(&tr, &data_tr, &rs_tr, DDRA, 0x0f);
+        assert_eq!(traces_to_value(&pa_tr) & 0x0f, 0x05);
+        assert!(
+            pa_tr.iter().skip(4).all(|t| floating!(t)),
+            "input pins should not be driven"
+        );
+
+        write(&tr, &data_tr, &rs_tr, PRA, 0x3c);
+        assert_eq!(traces_to_value(&pa_tr) & 0x0f, 0x0c);
+    }
+
+    #[test]
+    fn port_inputs_pulled_up() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+        assert_eq!(
+            read(&tr, &data_tr, &rs_tr, PRA),
+            0xff,
+            "unconnected inputs should read high"
+        );
+
+        clear!(tr[PA1], tr[PA6]);
+        assert_eq!(read(&tr, &data_tr, &rs_tr, PRA), 0xbd);
+    }
+
+    #[test]
+    fn pc_handshake() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+
+        read(&tr, &data_tr, &rs_tr, PRA);
+        assert!(high!(tr[PC]), "port A access should not pulse PC");
+
+        read(&tr, &data_tr, &rs_tr, PRB);
+        assert!(low!(tr[PC]), "PC should go low after a port B read");
+        cycle(&tr);
+        assert!(high!(tr[PC]), "PC should go high after one cycle");
+
+        write(&tr, &data_tr, &rs_tr, PRB, 0);
+        assert!(low!(tr[PC]), "PC should go low after a port B write");
+        cycle(&tr);
+        assert!(high!(tr[PC]));
+    }
+
+    #[test]
+    fn flag_sets_icr() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+        write(&tr, &data_tr, &rs_tr, ICR, ICR_IR | ICR_FLAG);
+        assert!(high!(tr[IRQ]));
+
+        clear!(tr[FLAG]);
+        assert!(low!(tr[IRQ]), "FLAG falling edge should assert IRQ");
+        set!(tr[FLAG]);
+        assert!(low!(tr[IRQ]), "FLAG rising edge should not release IRQ");
+
+        assert_eq!(read(&tr, &data_tr, &rs_tr, ICR), ICR_IR | ICR_FLAG);
+        assert!(high!(tr[IRQ]), "reading ICR should release IRQ");
+        assert_eq!(read(&tr, &data_tr, &rs_tr, ICR), 0);
+    }
+
+    #[test]
+    fn timer_counts_phi2() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+
+        write(&tr, &data_tr, &rs_tr, TALO, 10);
+        write(&tr, &data_tr, &rs_tr, TAHI, 0);
+        write(&tr, &data_tr, &rs_tr, ICR, ICR_IR | ICR_TA);
+        write(&tr, &data_tr, &rs_tr, CRA, CR_START);
+
+        // The cycle that wrote CRA was the first count
+        for _ in 0..9 {
+            cycle(&tr);
+        }
+        assert!(high!(tr[IRQ]));
+        cycle(&tr);
+        assert!(low!(tr[IRQ]), "timer A should underflow on the 11th count");
+    }
+
+    #[test]
+    fn reset() {
+        let (_, tr, data_tr, rs_tr) = before_each();
+        write(&tr, &data_tr, &rs_tr, DDRA, 0xff);
+        assert!(!floating!(tr[PA0]));
+
+        clear!(tr[RES]);
+        set!(tr[RES]);
+        assert!(floating!(tr[PA0]), "reset should make port pins inputs");
+        assert_eq!(read(&tr, &data_tr, &rs_tr, DDRA), 0);
+    }
+}
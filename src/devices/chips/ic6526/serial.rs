@@ -0,0 +1,145 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+/// The 6526's serial port: the serial data register (SDR) and the shift register behind
+/// it.
+///
+/// Data is shifted MSB first, one bit per CNT cycle, over the SP pin. In input mode, a bit
+/// is shifted in from SP on each rising edge of CNT, which is driven by the device on the
+/// other end. After 8 bits the shift register is copied into SDR, where it can be read.
+///
+/// In output mode, the chip drives both SP and CNT, using timer A as the baud rate
+/// generator. Every underflow of timer A toggles CNT, so that each bit takes two underflows:
+/// the bit is put on SP as CNT goes low, and it's shifted out as CNT goes high again.
+/// Writing SDR loads the value into the shift register if it's idle, and the next
+/// underflows start shifting it out. If SDR is written again while a byte is still
+/// shifting, the new value waits until that byte is done and then follows it without a
+/// break.
+///
+/// In both modes, the chip calls `shift_in` or `underflow` for each bit or half-bit, which
+/// report when a byte has been completed so that the chip can raise its interrupt.
+#[derive(Clone, Debug)]
+pub struct SerialPort {
+    /// The serial data register. In input mode this holds the last byte that was received;
+    /// in output mode, the last byte that was written.
+    data: u8,
+
+    /// The shift register.
+    shift: u8,
+
+    /// The number of bits of the current byte that have been shifted.
+    bits: u8,
+
+    /// Whether a byte written to SDR is waiting for the shift register (output mode only).
+    pending: bool,
+
+    /// Whether a byte is being shifted out (output mode only).
+    active: bool,
+
+    /// The level being driven on SP (output mode only).
+    sp: bool,
+
+    /// The level being driven on CNT (output mode only).
+    cnt: bool,
+}
+
+impl SerialPort {
+    /// Returns the value of SDR.
+    pub fn read(&self) -> u8 {
+        self.data
+    }
+
+    /// Writes SDR. In output mode, this also queues the value to be shifted out.
+    pub fn write(&mut self, value: u8, output: bool) {
+        self.data = value;
+        if output {
+            if self.active {
+                self.pending = true;
+            } else {
+                self.shift = value;
+                self.bits = 0;
+                self.active = true;
+            }
+        }
+    }
+
+    /// Returns the level the port drives on SP. Except while shifting out, this is high
+    /// (which is also what SP reads as when nothing drives it).
+    pub fn sp(&self) -> bool {
+        self.sp
+    }
+
+    /// Returns the level the port drives on CNT. Except while shifting out, this is high.
+    pub fn cnt(&self) -> bool {
+        self.cnt
+    }
+
+    /// Abandons any byte being shifted in or out and releases SP and CNT. This happens when
+    /// the port changes between input and output modes.
+    pub fn reset(&mut self) {
+        self.shift = 0;
+        self.bits = 0;
+        self.pending = false;
+        self.active = false;
+        self.sp = true;
+        self.cnt = true;
+    }
+
+    /// Shifts in one bit from SP (input mode). Returns `true` if the bit completed a byte,
+    /// which is then in SDR.
+    pub fn shift_in(&mut self, sp: bool) -> bool {
+        self.shift = self.shift << 1 | sp as u8;
+        self.bits += 1;
+        if self.bits == 8 {
+            self.bits = 0;
+            self.data = self.shift;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Handles an underflow of timer A (output mode), toggling CNT and shifting out a bit
+    /// every second call. Returns `true` if the underflow completed a byte.
+    pub fn underflow(&mut self) -> bool {
+        if !self.active {
+            return false;
+        }
+
+        self.cnt = !self.cnt;
+        if !self.cnt {
+            self.sp = self.shift & 0x80 != 0;
+            self.shift <<= 1;
+            return false;
+        }
+
+        self.bits += 1;
+        if self.bits < 8 {
+            return false;
+        }
+        self.bits = 0;
+        if self.pending {
+            self.shift = self.data;
+            self.pending = false;
+        } else {
+            self.active = false;
+        }
+        true
+    }
+}
+
+impl Default for SerialPort {
+    fn default() -> Self {
+        SerialPort {
+            data: 0,
+            shift: 0,
+            bits: 0,
+            pending: false,
+            active: false,
+            sp: true,
+            cnt: true,
+        }
+    }
+}
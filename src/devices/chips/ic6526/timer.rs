@@ -0,0 +1,206 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use super::constants::{CR_LOAD, CR_RUNMODE, CR_START};
+
+/// One of the 6526's two 16-bit interval timers.
+///
+/// Each timer has a latch, which holds the value written to its two registers, and a
+/// counter, which counts down from the latch value. When the counter has reached 0, the
+/// next count underflows it instead: the latch is reloaded into the counter and the timer
+/// reports the underflow. A timer therefore underflows once every latch + 1 counts. In
+/// one-shot mode the timer also stops itself when it underflows.
+///
+/// The latch and the counter are entirely separate. Writes to the timer's registers only
+/// ever change the latch, and reads only ever return the counter. The counter is loaded
+/// from the latch at exactly three points:
+///
+/// 1. on the count after the counter reaches 0 (the underflow);
+/// 2. when the LOAD bit is written to the control register; and
+/// 3. when the high byte is written while the timer is stopped (or in one-shot mode).
+///
+/// This means that changing the latch of a running timer has no effect on the period in
+/// progress; it completes with the old value, and the new value is used from the next
+/// underflow on. Software that reprograms timers on the fly (sample players and PWM
+/// routines, for example) depends on this.
+///
+/// What a timer counts (clock cycles, CNT transitions, or the other timer's underflows) is
+/// decided by the chip, which calls `count` for each of them.
+#[derive(Clone, Debug, Default)]
+pub struct Timer {
+    /// The value that's loaded into the counter on underflow or force-load.
+    latch: u16,
+
+    /// The current value of the counter.
+    counter: u16,
+
+    /// The control register. The meanings of bits 0-4 are common to both timers and are
+    /// handled here; the rest are handled by the chip.
+    control: u8,
+}
+
+impl Timer {
+    /// Returns the current value of the counter.
+    pub fn counter(&self) -> u16 {
+        self.counter
+    }
+
+    /// Returns the value of the control register.
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+
+    /// Determines whether the timer is running.
+    pub fn running(&self) -> bool {
+        self.control & CR_START != 0
+    }
+
+    /// Determines whether the timer is in one-shot mode.
+    pub fn one_shot(&self) -> bool {
+        self.control & CR_RUNMODE != 0
+    }
+
+    /// Writes the low byte of the latch.
+    pub fn write_lo(&mut self, value: u8) {
+        self.latch = (self.latch & 0xff00) | value as u16;
+    }
+
+    /// Writes the high byte of the latch. If the timer is stopped, the new latch value is
+    /// also loaded into the counter. In one-shot mode, the latch is loaded and the timer is
+    /// started whether it was running or not.
+    pub fn write_hi(&mut self, value: u8) {
+        self.latch = (self.latch & 0x00ff) | (value as u16) << 8;
+        if self.one_shot() {
+            self.counter = self.latch;
+            self.control |= CR_START;
+        } else if !self.running() {
+            self.counter = self.latch;
+        }
+    }
+
+    /// Writes the control register. If the LOAD bit is set, the latch is loaded into the
+    /// counter immediately, though the bit itself isn't stored.
+    pub fn write_control(&mut self, value: u8) {
+        if value & CR_LOAD != 0 {
+            self.counter = self.latch;
+        }
+        self.control = value & !CR_LOAD;
+    }
+
+    /// Counts the timer down once, if it's running. Returns `true` if the count caused the
+    /// timer to underflow.
+    pub fn count(&mut self) -> bool {
+        if !self.running() {
+            return false;
+        }
+        if self.counter == 0 {
+            self.counter = self.latch;
+            if self.one_shot() {
+                self.control &= !CR_START;
+            }
+            true
+        } else {
+            self.counter -= 1;
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Creates a running continuous timer with the given latch value.
+    fn running(latch: u16) -> Timer {
+        let mut timer = Timer::default();
+        timer.write_lo(latch as u8);
+        timer.write_hi((latch >> 8) as u8);
+        timer.write_control(CR_START);
+        timer
+    }
+
+    /// Counts a timer `n` times and returns the counts (numbered from 1) on which it
+    /// underflowed.
+    fn underflows(timer: &mut Timer, n: usize) -> Vec<usize> {
+        (1..=n).filter(|_| timer.count()).collect()
+    }
+
+    #[test]
+    fn counter_sequence() {
+        let mut timer = running(3);
+        let mut seen = vec![timer.counter()];
+        for _ in 0..8 {
+            timer.count();
+            seen.push(timer.counter());
+        }
+        assert_eq!(seen, vec![3, 2, 1, 0, 3, 2, 1, 0, 3]);
+    }
+
+    #[test]
+    fn latch_change_mid_count() {
+        let mut timer = running(9);
+        assert_eq!(underflows(&mut timer, 4), vec![]);
+        assert_eq!(timer.counter(), 5);
+
+        // The period in progress finishes with the old latch value...
+        timer.write_lo(4);
+        timer.write_hi(0);
+        assert_eq!(
+            timer.counter(),
+            5,
+            "latch write should not touch the counter"
+        );
+        assert_eq!(underflows(&mut timer, 6), vec![6]);
+
+        // ...and the next ones use the new one
+        assert_eq!(timer.counter(), 4);
+        assert_eq!(underflows(&mut timer, 15), vec![5, 10, 15]);
+    }
+
+    #[test]
+    fn latch_change_at_zero() {
+        // A latch written while the counter sits at 0 is the one that gets reloaded
+        let mut timer = running(9);
+        underflows(&mut timer, 9);
+        assert_eq!(timer.counter(), 0);
+
+        timer.write_lo(2);
+        timer.write_hi(0);
+        assert_eq!(underflows(&mut timer, 1), vec![1]);
+        assert_eq!(timer.counter(), 2);
+        assert_eq!(underflows(&mut timer, 6), vec![3, 6]);
+    }
+
+    #[test]
+    fn force_load_mid_count() {
+        let mut timer = running(9);
+        underflows(&mut timer, 4);
+
+        timer.write_lo(20);
+        timer.write_hi(0);
+        timer.write_control(CR_START | CR_LOAD);
+        assert_eq!(timer.counter(), 20, "force load should reload immediately");
+        assert_eq!(timer.control(), CR_START);
+
+        // The period restarts from the forced value
+        assert_eq!(underflows(&mut timer, 42), vec![21, 42]);
+    }
+
+    #[test]
+    fn force_load_stopped() {
+        let mut timer = running(9);
+        underflows(&mut timer, 4);
+        timer.write_control(0);
+
+        timer.write_control(CR_LOAD);
+        assert_eq!(timer.counter(), 9);
+        assert_eq!(
+            underflows(&mut timer, 20),
+            vec![],
+            "stopped timer should not count"
+        );
+        assert_eq!(timer.counter(), 9);
+    }
+}
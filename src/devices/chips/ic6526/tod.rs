@@ -0,0 +1,298 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::utils::bcd::bcd_add;
+
+/// Bit of the hours register that is set for PM and clear for AM.
+const PM: u8 = 0x80;
+
+/// The bits of each register (tenths, seconds, minutes, hours) that actually exist. The
+/// others always read 0.
+const MASKS: [u8; 4] = [0x0f, 0x7f, 0x7f, 0x9f];
+
+/// The 6526's time-of-day clock.
+///
+/// The clock keeps 12-hour time in four BCD registers: tenths of seconds, seconds, minutes,
+/// and hours, with bit 7 of the hours register set for PM. It's driven by the power line
+/// frequency rather than by φ2, so it counts tenths of seconds by dividing a 50Hz or 60Hz
+/// input by 5 or 6. Hours run from 1 to 12, and the AM/PM bit flips when the hour goes from
+/// 11 to 12, not from 12 to 1.
+///
+/// Because the registers have to be read one at a time while the clock keeps running,
+/// reading the hours register latches all four. Reads then come from the latch until the
+/// tenths register is read, which releases it. The clock itself keeps counting the whole
+/// time. Writing works the other way: writing the hours register stops the clock, and
+/// writing the tenths register starts it again, so that a new time can be set without it
+/// changing part-way through.
+///
+/// The same registers also set an alarm time (the chip decides which when they're
+/// written). The alarm can't be read back. Every time the clock changes, it's compared to
+/// the alarm, and `tick` and `write_time` report a match so that the chip can raise its
+/// interrupt.
+#[derive(Clone, Debug)]
+pub struct Tod {
+    /// The current time, as tenths, seconds, minutes, and hours.
+    time: [u8; 4],
+
+    /// The alarm time, in the same format as `time`.
+    alarm: [u8; 4],
+
+    /// The time as it was when the hours register was read, if it hasn't been released by
+    /// a read of the tenths register since.
+    latch: Option<[u8; 4]>,
+
+    /// Whether the clock is counting. It's stopped by writes to the hours register and
+    /// started by writes to the tenths register.
+    running: bool,
+
+    /// The number of input ticks since the tenths register last advanced.
+    ticks: u8,
+}
+
+impl Tod {
+    /// Creates a new time-of-day clock in its reset state: stopped at 1:00:00.0 AM, with
+    /// the alarm at 0:00:00.0 AM (which the clock can never reach unless it's set there).
+    pub fn new() -> Tod {
+        Tod {
+            time: [0, 0, 0, 0x01],
+            alarm: [0; 4],
+            latch: None,
+            running: false,
+            ticks: 0,
+        }
+    }
+
+    /// Reads one of the clock's registers (0 for tenths through 3 for hours). Reading the
+    /// hours latches the time, and reading the tenths releases the latch.
+    pub fn read(&mut self, reg: usize) -> u8 {
+        if reg == 3 && self.latch.is_none() {
+            self.latch = Some(self.time);
+        }
+        let value = self.latch.unwrap_or(self.time)[reg];
+        if reg == 0 {
+            self.latch = None;
+        }
+        value
+    }
+
+    /// Writes one of the clock's time registers (0 for tenths through 3 for hours).
+    /// Writing the hours stops the clock and writing the tenths starts it. Returns `true`
+    /// if the new time matches the alarm.
+    ///
+    /// As on the real chip, writing 12 to the hours flips the AM/PM bit that's written
+    /// along with it. Software that sets the clock to 12 PM has to write $12 (12 AM) to get
+    /// it.
+    pub fn write_time(&mut self, reg: usize, value: u8) -> bool {
+        let mut value = value & MASKS[reg];
+        match reg {
+            0 => self.running = true,
+            3 => {
+                self.running = false;
+                if value & !PM == 0x12 {
+                    value ^= PM;
+                }
+            }
+            _ => {}
+        }
+        self.time[reg] = value;
+        self.time == self.alarm
+    }
+
+    /// Writes one of the clock's alarm registers (0 for tenths through 3 for hours). Unlike
+    /// time writes, these don't stop or start the clock or flip the AM/PM bit.
+    pub fn write_alarm(&mut self, reg: usize, value: u8) {
+        self.alarm[reg] = value & MASKS[reg];
+    }
+
+    /// Handles one tick of the power line input, which is 50Hz if `fifty` is true or 60Hz
+    /// if it's false. Returns `true` if the tick advanced the time to the alarm time.
+    pub fn tick(&mut self, fifty: bool) -> bool {
+        if !self.running {
+            return false;
+        }
+        self.ticks += 1;
+        if self.ticks < if fifty { 5 } else { 6 } {
+            return false;
+        }
+        self.ticks = 0;
+        self.advance();
+        self.time == self.alarm
+    }
+
+    /// Advances the time by a tenth of a second. Each register counts up in BCD and carries
+    /// into the next when it rolls over. Registers holding invalid BCD (which can be
+    /// written) count up from their invalid values until they wrap around.
+    fn advance(&mut self) {
+        let [tenths, seconds, minutes, hours] = &mut self.time;
+
+        *tenths = (*tenths + 1) & 0x0f;
+        if *tenths != 0x0a {
+            return;
+        }
+        *tenths = 0;
+
+        if !increment(seconds, 0x60) || !increment(minutes, 0x60) {
+            return;
+        }
+
+        let hour = *hours & !PM;
+        *hours = match hour {
+            0x11 => 0x12 | ((*hours & PM) ^ PM),
+            0x12 => 0x01 | (*hours & PM),
+            _ => (bcd_add(hour, 1, false).value & 0x1f) | (*hours & PM),
+        };
+    }
+}
+
+impl Default for Tod {
+    fn default() -> Self {
+        Tod::new()
+    }
+}
+
+/// Adds 1 to a 7-bit BCD register, wrapping it to 0 when it reaches `limit`. Returns `true`
+/// if it wrapped.
+fn increment(value: &mut u8, limit: u8) -> bool {
+    *value = bcd_add(*value, 1, false).value & 0x7f;
+    if *value == limit {
+        *value = 0;
+        true
+    } else {
+        false
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Creates a running clock set to the given hours, minutes, seconds, and tenths.
+    fn at(hours: u8, minutes: u8, seconds: u8, tenths: u8) -> Tod {
+        let mut tod = Tod::new();
+        tod.write_time(3, hours);
+        tod.write_time(2, minutes);
+        tod.write_time(1, seconds);
+        tod.write_time(0, tenths);
+        tod
+    }
+
+    /// Advances a clock by a tenth of a second at 60Hz.
+    fn tenth(tod: &mut Tod) {
+        for _ in 0..6 {
+            tod.tick(false);
+        }
+    }
+
+    #[test]
+    fn counts_bcd() {
+        let mut tod = at(0x01, 0x00, 0x08, 0x9);
+        tenth(&mut tod);
+        assert_eq!(tod.time, [0x0, 0x09, 0x00, 0x01]);
+        for _ in 0..10 {
+            tenth(&mut tod);
+        }
+        assert_eq!(tod.time, [0x0, 0x10, 0x00, 0x01], "seconds should be BCD");
+    }
+
+    #[test]
+    fn rolls_into_pm_at_twelve() {
+        let mut tod = at(0x11, 0x59, 0x59, 0x9);
+        tenth(&mut tod);
+        assert_eq!(tod.time, [0, 0, 0, PM | 0x12], "11 AM should roll to 12 PM");
+    }
+
+    #[test]
+    fn rolls_from_twelve_to_one() {
+        let mut tod = at(0x12, 0x59, 0x59, 0x9);
+        assert_eq!(tod.time[3], PM | 0x12, "writing $12 should flip to PM");
+        tenth(&mut tod);
+        assert_eq!(tod.time, [0, 0, 0, PM | 0x01], "12 PM should roll to 1 PM");
+    }
+
+    #[test]
+    fn rolls_into_am_at_twelve() {
+        let mut tod = at(PM | 0x11, 0x59, 0x59, 0x9);
+        tenth(&mut tod);
+        assert_eq!(tod.time, [0, 0, 0, 0x12], "11 PM should roll to 12 AM");
+    }
+
+    #[test]
+    fn write_twelve_flips_pm() {
+        let mut tod = Tod::new();
+        tod.write_time(3, 0x12);
+        assert_eq!(tod.time[3], PM | 0x12);
+        tod.write_time(3, PM | 0x12);
+        assert_eq!(tod.time[3], 0x12);
+        tod.write_time(3, PM | 0x11);
+        assert_eq!(tod.time[3], PM | 0x11, "other hours should not flip");
+
+        tod.write_alarm(3, 0x12);
+        assert_eq!(tod.alarm[3], 0x12, "alarm writes should not flip");
+    }
+
+    #[test]
+    fn tick_rates() {
+        let mut tod = at(0x01, 0, 0, 0);
+        for _ in 0..5 {
+            tod.tick(true);
+        }
+        assert_eq!(tod.time[0], 1, "50Hz should advance every 5 ticks");
+
+        let mut tod = at(0x01, 0, 0, 0);
+        for _ in 0..5 {
+            tod.tick(false);
+        }
+        assert_eq!(tod.time[0], 0, "60Hz should not advance after 5 ticks");
+        tod.tick(false);
+        assert_eq!(tod.time[0], 1, "60Hz should advance every 6 ticks");
+    }
+
+    #[test]
+    fn hours_write_stops_tenths_write_starts() {
+        let mut tod = at(0x01, 0, 0, 0);
+        tod.write_time(3, 0x02);
+        for _ in 0..60 {
+            tod.tick(false);
+        }
+        assert_eq!(
+            tod.time,
+            [0, 0, 0, 0x02],
+            "clock should stop on hours write"
+        );
+
+        tod.write_time(0, 0);
+        tenth(&mut tod);
+        assert_eq!(
+            tod.time,
+            [1, 0, 0, 0x02],
+            "clock should start on tenths write"
+        );
+    }
+
+    #[test]
+    fn reset_stopped() {
+        let mut tod = Tod::new();
+        for _ in 0..60 {
+            tod.tick(false);
+        }
+        assert_eq!(tod.time, [0, 0, 0, 0x01]);
+    }
+
+    #[test]
+    fn alarm_match() {
+        let mut tod = at(0x01, 0, 0, 0);
+        tod.write_alarm(0, 2);
+        tod.write_alarm(3, 0x01);
+
+        tenth(&mut tod);
+        let matches: Vec<bool> = (0..6).map(|_| tod.tick(false)).collect();
+        assert_eq!(
+            matches,
+            vec![false, false, false, false, false, true],
+            "reaching the alarm time should match"
+        );
+        assert!(tod.write_time(0, 2), "writing the alarm time should match");
+    }
+}
@@ -0,0 +1,957 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+pub mod constants {
+    /// Register address of sprite 0's X coordinate. Each sprite's X and Y coordinates
+    /// follow in pairs.
+    pub const M0X: u16 = 0x00;
+    /// Register address of bit 8 of each sprite's X coordinate.
+    pub const MSIGX: u16 = 0x10;
+    /// Register address of control register 1, which holds bit 8 of the raster counter.
+    pub const CR1: u16 = 0x11;
+    /// Register address of the low 8 bits of the raster counter.
+    pub const RASTER: u16 = 0x12;
+    /// Register address of the light pen X coordinate.
+    pub const LPX: u16 = 0x13;
+    /// Register address of the light pen Y coordinate.
+    pub const LPY: u16 = 0x14;
+    /// Register address of the sprite enable register.
+    pub const ME: u16 = 0x15;
+    /// Register address of control register 2.
+    pub const CR2: u16 = 0x16;
+    /// Register address of the sprite Y expansion register.
+    pub const MYE: u16 = 0x17;
+    /// Register address of the memory pointers.
+    pub const MEMPTR: u16 = 0x18;
+    /// Register address of the interrupt register, which holds the interrupt latch.
+    pub const IRR: u16 = 0x19;
+    /// Register address of the interrupt enable register.
+    pub const IMR: u16 = 0x1a;
+    /// Register address of the sprite-sprite collision register.
+    pub const MMC: u16 = 0x1e;
+    /// Register address of the sprite-data collision register.
+    pub const MBC: u16 = 0x1f;
+    /// Register address of the border color, the first of the color registers.
+    pub const EC: u16 = 0x20;
+    /// Register address of background color 0. Background colors 1-3 follow it.
+    pub const B0C: u16 = 0x21;
+    /// Register address of the color of sprite 0. The other sprites' colors follow it.
+    pub const M0C: u16 = 0x27;
+    /// Register address of the color of sprite 7, the last of the color registers.
+    pub const M7C: u16 = 0x2e;
+
+    /// Control register 1 bit that holds bit 8 of the raster counter (on read) or of the
+    /// raster compare value (on write).
+    pub const CR1_RST8: u8 = 0x80;
+    /// Control register 1 bit that enables (1) or blanks (0) the display.
+    pub const CR1_DEN: u8 = 0x10;
+    /// Control register 1 bits that hold the vertical fine scroll.
+    pub const CR1_YSCROLL: u8 = 0x07;
+
+    /// Interrupt bit for the raster counter reaching the raster compare value.
+    pub const IR_RST: u8 = 0x01;
+    /// Interrupt bit for a negative transition on the light pen input.
+    pub const IR_LP: u8 = 0x08;
+    /// Interrupt register bit that is set on read if any enabled interrupt has occurred.
+    pub const IR_IRQ: u8 = 0x80;
+}
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::{
+    components::{addressable::Addressable, clock::Clocked},
+    utils::write_registers,
+};
+
+use self::constants::*;
+
+/// The number of registers that the chip has. Addresses from here up to $3F read $FF.
+const REGISTERS: usize = 0x2f;
+
+/// The first and last raster lines in which a badline can occur.
+const BADLINES: (u16, u16) = (0x30, 0xf7);
+
+/// The first and last cycles of a line (counting from 0) in which BA is low on a badline.
+/// BA goes low three cycles before the VIC takes the bus at cycle 14, so that a CPU write
+/// in progress can finish.
+const BA_CYCLES: (u16, u16) = (11, 53);
+
+/// The cycles (counting from 0) in which sprite DMA can be turned on.
+const SPRITE_DMA_CYCLES: (u16, u16) = (54, 55);
+
+/// The cycle in which sprite display is turned on for sprites whose DMA is on.
+const SPRITE_DISPLAY_CYCLE: u16 = 57;
+
+/// The cycles in which the sprite data counters advance by 2 and then by 1.
+const SPRITE_MCBASE_CYCLES: (u16, u16) = (14, 15);
+
+/// The value of a sprite's data counter after its last line of data has been read.
+const SPRITE_END: u8 = 63;
+
+/// The television standard that a VIC is made for, which decides the timing of its frames.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VideoStandard {
+    /// NTSC, as generated by the 6567 (R8): 65 cycles per line and 263 lines per frame.
+    Ntsc,
+
+    /// PAL, as generated by the 6569: 63 cycles per line and 312 lines per frame.
+    Pal,
+}
+
+impl VideoStandard {
+    /// Returns the number of φ2 cycles in each raster line.
+    pub fn cycles_per_line(self) -> u16 {
+        match self {
+            VideoStandard::Ntsc => 65,
+            VideoStandard::Pal => 63,
+        }
+    }
+
+    /// Returns the number of raster lines in each frame.
+    pub fn lines_per_frame(self) -> u16 {
+        match self {
+            VideoStandard::Ntsc => 263,
+            VideoStandard::Pal => 312,
+        }
+    }
+}
+
+/// A snapshot of a VIC's registers and raster position, for register viewers.
+///
+/// `registers` holds the value that a read of each register would return, except that
+/// taking the snapshot doesn't clear the collision registers the way a read does. The
+/// other fields are decoded from those registers or taken from the chip's internal state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VicState {
+    /// The registers at $00-$2E.
+    pub registers: [u8; REGISTERS],
+
+    /// The raster line being drawn.
+    pub raster: u16,
+
+    /// The cycle within the raster line, counting from 0.
+    pub cycle: u16,
+
+    /// The raster line at which the raster interrupt is raised.
+    pub raster_compare: u16,
+
+    /// The X coordinate of each sprite, including bit 8.
+    pub sprite_x: [u16; 8],
+
+    /// The Y coordinate of each sprite.
+    pub sprite_y: [u8; 8],
+
+    /// The sprite enable bits.
+    pub sprite_enable: u8,
+
+    /// The interrupt latch, without the IRQ bit.
+    pub irq_latch: u8,
+
+    /// The interrupt enable bits.
+    pub irq_enable: u8,
+
+    /// Whether the chip is asserting IRQ.
+    pub irq: bool,
+
+    /// The light pen coordinates.
+    pub light_pen: (u8, u8),
+
+    /// The border color.
+    pub border: u8,
+
+    /// Background colors 0-3.
+    pub background: [u8; 4],
+
+    /// The color of each sprite.
+    pub sprite_color: [u8; 8],
+}
+
+impl Display for VicState {
+    /// Writes the registers as a dump at their usual address of $D000, followed by the
+    /// raster position and interrupt state.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_registers(f, 0xd000, &self.registers)?;
+        writeln!(
+            f,
+            "Raster: ${:03X}  Cycle: {}  Compare: ${:03X}",
+            self.raster, self.cycle, self.raster_compare
+        )?;
+        write!(
+            f,
+            "IRQ: {}  Latch: ${:X}  Enable: ${:X}",
+            if self.irq { "asserted" } else { "clear" },
+            self.irq_latch,
+            self.irq_enable
+        )
+    }
+}
+
+/// An emulation of the 6567 Video Interface Chip (VIC-II), at the register level.
+///
+/// This is a first step toward a video chip. It has the VIC's 47 registers, accessed
+/// through `Addressable` (only the low 6 bits of the address are decoded, as on the real
+/// chip), and a raster counter that is advanced by `Clocked`, once per φ2 cycle, with the
+/// raster interrupt that goes with it. It doesn't fetch anything from memory or produce
+/// any video; the registers that control graphics and sprites simply hold whatever is
+/// written to them.
+///
+/// The chip is constructed for either NTSC or PAL timing. (The PAL chip is really the
+/// 6569, but the two are the same apart from their timing.)
+///
+/// | Address  | Description                                                             |
+/// | -------- | ----------------------------------------------------------------------- |
+/// | $00-$0F  | Sprite X and Y coordinates, in pairs.                                   |
+/// | $10      | Bit 8 of each sprite's X coordinate.                                    |
+/// | $11      | CR1. Bit 7 reads bit 8 of the raster counter and writes bit 8 of the    |
+/// |          | raster compare value.                                                   |
+/// | $12      | RASTER. Reads the raster counter; writes the raster compare value.      |
+/// | $13-$14  | LPX, LPY. Light pen coordinates (read only).                            |
+/// | $15      | Sprite enable.                                                          |
+/// | $16      | CR2. Bits 6-7 are unused.                                               |
+/// | $17      | Sprite Y expansion.                                                     |
+/// | $18      | MEMPTR. Bit 0 is unused.                                                |
+/// | $19      | IRR. Interrupt latch. Writing a 1 to a bit clears it. Bits 4-6 unused.  |
+/// | $1A      | IMR. Interrupt enable. Bits 4-7 are unused.                             |
+/// | $1B-$1D  | Sprite priority, multicolor, and X expansion.                           |
+/// | $1E-$1F  | Sprite-sprite and sprite-data collisions. Reading clears them.          |
+/// | $20-$2E  | Colors. Bits 4-7 are unused.                                            |
+/// | $2F-$3F  | Unused. Reads $FF.                                                      |
+///
+/// Unused bits always read 1.
+///
+/// ### Raster counter and interrupts
+///
+/// The raster counter counts lines from 0, advancing every 65 (NTSC) or 63 (PAL) cycles
+/// and going back to 0 after line 262 (NTSC) or 311 (PAL). Its current value can be read
+/// at any time from $12 and bit 7 of $11. When it reaches the raster compare value, the
+/// raster bit in the interrupt latch ($19) is set. Writing a compare value equal to the
+/// current line does the same, as it does on the real chip.
+///
+/// Each bit of the latch asserts IRQ (and sets bit 7 of $19) if its bit in $1A is set. The
+/// latch is acknowledged by writing 1s to the bits to clear, usually by writing back the
+/// value that was read. The sprite collision interrupts are never raised, since sprites
+/// aren't drawn.
+///
+/// ### Light pen
+///
+/// A negative transition on the light pen input, signaled by calling `light_pen`, latches
+/// the current beam position into LPX and LPY and raises the light pen interrupt. This only
+/// happens once per frame. The X coordinate is approximate: it's the number of cycles into
+/// the line times 4 (8 pixels per cycle, halved), which doesn't account for the offset
+/// between a line's first cycle and its first pixel.
+///
+/// ### Bus access
+///
+/// On a badline, the VIC needs the bus for 40 extra cycles to fetch character pointers,
+/// and pulls BA low to stop the CPU. That's approximated by `ba_low`, which reports BA as
+/// low for cycles 12 through 54 of every line in which a badline could occur: lines $30
+/// through $F7 whose low 3 bits match the vertical fine scroll in $11, while the display is
+/// enabled. (The real chip also requires the display to have been enabled in line $30 and
+/// varies the number of stolen cycles with the sprites that are being displayed.)
+///
+/// ### Sprites
+///
+/// Sprites aren't drawn, but the logic that decides when each one starts and stops being
+/// displayed is emulated, since programs that reuse sprites down the screen (multiplexers)
+/// depend on its timing. Each sprite has a DMA flip-flop, a display flip-flop, a Y
+/// expansion flip-flop, and a 6-bit data counter (MCBASE), which work on the same cycles as
+/// the real chip. Cycles here count from 0, so they're one less than in most documentation
+/// of the VIC, which counts from 1.
+///
+/// * In cycle 54, the expansion flip-flop of each sprite whose Y expansion bit is set is
+///   inverted. (It's always set while the Y expansion bit is clear.)
+/// * In cycles 54 and 55, each sprite that's enabled and whose Y coordinate matches the low
+///   8 bits of the raster counter has its DMA turned on, if it isn't already on. Its data
+///   counter is cleared and, if it's Y expanded, its expansion flip-flop is reset.
+/// * In cycle 57, each sprite whose DMA is on and whose Y coordinate matches has its
+///   display turned on. Its data is fetched during the rest of the line and the first
+///   line of it is shown on the next one.
+/// * In cycles 14 and 15, each sprite whose DMA is on and whose expansion flip-flop is set
+///   has its data counter advanced by 2 and then by 1. Once it reaches 63 (after 21 lines,
+///   or 42 when Y expanded), DMA and display are turned off.
+///
+/// Since the compare only happens in those two cycles, a sprite that's enabled or moved to
+/// the current line after cycle 55 (that is, with a register write in cycle 55 or later)
+/// misses it and doesn't start until the next time its Y coordinate matches. Turning DMA on
+/// only when it's off means that a sprite being displayed can't be restarted by moving it
+/// to the current line.
+///
+/// The NTSC chip uses the same cycles as the PAL chip for these; its two extra cycles are
+/// at the end of the line.
+pub struct Ic6567 {
+    /// The video standard, which sets the line length and frame height.
+    standard: VideoStandard,
+
+    /// The registers. Those whose values are kept elsewhere ($12-$14, $19, $1E, and $1F)
+    /// are unused here, and bit 7 of $11 is always 0.
+    regs: [u8; REGISTERS],
+
+    /// The raster line being drawn.
+    raster: u16,
+
+    /// The cycle within the current raster line, counting from 0.
+    cycle: u16,
+
+    /// The raster line at which the raster interrupt is raised.
+    compare: u16,
+
+    /// The interrupt latch.
+    latch: u8,
+
+    /// The light pen coordinates, as read from LPX and LPY.
+    lp: (u8, u8),
+
+    /// Whether the light pen has been triggered this frame.
+    lp_triggered: bool,
+
+    /// The sprite DMA flip-flops, a bit for each sprite.
+    sprite_dma: u8,
+
+    /// The sprite display flip-flops, a bit for each sprite.
+    sprite_display: u8,
+
+    /// The sprite Y expansion flip-flops, a bit for each sprite.
+    sprite_expand: u8,
+
+    /// The data counter (MCBASE) of each sprite.
+    mcbase: [u8; 8],
+}
+
+impl Ic6567 {
+    /// Creates a new VIC for the given video standard, with all registers clear and the
+    /// raster counter at the start of line 0.
+    pub fn new(standard: VideoStandard) -> Ic6567 {
+        Ic6567 {
+            standard,
+            regs: [0; REGISTERS],
+            raster: 0,
+            cycle: 0,
+            compare: 0,
+            latch: 0,
+            lp: (0, 0),
+            lp_triggered: false,
+            sprite_dma: 0,
+            sprite_display: 0,
+            sprite_expand: 0xff,
+            mcbase: [SPRITE_END; 8],
+        }
+    }
+
+    /// Returns the video standard the chip was made for.
+    pub fn standard(&self) -> VideoStandard {
+        self.standard
+    }
+
+    /// Returns the raster line being drawn.
+    pub fn raster(&self) -> u16 {
+        self.raster
+    }
+
+    /// Determines whether the chip is asserting its IRQ output, which happens while any
+    /// bit is set in the interrupt latch whose bit is also set in the enable register.
+    pub fn irq_asserted(&self) -> bool {
+        self.latch & self.regs[IMR as usize] & 0x0f != 0
+    }
+
+    /// Determines whether the chip is pulling BA low to take the bus from the CPU. See the
+    /// type's documentation for the (simplified) rules.
+    pub fn ba_low(&self) -> bool {
+        let cr1 = self.regs[CR1 as usize];
+        cr1 & CR1_DEN != 0
+            && (BADLINES.0..=BADLINES.1).contains(&self.raster)
+            && self.raster as u8 & CR1_YSCROLL == cr1 & CR1_YSCROLL
+            && (BA_CYCLES.0..=BA_CYCLES.1).contains(&self.cycle)
+    }
+
+    /// Returns the sprites whose DMA is on, as a bit for each sprite.
+    pub fn sprite_dma(&self) -> u8 {
+        self.sprite_dma
+    }
+
+    /// Returns the sprites that are being displayed, as a bit for each sprite.
+    pub fn sprite_display(&self) -> u8 {
+        self.sprite_display
+    }
+
+    /// Signals a negative transition on the light pen input. The first one in each frame
+    /// latches the beam position and raises the light pen interrupt; the rest are ignored.
+    pub fn light_pen(&mut self) {
+        if !self.lp_triggered {
+            self.lp_triggered = true;
+            self.lp = ((self.cycle * 4) as u8, self.raster as u8);
+            self.latch |= IR_LP;
+        }
+    }
+
+    /// Returns a snapshot of the chip's registers and raster position.
+    pub fn state(&self) -> VicState {
+        let mut registers = [0; REGISTERS];
+        for (reg, value) in registers.iter_mut().enumerate() {
+            *value = self.peek(reg as u16);
+        }
+        let reg = |r: u16| registers[r as usize];
+
+        let mut sprite_x = [0; 8];
+        let mut sprite_y = [0; 8];
+        let mut sprite_color = [0; 8];
+        for i in 0..8 {
+            let n = i as u16;
+            sprite_x[i] = reg(M0X + 2 * n) as u16 | (((reg(MSIGX) >> i) & 1) as u16) << 8;
+            sprite_y[i] = reg(M0X + 2 * n + 1);
+            sprite_color[i] = reg(M0C + n) & 0x0f;
+        }
+        let mut background = [0; 4];
+        for (i, color) in background.iter_mut().enumerate() {
+            *color = reg(B0C + i as u16) & 0x0f;
+        }
+
+        VicState {
+            registers,
+            raster: self.raster,
+            cycle: self.cycle,
+            raster_compare: self.compare,
+            sprite_x,
+            sprite_y,
+            sprite_enable: reg(ME),
+            irq_latch: self.latch,
+            irq_enable: self.regs[IMR as usize] & 0x0f,
+            irq: self.irq_asserted(),
+            light_pen: self.lp,
+            border: reg(EC) & 0x0f,
+            background,
+            sprite_color,
+        }
+    }
+
+    /// Returns the value that a read of a register would return, without the side effects
+    /// of reading.
+    fn peek(&self, reg: u16) -> u8 {
+        match reg {
+            CR1 => self.regs[CR1 as usize] | ((self.raster >> 1) as u8 & CR1_RST8),
+            RASTER => self.raster as u8,
+            LPX => self.lp.0,
+            LPY => self.lp.1,
+            CR2 => self.regs[CR2 as usize] | 0xc0,
+            MEMPTR => self.regs[MEMPTR as usize] | 0x01,
+            IRR => {
+                let irq = if self.irq_asserted() { IR_IRQ } else { 0 };
+                self.latch | irq | 0x70
+            }
+            IMR => self.regs[IMR as usize] | 0xf0,
+            EC..=M7C => self.regs[reg as usize] | 0xf0,
+            0x2f..=0x3f => 0xff,
+            _ => self.regs[reg as usize],
+        }
+    }
+
+    /// Returns the sprites that are enabled and whose Y coordinates match the low 8 bits of
+    /// the raster counter, as a bit for each sprite.
+    fn sprites_on_line(&self) -> u8 {
+        (0..8).fold(0, |bits, i| {
+            if self.regs[(M0X + 2 * i + 1) as usize] == self.raster as u8 {
+                bits | 1 << i
+            } else {
+                bits
+            }
+        }) & self.regs[ME as usize]
+    }
+
+    /// Does the sprite sequencer's work for the first phase of the current cycle. See the
+    /// type's documentation for what happens when.
+    fn clock_sprites(&mut self) {
+        let expand = self.regs[MYE as usize];
+        match self.cycle {
+            c if c == SPRITE_MCBASE_CYCLES.0 || c == SPRITE_MCBASE_CYCLES.1 => {
+                let step = if self.cycle == SPRITE_MCBASE_CYCLES.0 {
+                    2
+                } else {
+                    1
+                };
+                for i in 0..8 {
+                    let bit = 1 << i;
+                    if self.sprite_dma & self.sprite_expand & bit != 0 {
+                        self.mcbase[i] = (self.mcbase[i] + step) & 0x3f;
+                        if self.mcbase[i] == SPRITE_END {
+                            self.sprite_dma &= !bit;
+                            self.sprite_display &= !bit;
+                        }
+                    }
+                }
+            }
+            c if c == SPRITE_DMA_CYCLES.0 || c == SPRITE_DMA_CYCLES.1 => {
+                if self.cycle == SPRITE_DMA_CYCLES.0 {
+                    self.sprite_expand ^= expand;
+                }
+                let starting = self.sprites_on_line() & !self.sprite_dma;
+                for i in (0..8).filter(|i| starting & 1 << i != 0) {
+                    self.mcbase[i] = 0;
+                }
+                self.sprite_dma |= starting;
+                self.sprite_expand &= !(starting & expand);
+            }
+            SPRITE_DISPLAY_CYCLE => {
+                self.sprite_display |= self.sprite_dma & self.sprites_on_line();
+            }
+            _ => {}
+        }
+    }
+
+    /// Sets the raster compare value, raising the raster interrupt if it's changed to the
+    /// current line.
+    fn set_compare(&mut self, compare: u16) {
+        let changed = compare != self.compare;
+        self.compare = compare;
+        if changed && compare == self.raster {
+            self.latch |= IR_RST;
+        }
+    }
+}
+
+impl Addressable for Ic6567 {
+    fn read(&mut self, addr: u16) -> u8 {
+        let reg = addr & 0x3f;
+        let value = self.peek(reg);
+        if reg == MMC || reg == MBC {
+            self.regs[reg as usize] = 0;
+        }
+        value
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let reg = addr & 0x3f;
+        match reg {
+            CR1 => {
+                self.regs[CR1 as usize] = value & !CR1_RST8;
+                let compare = (self.compare & 0xff) | ((value & CR1_RST8) as u16) << 1;
+                self.set_compare(compare);
+            }
+            RASTER => {
+                let compare = (self.compare & 0x100) | value as u16;
+                self.set_compare(compare);
+            }
+            LPX | LPY | MMC | MBC | 0x2f..=0x3f => {}
+            IRR => self.latch &= !value & 0x0f,
+            MYE => {
+                self.regs[MYE as usize] = value;
+                self.sprite_expand |= !value;
+            }
+            _ => self.regs[reg as usize] = value,
+        }
+    }
+}
+
+impl Clocked for Ic6567 {
+    fn clock(&mut self, _cycle: u64) {
+        self.cycle += 1;
+        if self.cycle < self.standard.cycles_per_line() {
+            self.clock_sprites();
+            return;
+        }
+
+        self.cycle = 0;
+        self.raster += 1;
+        if self.raster == self.standard.lines_per_frame() {
+            self.raster = 0;
+            self.lp_triggered = false;
+        }
+        if self.raster == self.compare {
+            self.latch |= IR_RST;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::testing::{check_addressable, AddressableSpec};
+
+    fn clock_n(vic: &mut Ic6567, n: usize) {
+        for _ in 0..n {
+            vic.clock(0);
+        }
+    }
+
+    /// Clocks a VIC until it's at the start of the given line.
+    fn clock_to_line(vic: &mut Ic6567, line: u16) {
+        while vic.raster != line || vic.cycle != 0 {
+            vic.clock(0);
+        }
+    }
+
+    fn raster(vic: &mut Ic6567) -> u16 {
+        vic.read(RASTER) as u16 | ((vic.read(CR1) & CR1_RST8) as u16) << 1
+    }
+
+    fn raster_wraps(standard: VideoStandard, lines: usize, cycles: usize) {
+        let mut vic = Ic6567::new(standard);
+        assert_eq!(raster(&mut vic), 0);
+
+        clock_n(&mut vic, cycles - 1);
+        assert_eq!(raster(&mut vic), 0, "line should last {} cycles", cycles);
+        clock_n(&mut vic, 1);
+        assert_eq!(raster(&mut vic), 1);
+
+        clock_n(&mut vic, (lines - 2) * cycles);
+        assert_eq!(raster(&mut vic), lines as u16 - 1);
+        clock_n(&mut vic, cycles);
+        assert_eq!(raster(&mut vic), 0, "frame should last {} lines", lines);
+    }
+
+    #[test]
+    fn raster_wraps_ntsc() {
+        raster_wraps(VideoStandard::Ntsc, 263, 65);
+    }
+
+    #[test]
+    fn raster_wraps_pal() {
+        raster_wraps(VideoStandard::Pal, 312, 63);
+    }
+
+    #[test]
+    fn live_raster_reads() {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        vic.write(CR1, 0x1b);
+        clock_to_line(&mut vic, 0x123);
+        assert_eq!(vic.read(RASTER), 0x23);
+        assert_eq!(vic.read(CR1), 0x9b, "bit 7 should be raster bit 8");
+
+        vic.write(RASTER, 0x40);
+        assert_eq!(
+            vic.read(RASTER),
+            0x23,
+            "writes should set compare, not raster"
+        );
+    }
+
+    #[test]
+    fn raster_irq_at_line() {
+        let mut vic = Ic6567::new(VideoStandard::Ntsc);
+        vic.write(IMR, IR_RST);
+        vic.write(CR1, CR1_RST8);
+        vic.write(RASTER, 0x05);
+
+        clock_to_line(&mut vic, 0x104);
+        clock_n(&mut vic, 64);
+        assert!(!vic.irq_asserted(), "IRQ should not fire before line $105");
+        assert_eq!(vic.read(IRR), 0x70);
+
+        clock_n(&mut vic, 1);
+        assert_eq!(raster(&mut vic), 0x105);
+        assert!(vic.irq_asserted(), "IRQ should fire at line $105");
+        assert_eq!(vic.read(IRR), 0xf1);
+    }
+
+    #[test]
+    fn raster_irq_disabled() {
+        let mut vic = Ic6567::new(VideoStandard::Ntsc);
+        vic.write(RASTER, 0x10);
+        clock_to_line(&mut vic, 0x10);
+        assert!(!vic.irq_asserted(), "IRQ should not fire if not enabled");
+        assert_eq!(
+            vic.read(IRR),
+            0x71,
+            "latch should be set even if not enabled"
+        );
+
+        vic.write(IMR, IR_RST);
+        assert!(
+            vic.irq_asserted(),
+            "enabling should assert a latched interrupt"
+        );
+    }
+
+    #[test]
+    fn compare_write_on_current_line() {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        clock_to_line(&mut vic, 0x20);
+        vic.write(IMR, IR_RST);
+        vic.write(RASTER, 0x20);
+        assert!(vic.irq_asserted());
+    }
+
+    #[test]
+    fn acknowledge_clears_latch() {
+        let mut vic = Ic6567::new(VideoStandard::Ntsc);
+        vic.write(IMR, IR_RST | IR_LP);
+        vic.write(RASTER, 0x10);
+        clock_to_line(&mut vic, 0x10);
+        vic.light_pen();
+        assert_eq!(vic.read(IRR), 0xf9);
+
+        vic.write(IRR, IR_RST);
+        assert_eq!(
+            vic.read(IRR),
+            0xf8,
+            "only the written bit should be cleared"
+        );
+        assert!(vic.irq_asserted());
+
+        vic.write(IRR, 0xff);
+        assert_eq!(vic.read(IRR), 0x70);
+        assert!(!vic.irq_asserted(), "IRQ should be released");
+    }
+
+    #[test]
+    fn light_pen_once_per_frame() {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        clock_to_line(&mut vic, 0x80);
+        clock_n(&mut vic, 10);
+        vic.light_pen();
+        assert_eq!(vic.read(LPX), 40);
+        assert_eq!(vic.read(LPY), 0x80);
+
+        clock_to_line(&mut vic, 0x90);
+        vic.light_pen();
+        assert_eq!(
+            vic.read(LPY),
+            0x80,
+            "second trigger in a frame should be ignored"
+        );
+
+        clock_n(&mut vic, 1);
+        clock_to_line(&mut vic, 0x90);
+        vic.light_pen();
+        assert_eq!(
+            vic.read(LPY),
+            0x90,
+            "trigger in the next frame should latch"
+        );
+    }
+
+    #[test]
+    fn badlines() {
+        let mut vic = Ic6567::new(VideoStandard::Ntsc);
+        vic.write(CR1, CR1_DEN | 0x03);
+
+        clock_to_line(&mut vic, 0x33);
+        let ba: Vec<bool> = (0..65)
+            .map(|_| {
+                let low = vic.ba_low();
+                vic.clock(0);
+                low
+            })
+            .collect();
+        assert_eq!(ba.iter().filter(|low| **low).count(), 43);
+        assert!(!ba[10] && ba[11] && ba[53] && !ba[54]);
+
+        clock_to_line(&mut vic, 0x34);
+        clock_n(&mut vic, 20);
+        assert!(!vic.ba_low(), "only every 8th line should be a badline");
+
+        clock_to_line(&mut vic, 0x3b);
+        clock_n(&mut vic, 20);
+        assert!(vic.ba_low());
+
+        vic.write(CR1, 0x03);
+        assert!(!vic.ba_low(), "blanked display should have no badlines");
+    }
+
+    #[test]
+    fn no_badlines_outside_display() {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        vic.write(CR1, CR1_DEN);
+        clock_to_line(&mut vic, 0x28);
+        clock_n(&mut vic, 20);
+        assert!(!vic.ba_low());
+        clock_to_line(&mut vic, 0xf8);
+        clock_n(&mut vic, 20);
+        assert!(!vic.ba_low());
+    }
+
+    #[test]
+    fn unused_bits() {
+        let mut vic = Ic6567::new(VideoStandard::Ntsc);
+        for reg in 0..0x40 {
+            vic.write(reg, 0);
+        }
+        assert_eq!(vic.read(CR2), 0xc0);
+        assert_eq!(vic.read(MEMPTR), 0x01);
+        assert_eq!(vic.read(IRR), 0x70);
+        assert_eq!(vic.read(IMR), 0xf0);
+        for reg in EC..=M7C {
+            assert_eq!(vic.read(reg), 0xf0, "Register ${:02X} should read $F0", reg);
+        }
+        for reg in 0x2f..0x40 {
+            assert_eq!(vic.read(reg), 0xff, "Register ${:02X} should read $FF", reg);
+        }
+    }
+
+    #[test]
+    fn addressable_contract() {
+        // The sprite registers hold all 8 bits of whatever is written to them
+        let spec = AddressableSpec {
+            stored: vec![0x00..=0x10, 0x15..=0x15, 0x17..=0x17, 0x1b..=0x1d],
+            mirror: Some(64),
+        };
+        check_addressable(|| Box::new(Ic6567::new(VideoStandard::Pal)), spec);
+    }
+
+    /// Sets up a VIC with a sprite, some colors, and a raster interrupt that has fired.
+    fn state_vic() -> Ic6567 {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        vic.write(M0X + 2, 0x40);
+        vic.write(M0X + 3, 0x80);
+        vic.write(MSIGX, 0x02);
+        vic.write(ME, 0x02);
+        vic.write(EC, 0x0e);
+        vic.write(B0C, 0x06);
+        vic.write(M0C + 1, 0x01);
+        vic.write(IMR, IR_RST);
+        vic.write(CR1, 0x9b);
+        vic.write(RASTER, 0x05);
+        clock_to_line(&mut vic, 0x105);
+        clock_n(&mut vic, 5);
+        vic
+    }
+
+    #[test]
+    fn state_fields() {
+        let vic = state_vic();
+        let state = vic.state();
+        assert_eq!(state.raster, 0x105);
+        assert_eq!(state.cycle, 5);
+        assert_eq!(state.raster_compare, 0x105);
+        assert_eq!(state.sprite_x, [0, 0x140, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(state.sprite_y, [0, 0x80, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(state.sprite_enable, 0x02);
+        assert_eq!(state.sprite_color, [0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(state.border, 0x0e);
+        assert_eq!(state.background, [0x06, 0, 0, 0]);
+        assert_eq!(state.irq_latch, IR_RST);
+        assert_eq!(state.irq_enable, IR_RST);
+        assert!(state.irq);
+        assert_eq!(state.registers[CR1 as usize], 0x9b);
+        assert_eq!(state.registers[IRR as usize], 0xf1);
+    }
+
+    #[test]
+    fn state_dump() {
+        let vic = state_vic();
+        assert_eq!(
+            vic.state().to_string(),
+            "D000: 00 00 40 80 00 00 00 00 00 00 00 00 00 00 00 00\n\
+             D010: 02 9B 05 00 00 02 C0 00 01 F1 F1 00 00 00 00 00\n\
+             D020: FE F6 F0 F0 F0 F0 F0 F0 F1 F0 F0 F0 F0 F0 F0\n\
+             Raster: $105  Cycle: 5  Compare: $105\n\
+             IRQ: asserted  Latch: $1  Enable: $1"
+        );
+    }
+
+    /// Clocks a VIC until it's at the given cycle of the given line.
+    fn clock_to(vic: &mut Ic6567, line: u16, cycle: u16) {
+        clock_to_line(vic, line);
+        clock_n(vic, cycle as usize);
+    }
+
+    /// Enables sprite 0 at Y coordinate $40.
+    fn sprite_at_40(vic: &mut Ic6567) {
+        vic.write(M0X + 1, 0x40);
+        vic.write(ME, 0x01);
+    }
+
+    #[test]
+    fn sprite_dma_and_display() {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        sprite_at_40(&mut vic);
+
+        clock_to(&mut vic, 0x40, 53);
+        assert_eq!(vic.sprite_dma(), 0);
+        clock_n(&mut vic, 1);
+        assert_eq!(vic.sprite_dma(), 0x01, "DMA should turn on in cycle 54");
+        assert_eq!(vic.sprite_display(), 0);
+        clock_n(&mut vic, 3);
+        assert_eq!(
+            vic.sprite_display(),
+            0x01,
+            "display should turn on in cycle 57"
+        );
+    }
+
+    #[test]
+    fn sprite_lasts_21_lines() {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        sprite_at_40(&mut vic);
+
+        clock_to(&mut vic, 0x40 + 21, 14);
+        assert_eq!(vic.sprite_display(), 0x01);
+        clock_n(&mut vic, 1);
+        assert_eq!(vic.sprite_display(), 0, "display should end after 21 lines");
+        assert_eq!(vic.sprite_dma(), 0);
+    }
+
+    #[test]
+    fn expanded_sprite_lasts_42_lines() {
+        let mut vic = Ic6567::new(VideoStandard::Ntsc);
+        sprite_at_40(&mut vic);
+        vic.write(MYE, 0x01);
+
+        clock_to(&mut vic, 0x40 + 41, 16);
+        assert_eq!(vic.sprite_display(), 0x01);
+        clock_to(&mut vic, 0x40 + 42, 16);
+        assert_eq!(vic.sprite_display(), 0, "display should end after 42 lines");
+    }
+
+    /// Writes a sprite's Y coordinate or enable bit during the given cycle of the line that
+    /// the sprite is on, and returns whether its DMA turned on in that line.
+    fn late_write(cycle: u16, reg: u16, value: u8) -> bool {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        sprite_at_40(&mut vic);
+        vic.write(reg, 0);
+        clock_to(&mut vic, 0x40, cycle);
+        vic.write(reg, value);
+        clock_to(&mut vic, 0x41, 0);
+        let on = vic.sprite_dma() != 0;
+
+        if !on {
+            clock_to(&mut vic, 0x40, 56);
+            assert_eq!(
+                vic.sprite_dma(),
+                0x01,
+                "sprite should start the next time its line comes around"
+            );
+        }
+        on
+    }
+
+    #[test]
+    fn sprite_y_written_late() {
+        for cycle in [0, 20, 53, 54] {
+            assert!(
+                late_write(cycle, M0X + 1, 0x40),
+                "Y written in cycle {} should start sprite on its line",
+                cycle
+            );
+        }
+        for cycle in [55, 56, 60, 62] {
+            assert!(
+                !late_write(cycle, M0X + 1, 0x40),
+                "Y written in cycle {} should miss its line",
+                cycle
+            );
+        }
+    }
+
+    #[test]
+    fn sprite_enabled_late() {
+        assert!(late_write(54, ME, 0x01));
+        assert!(!late_write(55, ME, 0x01));
+    }
+
+    #[test]
+    fn sprite_not_restarted() {
+        let mut vic = Ic6567::new(VideoStandard::Pal);
+        sprite_at_40(&mut vic);
+
+        clock_to(&mut vic, 0x48, 20);
+        vic.write(M0X + 1, 0x48);
+        clock_to(&mut vic, 0x40 + 21, 16);
+        assert_eq!(
+            vic.sprite_display(),
+            0,
+            "moving a displayed sprite to the current line should not restart it"
+        );
+        assert_eq!(vic.sprite_dma(), 0);
+    }
+}
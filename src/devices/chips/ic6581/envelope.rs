@@ -0,0 +1,219 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+/// The number of cycles between steps of the envelope counter for each of the 16 attack,
+/// decay, and release rates. (Decay and release steps are further divided by the
+/// exponential counter.)
+const RATE_PERIODS: [u16; 16] = [
+    9, 32, 63, 95, 149, 220, 267, 313, 392, 977, 1954, 3126, 3907, 11720, 19532, 31251,
+];
+
+/// The envelope values at which the exponential counter's period changes during decay and
+/// release, with the period that takes effect at each.
+const EXP_PERIODS: [(u8, u8); 7] = [
+    (0xff, 1),
+    (0x5d, 2),
+    (0x36, 4),
+    (0x1a, 8),
+    (0x0e, 16),
+    (0x06, 30),
+    (0x00, 1),
+];
+
+/// The phase that an envelope generator is in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    Attack,
+    DecaySustain,
+    Release,
+}
+
+/// The envelope generator of one of the 6581's three voices.
+///
+/// The envelope is an 8-bit counter. Setting the gate bit starts the attack, which counts
+/// up to $FF at the attack rate. The decay then counts down at the decay rate until it
+/// reaches the sustain level, where it stays for as long as the gate is set. Clearing the
+/// gate starts the release, which counts down to 0 at the release rate, from wherever the
+/// counter is (even in the middle of the attack).
+///
+/// The attack is linear. Decay and release approximate an exponential curve by slowing
+/// down as the counter gets lower: each step takes 1, 2, 4, 8, 16, or 30 times the rate
+/// period, depending on the counter's value.
+///
+/// The rate counter that times the steps is compared for equality with the rate period,
+/// just as on the real chip. If the period is changed to one lower than the count so far,
+/// the counter has to run all the way around 15 bits before the next step, which is the
+/// well-known "ADSR bug".
+#[derive(Clone, Debug)]
+pub struct Envelope {
+    /// The current phase.
+    state: State,
+
+    /// The envelope value, which is the voice's output level.
+    counter: u8,
+
+    /// The cycles counted towards the next step. This is a 15-bit counter.
+    rate_counter: u16,
+
+    /// The rate steps counted towards the next decay or release step.
+    exp_counter: u8,
+
+    /// The number of rate steps per decay or release step.
+    exp_period: u8,
+
+    /// Whether the counter has reached 0 and stopped. It only starts again with an attack.
+    hold_zero: bool,
+}
+
+impl Envelope {
+    /// Returns the envelope value.
+    pub fn output(&self) -> u8 {
+        self.counter
+    }
+
+    /// Handles a change of the gate bit. Setting it starts the attack, and clearing it
+    /// starts the release.
+    pub fn gate(&mut self, on: bool) {
+        if on {
+            self.state = State::Attack;
+            self.hold_zero = false;
+        } else {
+            self.state = State::Release;
+        }
+    }
+
+    /// Advances the envelope by one cycle with the given attack/decay and sustain/release
+    /// register values.
+    pub fn clock(&mut self, ad: u8, sr: u8) {
+        let rate = match self.state {
+            State::Attack => ad >> 4,
+            State::DecaySustain => ad & 0x0f,
+            State::Release => sr & 0x0f,
+        };
+
+        self.rate_counter = (self.rate_counter + 1) & 0x7fff;
+        if self.rate_counter != RATE_PERIODS[rate as usize] {
+            return;
+        }
+        self.rate_counter = 0;
+
+        if self.state != State::Attack {
+            self.exp_counter += 1;
+            if self.exp_counter != self.exp_period {
+                return;
+            }
+        }
+        self.exp_counter = 0;
+        if self.hold_zero {
+            return;
+        }
+
+        match self.state {
+            State::Attack => {
+                self.counter = self.counter.wrapping_add(1);
+                if self.counter == 0xff {
+                    self.state = State::DecaySustain;
+                }
+            }
+            State::DecaySustain => {
+                if self.counter != (sr >> 4) * 0x11 {
+                    self.counter -= 1;
+                }
+            }
+            State::Release => self.counter = self.counter.wrapping_sub(1),
+        }
+
+        if let Some((_, period)) = EXP_PERIODS.iter().find(|(at, _)| *at == self.counter) {
+            self.exp_period = *period;
+            self.hold_zero = self.counter == 0;
+        }
+    }
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope {
+            state: State::Release,
+            counter: 0,
+            rate_counter: 0,
+            exp_counter: 0,
+            exp_period: 1,
+            hold_zero: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Clocks an envelope until its output changes, returning the number of cycles it took
+    /// (or `None` if it didn't change within `limit` cycles).
+    fn cycles_to_step(env: &mut Envelope, ad: u8, sr: u8, limit: usize) -> Option<usize> {
+        let start = env.output();
+        (1..=limit).find(|_| {
+            env.clock(ad, sr);
+            env.output() != start
+        })
+    }
+
+    #[test]
+    fn attack_rate() {
+        let mut env = Envelope::default();
+        env.gate(true);
+        assert_eq!(cycles_to_step(&mut env, 0x20, 0, 1000), Some(63));
+        assert_eq!(cycles_to_step(&mut env, 0x20, 0, 1000), Some(63));
+    }
+
+    #[test]
+    fn exponential_decay() {
+        let mut env = Envelope::default();
+        env.gate(true);
+        while env.output() != 0xff {
+            env.clock(0x00, 0x00);
+        }
+        // Decay rate 0 is 9 cycles per step, multiplied by the exponential period
+        assert_eq!(cycles_to_step(&mut env, 0x00, 0x00, 1000), Some(9));
+        while env.output() != 0x5d {
+            env.clock(0x00, 0x00);
+        }
+        assert_eq!(cycles_to_step(&mut env, 0x00, 0x00, 1000), Some(18));
+        while env.output() != 0x06 {
+            env.clock(0x00, 0x00);
+        }
+        assert_eq!(cycles_to_step(&mut env, 0x00, 0x00, 1000), Some(270));
+    }
+
+    #[test]
+    fn holds_at_zero() {
+        let mut env = Envelope::default();
+        env.gate(true);
+        env.clock(0x00, 0x00);
+        while env.output() != 1 {
+            env.clock(0x00, 0x00);
+        }
+        env.gate(false);
+        while env.output() != 0 {
+            env.clock(0x00, 0x00);
+        }
+        assert_eq!(cycles_to_step(&mut env, 0x00, 0x00, 100_000), None);
+    }
+
+    #[test]
+    fn adsr_bug() {
+        let mut env = Envelope::default();
+        env.gate(true);
+        for _ in 0..100 {
+            env.clock(0xf0, 0);
+        }
+        assert_eq!(env.output(), 0);
+
+        // The rate counter is already past 9, so it has to wrap around before matching
+        assert_eq!(
+            cycles_to_step(&mut env, 0x00, 0, 0x8000),
+            Some(0x8000 - 100 + 9)
+        );
+    }
+}
@@ -0,0 +1,490 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+mod envelope;
+mod oscillator;
+mod state;
+
+pub mod constants {
+    /// Register address of the low byte of voice 1's frequency. The registers of voices 2
+    /// and 3 are at the same offsets from this plus `VOICE_SIZE` and twice `VOICE_SIZE`.
+    pub const FRELO1: u16 = 0x00;
+    /// Register address of the high byte of voice 1's frequency.
+    pub const FREHI1: u16 = 0x01;
+    /// Register address of the low byte of voice 1's pulse width.
+    pub const PWLO1: u16 = 0x02;
+    /// Register address of the high 4 bits of voice 1's pulse width.
+    pub const PWHI1: u16 = 0x03;
+    /// Register address of voice 1's control register.
+    pub const VCREG1: u16 = 0x04;
+    /// Register address of voice 1's attack and decay rates.
+    pub const ATDCY1: u16 = 0x05;
+    /// Register address of voice 1's sustain level and release rate.
+    pub const SUREL1: u16 = 0x06;
+    /// The number of registers per voice.
+    pub const VOICE_SIZE: u16 = 7;
+
+    /// Register address of the low 3 bits of the filter cutoff frequency.
+    pub const CUTLO: u16 = 0x15;
+    /// Register address of the high 8 bits of the filter cutoff frequency.
+    pub const CUTHI: u16 = 0x16;
+    /// Register address of the filter resonance and voice routing.
+    pub const RESON: u16 = 0x17;
+    /// Register address of the filter mode and master volume.
+    pub const SIGVOL: u16 = 0x18;
+    /// Register address of the paddle X value.
+    pub const POTX: u16 = 0x19;
+    /// Register address of the paddle Y value.
+    pub const POTY: u16 = 0x1a;
+    /// Register address of the upper 8 bits of voice 3's oscillator output.
+    pub const RANDOM: u16 = 0x1b;
+    /// Register address of voice 3's envelope output.
+    pub const ENV3: u16 = 0x1c;
+
+    /// Control register bit that starts the attack (1) or release (0).
+    pub const CTRL_GATE: u8 = 0x01;
+    /// Control register bit that syncs the oscillator to the previous voice's.
+    pub const CTRL_SYNC: u8 = 0x02;
+    /// Control register bit that ring modulates the triangle wave with the previous voice.
+    pub const CTRL_RING: u8 = 0x04;
+    /// Control register bit that holds the oscillator at 0.
+    pub const CTRL_TEST: u8 = 0x08;
+    /// Control register bit that selects the triangle waveform.
+    pub const CTRL_TRI: u8 = 0x10;
+    /// Control register bit that selects the sawtooth waveform.
+    pub const CTRL_SAW: u8 = 0x20;
+    /// Control register bit that selects the pulse waveform.
+    pub const CTRL_PULSE: u8 = 0x40;
+    /// Control register bit that selects the noise waveform.
+    pub const CTRL_NOISE: u8 = 0x80;
+}
+
+use crate::components::{addressable::Addressable, clock::Clocked};
+
+use self::{constants::*, envelope::Envelope, oscillator::Oscillator};
+
+pub use self::state::{FilterState, SidState, VoiceState};
+
+/// The number of writable registers, which are all of those before POTX.
+const WRITABLE: usize = POTX as usize;
+
+/// The number of registers, writable and read-only.
+const REGISTERS: usize = ENV3 as usize + 1;
+
+/// An emulation of the 6581 Sound Interface Device, at the register level.
+///
+/// The SID has three voices, each made up of an oscillator and an envelope generator, and a
+/// programmable filter that they can be routed through. This emulation has the chip's 29
+/// registers, accessed through `Addressable` (only the low 5 bits of the address are
+/// decoded, as on the real chip), and advances the voices' oscillators and envelopes once
+/// per φ2 cycle through `Clocked`. It doesn't produce any audio yet, and the filter and
+/// volume registers are only stored.
+///
+/// | Address | Name    | Description                                                    |
+/// | ------- | ------- | -------------------------------------------------------------- |
+/// | $00-$06 |         | Voice 1: frequency (16 bits), pulse width (12 bits), control,  |
+/// |         |         | attack/decay, and sustain/release.                             |
+/// | $07-$0D |         | Voice 2, with the same registers as voice 1.                   |
+/// | $0E-$14 |         | Voice 3, with the same registers as voice 1.                   |
+/// | $15     | CUTLO   | Filter cutoff frequency, low 3 bits.                           |
+/// | $16     | CUTHI   | Filter cutoff frequency, high 8 bits.                          |
+/// | $17     | RESON   | Filter resonance and voice routing.                            |
+/// | $18     | SIGVOL  | Filter mode and master volume.                                 |
+/// | $19     | POTX    | Paddle X value (read only).                                    |
+/// | $1A     | POTY    | Paddle Y value (read only).                                    |
+/// | $1B     | RANDOM  | Upper 8 bits of voice 3's oscillator output (read only).       |
+/// | $1C     | ENV3    | Voice 3's envelope value (read only).                          |
+///
+/// All of the registers from $00 to $18 are write-only. On the real chip, reading one
+/// returns whatever was last on the data bus, fading away after a while. Here they read 0,
+/// as do the unused addresses $1D-$1F.
+///
+/// RANDOM and ENV3 are live views of voice 3, which is why software uses them as random
+/// number sources (with voice 3 set to noise) and as modulation sources. The paddle values
+/// come from analog inputs on the real chip; they're set here with `set_pot_x` and
+/// `set_pot_y`.
+///
+/// Each voice is hard synced and ring modulated by the voice before it: voice 1 by voice
+/// 3, voice 2 by voice 1, and voice 3 by voice 2.
+pub struct Ic6581 {
+    /// The write-only registers, as last written.
+    regs: [u8; WRITABLE],
+
+    /// The voices' oscillators.
+    oscillators: [Oscillator; 3],
+
+    /// The voices' envelope generators.
+    envelopes: [Envelope; 3],
+
+    /// The paddle X value.
+    pot_x: u8,
+
+    /// The paddle Y value.
+    pot_y: u8,
+}
+
+impl Ic6581 {
+    /// Creates a new 6581 in the state it has after a reset: all registers are 0, and all
+    /// envelopes are at 0.
+    pub fn new() -> Ic6581 {
+        Ic6581 {
+            regs: [0; WRITABLE],
+            oscillators: Default::default(),
+            envelopes: Default::default(),
+            pot_x: 0,
+            pot_y: 0,
+        }
+    }
+
+    /// Sets the value read from POTX, as the chip would measure it from the POT X line.
+    pub fn set_pot_x(&mut self, value: u8) {
+        self.pot_x = value;
+    }
+
+    /// Sets the value read from POTY, as the chip would measure it from the POT Y line.
+    pub fn set_pot_y(&mut self, value: u8) {
+        self.pot_y = value;
+    }
+
+    /// Returns the 12-bit waveform output of a voice (0-2).
+    pub fn waveform(&self, voice: usize) -> u16 {
+        let source = (voice + 2) % 3;
+        let base = voice * VOICE_SIZE as usize;
+        let pw = self.regs[base + PWLO1 as usize] as u16
+            | ((self.regs[base + PWHI1 as usize] & 0x0f) as u16) << 8;
+        self.oscillators[voice].output(
+            self.regs[base + VCREG1 as usize],
+            pw,
+            self.oscillators[source].msb(),
+        )
+    }
+
+    /// Returns the envelope value of a voice (0-2).
+    pub fn envelope(&self, voice: usize) -> u8 {
+        self.envelopes[voice].output()
+    }
+
+    /// Returns the master volume, from the low 4 bits of SIGVOL.
+    pub fn volume(&self) -> u8 {
+        self.regs[SIGVOL as usize] & 0x0f
+    }
+
+    /// Returns a snapshot of the chip's registers and voices.
+    pub fn state(&self) -> SidState {
+        let mut registers = [0; REGISTERS];
+        registers[..WRITABLE].copy_from_slice(&self.regs);
+        registers[POTX as usize] = self.pot_x;
+        registers[POTY as usize] = self.pot_y;
+        registers[RANDOM as usize] = (self.waveform(2) >> 4) as u8;
+        registers[ENV3 as usize] = self.envelope(2);
+
+        let voices = [0, 1, 2].map(|voice| {
+            let reg = |r: u16| self.regs[voice * VOICE_SIZE as usize + r as usize];
+            VoiceState {
+                frequency: reg(FRELO1) as u16 | (reg(FREHI1) as u16) << 8,
+                pulse_width: reg(PWLO1) as u16 | ((reg(PWHI1) & 0x0f) as u16) << 8,
+                control: reg(VCREG1),
+                attack: reg(ATDCY1) >> 4,
+                decay: reg(ATDCY1) & 0x0f,
+                sustain: reg(SUREL1) >> 4,
+                release: reg(SUREL1) & 0x0f,
+                waveform: self.waveform(voice),
+                envelope: self.envelope(voice),
+            }
+        });
+        let reg = |r: u16| self.regs[r as usize];
+        let filter = FilterState {
+            cutoff: (reg(CUTLO) & 0x07) as u16 | (reg(CUTHI) as u16) << 3,
+            resonance: reg(RESON) >> 4,
+            routing: reg(RESON) & 0x0f,
+            mode: reg(SIGVOL) >> 4,
+        };
+
+        SidState {
+            registers,
+            voices,
+            filter,
+            volume: self.volume(),
+        }
+    }
+}
+
+impl Default for Ic6581 {
+    fn default() -> Self {
+        Ic6581::new()
+    }
+}
+
+impl Addressable for Ic6581 {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr & 0x1f {
+            POTX => self.pot_x,
+            POTY => self.pot_y,
+            RANDOM => (self.waveform(2) >> 4) as u8,
+            ENV3 => self.envelope(2),
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let index = (addr & 0x1f) as usize;
+        if index >= WRITABLE {
+            return;
+        }
+
+        let reg = index as u16 % VOICE_SIZE;
+        if index < 3 * VOICE_SIZE as usize && reg == VCREG1 {
+            let voice = index / VOICE_SIZE as usize;
+            if (self.regs[index] ^ value) & CTRL_GATE != 0 {
+                self.envelopes[voice].gate(value & CTRL_GATE != 0);
+            }
+        }
+        self.regs[index] = value;
+    }
+}
+
+impl Clocked for Ic6581 {
+    fn clock(&mut self, _cycle: u64) {
+        let mut wrapped = [false; 3];
+        for (voice, wrap) in wrapped.iter_mut().enumerate() {
+            let base = voice * VOICE_SIZE as usize;
+            let freq = self.regs[base + FRELO1 as usize] as u16
+                | (self.regs[base + FREHI1 as usize] as u16) << 8;
+            *wrap = self.oscillators[voice].clock(freq, self.regs[base + VCREG1 as usize]);
+        }
+
+        for voice in 0..3 {
+            let base = voice * VOICE_SIZE as usize;
+            let control = self.regs[base + VCREG1 as usize];
+            if control & CTRL_SYNC != 0 && wrapped[(voice + 2) % 3] {
+                self.oscillators[voice].reset();
+            }
+            self.envelopes[voice].clock(
+                self.regs[base + ATDCY1 as usize],
+                self.regs[base + SUREL1 as usize],
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VOICE3: u16 = 2 * VOICE_SIZE;
+
+    fn clock_n(sid: &mut Ic6581, n: usize) {
+        for _ in 0..n {
+            sid.clock(0);
+        }
+    }
+
+    fn set_freq(sid: &mut Ic6581, base: u16, freq: u16) {
+        sid.write(base + FRELO1, freq as u8);
+        sid.write(base + FREHI1, (freq >> 8) as u8);
+    }
+
+    #[test]
+    fn write_only_registers_read_zero() {
+        let mut sid = Ic6581::new();
+        for addr in 0..WRITABLE as u16 {
+            sid.write(addr, 0xff);
+            assert_eq!(sid.read(addr), 0, "Register ${:02X} should read 0", addr);
+        }
+        assert_eq!(sid.volume(), 0x0f);
+    }
+
+    #[test]
+    fn pots() {
+        let mut sid = Ic6581::new();
+        sid.set_pot_x(0x12);
+        sid.set_pot_y(0xfe);
+        assert_eq!(sid.read(POTX), 0x12);
+        assert_eq!(sid.read(POTY), 0xfe);
+        assert_eq!(
+            sid.read(POTX + 0x20),
+            0x12,
+            "registers should mirror every 32"
+        );
+    }
+
+    #[test]
+    fn sawtooth_osc3() {
+        let mut sid = Ic6581::new();
+        set_freq(&mut sid, VOICE3, 0x1000);
+        sid.write(VOICE3 + VCREG1, CTRL_SAW);
+        clock_n(&mut sid, 0x10);
+        assert_eq!(sid.read(RANDOM), 0x01);
+        clock_n(&mut sid, 0x70);
+        assert_eq!(sid.read(RANDOM), 0x08);
+    }
+
+    #[test]
+    fn noise_osc3_changes() {
+        let mut sid = Ic6581::new();
+        set_freq(&mut sid, VOICE3, 0xffff);
+        sid.write(VOICE3 + VCREG1, CTRL_NOISE);
+
+        let mut values = vec![];
+        for _ in 0..64 {
+            clock_n(&mut sid, 100);
+            values.push(sid.read(RANDOM));
+        }
+        values.sort_unstable();
+        values.dedup();
+        assert!(
+            values.len() > 16,
+            "noise should produce many values, got {:?}",
+            values
+        );
+    }
+
+    #[test]
+    fn test_bit_holds_oscillator() {
+        let mut sid = Ic6581::new();
+        set_freq(&mut sid, VOICE3, 0x1000);
+        sid.write(VOICE3 + VCREG1, CTRL_SAW | CTRL_TEST);
+        clock_n(&mut sid, 0x100);
+        assert_eq!(sid.read(RANDOM), 0);
+
+        sid.write(VOICE3 + VCREG1, CTRL_PULSE | CTRL_TEST);
+        assert_eq!(sid.read(RANDOM), 0xff, "test bit should hold pulse high");
+    }
+
+    #[test]
+    fn pulse_width() {
+        let mut sid = Ic6581::new();
+        set_freq(&mut sid, VOICE3, 0x8000);
+        sid.write(VOICE3 + PWLO1, 0x00);
+        sid.write(VOICE3 + PWHI1, 0x08);
+        sid.write(VOICE3 + VCREG1, CTRL_PULSE);
+        clock_n(&mut sid, 0xff);
+        assert_eq!(sid.read(RANDOM), 0x00);
+        clock_n(&mut sid, 1);
+        assert_eq!(sid.read(RANDOM), 0xff);
+    }
+
+    #[test]
+    fn hard_sync() {
+        let mut sid = Ic6581::new();
+        // Voice 2's MSB rises at cycle $100 and then every $200 cycles, resetting voice 3
+        set_freq(&mut sid, VOICE_SIZE, 0x8000);
+        set_freq(&mut sid, VOICE3, 0x1000);
+        sid.write(VOICE3 + VCREG1, CTRL_SAW | CTRL_SYNC);
+        clock_n(&mut sid, 0x100);
+        assert_eq!(sid.read(RANDOM), 0x00);
+        clock_n(&mut sid, 0x1ff);
+        assert_eq!(sid.read(RANDOM), 0x1f);
+        clock_n(&mut sid, 1);
+        assert_eq!(sid.read(RANDOM), 0x00, "voice 2 should reset voice 3");
+
+        sid.write(VOICE3 + VCREG1, CTRL_SAW);
+        clock_n(&mut sid, 0x200);
+        assert_eq!(
+            sid.read(RANDOM),
+            0x20,
+            "oscillator should run free without sync"
+        );
+    }
+
+    #[test]
+    fn adsr_cycle_env3() {
+        let mut sid = Ic6581::new();
+        // Attack 2 (63 cycles/step), decay 0, sustain $8, release 1
+        sid.write(VOICE3 + ATDCY1, 0x20);
+        sid.write(VOICE3 + SUREL1, 0x81);
+        sid.write(VOICE3 + VCREG1, CTRL_GATE);
+
+        let mut last = 0;
+        for _ in 0..255 {
+            clock_n(&mut sid, 63);
+            let env = sid.read(ENV3);
+            assert_eq!(env, last + 1, "attack should rise one step every 63 cycles");
+            last = env;
+        }
+        assert_eq!(last, 0xff);
+
+        clock_n(&mut sid, 20_000);
+        assert_eq!(
+            sid.read(ENV3),
+            0x88,
+            "decay should stop at the sustain level"
+        );
+        clock_n(&mut sid, 20_000);
+        assert_eq!(sid.read(ENV3), 0x88, "sustain should hold while gated");
+
+        sid.write(VOICE3 + VCREG1, 0);
+        let mut last = 0x88;
+        for _ in 0..50 {
+            clock_n(&mut sid, 1000);
+            let env = sid.read(ENV3);
+            assert!(env <= last, "release should only fall");
+            last = env;
+        }
+        assert!(last < 0x88, "release should have started");
+        clock_n(&mut sid, 100_000);
+        assert_eq!(sid.read(ENV3), 0, "release should end at 0");
+    }
+
+    /// Sets up a SID with voice 1 set up for a pulse wave, voice 3 with its test bit set,
+    /// and the filter and volume set.
+    fn state_sid() -> Ic6581 {
+        let mut sid = Ic6581::new();
+        set_freq(&mut sid, 0, 0x1234);
+        sid.write(PWHI1, 0x08);
+        sid.write(VCREG1, CTRL_PULSE);
+        sid.write(ATDCY1, 0x29);
+        sid.write(SUREL1, 0xa5);
+        sid.write(VOICE3 + VCREG1, CTRL_PULSE | CTRL_TEST);
+        sid.write(CUTLO, 0x05);
+        sid.write(CUTHI, 0x40);
+        sid.write(RESON, 0xf3);
+        sid.write(SIGVOL, 0x9f);
+        sid.set_pot_x(0x80);
+        sid
+    }
+
+    #[test]
+    fn state_fields() {
+        let mut sid = state_sid();
+        let state = sid.state();
+        let voice = state.voices[0];
+        assert_eq!(voice.frequency, 0x1234);
+        assert_eq!(voice.pulse_width, 0x800);
+        assert_eq!(voice.control, CTRL_PULSE);
+        assert_eq!(
+            (voice.attack, voice.decay, voice.sustain, voice.release),
+            (2, 9, 10, 5)
+        );
+        assert_eq!(state.voices[2].waveform, 0xfff);
+        assert_eq!(state.filter.cutoff, 0x205);
+        assert_eq!(state.filter.resonance, 0x0f);
+        assert_eq!(state.filter.routing, 0x03);
+        assert_eq!(state.filter.mode, 0x09);
+        assert_eq!(state.volume, 0x0f);
+        assert_eq!(state.registers[POTX as usize], 0x80);
+        assert_eq!(state.registers[RANDOM as usize], 0xff);
+
+        sid.write(VCREG1, CTRL_PULSE | CTRL_GATE);
+        clock_n(&mut sid, 1000);
+        let state = sid.state();
+        assert!(state.voices[0].envelope > 0, "Envelope should be in attack");
+        assert_eq!(state.voices[0].envelope, sid.envelope(0));
+    }
+
+    #[test]
+    fn state_dump() {
+        let sid = state_sid();
+        assert_eq!(
+            sid.state().to_string(),
+            "D400: 34 12 00 08 40 29 A5 00 00 00 00 00 00 00 00 00\n\
+             D410: 00 00 48 00 00 05 40 F3 9F 80 00 FF 00\n\
+             Voice 1: Freq $1234  PW $800  Ctrl $40  ADSR 29A5  Wave $000  Env $00\n\
+             Voice 2: Freq $0000  PW $000  Ctrl $00  ADSR 0000  Wave $000  Env $00\n\
+             Voice 3: Freq $0000  PW $000  Ctrl $48  ADSR 0000  Wave $FFF  Env $00\n\
+             Filter: Cutoff $205  Res $F  Route $3  Mode $9  Volume $F"
+        );
+    }
+}
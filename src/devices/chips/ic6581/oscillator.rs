@@ -0,0 +1,138 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use super::constants::{CTRL_NOISE, CTRL_PULSE, CTRL_RING, CTRL_SAW, CTRL_TEST, CTRL_TRI};
+
+/// The value of the noise shift register after a reset, or after the test bit is cleared.
+const NOISE_RESET: u32 = 0x7f_fff8;
+
+/// The oscillator (waveform generator) of one of the 6581's three voices.
+///
+/// The oscillator is a 24-bit phase accumulator that has the voice's 16-bit frequency
+/// value added to it every cycle. Its waveforms are derived from the top 12 bits of the
+/// accumulator:
+///
+/// * Sawtooth is the top 12 bits themselves.
+/// * Triangle is the top 12 bits below the MSB, inverted while the MSB is set (which can be
+///   XORed with the MSB of another oscillator for ring modulation).
+/// * Pulse is all 1s while the top 12 bits are at least the pulse width, and all 0s
+///   otherwise. It's also all 1s while the test bit is set.
+/// * Noise is taken from 8 bits of a 23-bit linear feedback shift register, which is
+///   shifted whenever bit 19 of the accumulator goes from 0 to 1.
+///
+/// Selecting more than one waveform ANDs them together, which is roughly (but not exactly)
+/// what the real chip does. Selecting none produces 0.
+///
+/// While the test bit is set, the accumulator is held at 0 and the shift register is
+/// cleared; clearing the bit resets the shift register. Hard sync is handled by the chip,
+/// which calls `reset` when this oscillator's sync source wraps around.
+#[derive(Clone, Debug)]
+pub struct Oscillator {
+    /// The phase accumulator. Only the low 24 bits are used.
+    acc: u32,
+
+    /// The noise shift register. Only the low 23 bits are used.
+    noise: u32,
+
+    /// Whether the test bit was set the last time the oscillator was clocked.
+    test: bool,
+}
+
+impl Oscillator {
+    /// Returns the MSB of the accumulator, which is used for ring modulation.
+    pub fn msb(&self) -> bool {
+        self.acc & 0x80_0000 != 0
+    }
+
+    /// Resets the accumulator to 0 (hard sync).
+    pub fn reset(&mut self) {
+        self.acc = 0;
+    }
+
+    /// Advances the oscillator by one cycle with the given frequency and control register
+    /// value. Returns `true` if the accumulator's MSB went from 0 to 1, which syncs any
+    /// oscillator that uses this one as its sync source.
+    pub fn clock(&mut self, freq: u16, control: u8) -> bool {
+        let test = control & CTRL_TEST != 0;
+        if test {
+            self.acc = 0;
+            self.noise = 0;
+            self.test = true;
+            return false;
+        }
+        if self.test {
+            self.noise = NOISE_RESET;
+            self.test = false;
+        }
+
+        let prev = self.acc;
+        self.acc = (self.acc + freq as u32) & 0xff_ffff;
+
+        if prev & 0x08_0000 == 0 && self.acc & 0x08_0000 != 0 {
+            let bit = ((self.noise >> 22) ^ (self.noise >> 17)) & 1;
+            self.noise = ((self.noise << 1) & 0x7f_ffff) | bit;
+        }
+        prev & 0x80_0000 == 0 && self.acc & 0x80_0000 != 0
+    }
+
+    /// Returns the 12-bit waveform output for the given control register value and 12-bit
+    /// pulse width. `ring_msb` is the MSB of the ring modulation source.
+    pub fn output(&self, control: u8, pw: u16, ring_msb: bool) -> u16 {
+        let waveforms = [
+            (
+                CTRL_TRI,
+                self.triangle(control & CTRL_RING != 0 && ring_msb),
+            ),
+            (CTRL_SAW, self.sawtooth()),
+            (CTRL_PULSE, self.pulse(pw, control & CTRL_TEST != 0)),
+            (CTRL_NOISE, self.noise()),
+        ];
+        let mut selected = waveforms.iter().filter(|(bit, _)| control & bit != 0);
+        match selected.next() {
+            Some((_, first)) => selected.fold(*first, |value, (_, wave)| value & wave),
+            None => 0,
+        }
+    }
+
+    fn sawtooth(&self) -> u16 {
+        (self.acc >> 12) as u16
+    }
+
+    fn triangle(&self, ring: bool) -> u16 {
+        let invert = self.msb() ^ ring;
+        let value = if invert { !self.acc } else { self.acc };
+        ((value >> 11) & 0xfff) as u16
+    }
+
+    fn pulse(&self, pw: u16, test: bool) -> u16 {
+        if test || self.sawtooth() >= pw & 0xfff {
+            0xfff
+        } else {
+            0
+        }
+    }
+
+    fn noise(&self) -> u16 {
+        let n = self.noise;
+        ((n & 0x40_0000) >> 11
+            | (n & 0x10_0000) >> 10
+            | (n & 0x01_0000) >> 7
+            | (n & 0x00_2000) >> 5
+            | (n & 0x00_0800) >> 4
+            | (n & 0x00_0080) >> 1
+            | (n & 0x00_0010) << 1
+            | (n & 0x00_0004) << 2) as u16
+    }
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Oscillator {
+            acc: 0,
+            noise: NOISE_RESET,
+            test: false,
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use std::fmt::{self, Display, Formatter};
+
+use crate::utils::write_registers;
+
+use super::REGISTERS;
+
+/// A snapshot of one of a SID's voices.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VoiceState {
+    /// The 16-bit frequency value.
+    pub frequency: u16,
+
+    /// The 12-bit pulse width.
+    pub pulse_width: u16,
+
+    /// The control register, which holds the waveform, test, ring, sync, and gate bits.
+    pub control: u8,
+
+    /// The attack rate (0-15).
+    pub attack: u8,
+
+    /// The decay rate (0-15).
+    pub decay: u8,
+
+    /// The sustain level (0-15).
+    pub sustain: u8,
+
+    /// The release rate (0-15).
+    pub release: u8,
+
+    /// The 12-bit waveform output.
+    pub waveform: u16,
+
+    /// The envelope value.
+    pub envelope: u8,
+}
+
+/// A snapshot of a SID's filter settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FilterState {
+    /// The 11-bit cutoff frequency value.
+    pub cutoff: u16,
+
+    /// The resonance (0-15).
+    pub resonance: u8,
+
+    /// The voices routed through the filter: bits 0-2 for voices 1-3 and bit 3 for the
+    /// external input.
+    pub routing: u8,
+
+    /// The filter mode: bit 0 for low pass, bit 1 for band pass, bit 2 for high pass, and
+    /// bit 3 to disconnect voice 3 from the output.
+    pub mode: u8,
+}
+
+/// A snapshot of a SID's registers and voices, for register viewers.
+///
+/// `registers` holds what each register was last written with, since the write-only
+/// registers can't be read back from the chip, along with the current values of the
+/// read-only registers. The other fields are decoded from those registers or taken from the
+/// voices' oscillators and envelopes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SidState {
+    /// The registers at $00-$1C.
+    pub registers: [u8; REGISTERS],
+
+    /// The three voices.
+    pub voices: [VoiceState; 3],
+
+    /// The filter settings.
+    pub filter: FilterState,
+
+    /// The master volume (0-15).
+    pub volume: u8,
+}
+
+impl Display for SidState {
+    /// Writes the registers as a dump at their usual address of $D400, followed by a line
+    /// for each voice and one for the filter and volume.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write_registers(f, 0xd400, &self.registers)?;
+        for (i, voice) in self.voices.iter().enumerate() {
+            writeln!(
+                f,
+                "Voice {}: Freq ${:04X}  PW ${:03X}  Ctrl ${:02X}  ADSR {:X}{:X}{:X}{:X}  \
+                 Wave ${:03X}  Env ${:02X}",
+                i + 1,
+                voice.frequency,
+                voice.pulse_width,
+                voice.control,
+                voice.attack,
+                voice.decay,
+                voice.sustain,
+                voice.release,
+                voice.waveform,
+                voice.envelope
+            )?;
+        }
+        write!(
+            f,
+            "Filter: Cutoff ${:03X}  Res ${:X}  Route ${:X}  Mode ${:X}  Volume ${:X}",
+            self.filter.cutoff,
+            self.filter.resonance,
+            self.filter.routing,
+            self.filter.mode,
+            self.volume
+        )
+    }
+}
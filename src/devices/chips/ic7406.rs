@@ -45,7 +45,9 @@ use std::{cell::RefCell, rc::Rc};
 
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange, DUMMY},
+        delay::{drive, DelayRef},
+        device::{next_id, Device, DeviceRef, LevelChange, DUMMY},
+        level::Level,
         pin::{
             Mode::{Input, Output, Unconnected},
             Pin,
@@ -83,19 +85,42 @@ const INPUTS: [usize; 6] = [A1, A2, A3, A4, A5, A6];
 /// ```
 /// GND and Vcc are ground and power supply pins respectively, and they are not emulated.
 ///
+/// The outputs of the 7406 are open-collector, meaning that they can only pull their traces
+/// low. When an output is "high", it actually floats and relies on a pull-up resistor on
+/// its trace to provide the high level. This lets several open-collector outputs share a
+/// single trace in a wired-AND configuration, where the trace is low if any of them are
+/// pulling it low.
+///
 /// In the Commodore 64, U8 is a 7406. It's responsible for inverting logic signals that are
 /// expected in the inverse they're given, such as the 6567's AEC signal being turned into
 /// the inverse AEC signal for the 82S100.
 pub struct Ic7406 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 7406, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
+
+    /// The delay that output changes go through, if there is one.
+    delay: Option<DelayRef>,
 }
 
 impl Ic7406 {
     /// Creates a new 7406 hex inverter emulation and returns a shared, internally mutable
     /// reference to it.
     pub fn new() -> DeviceRef {
+        Ic7406::build(None)
+    }
+
+    /// Creates a new 7406 hex inverter emulation whose outputs change after a propagation
+    /// delay, and returns a shared, internally mutable reference to it.
+    pub fn with_delay(delay: &DelayRef) -> DeviceRef {
+        Ic7406::build(Some(Rc::clone(delay)))
+    }
+
+    /// Creates a new 7406, with or without a delay.
+    fn build(delay: Option<DelayRef>) -> DeviceRef {
         // Input pins. In the TI data sheet, these are named "1A", "2A", etc., and the C64
         // schematic does not suggest names for them. Since these names are not legal
         // variable names, we've switched the letter and number.
@@ -113,13 +138,16 @@ impl Ic7406 {
         let y4 = pin!(Y4, "Y4", Output);
         let y5 = pin!(Y5, "Y5", Output);
         let y6 = pin!(Y6, "Y6", Output);
+        open_collector!(y1, y2, y3, y4, y5, y6);
 
         // Power supply and ground pins, not emulated
         let gnd = pin!(GND, "GND", Unconnected);
         let vcc = pin!(VCC, "VCC", Unconnected);
 
         let device: DeviceRef = new_ref!(Ic7406 {
+            id: next_id(),
             pins: pins![a1, a2, a3, a4, a5, a6, y1, y2, y3, y4, y5, y6, vcc, gnd],
+            delay,
         });
 
         // All outputs begin high since all of the inputs begin non-high.
@@ -155,12 +183,19 @@ impl Ic7406 {
         let y4 = Pin::new(Y4, "Y4", Output);
         let y5 = Pin::new(Y5, "Y5", Output);
         let y6 = Pin::new(Y6, "Y6", Output);
+        y1.borrow_mut().set_open_collector(true);
+        y2.borrow_mut().set_open_collector(true);
+        y3.borrow_mut().set_open_collector(true);
+        y4.borrow_mut().set_open_collector(true);
+        y5.borrow_mut().set_open_collector(true);
+        y6.borrow_mut().set_open_collector(true);
 
         // Power supply and ground pins, not emulated
         let gnd = Pin::new(GND, "GND", Unconnected);
         let vcc = Pin::new(VCC, "VCC", Unconnected);
 
         let device: Rc<RefCell<dyn Device>> = Rc::new(RefCell::new(Ic7406 {
+            id: next_id(),
             pins: RefVec::with_vec(vec![
                 Rc::clone(&dummy),
                 Rc::clone(&a1),
@@ -178,6 +213,7 @@ impl Ic7406 {
                 Rc::clone(&a6),
                 Rc::clone(&vcc),
             ]),
+            delay: None,
         }));
 
         // All outputs begin high since all of the inputs begin non-high.
@@ -213,6 +249,10 @@ fn output_for(input: usize) -> usize {
 }
 
 impl Device for Ic7406 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -223,13 +263,13 @@ impl Device for Ic7406 {
 
     fn update(&mut self, event: &LevelChange) {
         match event {
-            LevelChange(pin) if INPUTS.contains(&number!(pin)) => {
+            LevelChange(pin, level) if INPUTS.contains(&number!(pin)) => {
                 let o = output_for(number!(pin));
-                if high!(pin) {
-                    clear!(self.pins[o]);
-                } else {
-                    set!(self.pins[o]);
-                }
+                let output = match level.digital() {
+                    Level::High => Level::Low,
+                    _ => Level::High,
+                };
+                drive(&self.delay, &self.pins[o], output);
             }
             _ => {}
         }
@@ -238,13 +278,28 @@ impl Device for Ic7406 {
 
 #[cfg(test)]
 mod test {
-    use crate::{components::trace::Trace, test_utils::make_traces};
+    use crate::{
+        components::{
+            clock::{phase, Scheduler},
+            delay::Delay,
+            trace::Trace,
+        },
+        test_utils::make_traces,
+    };
 
     use super::*;
 
+    const OUTPUTS: [usize; 6] = [Y1, Y2, Y3, Y4, Y5, Y6];
+
     fn before_each() -> (DeviceRef, RefVec<Trace>) {
         let chip = Ic7406::new();
         let tr = make_traces(&chip);
+
+        // The outputs are open-collector, so they need pull-ups to go high
+        for y in OUTPUTS {
+            pull_up!(tr[y]);
+        }
+
         (chip, tr)
     }
 
@@ -294,6 +349,68 @@ mod test {
         assert!(high!(tr[Y6]), "Y6 should be high when A6 is low");
     }
 
+    #[test]
+    fn inputs_independent() {
+        let (_, tr) = before_each();
+
+        for a in INPUTS {
+            clear!(tr[a]);
+        }
+
+        for (i, a) in IntoIterator::into_iter(INPUTS).enumerate() {
+            set!(tr[a]);
+            for (j, other) in IntoIterator::into_iter(INPUTS).enumerate() {
+                let y = output_for(other);
+                if i == j {
+                    assert!(
+                        low!(tr[y]),
+                        "Y{} should be low when A{} is high",
+                        j + 1,
+                        j + 1
+                    );
+                } else {
+                    assert!(
+                        high!(tr[y]),
+                        "Y{} should not be affected when A{} goes high",
+                        j + 1,
+                        i + 1
+                    );
+                }
+            }
+            clear!(tr[a]);
+        }
+    }
+
+    #[test]
+    fn wired_and() {
+        let (chip, tr) = before_each();
+
+        // Tie Y1 and Y2 to a single pulled-up trace, like the interrupt lines they drive
+        let pins = chip.borrow().pins();
+        let irq = trace!(pins[Y1], pins[Y2]);
+        pull_up!(irq);
+
+        clear!(tr[A1]);
+        clear!(tr[A2]);
+        assert!(
+            high!(irq),
+            "Shared trace should be high when no output is low"
+        );
+
+        set!(tr[A1]);
+        assert!(low!(irq), "Shared trace should be low when Y1 is low");
+
+        set!(tr[A2]);
+        clear!(tr[A1]);
+        assert!(low!(irq), "Shared trace should be low when Y2 is low");
+
+        clear!(tr[A2]);
+        assert!(
+            high!(irq),
+            "Shared trace should be high when both outputs release"
+        );
+    }
+
     // Duplicate tests using no macros. These use the non-macro creation function as well
     // because I like the symmetry. Only this struct has non-macro versions of the tests,
     // and it's just for demonstration purposes.
@@ -343,4 +460,31 @@ mod test {
         tr[A6].borrow_mut().clear();
         assert!(tr[Y6].borrow().high(), "Y6 should be high when A6 is low");
     }
+
+    #[test]
+    fn delayed_output() {
+        let delay = Delay::new(3);
+        let chip = Ic7406::with_delay(&delay);
+        let tr = make_traces(&chip);
+        pull_up!(tr[Y1]);
+        let mut scheduler = Scheduler::new();
+        scheduler.register(phase::OBSERVERS, delay.clocked());
+
+        let a1 = clone_ref!(tr[A1]);
+        scheduler.at(
+            5,
+            Box::new(move |_| {
+                set!(a1);
+            }),
+        );
+        scheduler.tick_n(8);
+        assert!(high!(tr[Y1]), "Y1 should not change before the delay");
+        scheduler.tick();
+        assert!(low!(tr[Y1]), "Y1 should go low 3 cycles after A1 goes high");
+
+        // Changing the delay affects changes scheduled after it
+        delay.set_propagation_delay(0);
+        clear!(tr[A1]);
+        assert!(high!(tr[Y1]), "Y1 should change at once with no delay");
+    }
 }
@@ -38,9 +38,13 @@ pub mod constants {
     pub const GND: usize = 7;
 }
 
+use std::rc::Rc;
+
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        delay::{drive, DelayRef},
+        device::{next_id, Device, DeviceRef, LevelChange},
+        level::Level,
         pin::{
             Mode::{Input, Output, Unconnected},
             Pin,
@@ -87,15 +91,32 @@ const INPUTS: [usize; 8] = [A1, A2, A3, A4, B1, B2, B3, B4];
 /// signal from the 6567 VIC and the DMA signal from the expansion port combining into the
 /// `RDY` signal for the 6510 CPU.
 pub struct Ic7408 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 7408, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
+
+    /// The delay that output changes go through, if there is one.
+    delay: Option<DelayRef>,
 }
 
 impl Ic7408 {
     /// Creates a new 7408 quad 2-input AND gate emulation and returns a shared, internally
     /// mutable reference to it.
     pub fn new() -> DeviceRef {
+        Ic7408::build(None)
+    }
+
+    /// Creates a new 7408 quad 2-input AND gate emulation whose outputs change after a
+    /// propagation delay, and returns a shared, internally mutable reference to it.
+    pub fn with_delay(delay: &DelayRef) -> DeviceRef {
+        Ic7408::build(Some(Rc::clone(delay)))
+    }
+
+    /// Creates a new 7408, with or without a delay.
+    fn build(delay: Option<DelayRef>) -> DeviceRef {
         // Gate 1 inputs and output
         let a1 = pin!(A1, "A1", Input);
         let b1 = pin!(B1, "B1", Input);
@@ -121,7 +142,9 @@ impl Ic7408 {
         let gnd = pin!(GND, "GND", Unconnected);
 
         let device: DeviceRef = new_ref!(Ic7408 {
+            id: next_id(),
             pins: pins![a1, a2, a3, a4, b1, b2, b3, b4, y1, y2, y3, y4, vcc, gnd],
+            delay,
         });
 
         // All output pins begin low because none have any high inputs.
@@ -149,6 +172,10 @@ fn input_output_for(input: usize) -> (usize, usize) {
 }
 
 impl Device for Ic7408 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -159,18 +186,14 @@ impl Device for Ic7408 {
 
     fn update(&mut self, event: &LevelChange) {
         match event {
-            LevelChange(pin) if INPUTS.contains(&number!(pin)) => {
-                if high!(pin) {
-                    let (i, o) = input_output_for(number!(pin));
-                    if high!(self.pins[i]) {
-                        set!(self.pins[o]);
-                    } else {
-                        clear!(self.pins[o]);
-                    }
-                } else {
-                    let (_, o) = input_output_for(number!(pin));
-                    clear!(self.pins[o]);
-                }
+            LevelChange(pin, level) if INPUTS.contains(&number!(pin)) => {
+                let (i, o) = input_output_for(number!(pin));
+                let other = self.pins[i].borrow().signal();
+                let output = match (level.digital(), other.digital()) {
+                    (Level::High, Level::High) => Level::High,
+                    _ => Level::Low,
+                };
+                drive(&self.delay, &self.pins[o], output);
             }
             _ => {}
         }
@@ -179,7 +202,14 @@ impl Device for Ic7408 {
 
 #[cfg(test)]
 mod test {
-    use crate::{components::trace::Trace, test_utils::make_traces};
+    use crate::{
+        components::{
+            clock::{phase, Scheduler},
+            delay::Delay,
+            trace::Trace,
+        },
+        test_utils::make_traces,
+    };
 
     use super::*;
 
@@ -308,4 +338,56 @@ mod test {
             "Y4 should be high when A4 and B4 are both high"
         );
     }
+
+    /// Runs 40 cycles with a 7408 whose outputs are delayed by 4 cycles, with B1 high and
+    /// A1 set high and low in the given cycles. Returns the cycles in which Y1 is high.
+    fn delayed(pulses: &[(u64, u64)]) -> Vec<u64> {
+        let delay = Delay::new(4);
+        let chip = Ic7408::with_delay(&delay);
+        let tr = make_traces(&chip);
+        set!(tr[B1]);
+
+        let mut scheduler = Scheduler::new();
+        scheduler.register(phase::OBSERVERS, delay.clocked());
+        for &(rise, fall) in pulses {
+            let a1 = clone_ref!(tr[A1]);
+            scheduler.at(
+                rise,
+                Box::new(move |_| {
+                    set!(a1);
+                }),
+            );
+            let a1 = clone_ref!(tr[A1]);
+            scheduler.at(
+                fall,
+                Box::new(move |_| {
+                    clear!(a1);
+                }),
+            );
+        }
+
+        (0..40)
+            .filter(|_| {
+                scheduler.tick();
+                high!(tr[Y1])
+            })
+            .collect()
+    }
+
+    #[test]
+    fn short_pulse_swallowed() {
+        assert!(
+            delayed(&[(10, 12)]).is_empty(),
+            "pulse shorter than the delay should not reach Y1"
+        );
+    }
+
+    #[test]
+    fn long_pulse_delayed() {
+        assert_eq!(
+            delayed(&[(10, 20)]),
+            (14..24).collect::<Vec<u64>>(),
+            "Y1 should follow A1 4 cycles later"
+        );
+    }
 }
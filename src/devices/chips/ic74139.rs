@@ -42,7 +42,7 @@ pub mod constants {
 
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
             Pin,
@@ -124,6 +124,9 @@ use self::constants::*;
 /// the same). Its two demultiplexers are chained together to provide additional address
 /// decoding when the PLA's IO output is selected.
 pub struct Ic74139 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 74139, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
@@ -156,6 +159,7 @@ impl Ic74139 {
         let gnd = pin!(GND, "GND", Unconnected);
 
         let device: DeviceRef = new_ref!(Ic74139 {
+            id: next_id(),
             pins: pins![a1, a2, b1, b2, g1, g2, y10, y11, y12, y13, y20, y21, y22, y23, vcc, gnd]
         });
 
@@ -198,6 +202,10 @@ fn input_control_for(index: usize) -> (usize, usize) {
 }
 
 impl Device for Ic74139 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -242,14 +250,14 @@ impl Device for Ic74139 {
             // We do split the arms for the A pin versus the B pin becuase we need to do
             // something different based on which one it is (HL for AB produces a different
             // output than LH, for example)
-            LevelChange(pin) if number!(pin) == A1 || number!(pin) == A2 => {
+            LevelChange(pin, level) if number!(pin) == A1 || number!(pin) == A2 => {
                 let (b, g) = input_control_for(number!(pin));
                 let (y0, y1, y2, y3) = outputs(number!(pin));
 
                 if high!(self.pins[g]) {
                     set!(self.pins[y0], self.pins[y1], self.pins[y2], self.pins[y3]);
                 } else {
-                    if high!(pin) {
+                    if level.high() {
                         if high!(self.pins[b]) {
                             hh!(y0, y1, y2, y3);
                         } else {
@@ -264,14 +272,14 @@ impl Device for Ic74139 {
                     }
                 }
             }
-            LevelChange(pin) if number!(pin) == B1 || number!(pin) == B2 => {
+            LevelChange(pin, level) if number!(pin) == B1 || number!(pin) == B2 => {
                 let (a, g) = input_control_for(number!(pin));
                 let (y0, y1, y2, y3) = outputs(number!(pin));
 
                 if high!(self.pins[g]) {
                     set!(self.pins[y0], self.pins[y1], self.pins[y2], self.pins[y3]);
                 } else {
-                    if high!(pin) {
+                    if level.high() {
                         if high!(self.pins[a]) {
                             hh!(y0, y1, y2, y3);
                         } else {
@@ -286,11 +294,11 @@ impl Device for Ic74139 {
                     }
                 }
             }
-            LevelChange(pin) if number!(pin) == G1 || number!(pin) == G2 => {
+            LevelChange(pin, level) if number!(pin) == G1 || number!(pin) == G2 => {
                 let (a, b) = inputs(number!(pin));
                 let (y0, y1, y2, y3) = outputs(number!(pin));
 
-                if high!(pin) {
+                if level.high() {
                     set!(self.pins[y0], self.pins[y1], self.pins[y2], self.pins[y3]);
                 } else {
                     match (high!(self.pins[a]), high!(self.pins[b])) {
@@ -46,7 +46,7 @@ pub mod constants {
 
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
             Pin,
@@ -103,6 +103,9 @@ const B_INPUTS: [usize; 4] = [B1, B2, B3, B4];
 /// from the A6 and A7 lines from the 6567 VIC and the VA14 and VA15 lines from one of the
 /// 6526 CIAs.
 pub struct Ic74258 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 74258, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
@@ -146,6 +149,7 @@ impl Ic74258 {
         let gnd = pin!(GND, "GND", Unconnected);
 
         let device: DeviceRef = new_ref!(Ic74258 {
+            id: next_id(),
             pins: pins![a1, a2, a3, a4, b1, b2, b3, b4, y1, y2, y3, y4, oe, sel, vcc, gnd],
         });
 
@@ -168,6 +172,10 @@ fn output_for(input: usize) -> usize {
 }
 
 impl Device for Ic74258 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -227,43 +235,43 @@ impl Device for Ic74258 {
         }
 
         match event {
-            LevelChange(pin) if A_INPUTS.contains(&number!(pin)) => {
+            LevelChange(pin, level) if A_INPUTS.contains(&number!(pin)) => {
                 let y = output_for(number!(pin));
                 if high!(self.pins[OE]) {
                     float!(self.pins[y]);
                 } else if low!(self.pins[SEL]) {
-                    if high!(pin) {
+                    if level.high() {
                         clear!(self.pins[y]);
                     } else {
                         set!(self.pins[y]);
                     }
                 }
             }
-            LevelChange(pin) if B_INPUTS.contains(&number!(pin)) => {
+            LevelChange(pin, level) if B_INPUTS.contains(&number!(pin)) => {
                 let y = output_for(number!(pin));
                 if high!(self.pins[OE]) {
                     float!(self.pins[y]);
                 } else if high!(self.pins[SEL]) {
-                    if high!(pin) {
+                    if level.high() {
                         clear!(self.pins[y]);
                     } else {
                         set!(self.pins[y]);
                     }
                 }
             }
-            LevelChange(pin) if number!(pin) == SEL => {
+            LevelChange(pin, level) if number!(pin) == SEL => {
                 if high!(self.pins[OE]) {
                     float!(self.pins[Y1], self.pins[Y2], self.pins[Y3], self.pins[Y4]);
                 } else {
-                    if high!(pin) {
+                    if level.high() {
                         select_b!();
                     } else {
                         select_a!();
                     }
                 }
             }
-            LevelChange(pin) if number!(pin) == OE => {
-                if high!(pin) {
+            LevelChange(pin, level) if number!(pin) == OE => {
+                if level.high() {
                     float!(self.pins[Y1], self.pins[Y2], self.pins[Y3], self.pins[Y4]);
                 } else {
                     if high!(self.pins[SEL]) {
@@ -51,7 +51,7 @@ pub mod constants {
 
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
             Pin,
@@ -113,13 +113,17 @@ const OUTPUTS: [usize; 8] = [Q0, Q1, Q2, Q3, Q4, Q5, Q6, Q7];
 /// lines are switched to the high 8 bits, those bits do not leak onto the low 8 bits of the
 /// main bus.
 pub struct Ic74373 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 74373, along with a dummy pin (at index 0) to ensure that the vector
     /// index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
 
-    /// The latched output values for each output pin. When the outputs are not being
-    /// latched, all of the values here will be `None`.
-    latches: Vec<Option<f64>>,
+    /// The value of each latch, which is what its output shows whenever OE is low. While
+    /// LE is high, each latch follows its input; while LE is low, it holds the value its
+    /// input had when LE went low.
+    latches: [bool; 8],
 }
 
 impl Ic74373 {
@@ -144,8 +148,8 @@ impl Ic74373 {
         let q6 = pin!(Q6, "Q6", Output);
         let q7 = pin!(Q7, "Q7", Output);
 
-        // Output enable. When this is high, the outputs function normally according to
-        // their inputs and LE. When this is low, the outputs are all hi-Z.
+        // Output enable. When this is low, the outputs show the values of the latches. When
+        // this is high, the outputs are all hi-Z, though the latches still work as usual.
         let oe = pin!(OE, "OE", Input);
 
         // Latch enable. When set high, data flows transparently through the device, with
@@ -157,36 +161,53 @@ impl Ic74373 {
         let vcc = pin!(VCC, "VCC", Unconnected);
         let gnd = pin!(GND, "GND", Unconnected);
 
+        // The latches start out holding whatever is on the inputs
+        let latches = [&d0, &d1, &d2, &d3, &d4, &d5, &d6, &d7].map(|d| high!(d));
+
         let device: DeviceRef = new_ref!(Ic74373 {
+            id: next_id(),
             pins: pins![
                 d0, d1, d2, d3, d4, d5, d6, d7, q0, q1, q2, q3, q4, q5, q6, q7, oe, le, vcc, gnd
             ],
-            latches: vec![None; 8],
+            latches,
         });
 
-        clear!(q0, q1, q2, q3, q4, q5, q6, q7);
+        // OE isn't high, so the outputs start out driven with the latches' values
+        for (q, &latch) in [&q0, &q1, &q2, &q3, &q4, &q5, &q6, &q7]
+            .iter()
+            .zip(&latches)
+        {
+            if latch {
+                set!(q);
+            } else {
+                clear!(q);
+            }
+        }
         attach_to!(device, d0, d1, d2, d3, d4, d5, d6, d7, le, oe);
 
         device
     }
-}
 
-/// Maps each input pin assignment to its corresponding output pin assignm,ent.
-fn output_for(input: usize) -> usize {
-    match input {
-        D0 => Q0,
-        D1 => Q1,
-        D2 => Q2,
-        D3 => Q3,
-        D4 => Q4,
-        D5 => Q5,
-        D6 => Q6,
-        D7 => Q7,
-        _ => 0,
+    /// Sets the level of one output to match its latch, or floats it if the outputs aren't
+    /// enabled (OE is high). The level of OE is passed in because OE's pin can't be read
+    /// while its own change is being handled.
+    fn drive(&self, i: usize, enabled: bool) {
+        let q = &self.pins[OUTPUTS[i]];
+        if !enabled {
+            float!(q);
+        } else if self.latches[i] {
+            set!(q);
+        } else {
+            clear!(q);
+        }
     }
 }
 
 impl Device for Ic74373 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -197,54 +218,25 @@ impl Device for Ic74373 {
 
     fn update(&mut self, event: &LevelChange) {
         match event {
-            LevelChange(pin) if INPUTS.contains(&number!(pin)) => {
-                if high!(self.pins[LE]) && !high!(self.pins[OE]) {
-                    let q = output_for(number!(pin));
-                    if high!(pin) {
-                        set!(self.pins[q]);
-                    } else {
-                        clear!(self.pins[q]);
-                    }
+            LevelChange(pin, level) if INPUTS.contains(&number!(pin)) => {
+                if high!(self.pins[LE]) {
+                    let i = INPUTS.iter().position(|&d| d == number!(pin)).unwrap();
+                    self.latches[i] = level.high();
+                    self.drive(i, !high!(self.pins[OE]));
                 }
             }
-            LevelChange(pin) if number!(pin) == LE => {
-                if high!(pin) {
-                    for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-                        let q = output_for(d);
-                        if high!(self.pins[d]) {
-                            set!(self.pins[q]);
-                        } else {
-                            clear!(self.pins[q]);
-                        }
-                        self.latches[i] = None;
-                    }
-                } else {
-                    for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-                        self.latches[i] = if high!(self.pins[d]) {
-                            Some(1.0)
-                        } else {
-                            Some(0.0)
-                        };
-                    }
+            LevelChange(pin, _) if number!(pin) == LE => {
+                // Going high, the latches start following their inputs again; going low,
+                // they hold what the inputs are now. Either way, that's the inputs' levels.
+                let enabled = !high!(self.pins[OE]);
+                for (i, d) in INPUTS.iter().enumerate() {
+                    self.latches[i] = high!(self.pins[*d]);
+                    self.drive(i, enabled);
                 }
             }
-            LevelChange(pin) if number!(pin) == OE => {
-                if high!(pin) {
-                    for q in OUTPUTS {
-                        float!(self.pins[q]);
-                    }
-                } else {
-                    let latched = !high!(self.pins[LE]);
-                    for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-                        let q = output_for(d);
-                        if latched {
-                            set_level!(self.pins[q], self.latches[i]);
-                        } else if high!(self.pins[d]) {
-                            set!(self.pins[q]);
-                        } else {
-                            clear!(self.pins[q]);
-                        }
-                    }
+            LevelChange(pin, level) if number!(pin) == OE => {
+                for i in 0..OUTPUTS.len() {
+                    self.drive(i, !level.high());
                 }
             }
             _ => (),
@@ -254,166 +246,199 @@ impl Device for Ic74373 {
 
 #[cfg(test)]
 mod test {
-    use crate::{components::trace::Trace, test_utils::make_traces};
+    use crate::components::{bus::Bus, trace::TraceRef};
 
     use super::*;
 
-    fn before_each() -> (DeviceRef, RefVec<Trace>) {
+    fn before_each() -> (DeviceRef, Bus, Bus, TraceRef, TraceRef) {
         let chip = Ic74373::new();
-        let tr = make_traces(&chip);
-        set!(tr[LE]);
-        clear!(tr[OE]);
-        (chip, tr)
+        let pins = chip.borrow().pins();
+
+        let mut d = Bus::new(8, "D");
+        d.connect(&INPUTS.map(|n| pins.get_ref(n))).unwrap();
+        let mut q = Bus::new(8, "Q");
+        q.connect(&OUTPUTS.map(|n| pins.get_ref(n))).unwrap();
+
+        let le = trace!(pins[LE]);
+        let oe = trace!(pins[OE]);
+        set!(le);
+        clear!(oe);
+        (chip, d, q, le, oe)
     }
 
     #[test]
     fn pass_high_le() {
-        let (_, tr) = before_each();
+        let (_, mut d, q, _, _) = before_each();
 
-        for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-            let q = output_for(d);
-            set!(tr[d]);
+        for i in 0..8 {
+            set!(d.trace(i));
             assert!(
-                high!(tr[q]),
+                high!(q.trace(i)),
                 "Q{0} should be high when LE is high and D{0} is high",
                 i
             );
         }
+        assert_eq!(q.read(), Some(0xff));
 
-        for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-            let q = output_for(d);
-            clear!(tr[d]);
+        for i in 0..8 {
+            clear!(d.trace(i));
             assert!(
-                low!(tr[q]),
+                low!(q.trace(i)),
                 "Q{0} should be low when LE is high and D{0} is low",
                 i
             );
         }
+        assert_eq!(q.read(), Some(0x00));
+
+        d.set_value(0x96);
+        assert_eq!(q.read(), Some(0x96));
     }
 
     #[test]
     fn latch_low_le() {
-        let (_, tr) = before_each();
+        let (_, mut d, q, le, _) = before_each();
 
         // Sets outputs to 01010101 (Q7-Q0)
-        for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-            set_level!(tr[d], Some(((i + 1) % 2) as f64));
-        }
-
-        clear!(tr[LE]);
+        d.set_value(0x55);
+        clear!(le);
 
         // Odd outputs remain low even when inputs are all set high
-        for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-            let q = output_for(d);
-            set!(tr[d]);
-            assert_eq!(
-                level!(tr[q]).unwrap(),
-                ((i + 1) % 2) as f64,
-                "Q{} should remain {} when LE is low",
-                i,
-                if i % 2 == 0 { "high" } else { "low" }
-            );
-        }
+        d.set_value(0xff);
+        assert_eq!(q.read(), Some(0x55), "Q should remain $55 when LE is low");
+
         // Even outputs remain high even when inputs are set low
-        for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-            let q = output_for(d);
-            clear!(tr[d]);
-            assert_eq!(
-                level!(tr[q]).unwrap(),
-                ((i + 1) % 2) as f64,
-                "Q{} should remain {} when LE is low",
-                i,
-                if i % 2 == 0 { "high" } else { "low" }
-            );
-        }
+        d.set_value(0x00);
+        assert_eq!(q.read(), Some(0x55), "Q should remain $55 when LE is low");
     }
 
     #[test]
     fn pass_return_to_high_le() {
-        let (_, tr) = before_each();
+        let (_, mut d, q, le, _) = before_each();
 
         // Sets outputs to 01010101 (Q7-Q0)
-        for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-            set_level!(tr[d], Some(((i + 1) % 2) as f64));
-        }
+        d.set_value(0x55);
+        clear!(le);
 
-        clear!(tr[LE]);
-
-        for (i, d) in IntoIterator::into_iter(INPUTS).enumerate() {
-            let q = output_for(d);
-            // All inputs are set high here
-            set!(tr[d]);
-            assert_eq!(
-                level!(tr[q]).unwrap(),
-                ((i + 1) % 2) as f64,
-                "Q{} should remain {} when LE is low",
-                i,
-                if i % 2 == 0 { "high" } else { "low" }
-            );
-        }
+        // All inputs are set high here
+        d.set_value(0xff);
+        assert_eq!(q.read(), Some(0x55), "Q should remain $55 when LE is low");
 
-        set!(tr[LE]);
+        set!(le);
 
         // Outputs immediately switch to input value, which is still all high
-        for (i, q) in IntoIterator::into_iter(OUTPUTS).enumerate() {
-            assert!(
-                high!(tr[q]),
-                "Q{0} should be high when LE is high and D{0} is high",
-                i
-            )
-        }
+        assert_eq!(
+            q.read(),
+            Some(0xff),
+            "Q should match D as soon as LE goes high"
+        );
     }
 
     #[test]
     fn float_high_oe() {
-        let (_, tr) = before_each();
+        let (_, mut d, q, _, oe) = before_each();
+
+        d.set_value(0xff);
+        set!(oe);
 
-        for d in INPUTS {
-            set!(tr[d]);
+        for i in 0..8 {
+            assert!(floating!(q.trace(i)), "Q{} should float when OE is high", i);
         }
+        assert_eq!(q.read(), None);
+
+        clear!(oe);
+        assert_eq!(
+            q.read(),
+            Some(0xff),
+            "Q should match D when OE is low and LE is high"
+        );
+    }
 
-        set!(tr[OE]);
+    #[test]
+    fn recall_latch_high_oe() {
+        let (_, mut d, q, le, oe) = before_each();
 
-        for (i, q) in IntoIterator::into_iter(OUTPUTS).enumerate() {
-            assert!(floating!(tr[q]), "Q{} should float when OE is high", i);
-        }
+        d.set_value(0xff);
+        set!(oe);
 
-        clear!(tr[OE]);
+        // Clears D0, D2, D4, and D6
+        d.set_value(0xaa);
+        clear!(le);
+        clear!(oe);
 
-        for (i, q) in IntoIterator::into_iter(OUTPUTS).enumerate() {
-            assert!(
-                high!(tr[q]),
-                "Q{0} should be high when LE is high and D{0} is high",
-                i
-            );
-        }
+        assert_eq!(
+            q.read(),
+            Some(0xaa),
+            "Q should recall the value latched while OE was high"
+        );
     }
 
     #[test]
-    fn recall_latch_high_oe() {
-        let (_, tr) = before_each();
+    fn partial_float_inputs() {
+        let (_, mut d, q, _, _) = before_each();
+
+        d.set_value(0xff);
+        float!(d.trace(3));
+
+        assert_eq!(d.read(), None, "D should not read while a line floats");
+        assert_eq!(d.value(), 0xf7);
+        assert_eq!(
+            q.read(),
+            Some(0xf7),
+            "a floating input passes through the latch as low"
+        );
+    }
 
-        for d in INPUTS {
-            set!(tr[d]);
-        }
+    #[test]
+    fn power_on_oe_toggle() {
+        // LE is never connected or touched, so the latches still hold what they were
+        // given at construction
+        let chip = Ic74373::new();
+        let pins = chip.borrow().pins();
+        let mut d = Bus::new(8, "D");
+        d.connect(&INPUTS.map(|n| pins.get_ref(n))).unwrap();
+        let mut q = Bus::new(8, "Q");
+        q.connect(&OUTPUTS.map(|n| pins.get_ref(n))).unwrap();
+        let oe = trace!(pins[OE]);
+
+        set!(oe);
+        assert_eq!(q.read(), None, "Q should float when OE is high");
+        clear!(oe);
+        assert_eq!(
+            q.read(),
+            Some(d.value()),
+            "Q should be driven with the inputs from power-on when OE goes low"
+        );
+    }
 
-        set!(tr[OE]);
+    #[test]
+    fn latch_while_oe_high() {
+        let (_, mut d, q, le, oe) = before_each();
+
+        d.set_value(0x3c);
+        clear!(le);
+        set!(oe);
+        d.set_value(0xc3);
+        assert_eq!(q.read(), None, "Q should float while OE is high");
+
+        clear!(oe);
+        assert_eq!(
+            q.read(),
+            Some(0x3c),
+            "Q should show the value latched before OE went high"
+        );
+    }
 
-        for d in [D0, D2, D4, D6] {
-            clear!(tr[d]);
-        }
-        clear!(tr[LE]);
-
-        clear!(tr[OE]);
-
-        for (i, q) in IntoIterator::into_iter(OUTPUTS).enumerate() {
-            assert_eq!(
-                level!(tr[q]).unwrap(),
-                (i % 2) as f64,
-                "Q{} should remain {} when LE is low",
-                i,
-                if i % 2 == 0 { "low" } else { "high" }
-            )
-        }
+    #[test]
+    fn follow_inputs_while_oe_high() {
+        let (_, mut d, q, _, oe) = before_each();
+
+        set!(oe);
+        d.set_value(0x81);
+        clear!(oe);
+        assert_eq!(
+            q.read(),
+            Some(0x81),
+            "Q should show inputs that changed while OE was high and LE was high"
+        );
     }
 }
@@ -126,19 +126,42 @@ pub mod constants {
     pub const ROMH: usize = F7;
 }
 
+#[cfg(test)]
+use std::cell::Cell;
+
 use crate::{
     components::{
-        device::{Device, DeviceRef, LevelChange},
+        device::{next_id, Device, DeviceRef, LevelChange},
         pin::{
             Mode::{Input, Output, Unconnected},
             Pin,
         },
     },
+    devices::pla::pla_output,
     vectors::RefVec,
 };
 
 use self::constants::*;
 
+/// The input pins, in the order of their bits in an input word.
+const INPUTS: [usize; 16] = [
+    I0, I1, I2, I3, I4, I5, I6, I7, I8, I9, I10, I11, I12, I13, I14, I15,
+];
+
+/// The output pins, in the order of their bits in an output word.
+const OUTPUTS: [usize; 8] = [F0, F1, F2, F3, F4, F5, F6, F7];
+
+#[cfg(test)]
+thread_local! {
+    /// The number of times the outputs have been worked out from the inputs. Tests use this
+    /// to check that input changes that leave the input word alone skip that work.
+    static EVALUATIONS: Cell<usize> = const { Cell::new(0) };
+
+    /// The number of writes `drive` has made to output pins. Tests use this to check that
+    /// outputs that don't change aren't driven again.
+    static WRITES: Cell<usize> = const { Cell::new(0) };
+}
+
 /// An emulation of the 82S100 Programmable Logic Array, as it was programmed for early
 /// Commodore 64s.
 ///
@@ -191,39 +214,39 @@ use self::constants::*;
 /// ### Input pin assignments
 ///
 /// * I0: CAS. This is the control line from the VIC that enables access to RAM. Instead of
-///     going directly to RAM, this line comes to the PLA and if RAM access is selected, the
-///     CASRAM output is sent to the RAM chips.
+///   going directly to RAM, this line comes to the PLA and if RAM access is selected, the
+///   CASRAM output is sent to the RAM chips.
 /// * I1: LORAM. This line controls the memory block from $A000 - $BFFF. When high (normal),
-///     the BASIC ROM is available in this memory block. When low, it's switched out for
-///     RAM.
+///   the BASIC ROM is available in this memory block. When low, it's switched out for
+///   RAM.
 /// * I2: HIRAM. This line controls the memory area from $E000 - $FFFF. When high (normal),
-///     the KERNAL ROM is available in this memory block. When low, it's switched out for
-///     RAM.
+///   the KERNAL ROM is available in this memory block. When low, it's switched out for
+///   RAM.
 /// * I3: CHAREN. This line partially controls the memory block from $D000 - $DFFF. When
-///     high (normal), the I/O devices appear in this memory block. When low, the character
-///     ROM appears here instead. This line can be overridden by other signals, including
-///     those allowing this memory block to contain RAM instead.
+///   high (normal), the I/O devices appear in this memory block. When low, the character
+///   ROM appears here instead. This line can be overridden by other signals, including
+///   those allowing this memory block to contain RAM instead.
 /// * I4, I14-I15: VA14, VA13, and VA12. When the VIC is active, these are used to determine
-///     which block of memory the VIC is trying to access. VA14 is active low because that's
-///     the way it's generated by CIA2; it's inverted back into active high before being
-///     used to access memory but not for use with the PLA.
+///   which block of memory the VIC is trying to access. VA14 is active low because that's
+///   the way it's generated by CIA2; it's inverted back into active high before being
+///   used to access memory but not for use with the PLA.
 /// * I5-I8: A15-A12. Used to determine which block of memory the CPU wishes to access while
-///     the CPU is active.
+///   the CPU is active.
 /// * I9: BA. The VIC sets this "Bus Available" line high to indicate normal bus operation,
-///     switching between VIC and CPU each cycle. The VIC sets it low when it intends to
-///     take exclusive access of the address bus.
+///   switching between VIC and CPU each cycle. The VIC sets it low when it intends to
+///   take exclusive access of the address bus.
 /// * I10: AEC. This inverse of the VIC's "Address Enable Control" signal indicates which of
-///     the CPU (low) or VIC (high) is in control of the data bus.
+///   the CPU (low) or VIC (high) is in control of the data bus.
 /// * I11: R_W. A high signal means that memory is being read, while a low means memory is
-///     being written. This makes a difference to what memory is being accessed. For
-///     example, ROM cannot be written; if an attempt is made to write to a ROM address
-///     while ROM is banked in, the write will happen to RAM instead.
+///   being written. This makes a difference to what memory is being accessed. For
+///   example, ROM cannot be written; if an attempt is made to write to a ROM address
+///   while ROM is banked in, the write will happen to RAM instead.
 /// * I12-I13: GAME and EXROM. These are used by cartridges to indicate the presence of
-///     addressable memory within them. There are two for-sure states for these two signals:
-///     if both are high (the normal state), there is no cartridge; and if EXROM is high and
-///     GAME is low, there is an Ultimax cartridge. Further mapping to cartridge ROM depends
-///     not only on these two signals but also on LORAM, HIRAM, and CHAREN` there is a nice
-///     table at https://www.c64-wiki.com/wiki/Bank_Switching#Mode_Table.
+///   addressable memory within them. There are two for-sure states for these two signals:
+///   if both are high (the normal state), there is no cartridge; and if EXROM is high and
+///   GAME is low, there is an Ultimax cartridge. Further mapping to cartridge ROM depends
+///   not only on these two signals but also on LORAM, HIRAM, and CHAREN` there is a nice
+///   table at https://www.c64-wiki.com/wiki/Bank_Switching#Mode_Table.
 ///
 /// ### Output pin assignments
 ///
@@ -232,23 +255,23 @@ use self::constants::*;
 /// given time and at most one will be low.
 ///
 /// * F0: CASRAM. This is the signal that ultimately enables RAM. The production of this
-///     signal is very different from the others. For all other output, if one of their
-///     terms is selected, that output will be selected. For CASRAM, its terms are combined
-///     with the terms from all other outputs (except for GR_W), and if any of the terms are
-///     selected, then CASRAM will be *de*selected. (Even if all of those other outputs are
-///     deselected, CASRAM will be deselected if CAS is high or if certain addresses are
-///     accessed while an Ultimax cartridge is plugged in. In these cases, no PLA outputs
-///     will be selected.)
+///   signal is very different from the others. For all other output, if one of their
+///   terms is selected, that output will be selected. For CASRAM, its terms are combined
+///   with the terms from all other outputs (except for GR_W), and if any of the terms are
+///   selected, then CASRAM will be *de*selected. (Even if all of those other outputs are
+///   deselected, CASRAM will be deselected if CAS is high or if certain addresses are
+///   accessed while an Ultimax cartridge is plugged in. In these cases, no PLA outputs
+///   will be selected.)
 /// * F1: BASIC. Enables the BASIC ROM.
 /// * F2: KERNAL. Enables the KERNAL ROM.
 /// * F3: CHAROM. Enables the Character ROM.
 /// * F4: GR_W. Indicates that the static color RAM is being written to. Note that this is
-///     the only output that is not actually a chip enable signal; color RAM is possibly
-///     enabled if the IO output is selected (see the Ic74139 for details).
+///   the only output that is not actually a chip enable signal; color RAM is possibly
+///   enabled if the IO output is selected (see the Ic74139 for details).
 /// * F5: IO. Indicates that one of the following are going to be enabled: CIA1 registers,
-///     CIA2 registers, VIC registers, SID registers, color RAM, expansion port I/O from
-///     address $DE00 - $DEFF, or expansion port I/O from address $DF00 - $DFFF. Which of
-///     these is actually enabled is done by decoding A8-A11, which is done by the 74139.
+///   CIA2 registers, VIC registers, SID registers, color RAM, expansion port I/O from
+///   address $DE00 - $DEFF, or expansion port I/O from address $DF00 - $DFFF. Which of
+///   these is actually enabled is done by decoding A8-A11, which is done by the 74139.
 /// * F6: ROML. Enables expansion port ROM from $8000 - $9FFF.
 /// * F7: ROMH. Enables expansion port ROM from $E000 - $EFFF.
 ///
@@ -256,7 +279,8 @@ use self::constants::*;
 /// 82S100 in "The C64 PLA Dissected" at
 /// http://skoe.de/docs/c64-dissected/pla/c64_pla_dissected_a4ds.pdf. This document was used
 /// to derive all of the logic in this object and has a number of interesting stories
-/// besides (if you find that sort of thing interesting).
+/// besides (if you find that sort of thing interesting). The logic itself is in
+/// `devices::pla`, where it can be used without the pins.
 ///
 /// Additionally, the 82S100 has an active-low chip enable pin CE which is not used in the
 /// Commodore 64 (it is tied directly to ground and therefore is always low, so the chip is
@@ -323,12 +347,32 @@ use self::constants::*;
 /// | --- | ----- | -------- | ----------------------------------------------------------- |
 /// | 28  | VCC   |          | +5V power supply. Not emulated.                             |
 ///
+/// The 82S100's inputs are TTL inputs, and an unconnected TTL input floats high. Any input
+/// pin that's floating is therefore read as high. OE is different: the outputs are only
+/// disabled while it's actually high, since it's tied to ground in the C64 and should
+/// behave that way even if nothing has been connected to it. The outputs only change when
+/// the inputs change in a way that changes them; setting an input to the level it already
+/// had, or changing one that none of the outputs depend on at the time, drives nothing.
+///
 /// In the Commodore 64, U17 is an 82S100. As detailed extensively above, it was used to
 /// decode signals to determine which chip would receive a particular read or write.
 pub struct Ic82S100 {
+    /// The unique identifier of this device.
+    id: usize,
+
     /// The pins of the 82S100, along with a dummy pin (at index 0) to ensure that the
     /// vector index of the others matches the 1-based pin assignments.
     pins: RefVec<Pin>,
+
+    /// The levels of the input pins as of their last changes, with bit n holding the level
+    /// of In.
+    inputs: u16,
+
+    /// The levels last driven onto the output pins, with bit n holding the level of Fn.
+    outputs: u8,
+
+    /// Whether the outputs are being driven. This is false while OE is high.
+    enabled: bool,
 }
 
 impl Ic82S100 {
@@ -372,24 +416,54 @@ impl Ic82S100 {
         let vcc = pin!(VCC, "VCC", Unconnected);
         let vss = pin!(VSS, "VSS", Unconnected);
 
-        let device: DeviceRef = new_ref!(Ic82S100 {
+        let mut pla = Ic82S100 {
+            id: next_id(),
             pins: pins![
                 i0, i1, i2, i3, i4, i5, i6, i7, i8, i9, i10, i11, i12, i13, i14, i15, f0, f1, f2,
                 f3, f4, f5, f6, f7, oe, fe, vcc, vss
             ],
-        });
+            inputs: 0xffff,
+            outputs: 0,
+            enabled: true,
+        };
 
-        clear!(f0);
-        set!(f1, f2, f3, f4, f5, f6, f7);
+        // Every input starts out floating, which reads as high
+        pla.drive(pla.evaluate(), 0xff);
+
+        let device: DeviceRef = new_ref!(pla);
         attach_to!(
             device, i0, i1, i2, i3, i4, i5, i6, i7, i8, i9, i10, i11, i12, i13, i14, i15, oe
         );
 
         device
     }
+
+    /// Returns the levels of the outputs for the current levels of the inputs.
+    fn evaluate(&self) -> u8 {
+        #[cfg(test)]
+        EVALUATIONS.with(|e| e.set(e.get() + 1));
+        pla_output(self.inputs)
+    }
+
+    /// Drives the output pins whose bits are set in `changed` to the levels of the same bits
+    /// in `outputs`.
+    fn drive(&mut self, outputs: u8, changed: u8) {
+        for (bit, &n) in OUTPUTS.iter().enumerate() {
+            if changed & (1 << bit) != 0 {
+                #[cfg(test)]
+                WRITES.with(|w| w.set(w.get() + 1));
+                set_level!(self.pins[n], outputs & (1 << bit) != 0);
+            }
+        }
+        self.outputs = outputs;
+    }
 }
 
 impl Device for Ic82S100 {
+    fn id(&self) -> usize {
+        self.id
+    }
+
     fn pins(&self) -> RefVec<Pin> {
         self.pins.clone()
     }
@@ -399,289 +473,43 @@ impl Device for Ic82S100 {
     }
 
     fn update(&mut self, event: &LevelChange) {
-        macro_rules! value_in {
-            ($pin:expr, $target:expr) => {
-                if number!($pin) == $target {
-                    high!($pin)
+        match event {
+            LevelChange(pin, _) if number!(pin) == OE => {
+                if high!(pin) {
+                    self.enabled = false;
+                    float!(
+                        self.pins[F0],
+                        self.pins[F1],
+                        self.pins[F2],
+                        self.pins[F3],
+                        self.pins[F4],
+                        self.pins[F5],
+                        self.pins[F6],
+                        self.pins[F7]
+                    );
                 } else {
-                    high!(self.pins[$target])
+                    // The outputs were floating, so every one of them has to be driven
+                    // again
+                    self.enabled = true;
+                    self.drive(self.evaluate(), 0xff);
                 }
-            };
-        }
-        macro_rules! value_out {
-            ($value:expr, $target:expr) => {
-                set_level!(
-                    self.pins[$target],
-                    if $value { Some(1.0) } else { Some(0.0) }
-                )
-            };
-        }
-
-        match event {
-            LevelChange(pin) if number!(pin) == OE && high!(pin) => {
-                float!(
-                    self.pins[F0],
-                    self.pins[F1],
-                    self.pins[F2],
-                    self.pins[F3],
-                    self.pins[F4],
-                    self.pins[F5],
-                    self.pins[F6],
-                    self.pins[F7]
-                );
             }
-            LevelChange(pin) => {
-                // These are the product term equations programmed into the PLA for use in a
-                // C64. The names for each signal reflect the names of the pins that those
-                // signals come from, and while that is an excellent way to make long and
-                // complex code succinct, it doesn't do much for the human reader. For that
-                // reason, each term has a comment to describe in more human terms what is
-                // happening with that piece of the algorithm.
-                //
-                // Each P-term below has a comment with three lines. The first line
-                // describes the state of the three 6510 I/O port lines that are used for
-                // bank switching (LORAM, HIRAM, and CHAREN). The second line is the memory
-                // address that needs to be accessed to select that P-term (this is from
-                // either the regular address bus when the CPU is active or the VIC address
-                // bus when the VIC is active). The final line gives information about
-                // whether the CPU or the VIC is active, whether the memory access is a read
-                // or a write, and what type (if any) of cartridge must be plugged into the
-                // expansion port (the cartridge informaion takes into account the values of
-                // LORAM, HIRAM, and CHAREN already).
-                //
-                // If any piece of information is not given, its value doesn't matter to
-                // that P-term. For example, in p0, the comment says that LORAM and HIRAM
-                // must both be deselected. CHAREN isn't mentioned because whether it is
-                // selected or not doesn't change whether that P-term is selected or not.
-                //
-                // Oftentimes, the reason for multiple terms for one output selection is the
-                // limitation on what can be checked in a single logic term, given that no
-                // ORs are possible in the production of P-terms. For example, it is very
-                // common to see two terms that are identical except that one indicates "no
-                // cartridge or 8k cartridge" while the other has "16k cartridge". These two
-                // terms together really mean "anything but an Ultimax cartridge", but
-                // there's no way to do that in a single term with only AND and NOT.
-                //
-                // This information comes from the excellent paper available at
-                // skoe.de/docs/c64-dissected/pla/c64_pla_dissected_a4ds.pdf. If this sort
-                // of thing interests you, there's no better place for information about the
-                // C64 PLA.
-                let cas = value_in!(pin, CAS);
-                let loram = value_in!(pin, LORAM);
-                let hiram = value_in!(pin, HIRAM);
-                let charen = value_in!(pin, CHAREN);
-                let va14 = value_in!(pin, VA14);
-                let a15 = value_in!(pin, A15);
-                let a14 = value_in!(pin, A14);
-                let a13 = value_in!(pin, A13);
-                let a12 = value_in!(pin, A12);
-                let ba = value_in!(pin, BA);
-                let aec = value_in!(pin, AEC);
-                let r_w = value_in!(pin, R_W);
-                let exrom = value_in!(pin, EXROM);
-                let game = value_in!(pin, GAME);
-                let va13 = value_in!(pin, VA13);
-                let va12 = value_in!(pin, VA12);
-
-                // LORAM deselected, HIRAM deselected
-                // $A000 - $BFFF
-                // CPU active, Read, No cartridge or 8k cartridge
-                let p0 = loram & hiram & a15 & !a14 & a13 & !aec & r_w & game;
-
-                // HIRAM deselected
-                // $E000 - $FFFF
-                // CPU active, Read, No cartridge or 8k cartridge
-                let p1 = hiram & a15 & a14 & a13 & !aec & r_w & game;
-
-                // HIRAM deselected
-                // $E000 - $FFFF
-                // CPU active, Read, 16k cartridge
-                let p2 = hiram & a15 & a14 & a13 & !aec & r_w & !exrom & !game;
-
-                // HIRAM deselected, CHAREN selected
-                // $D000 - $DFFF
-                // CPU active, Read, No cartridge or 8k cartridge
-                let p3 = hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game;
-
-                // LORAM deselected, CHAREN selected
-                // $D000 - $DFFF
-                // CPU active, Read, No cartridge or 8k cartridge
-                let p4 = loram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game;
-
-                // HIRAM deselected, CHAREN selected
-                // $D000 - $DFFF
-                // CPU active, Read, 16k cartridge
-                let p5 = hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & !exrom & !game;
-
-                //
-                // $1000 - $1FFF or $9000 - $9FFF
-                // VIC active, No cartridge or 8k cartridge
-                let p6 = va14 & !va13 & va12 & aec & game;
-
-                //
-                // $1000 - $1FFF or $9000 - $9FFF
-                // VIC active, 16k cartridge
-                let p7 = va14 & !va13 & va12 & aec & !exrom & !game;
-
-                // Unused. May be a relic from earlier design in C64 prototypes that never
-                // got removed.
-                // let p8 = cas & a15 & a14 & !a12 & a11 & !aec & !r_w;
-
-                // HIRAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Bus available, Read, No cartridge or 8k cartridge
-                let p9 = hiram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & game;
-
-                // HIRAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Write, No cartridge or 8k cartridge
-                let p10 = hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game;
-
-                // LORAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Bus available, Read, No cartridge or 8k cartridge
-                let p11 = loram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & game;
-
-                // LORAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Write, No cartridge or 8k cartridge
-                let p12 = loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game;
-
-                // HIRAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Bus available, Read, 16k cartridge
-                let p13 =
-                    hiram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & !exrom & !game;
-
-                // HIRAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Write, 16k cartridge
-                let p14 = hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game;
-
-                // LORAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Bus available, Read, 16k cartridge
-                let p15 =
-                    loram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & !exrom & !game;
-
-                // LORAM deselected, CHAREN deselected
-                // $D000 - $DFFF
-                // CPU active, Write, 16k cartridge
-                let p16 = loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game;
-
-                //
-                // $D000 - $DFFF
-                // CPU active, Bus available, Read, Ultimax cartridge
-                let p17 = a15 & a14 & !a13 & a12 & !aec & ba & r_w & exrom & !game;
-
-                //
-                // $D000 - $DFFF
-                // CPU active, Write, Ultimax cartridge
-                let p18 = a15 & a14 & !a13 & a12 & !aec & !r_w & exrom & !game;
-
-                // LORAM deselected, HIRAM deselected
-                // $8000 - $9FFF
-                // CPU active, Read, 8k or 16k cartridge
-                let p19 = loram & hiram & a15 & !a14 & !a13 & !aec & r_w & !exrom;
-
-                //
-                // $8000 - $9FFF
-                // CPU active, Ultimax cartridge
-                let p20 = a15 & !a14 & !a13 & !aec & exrom & !game;
-
-                // HIRAM deselected
-                // $A000 - $BFFF
-                // CPU active, Read, 16k cartridge
-                let p21 = hiram & a15 & !a14 & a13 & !aec & r_w & !exrom & !game;
-
-                //
-                // $E000 - $EFFF
-                // CPU active, Ultimax cartridge
-                let p22 = a15 & a14 & a13 & !aec & exrom & !game;
-
-                //
-                // $3000 - $3FFF, $7000 - $7FFF, $B000 - $BFFF, or $E000 - $EFFF
-                // VIC active, Ultimax cartridge
-                let p23 = va13 & va12 & aec & exrom & !game;
-
-                //
-                // $1000 - $1FFF or $3000 - $3FFF
-                // Ultimax cartridge
-                let p24 = !a15 & !a14 & a12 & exrom & !game;
-
-                //
-                // $2000 - $3FFF
-                // Ultimax cartridge
-                let p25 = !a15 & !a14 & a13 & exrom & !game;
-
-                //
-                // $4000 - $7FFF
-                // Ultimax cartridge
-                let p26 = !a15 & a14 & exrom & !game;
-
-                //
-                // $A000 - $BFFF
-                // Ultimax cartridge
-                let p27 = a15 & !a14 & a13 & exrom & !game;
-
-                //
-                // $C000 - $CFFF
-                // Ultimax cartridge
-                let p28 = a15 & a14 & !a13 & !a12 & exrom & !game;
-
-                // Unused.
-                // let p29 = !loram;
-
-                // CAS deselected
-                //
-                //
-                let p30 = cas;
-
-                // CAS selected
-                // $D000 - $DFFF
-                // CPU access, Write
-                let p31 = !cas & a15 & a14 & !a13 & a12 & !aec & !r_w;
-
-                // This is the sum-term (S-term) portion of the logic, where the P-terms
-                // calculated above are logically ORed to poroduce a single output. This is
-                // much simpler than P-term production because the P-terms handle everything
-                // about chip selection, except that each chip may be the choice of several
-                // different P-terms. That's the role of the S-term logic, to combine
-                // P-terms to come up with single outputs.
-
-                // Selects BASIC ROM.
-                let s1 = p0;
-
-                // Selects KERNAL ROM.
-                let s2 = p1 | p2;
-
-                // Selects Character ROM.
-                let s3 = p3 | p4 | p5 | p6 | p7;
-
-                // Selects I/O, color RAM, or processor registers.
-                let s4 = p9 | p10 | p11 | p12 | p13 | p14 | p15 | p16 | p17 | p18;
-
-                // Selects low cartridge ROM.
-                let s5 = p19 | p20;
-
-                // Selects high cartridge ROM.
-                let s6 = p21 | p22 | p23;
-
-                // Selects write mode for color RAM.
-                let s7 = p31;
-
-                // Deselects RAM. This is the only *de*selection, which is why it is the
-                // only one not inverted in the state assignment below.
-                let s0 = s1 | s2 | s3 | s4 | s5 | s6 | p24 | p25 | p26 | p27 | p28 | p30;
-
-                value_out!(s0, CASRAM);
-                value_out!(!s1, BASIC);
-                value_out!(!s2, KERNAL);
-                value_out!(!s3, CHAROM);
-                value_out!(!s7, GR_W);
-                value_out!(!s4, IO);
-                value_out!(!s5, ROML);
-                value_out!(!s6, ROMH);
+            LevelChange(pin, _) => {
+                if let Some(bit) = INPUTS.iter().position(|&n| n == number!(pin)) {
+                    // Floating inputs read as high, just like unconnected TTL inputs
+                    let inputs = if low!(pin) {
+                        self.inputs & !(1 << bit)
+                    } else {
+                        self.inputs | 1 << bit
+                    };
+                    if inputs != self.inputs {
+                        self.inputs = inputs;
+                        if self.enabled {
+                            let outputs = self.evaluate();
+                            self.drive(outputs, outputs ^ self.outputs);
+                        }
+                    }
+                }
             }
         }
     }
@@ -690,135 +518,18 @@ impl Device for Ic82S100 {
 #[cfg(test)]
 mod test {
     use crate::{
-        components::trace::{Trace, TraceRef},
-        test_utils::{make_traces, traces_to_value, value_to_traces},
+        components::{pin::with_batch, trace::Trace},
+        test_utils::{make_traces, pla_reference, traces_to_value, value_to_traces},
     };
 
     use super::*;
 
-    const INPUTS: [usize; 16] = [
-        I0, I1, I2, I3, I4, I5, I6, I7, I8, I9, I10, I11, I12, I13, I14, I15,
-    ];
-    const OUTPUTS: [usize; 8] = [F0, F1, F2, F3, F4, F5, F6, F7];
-
-    // This function was adapted from a C program that provides a 64k table of outputs for
-    // PLA based on all of the possible inputs. The original is located at
-    // http://www.zimmers.net/anonftp/pub/cbm/firmware/computers/c64/pla.c.
-    fn get_expected(input: u16) -> u8 {
-        let cas = input & (1 << 0) != 0;
-        let loram = input & (1 << 1) != 0;
-        let hiram = input & (1 << 2) != 0;
-        let charen = input & (1 << 3) != 0;
-        let va14 = input & (1 << 4) != 0;
-        let a15 = input & (1 << 5) != 0;
-        let a14 = input & (1 << 6) != 0;
-        let a13 = input & (1 << 7) != 0;
-        let a12 = input & (1 << 8) != 0;
-        let ba = input & (1 << 9) != 0;
-        let aec = input & (1 << 10) != 0;
-        let r_w = input & (1 << 11) != 0;
-        let exrom = input & (1 << 12) != 0;
-        let game = input & (1 << 13) != 0;
-        let va13 = input & (1 << 14) != 0;
-        let va12 = input & (1 << 15) != 0;
-
-        let f0 = (loram & hiram & a15 & !a14 & a13 & !aec & r_w & game)
-            | (hiram & a15 & a14 & a13 & !aec & r_w & game)
-            | (hiram & a15 & a14 & a13 & !aec & r_w & !exrom & !game)
-            | (hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game)
-            | (loram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game)
-            | (hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & !exrom & !game)
-            | (va14 & aec & game & !va13 & va12)
-            | (va14 & aec & !exrom & !game & !va13 & va12)
-            | (hiram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & game)
-            | (hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game)
-            | (loram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & game)
-            | (loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game)
-            | (hiram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & !exrom & !game)
-            | (hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game)
-            | (loram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & !exrom & !game)
-            | (loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game)
-            | (a15 & a14 & !a13 & a12 & ba & !aec & r_w & exrom & !game)
-            | (a15 & a14 & !a13 & a12 & !aec & !r_w & exrom & !game)
-            | (loram & hiram & a15 & !a14 & !a13 & !aec & r_w & !exrom)
-            | (a15 & !a14 & !a13 & !aec & exrom & !game)
-            | (hiram & a15 & !a14 & a13 & !aec & r_w & !exrom & !game)
-            | (a15 & a14 & a13 & !aec & exrom & !game)
-            | (aec & exrom & !game & va13 & va12)
-            | (!a15 & !a14 & a12 & exrom & !game)
-            | (!a15 & !a14 & a13 & exrom & !game)
-            | (!a15 & a14 & exrom & !game)
-            | (a15 & !a14 & a13 & exrom & !game)
-            | (a15 & a14 & !a13 & !a12 & exrom & !game)
-            | cas;
-        let f1 = !loram | !hiram | !a15 | a14 | !a13 | aec | !r_w | !game;
-        let f2 = (!hiram | !a15 | !a14 | !a13 | aec | !r_w | !game)
-            & (!hiram | !a15 | !a14 | !a13 | aec | !r_w | exrom | game);
-        let f3 = (!hiram | charen | !a15 | !a14 | a13 | !a12 | aec | !r_w | !game)
-            & (!loram | charen | !a15 | !a14 | a13 | !a12 | aec | !r_w | !game)
-            & (!hiram | charen | !a15 | !a14 | a13 | !a12 | aec | !r_w | exrom | game)
-            & (!va14 | !aec | !game | va13 | !va12)
-            & (!va14 | !aec | exrom | game | va13 | !va12);
-        let f4 = cas | !a15 | !a14 | a13 | !a12 | aec | r_w;
-        let f5 = (!hiram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | !game)
-            & (!hiram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | !game)
-            & (!loram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | !game)
-            & (!loram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | !game)
-            & (!hiram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | exrom | game)
-            & (!hiram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | exrom | game)
-            & (!loram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | exrom | game)
-            & (!loram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | exrom | game)
-            & (!a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | !exrom | game)
-            & (!a15 | !a14 | a13 | !a12 | aec | r_w | !exrom | game);
-        let f6 = (!loram | !hiram | !a15 | a14 | a13 | aec | !r_w | exrom)
-            & (!a15 | a14 | a13 | aec | !exrom | game);
-        let f7 = (!hiram | !a15 | a14 | !a13 | aec | !r_w | exrom | game)
-            & (!a15 | !a14 | !a13 | aec | !exrom | game)
-            & (!aec | !exrom | game | !va13 | !va12);
-
-        let mut output = 0;
-        if f0 {
-            output |= 1 << 0;
-        }
-        if f1 {
-            output |= 1 << 1;
-        }
-        if f2 {
-            output |= 1 << 2;
-        }
-        if f3 {
-            output |= 1 << 3;
-        }
-        if f4 {
-            output |= 1 << 4;
-        }
-        if f5 {
-            output |= 1 << 5;
-        }
-        if f6 {
-            output |= 1 << 6;
-        }
-        if f7 {
-            output |= 1 << 7;
-        }
-
-        output
-    }
-
     fn before_each() -> (DeviceRef, RefVec<Trace>, RefVec<Trace>, RefVec<Trace>) {
         let device = Ic82S100::new();
         let tr = make_traces(&device);
 
-        let trin = RefVec::with_vec(
-            IntoIterator::into_iter(INPUTS)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
-        let trout = RefVec::with_vec(
-            IntoIterator::into_iter(OUTPUTS)
-                .map(|p| clone_ref!(tr[p]))
-                .collect::<Vec<TraceRef>>(),
-        );
+        let trin = tr.map_indices(&INPUTS);
+        let trout = tr.map_indices(&OUTPUTS);
 
         (device, tr, trin, trout)
     }
@@ -844,16 +555,98 @@ mod test {
         clear!(tr[OE]);
 
         for value in 0..0xffff {
-            let expected = get_expected(value);
+            let expected = pla_reference(value);
 
             value_to_traces(value as usize, &trin);
             let actual = traces_to_value(&trout);
 
             assert_eq!(
-                actual as usize, expected as usize,
+                actual, expected as usize,
                 "Incorrect output for input {:016b}: expected {:08b}, actual {:08b}",
                 value, expected, actual
             );
         }
     }
+
+    #[test]
+    fn batched_matches_unbatched() {
+        let (_, tr1, trin1, trout1) = before_each();
+        let (_, tr2, trin2, trout2) = before_each();
+        clear!(tr1[OE]);
+        clear!(tr2[OE]);
+
+        // Stepping by an odd number that isn't a power of two changes many inputs at once
+        // and still visits every value
+        let mut value = 0usize;
+        for _ in 0..0x10000 {
+            value = (value + 0x9e37) & 0xffff;
+            value_to_traces(value, &trin1);
+            with_batch(|| value_to_traces(value, &trin2));
+
+            assert_eq!(
+                traces_to_value(&trout2),
+                traces_to_value(&trout1),
+                "Batched output differs for input {:016b}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn floating_inputs_read_high() {
+        let (_, tr, trin, trout) = before_each();
+
+        // Nothing has driven the inputs yet, so every one of them floats
+        clear!(tr[OE]);
+        assert_eq!(traces_to_value(&trout), pla_reference(0xffff) as usize);
+
+        value_to_traces(0, &trin);
+        assert!(low!(tr[CASRAM]), "RAM should be selected with CAS low");
+        float!(tr[CAS]);
+        assert!(high!(tr[CASRAM]), "floating CAS should deselect RAM");
+        assert_eq!(traces_to_value(&trout), pla_reference(1 << 0) as usize);
+    }
+
+    #[test]
+    fn only_changed_outputs_driven() {
+        let (_, tr, trin, _) = before_each();
+        clear!(tr[OE]);
+        value_to_traces(0, &trin);
+        WRITES.with(|w| w.set(0));
+
+        // Stepping through a Gray code changes exactly one input at a time, and most of
+        // those changes leave every output where it was
+        let mut expected = 0;
+        let mut last = 0;
+        for i in 1..0x10000usize {
+            let value = i ^ (i >> 1);
+            value_to_traces(value, &trin);
+            expected += (pla_reference(value as u16) ^ pla_reference(last as u16)).count_ones();
+            last = value;
+        }
+        assert_eq!(WRITES.with(Cell::get), expected as usize);
+        assert!(
+            expected < 0x10000,
+            "most input changes should change no outputs"
+        );
+    }
+
+    #[test]
+    fn unchanged_input_word_not_evaluated() {
+        let (_, tr, _, trout) = before_each();
+        clear!(tr[OE]);
+        EVALUATIONS.with(|e| e.set(0));
+        WRITES.with(|w| w.set(0));
+
+        // A floating input already reads as high, so driving it high doesn't change the
+        // input word, and neither does moving it to another high level
+        set!(tr[CAS]);
+        set_level!(tr[CAS], Some(0.75));
+        assert_eq!(EVALUATIONS.with(Cell::get), 0);
+        assert_eq!(WRITES.with(Cell::get), 0);
+
+        clear!(tr[CAS]);
+        assert_eq!(EVALUATIONS.with(Cell::get), 1);
+        assert_eq!(traces_to_value(&trout), pla_reference(0xfffe) as usize);
+    }
 }
@@ -3,24 +3,34 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
-mod ic2114;
+pub mod ic2114;
 mod ic2332;
 mod ic2364;
 mod ic4066;
+mod ic41464;
 mod ic4164;
+mod ic6526;
+mod ic6567;
+mod ic6581;
 mod ic7406;
 mod ic7408;
 mod ic74139;
 mod ic74257;
 mod ic74258;
 mod ic74373;
-mod ic82s100;
+pub mod ic82s100;
 
 pub use self::ic2114::Ic2114;
 pub use self::ic2332::Ic2332;
 pub use self::ic2364::Ic2364;
 pub use self::ic4066::Ic4066;
+pub use self::ic41464::Ic41464;
 pub use self::ic4164::Ic4164;
+pub use self::ic6526::{
+    CiaConfig, CiaConfigError, CiaModel, Ic6526, Ic6526Pins, ModelWarning, Quirk,
+};
+pub use self::ic6567::{Ic6567, VicState, VideoStandard};
+pub use self::ic6581::{FilterState, Ic6581, SidState, VoiceState};
 pub use self::ic7406::Ic7406;
 pub use self::ic7408::Ic7408;
 pub use self::ic74139::Ic74139;
@@ -28,3 +38,6 @@ pub use self::ic74257::Ic74257;
 pub use self::ic74258::Ic74258;
 pub use self::ic74373::Ic74373;
 pub use self::ic82s100::Ic82S100;
+
+#[cfg(test)]
+mod test;
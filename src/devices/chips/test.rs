@@ -0,0 +1,582 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+// Tests that wire several chips together to check how they interact. Tests of a single
+// chip's behavior belong in that chip's own module.
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    components::{
+        device::{next_id, Device, DeviceRef, LevelChange, DUMMY},
+        pin::{
+            Mode::{Input, Output},
+            Pin, PinRef,
+        },
+        trace::{ConnectError, Contention, Trace, TraceRef},
+    },
+    test_utils::{trace_each, traces_to_value, value_to_traces},
+    utils::{none_to_pins, value_to_pins},
+    vectors::RefVec,
+};
+
+use super::{
+    ic2114::constants as ram, ic7408::constants as ls08, ic74139::constants as ls139,
+    ic74373::constants as ls373, ic82s100::constants as pla, Ic2114, Ic4164, Ic7408, Ic74139,
+    Ic74373, Ic82S100,
+};
+
+/// A stand-in for the address outputs of the 6510. It drives its address onto its A0-A7
+/// pins while its enable pin is high and floats them while it's low, the same way that
+/// the 6510 tri-states its address bus when AEC goes low.
+struct CpuAddress {
+    id: usize,
+    pins: RefVec<Pin>,
+    addr_pins: RefVec<Pin>,
+    address: usize,
+}
+
+impl CpuAddress {
+    fn new(address: usize) -> DeviceRef {
+        let en = pin!(1, "EN", Input);
+        let addr_pins = RefVec::with_vec(
+            (0..8)
+                .map(|i| pin!(i + 2, "A", Output))
+                .collect::<Vec<PinRef>>(),
+        );
+        let mut pins = vec![pin!(0, DUMMY, Input), clone_ref!(en)];
+        pins.extend(addr_pins.iter().map(|p| clone_ref!(p)));
+
+        let device: DeviceRef = new_ref!(CpuAddress {
+            id: next_id(),
+            pins: RefVec::with_vec(pins),
+            addr_pins,
+            address,
+        });
+        attach_to!(device, en);
+        device
+    }
+}
+
+impl Device for CpuAddress {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn update(&mut self, event: &LevelChange) {
+        let LevelChange(pin, _) = event;
+        if high!(pin) {
+            value_to_pins(self.address, &self.addr_pins);
+        } else {
+            none_to_pins(&self.addr_pins);
+        }
+    }
+}
+
+/// The levels seen by each recorder pin, in the order in which they were seen.
+type History = Rc<RefCell<Vec<Vec<Option<f64>>>>>;
+
+/// Records every level change seen by each of its pins, in order. A recorder pin that sees a
+/// line go directly from one level to another, without floating in between, has seen two
+/// outputs drive the line at the same time.
+///
+/// Unlike `components::probe::Probe`, this keeps the raw levels its pins see without cycle
+/// numbers, which is all these tests need to look for overlapping drivers.
+struct LevelRecorder {
+    id: usize,
+    pins: RefVec<Pin>,
+    history: History,
+}
+
+impl LevelRecorder {
+    fn new(width: usize) -> (DeviceRef, History) {
+        let pins = RefVec::with_vec(
+            (0..width)
+                .map(|i| pin!(i, "REC", Input))
+                .collect::<Vec<PinRef>>(),
+        );
+        let history = Rc::new(RefCell::new(vec![vec![]; width]));
+        let device: DeviceRef = new_ref!(LevelRecorder {
+            id: next_id(),
+            pins: pins.clone(),
+            history: Rc::clone(&history),
+        });
+        for pin in pins.iter() {
+            attach!(pin, clone_ref!(device));
+        }
+        (device, history)
+    }
+}
+
+impl Device for LevelRecorder {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn update(&mut self, event: &LevelChange) {
+        let LevelChange(pin, _) = event;
+        self.history.borrow_mut()[number!(pin)].push(level!(pin));
+    }
+}
+
+const CPU_ADDRESS: usize = 0x5a;
+const VIC_ADDRESS: usize = 0xa5;
+
+/// Wires up the address bus handoff between the CPU and the VIC.
+///
+/// The VIC's address goes through a 74373 whose active-low OE is connected directly to AEC,
+/// so the latch drives the bus whenever AEC is low. The CPU's address outputs are enabled
+/// by AEC ANDed with φ0 through a 7408, so they are only driven during φ2 of a cycle in
+/// which the VIC has given the CPU the bus.
+///
+/// Returns the AEC trace, the φ0 trace, the address bus traces, and the recorder history.
+fn before_each() -> (TraceRef, TraceRef, RefVec<Trace>, History) {
+    let latch = Ic74373::new();
+    let gate = Ic7408::new();
+    let cpu = CpuAddress::new(CPU_ADDRESS);
+    let (recorder, history) = LevelRecorder::new(8);
+
+    let lp = latch.borrow().pins();
+    let gp = gate.borrow().pins();
+    let cp = cpu.borrow().pins();
+    let rp = recorder.borrow().pins();
+
+    let aec = trace!(lp[ls373::OE], gp[ls08::A1]);
+    let phi0 = trace!(gp[ls08::B1]);
+    let _cpu_en = trace!(gp[ls08::Y1], cp[1]);
+    let le = trace!(lp[ls373::LE]);
+    set!(le);
+
+    let vic_inputs = [
+        ls373::D0,
+        ls373::D1,
+        ls373::D2,
+        ls373::D3,
+        ls373::D4,
+        ls373::D5,
+        ls373::D6,
+        ls373::D7,
+    ];
+    let latch_outputs = [
+        ls373::Q0,
+        ls373::Q1,
+        ls373::Q2,
+        ls373::Q3,
+        ls373::Q4,
+        ls373::Q5,
+        ls373::Q6,
+        ls373::Q7,
+    ];
+
+    let vic = trace_each(&lp.map_indices(&vic_inputs));
+    let mut bus = RefVec::new();
+    for (i, q) in lp.map_indices(&latch_outputs).iter_ref().enumerate() {
+        bus.push(trace!(q, cp[i + 2], rp[i]));
+    }
+
+    // Start in the middle of φ2 of a CPU cycle
+    value_to_traces(VIC_ADDRESS, &vic);
+    set!(aec);
+    set!(phi0);
+    history.borrow_mut().iter_mut().for_each(|h| h.clear());
+
+    (aec, phi0, bus, history)
+}
+
+fn bus_floating(bus: &RefVec<Trace>) -> bool {
+    bus.iter_ref().all(|t| floating!(t))
+}
+
+#[test]
+fn aec_handoff() {
+    let (aec, phi0, bus, history) = before_each();
+    assert_eq!(
+        traces_to_value(&bus),
+        CPU_ADDRESS,
+        "CPU address should be on the bus during φ2"
+    );
+
+    for _ in 0..4 {
+        // φ2 ends, and the CPU releases the bus
+        clear!(phi0);
+        assert!(
+            bus_floating(&bus),
+            "Bus should float between φ2 and AEC going low"
+        );
+
+        // VIC half of the cycle
+        clear!(aec);
+        assert_eq!(
+            traces_to_value(&bus),
+            VIC_ADDRESS,
+            "VIC address should be on the bus while AEC is low"
+        );
+
+        // The VIC gives the bus back, and the latch releases it
+        set!(aec);
+        assert!(
+            bus_floating(&bus),
+            "Bus should float between AEC going high and φ2"
+        );
+
+        // CPU half of the cycle
+        set!(phi0);
+        assert_eq!(
+            traces_to_value(&bus),
+            CPU_ADDRESS,
+            "CPU address should be on the bus during φ2"
+        );
+    }
+
+    // No line should ever have gone directly from one driven level to another
+    for (i, line) in history.borrow().iter().enumerate() {
+        assert!(!line.is_empty(), "Line {} should have changed levels", i);
+        for pair in line.windows(2) {
+            assert!(
+                pair[0].is_none() || pair[1].is_none(),
+                "Line {} went from {:?} to {:?} without floating; both sides drove it",
+                i,
+                pair[0],
+                pair[1]
+            );
+        }
+    }
+}
+
+#[test]
+fn aec_low_during_phi2_holds_cpu_off() {
+    // When the VIC keeps AEC low through φ2 (as it does during bad lines and sprite
+    // fetches), the CPU must stay off the bus for the whole cycle.
+    let (aec, phi0, bus, history) = before_each();
+
+    clear!(phi0);
+    clear!(aec);
+    set!(phi0);
+    assert_eq!(
+        traces_to_value(&bus),
+        VIC_ADDRESS,
+        "VIC address should stay on the bus while AEC is held low"
+    );
+    clear!(phi0);
+    assert_eq!(traces_to_value(&bus), VIC_ADDRESS);
+
+    for line in history.borrow().iter() {
+        for pair in line.windows(2) {
+            assert!(pair[0].is_none() || pair[1].is_none());
+        }
+    }
+}
+
+#[test]
+fn latch_and_ram_contention() {
+    // A mis-wiring where a 74373 output shares a trace with a 2114 data pin, with the latch
+    // left enabled while the RAM is read
+    let latch = Ic74373::new();
+    let sram = Ic2114::new();
+    let lp = latch.borrow().pins();
+    let rp = sram.borrow().pins();
+
+    let shared = trace!(lp[ls373::Q0], rp[ram::D0]);
+    shared.borrow_mut().set_contention_policy(Contention::Warn);
+
+    let d0 = trace!(lp[ls373::D0]);
+    let le = trace!(lp[ls373::LE]);
+    let oe = trace!(lp[ls373::OE]);
+    let cs = trace!(rp[ram::CS]);
+    let we = trace!(rp[ram::WE]);
+    set!(cs);
+    set!(we);
+    for a in [
+        ram::A0,
+        ram::A1,
+        ram::A2,
+        ram::A3,
+        ram::A4,
+        ram::A5,
+        ram::A6,
+        ram::A7,
+        ram::A8,
+        ram::A9,
+    ] {
+        clear!(trace!(rp[a]));
+    }
+
+    set!(le);
+    clear!(oe);
+    set!(d0);
+    assert!(shared.borrow().contention_events().is_empty());
+
+    // Reading address 0 puts a 0 on D0 while the latch is driving a 1
+    clear!(cs);
+
+    let events = shared.borrow_mut().take_contention_events();
+    assert_eq!(events.len(), 1, "Contention should have been recorded once");
+    let drivers = &events[0].drivers;
+    assert!(
+        drivers.contains(&(ls373::Q0, "Q0", 1.0)),
+        "Event should identify the 74373's Q0 pin"
+    );
+    assert!(
+        drivers.contains(&(ram::D0, "D0", 0.0)),
+        "Event should identify the 2114's D0 pin"
+    );
+}
+
+/// The traces of the color RAM circuit that the color RAM tests drive.
+struct ColorRam {
+    sram: DeviceRef,
+    addr: RefVec<Trace>,
+    data: RefVec<Trace>,
+    cas: TraceRef,
+    r_w: TraceRef,
+    aec: TraceRef,
+    pla_aec: TraceRef,
+    charen: TraceRef,
+}
+
+/// Wires up color RAM the way it's wired on the board.
+///
+/// The 2114's WE is driven by the PLA's GR_W output, which is only low during a CPU write
+/// to $D000-$DFFF while CAS is low. GR_W doesn't look at the banking lines, so it goes low
+/// even when character ROM or RAM is banked in at $D000; what keeps those writes out of
+/// color RAM is CS. The PLA's IO output enables the 74139, which selects color RAM for
+/// $D800-$DBFF, and that select is ANDed with AEC by a 7408 so that the chip is also
+/// selected whenever the VIC has the bus. During the VIC's half of the cycle, the PLA sees
+/// AEC inverted (it's driven directly here rather than through an inverter), so GR_W is
+/// high and the VIC can only read.
+///
+/// Everything starts out idle, with the CPU on the bus and nothing selected.
+fn color_ram() -> ColorRam {
+    let pla_chip = Ic82S100::new();
+    let decoder = Ic74139::new();
+    let gate = Ic7408::new();
+    let sram = Ic2114::new();
+
+    let lp = pla_chip.borrow().pins();
+    let dp = decoder.borrow().pins();
+    let gp = gate.borrow().pins();
+    let rp = sram.borrow().pins();
+
+    let ram_addr = [
+        ram::A0,
+        ram::A1,
+        ram::A2,
+        ram::A3,
+        ram::A4,
+        ram::A5,
+        ram::A6,
+        ram::A7,
+        ram::A8,
+        ram::A9,
+    ];
+    let mut addr = trace_each(&rp.map_indices(&ram_addr));
+    addr.push(trace!(dp[ls139::A1]));
+    addr.push(trace!(dp[ls139::B1]));
+    for a in [pla::A12, pla::A13, pla::A14, pla::A15] {
+        addr.push(trace!(lp[a]));
+    }
+
+    let data = trace_each(&rp.map_indices(&[ram::D0, ram::D1, ram::D2, ram::D3]));
+
+    let _io = trace!(lp[pla::IO], dp[ls139::G1]);
+    let _color = trace!(dp[ls139::Y12], gp[ls08::A1]);
+    let _cs = trace!(gp[ls08::Y1], rp[ram::CS]);
+    let _gr_w = trace!(lp[pla::GR_W], rp[ram::WE]);
+
+    let aec = trace!(gp[ls08::B1]);
+    let pla_aec = trace!(lp[pla::AEC]);
+    let cas = trace!(lp[pla::CAS]);
+    let r_w = trace!(lp[pla::R_W]);
+    let charen = trace!(lp[pla::CHAREN]);
+
+    clear!(trace!(lp[pla::OE]));
+    for p in [pla::VA14, pla::VA13, pla::VA12] {
+        clear!(trace!(lp[p]));
+    }
+    for p in [pla::LORAM, pla::HIRAM, pla::BA, pla::EXROM, pla::GAME] {
+        set!(trace!(lp[p]));
+    }
+    set!(charen);
+    set!(cas);
+    set!(r_w);
+    set!(aec);
+    clear!(pla_aec);
+    value_to_traces(0, &addr);
+
+    ColorRam {
+        sram,
+        addr,
+        data,
+        cas,
+        r_w,
+        aec,
+        pla_aec,
+        charen,
+    }
+}
+
+impl ColorRam {
+    /// Runs a CPU access of the given address, writing `value` if there is one and reading
+    /// otherwise. The address goes onto the bus last so that WE is already settled when CS
+    /// goes low, and the access ends by raising CAS before the address changes so that
+    /// nothing is written while the address lines are changing.
+    fn cpu_access(&self, address: usize, value: Option<usize>) {
+        if let Some(value) = value {
+            value_to_traces(value, &self.data);
+            clear!(self.r_w);
+        }
+        clear!(self.cas);
+        value_to_traces(address, &self.addr);
+
+        set!(self.cas);
+        value_to_traces(0, &self.addr);
+        set!(self.r_w);
+    }
+
+    /// Returns the value stored in color RAM at the given address, read from the chip's
+    /// registers while it isn't selected.
+    fn stored(&self, address: usize) -> u8 {
+        value_to_traces(address & 0x3ff, &self.addr);
+        self.sram.borrow().registers()[3]
+    }
+}
+
+#[test]
+fn color_ram_cpu_write() {
+    let c = color_ram();
+    c.cpu_access(0xd805, Some(0xa));
+    assert_eq!(c.stored(0x005), 0xa, "CPU write to $D805 should store");
+    assert_eq!(c.stored(0x004), 0x0, "Other locations should be unchanged");
+    assert_eq!(c.stored(0x000), 0x0, "Other locations should be unchanged");
+}
+
+#[test]
+fn color_ram_cpu_read() {
+    let c = color_ram();
+    c.cpu_access(0xd805, Some(0x5));
+
+    value_to_traces(0xf, &c.data);
+    c.cpu_access(0xd805, None);
+    assert_eq!(c.stored(0x005), 0x5, "CPU read should not store");
+
+    clear!(c.cas);
+    value_to_traces(0xd805, &c.addr);
+    assert_eq!(
+        traces_to_value(&c.data),
+        0x5,
+        "CPU read should put the stored value on the data bus"
+    );
+}
+
+#[test]
+fn color_ram_vic_access() {
+    let c = color_ram();
+    c.cpu_access(0xd805, Some(0x5));
+
+    // The CPU's R/W line is still low when the VIC takes the bus, but GR_W can't go low
+    value_to_traces(0xa, &c.data);
+    clear!(c.r_w);
+    clear!(c.cas);
+    set!(c.pla_aec);
+    clear!(c.aec);
+    value_to_traces(0x005, &c.addr);
+    assert_eq!(
+        traces_to_value(&c.data),
+        0x5,
+        "VIC access should read color RAM"
+    );
+
+    set!(c.cas);
+    set!(c.aec);
+    clear!(c.pla_aec);
+    set!(c.r_w);
+    assert_eq!(c.stored(0x005), 0x5, "VIC access should not store");
+}
+
+#[test]
+fn color_ram_charen_write() {
+    let c = color_ram();
+    c.cpu_access(0xd805, Some(0x5));
+
+    // GR_W still goes low, but with character ROM banked in, IO doesn't select color RAM
+    clear!(c.charen);
+    c.cpu_access(0xd805, Some(0xa));
+    assert_eq!(
+        c.stored(0x005),
+        0x5,
+        "Write with character ROM banked in should not store"
+    );
+
+    set!(c.charen);
+    c.cpu_access(0xd805, Some(0xa));
+    assert_eq!(
+        c.stored(0x005),
+        0xa,
+        "Write with I/O banked in should store"
+    );
+}
+
+#[test]
+fn connect_by_name() {
+    let pla = Ic82S100::new();
+    let dram = Ic4164::new();
+
+    // The PLA's pins have their generic names, so its CAS input is I0
+    let cas = connect!(pla."I0", dram."CAS").unwrap();
+    let pla_cas = pla.borrow().pin(pla::CAS).unwrap();
+    let dram_cas = dram.borrow().pin_by_name("CAS").unwrap();
+    assert!(Rc::ptr_eq(&pla_cas.borrow().trace().unwrap(), &cas));
+    assert!(Rc::ptr_eq(&dram_cas.borrow().trace().unwrap(), &cas));
+
+    // The 4164 latches its row before its column, so RAS has to go low first
+    let ras = connect!(dram."RAS").unwrap();
+    clear!(ras);
+    clear!(cas);
+    assert!(low!(pla_cas), "PLA CAS should follow the trace");
+    assert!(low!(dram_cas), "4164 CAS should follow the trace");
+    set!(cas);
+    assert!(high!(pla_cas) && high!(dram_cas));
+}
+
+#[test]
+fn connect_by_name_errors() {
+    let pla = Ic82S100::new();
+    let dram = Ic4164::new();
+
+    let err = connect!(pla."CAS", dram."CAS").unwrap_err();
+    assert_eq!(err, ConnectError::NoSuchPin("pla".into(), "CAS".into()));
+    assert_eq!(err.to_string(), "device pla has no pin named CAS");
+    assert!(
+        dram.borrow()
+            .pin_by_name("CAS")
+            .unwrap()
+            .borrow()
+            .trace()
+            .is_none(),
+        "nothing should be connected after an error"
+    );
+
+    connect!(pla."I0", dram."CAS").unwrap();
+    assert_eq!(
+        connect!(dram."CAS", pla."I1").unwrap_err(),
+        ConnectError::AlreadyConnected("dram".into(), "CAS".into())
+    );
+}
@@ -0,0 +1,425 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! One of the C64's two control ports, with a joystick or a pair of paddles plugged in.
+
+pub mod constants {
+    /// The pin assignment for the up switch, connected to CIA 1's PA0 (port 2) or PB0
+    /// (port 1).
+    pub const UP: usize = 1;
+    /// The pin assignment for the down switch, connected to CIA 1's PA1 (port 2) or PB1
+    /// (port 1).
+    pub const DOWN: usize = 2;
+    /// The pin assignment for the left switch, connected to CIA 1's PA2 (port 2) or PB2
+    /// (port 1). This is also paddle A's fire button.
+    pub const LEFT: usize = 3;
+    /// The pin assignment for the right switch, connected to CIA 1's PA3 (port 2) or PB3
+    /// (port 1). This is also paddle B's fire button.
+    pub const RIGHT: usize = 4;
+    /// The pin assignment for paddle B's analog output, switched to the SID's POTY by the
+    /// 4066.
+    pub const POTY: usize = 5;
+    /// The pin assignment for the fire button, connected to CIA 1's PA4 (port 2) or PB4
+    /// (port 1).
+    pub const FIRE: usize = 6;
+    /// The pin assignment for the +5V power supply.
+    pub const VCC: usize = 7;
+    /// The pin assignment for the ground.
+    pub const GND: usize = 8;
+    /// The pin assignment for paddle A's analog output, switched to the SID's POTX by the
+    /// 4066.
+    pub const POTX: usize = 9;
+}
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    components::{
+        device::{next_id, Device, DeviceRef, LevelChange},
+        pin::{
+            Mode::{Output, Unconnected},
+            Pin,
+        },
+    },
+    vectors::RefVec,
+};
+
+use self::constants::*;
+
+/// One of the four directions that a joystick can be pushed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    /// Returns the pin assignment of the switch for this direction.
+    fn pin(self) -> usize {
+        match self {
+            Direction::Up => UP,
+            Direction::Down => DOWN,
+            Direction::Left => LEFT,
+            Direction::Right => RIGHT,
+        }
+    }
+}
+
+/// A DB9 control port.
+///
+/// A joystick is five switches, one for each direction and one for the fire button, each of
+/// which connects its line to ground when it's closed. The lines of port 1 go to CIA 1's
+/// PB0-PB4 and those of port 2 to PA0-PA4, which are the same lines that the keyboard
+/// matrix is scanned through; that's why pushing a joystick in port 1 makes keys appear to
+/// be pressed. Nothing on the port ever drives a line high, so its pins are open-drain
+/// outputs: a closed switch drives its line low, and an open one floats it and leaves the
+/// level to the CIA's pull-ups (and to the keyboard). A pressed key and a closed switch on
+/// the same line then both drive it low, which isn't contention.
+///
+/// Paddles are potentiometers that the SID measures by timing how long a capacitor takes
+/// to charge through them. Each port has two (A on POTX and B on POTY), and which port's
+/// paddles reach the SID is picked by a 4066 controlled by CIA 1's PA6 and PA7. Since there
+/// are no analog timing circuits here, a paddle's position is output directly as a level
+/// between `0.0` (a value of 0) and `1.0` (a value of 255). The paddles' fire buttons are
+/// on the left and right lines.
+///
+/// The port's outputs change as soon as `set_direction`, `set_fire`, or `set_paddle` is
+/// called. The constructor returns a reference to the concrete type so that these can be
+/// called after the port is wired up.
+pub struct ControlPort {
+    /// The unique identifier of this device.
+    id: usize,
+
+    /// The port's pins, along with a dummy pin (at index 0) to ensure that the vector index
+    /// of the others matches their pin assignments.
+    pins: RefVec<Pin>,
+
+    /// The closed switches, as a bit for each (up in bit 0, then down, left, right, and
+    /// fire in bit 4).
+    switches: u8,
+
+    /// The positions of paddles A and B.
+    paddles: [u8; 2],
+}
+
+impl ControlPort {
+    /// Creates a new control port with the joystick centered, the fire button released, and
+    /// both paddles at 0. Returns a shared, internally mutable reference to it.
+    pub fn new() -> Rc<RefCell<ControlPort>> {
+        let up = pin!(UP, "UP", Output);
+        let down = pin!(DOWN, "DOWN", Output);
+        let left = pin!(LEFT, "LEFT", Output);
+        let right = pin!(RIGHT, "RIGHT", Output);
+        let fire = pin!(FIRE, "FIRE", Output);
+
+        let potx = pin!(POTX, "POTX", Output);
+        let poty = pin!(POTY, "POTY", Output);
+
+        let vcc = pin!(VCC, "VCC", Unconnected);
+        let gnd = pin!(GND, "GND", Unconnected);
+
+        set_level!(potx, Some(0.0));
+        set_level!(poty, Some(0.0));
+
+        let port = new_ref!(ControlPort {
+            id: next_id(),
+            pins: pins![up, down, left, right, poty, fire, vcc, gnd, potx],
+            switches: 0,
+            paddles: [0; 2],
+        });
+
+        let device: DeviceRef = port.clone();
+        attach_to!(device, up, down, left, right, poty, fire, vcc, gnd, potx);
+
+        port
+    }
+
+    /// Closes (if `pushed` is `true`) or opens the switch for one direction.
+    pub fn set_direction(&mut self, direction: Direction, pushed: bool) {
+        self.set_switch(direction.pin(), pushed);
+    }
+
+    /// Presses (if `pressed` is `true`) or releases the fire button.
+    pub fn set_fire(&mut self, pressed: bool) {
+        self.set_switch(FIRE, pressed);
+    }
+
+    /// Sets the position of paddle A (if `which` is 0) or paddle B (if it's 1).
+    ///
+    /// # Panics
+    /// If `which` is anything other than 0 or 1.
+    pub fn set_paddle(&mut self, which: usize, value: u8) {
+        let number = match which {
+            0 => POTX,
+            1 => POTY,
+            _ => panic!("Paddle {} does not exist; it must be 0 or 1", which),
+        };
+        self.paddles[which] = value;
+        set_level!(self.pins[number], Some(value as f64 / 255.0));
+    }
+
+    /// Closes or opens the switch on the line with pin assignment `number`, driving the
+    /// line low or letting it float.
+    fn set_switch(&mut self, number: usize, closed: bool) {
+        let bit = 1 << if number == FIRE { 4 } else { number - 1 };
+        let pin = &self.pins[number];
+        if closed {
+            self.switches |= bit;
+            clear!(pin);
+        } else {
+            self.switches &= !bit;
+            float!(pin);
+        }
+    }
+}
+
+impl Device for ControlPort {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    /// Returns the closed switches (as a bit for each, up in bit 0 through fire in bit 4),
+    /// followed by the positions of paddles A and B.
+    fn registers(&self) -> Vec<u8> {
+        vec![self.switches, self.paddles[0], self.paddles[1]]
+    }
+
+    fn update(&mut self, _event: &LevelChange) {}
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        components::trace::{Contention, Resolution, Trace, TraceRef},
+        devices::{
+            chips::Ic4066,
+            keyboard::{constants::*, Key, KeyboardMatrix},
+        },
+        test_utils::make_traces,
+    };
+
+    use super::*;
+
+    fn before_each() -> (Rc<RefCell<ControlPort>>, RefVec<Trace>) {
+        let port = ControlPort::new();
+        let device: DeviceRef = port.clone();
+        let tr = make_traces(&device);
+        for number in [UP, DOWN, LEFT, RIGHT, FIRE] {
+            pull_up!(tr[number]);
+        }
+        (port, tr)
+    }
+
+    #[test]
+    fn initial() {
+        let (port, tr) = before_each();
+        for number in [UP, DOWN, LEFT, RIGHT, FIRE] {
+            assert!(high!(tr[number]), "line {} should be pulled up", number);
+        }
+        assert_eq!(port.borrow().registers(), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn directions() {
+        let (port, tr) = before_each();
+        port.borrow_mut().set_direction(Direction::Up, true);
+        port.borrow_mut().set_direction(Direction::Left, true);
+        assert!(low!(tr[UP]));
+        assert!(high!(tr[DOWN]));
+        assert!(low!(tr[LEFT]));
+        assert!(high!(tr[RIGHT]));
+        assert_eq!(port.borrow().registers()[0], 0x05);
+
+        port.borrow_mut().set_direction(Direction::Up, false);
+        assert!(
+            high!(tr[UP]),
+            "released switch should let line be pulled up"
+        );
+        assert!(low!(tr[LEFT]));
+    }
+
+    #[test]
+    fn fire() {
+        let (port, tr) = before_each();
+        port.borrow_mut().set_fire(true);
+        assert!(low!(tr[FIRE]));
+        assert_eq!(port.borrow().registers()[0], 0x10);
+        port.borrow_mut().set_fire(false);
+        assert!(high!(tr[FIRE]));
+    }
+
+    #[test]
+    fn released_line_floats() {
+        let port = ControlPort::new();
+        let device: DeviceRef = port.clone();
+        let tr = make_traces(&device);
+        assert!(floating!(tr[RIGHT]));
+        port.borrow_mut().set_direction(Direction::Right, true);
+        assert!(low!(tr[RIGHT]));
+        port.borrow_mut().set_direction(Direction::Right, false);
+        assert!(floating!(tr[RIGHT]), "port should never drive a line high");
+    }
+
+    #[test]
+    fn paddles() {
+        let (port, tr) = before_each();
+        port.borrow_mut().set_paddle(0, 255);
+        port.borrow_mut().set_paddle(1, 51);
+        assert_eq!(level!(tr[POTX]), Some(1.0));
+        assert_eq!(level!(tr[POTY]), Some(51.0 / 255.0));
+        assert_eq!(port.borrow().registers(), vec![0, 255, 51]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn paddle_out_of_range() {
+        let (port, _) = before_each();
+        port.borrow_mut().set_paddle(2, 0);
+    }
+
+    #[test]
+    fn paddles_through_4066() {
+        // The 4066's pin assignments for switch 1
+        const A1: usize = 1;
+        const B1: usize = 2;
+        const X1: usize = 13;
+
+        let port = ControlPort::new();
+        let switch = Ic4066::new();
+        let port_pins = port.borrow().pins();
+        let switch_pins = switch.borrow().pins();
+
+        let _potx = trace!(clone_ref!(port_pins[POTX]), clone_ref!(switch_pins[A1]));
+        let sid = trace!(clone_ref!(switch_pins[B1]));
+        let control = trace!(clone_ref!(switch_pins[X1]));
+
+        clear!(control);
+        port.borrow_mut().set_paddle(0, 102);
+        assert_eq!(level!(sid), Some(102.0 / 255.0));
+
+        set!(control);
+        port.borrow_mut().set_paddle(0, 204);
+        assert!(
+            floating!(sid),
+            "paddle should not reach the SID while the switch is open"
+        );
+
+        clear!(control);
+        assert_eq!(level!(sid), Some(204.0 / 255.0));
+    }
+
+    /// Wires a keyboard matrix and a control port together the way port 1 is wired, with
+    /// the joystick lines sharing CIA 1's PB0-PB4 with columns 0-4. Returns the row and
+    /// column traces; the rows stand in for port A and the columns for port B.
+    fn shared_lines(
+        keyboard: &Rc<RefCell<KeyboardMatrix>>,
+        port: &Rc<RefCell<ControlPort>>,
+    ) -> (Vec<TraceRef>, Vec<TraceRef>) {
+        let kb_pins = keyboard.borrow().pins();
+        let port_pins = port.borrow().pins();
+        let joystick = [UP, DOWN, LEFT, RIGHT, FIRE];
+
+        let rows = [R0, R1, R2, R3, R4, R5, R6, R7]
+            .iter()
+            .map(|row| trace!(clone_ref!(kb_pins[*row])))
+            .collect();
+        let cols = [C0, C1, C2, C3, C4, C5, C6, C7]
+            .iter()
+            .enumerate()
+            .map(|(i, col)| {
+                let trace = match joystick.get(i) {
+                    Some(line) => {
+                        trace!(clone_ref!(kb_pins[*col]), clone_ref!(port_pins[*line]))
+                    }
+                    None => trace!(clone_ref!(kb_pins[*col])),
+                };
+                pull_up!(trace);
+                trace.borrow_mut().set_resolution(Resolution::WiredAnd);
+                trace.borrow_mut().set_contention_policy(Contention::Warn);
+                trace
+            })
+            .collect();
+        (rows, cols)
+    }
+
+    /// Drives one row low and the rest high, and returns the column byte (1 for high).
+    fn select_row(rows: &[TraceRef], cols: &[TraceRef], n: usize) -> u8 {
+        for (i, row) in rows.iter().enumerate() {
+            if i == n {
+                clear!(row);
+            } else {
+                set!(row);
+            }
+        }
+        cols.iter()
+            .enumerate()
+            .fold(0, |value, (i, col)| value | (high!(col) as u8) << i)
+    }
+
+    #[test]
+    fn keyboard_and_joystick_different_lines() {
+        let keyboard = KeyboardMatrix::new();
+        let port = ControlPort::new();
+        let (rows, cols) = shared_lines(&keyboard, &port);
+
+        // A is at row 1, column 2; fire is on column 4
+        keyboard.borrow_mut().press(Key::A);
+        port.borrow_mut().set_fire(true);
+
+        assert_eq!(select_row(&rows, &cols, 1), !0x14);
+        assert_eq!(
+            select_row(&rows, &cols, 0),
+            !0x10,
+            "joystick should be seen no matter which row is selected"
+        );
+
+        port.borrow_mut().set_fire(false);
+        assert_eq!(select_row(&rows, &cols, 1), !0x04);
+        assert_eq!(select_row(&rows, &cols, 0), 0xff);
+    }
+
+    #[test]
+    fn keyboard_and_joystick_same_line() {
+        let keyboard = KeyboardMatrix::new();
+        let port = ControlPort::new();
+        let (rows, cols) = shared_lines(&keyboard, &port);
+
+        // A is at row 1, column 2, and left is on column 2
+        keyboard.borrow_mut().press(Key::A);
+        assert_eq!(select_row(&rows, &cols, 1), !0x04);
+        port.borrow_mut().set_direction(Direction::Left, true);
+        assert_eq!(select_row(&rows, &cols, 1), !0x04);
+        assert!(
+            !cols[2].borrow().contention(),
+            "key and joystick both pulling low should not contend"
+        );
+
+        port.borrow_mut().set_direction(Direction::Left, false);
+        assert_eq!(
+            select_row(&rows, &cols, 1),
+            !0x04,
+            "key should hold line low after joystick is released"
+        );
+        port.borrow_mut().set_direction(Direction::Left, true);
+        keyboard.borrow_mut().release(Key::A);
+        assert_eq!(
+            select_row(&rows, &cols, 1),
+            !0x04,
+            "joystick should hold line low after key is released"
+        );
+        port.borrow_mut().set_direction(Direction::Left, false);
+        assert_eq!(select_row(&rows, &cols, 1), 0xff);
+
+        for col in cols.iter() {
+            assert!(col.borrow().contention_events().is_empty());
+        }
+    }
+}
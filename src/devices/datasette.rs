@@ -0,0 +1,440 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! The Commodore 1530 Datasette, playing back tapes from TAP images.
+
+pub mod constants {
+    /// The pin assignment for the ground.
+    pub const GND: usize = 1;
+    /// The pin assignment for the +5V power supply.
+    pub const VCC: usize = 2;
+    /// The pin assignment for the motor control input, from the 6510's P5 (through a
+    /// transistor driver).
+    pub const MOTOR: usize = 3;
+    /// The pin assignment for the cassette read output, to CIA 1's FLAG.
+    pub const READ: usize = 4;
+    /// The pin assignment for the cassette write input, from the 6510's P3.
+    pub const WRITE: usize = 5;
+    /// The pin assignment for the sense output, to the 6510's P4.
+    pub const SENSE: usize = 6;
+}
+
+use std::{
+    cell::RefCell,
+    fmt::{self, Display, Formatter},
+    rc::Rc,
+};
+
+use crate::{
+    components::{
+        clock::Clocked,
+        device::{next_id, Device, LevelChange},
+        pin::{
+            Mode::{Input, Output, Unconnected},
+            Pin,
+        },
+    },
+    vectors::RefVec,
+};
+
+use self::constants::*;
+
+/// The signature at the start of every TAP image.
+const SIGNATURE: &[u8; 12] = b"C64-TAPE-RAW";
+
+/// The size of the TAP header. Pulse data starts right after it.
+const HEADER_SIZE: usize = 20;
+
+/// The number of cycles represented by each unit of a pulse length byte.
+const PULSE_UNIT: u64 = 8;
+
+/// The length, in cycles, of a pulse recorded as a 0 byte in a version 0 image. Version 0
+/// has no way to say how long these pulses really were, only that they were too long to
+/// fit in a byte.
+const V0_OVERFLOW: u64 = 256 * PULSE_UNIT;
+
+/// An error in reading a TAP image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TapError {
+    /// The image doesn't start with the TAP signature.
+    Signature,
+
+    /// The image's version isn't 0 or 1. (Version 2 is for the C16 and Plus/4.)
+    Version(u8),
+
+    /// The image is shorter than its header.
+    Truncated,
+}
+
+impl Display for TapError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            TapError::Signature => write!(f, "image is not a TAP file"),
+            TapError::Version(version) => {
+                write!(f, "TAP version {} is not supported", version)
+            }
+            TapError::Truncated => write!(f, "image is shorter than the TAP header"),
+        }
+    }
+}
+
+impl std::error::Error for TapError {}
+
+/// An emulation of the Commodore 1530 Datasette, the C64's cassette drive.
+///
+/// A tape is inserted as a TAP image, which records the time between each pair of falling
+/// edges on the read line. Each byte of the image's pulse data is one pulse, 8 cycles per
+/// unit. A 0 byte is a pulse too long for a byte; in a version 1 image it's followed by
+/// the pulse's exact length in cycles as a 3-byte little-endian number, and in a version 0
+/// image it's taken to be 2048 cycles.
+///
+/// The datasette is clocked at the system clock rate. While PLAY is pressed and the motor
+/// is running, each clock counts down the current pulse. A new pulse starts with a falling
+/// edge on the read line (which is what sets CIA 1's FLAG interrupt), and the line goes
+/// back high halfway through the pulse. When either PLAY is released or the motor stops,
+/// the tape stops where it is, partway through the current pulse if need be, and it picks
+/// up from the same spot when it starts again.
+///
+/// The motor line is an input, and the motor runs while it's high. (In the C64, it's driven
+/// from the 6510's P5 through a transistor, so it's high when P5 is low.) The sense line
+/// is pulled low while PLAY is pressed. Writing to tape isn't emulated, so the write pin
+/// is ignored.
+///
+/// Tapes are controlled with `insert`, `play`, `stop`, and `rewind`, which is why the
+/// constructor returns a reference to the concrete type.
+pub struct Datasette {
+    /// The unique identifier of this device.
+    id: usize,
+
+    /// The datasette's pins, along with a dummy pin (at index 0) to ensure that the vector
+    /// index of the others matches their pin assignments.
+    pins: RefVec<Pin>,
+
+    /// The version of the inserted image (0 or 1).
+    version: u8,
+
+    /// The pulse data of the inserted image.
+    data: Vec<u8>,
+
+    /// The index in `data` of the next pulse.
+    position: usize,
+
+    /// The length of the current pulse, in cycles.
+    length: u64,
+
+    /// The number of cycles left in the current pulse.
+    remaining: u64,
+
+    /// Whether PLAY is pressed.
+    playing: bool,
+}
+
+impl Datasette {
+    /// Creates a new datasette with no tape inserted and returns a shared, internally
+    /// mutable reference to it.
+    pub fn new() -> Rc<RefCell<Datasette>> {
+        let gnd = pin!(GND, "GND", Unconnected);
+        let vcc = pin!(VCC, "VCC", Unconnected);
+        let motor = pin!(MOTOR, "MOTOR", Input);
+        let read = pin!(READ, "READ", Output);
+        let write = pin!(WRITE, "WRITE", Unconnected);
+        let sense = pin!(SENSE, "SENSE", Output);
+
+        set!(read, sense);
+
+        new_ref!(Datasette {
+            id: next_id(),
+            pins: pins![gnd, vcc, motor, read, write, sense],
+            version: 0,
+            data: vec![],
+            position: 0,
+            length: 0,
+            remaining: 0,
+            playing: false,
+        })
+    }
+
+    /// Inserts a tape, given as the contents of a TAP image. The tape starts at its
+    /// beginning and PLAY is released.
+    pub fn insert(&mut self, tap: Vec<u8>) -> Result<(), TapError> {
+        if tap.len() < HEADER_SIZE {
+            return Err(TapError::Truncated);
+        }
+        if &tap[..SIGNATURE.len()] != SIGNATURE {
+            return Err(TapError::Signature);
+        }
+        let version = tap[12];
+        if version > 1 {
+            return Err(TapError::Version(version));
+        }
+        let size = u32::from_le_bytes([tap[16], tap[17], tap[18], tap[19]]) as usize;
+        let end = (HEADER_SIZE + size).min(tap.len());
+
+        self.version = version;
+        self.data = tap[HEADER_SIZE..end].to_vec();
+        self.rewind();
+        Ok(())
+    }
+
+    /// Presses PLAY. The tape moves whenever the motor is also running.
+    pub fn play(&mut self) {
+        self.playing = true;
+        clear!(self.pins[SENSE]);
+    }
+
+    /// Presses STOP, releasing PLAY. The tape stops where it is.
+    pub fn stop(&mut self) {
+        self.playing = false;
+        set!(self.pins[SENSE]);
+        set!(self.pins[READ]);
+    }
+
+    /// Rewinds the tape to its beginning. Like on the real datasette, this releases PLAY.
+    pub fn rewind(&mut self) {
+        self.stop();
+        self.position = 0;
+        self.length = 0;
+        self.remaining = 0;
+    }
+
+    /// Returns how far into the tape's pulse data the tape has played, in bytes.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Returns the length of the tape's pulse data, in bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Returns `true` if there's no tape inserted, or if the tape is blank.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Reads the length of the next pulse from the tape and advances past it, or returns
+    /// `None` at the end of the tape.
+    fn next_pulse(&mut self) -> Option<u64> {
+        let byte = *self.data.get(self.position)?;
+        self.position += 1;
+        if byte != 0 {
+            return Some(byte as u64 * PULSE_UNIT);
+        }
+        if self.version == 0 {
+            return Some(V0_OVERFLOW);
+        }
+
+        let bytes = self.data.get(self.position..self.position + 3);
+        self.position = (self.position + 3).min(self.data.len());
+        let bytes = bytes?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]) as u64)
+    }
+}
+
+impl Device for Datasette {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        vec![]
+    }
+
+    fn update(&mut self, _event: &LevelChange) {}
+}
+
+impl Clocked for Datasette {
+    fn clock(&mut self, _cycle: u64) {
+        if !self.playing || !high!(self.pins[MOTOR]) {
+            return;
+        }
+        if self.remaining == 0 {
+            match self.next_pulse() {
+                Some(length) if length > 0 => {
+                    self.length = length;
+                    self.remaining = length;
+                    clear!(self.pins[READ]);
+                }
+                _ => return,
+            }
+        }
+        self.remaining -= 1;
+        if self.length - self.remaining == self.length / 2 + 1 {
+            set!(self.pins[READ]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::components::trace::TraceRef;
+
+    use super::*;
+
+    /// Builds a TAP image of the given version holding the given pulse data.
+    fn tap(version: u8, data: &[u8]) -> Vec<u8> {
+        let mut bytes = SIGNATURE.to_vec();
+        bytes.extend([version, 0, 0, 0]);
+        bytes.extend((data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    /// Creates a datasette with a tape inserted and its motor running, returning it along
+    /// with its motor, read, and sense traces.
+    fn before_each(tape: Vec<u8>) -> (Rc<RefCell<Datasette>>, TraceRef, TraceRef, TraceRef) {
+        let datasette = Datasette::new();
+        let pins = datasette.borrow().pins();
+        let motor = trace!(pins[MOTOR]);
+        let read = trace!(pins[READ]);
+        let sense = trace!(pins[SENSE]);
+        set!(motor);
+        datasette.borrow_mut().insert(tape).unwrap();
+        (datasette, motor, read, sense)
+    }
+
+    /// Clocks a datasette for a number of cycles, starting at `start`, and returns the
+    /// cycles in which the read line fell.
+    fn edges(datasette: &Rc<RefCell<Datasette>>, read: &TraceRef, start: u64, n: u64) -> Vec<u64> {
+        let mut edges = vec![];
+        let mut last = high!(read);
+        for cycle in start..start + n {
+            datasette.borrow_mut().clock(cycle);
+            let now = high!(read);
+            if last && !now {
+                edges.push(cycle);
+            }
+            last = now;
+        }
+        edges
+    }
+
+    #[test]
+    fn pulse_edges() {
+        let (datasette, _, read, _) = before_each(tap(0, &[0x30, 0x40, 0x2f]));
+        datasette.borrow_mut().play();
+        assert_eq!(
+            edges(&datasette, &read, 0, 2000),
+            vec![0, 0x180, 0x380],
+            "Edges should be 8 cycles apart per unit of pulse length"
+        );
+        assert_eq!(datasette.borrow().position(), 3);
+    }
+
+    #[test]
+    fn square_wave() {
+        let (datasette, _, read, _) = before_each(tap(0, &[0x30, 0x30]));
+        datasette.borrow_mut().play();
+        datasette.borrow_mut().clock(0);
+        assert!(low!(read));
+        edges(&datasette, &read, 1, 0xbf);
+        assert!(
+            low!(read),
+            "Read line should be low for the first half of a pulse"
+        );
+        edges(&datasette, &read, 0xc0, 1);
+        assert!(
+            high!(read),
+            "Read line should be high for the second half of a pulse"
+        );
+    }
+
+    #[test]
+    fn v1_long_pulse() {
+        let (datasette, _, read, _) =
+            before_each(tap(1, &[0x30, 0x00, 0x10, 0x27, 0x00, 0x30, 0x30]));
+        datasette.borrow_mut().play();
+        assert_eq!(
+            edges(&datasette, &read, 0, 20000),
+            vec![0, 384, 10384, 10768]
+        );
+        assert_eq!(datasette.borrow().position(), 7);
+    }
+
+    #[test]
+    fn v0_overflow() {
+        let (datasette, _, read, _) = before_each(tap(0, &[0x30, 0x00, 0x10, 0x27]));
+        datasette.borrow_mut().play();
+        assert_eq!(
+            edges(&datasette, &read, 0, 5000),
+            vec![0, 384, 2432, 2560],
+            "A 0 byte in version 0 should be a 2048-cycle pulse"
+        );
+    }
+
+    #[test]
+    fn motor_pause() {
+        let (datasette, motor, read, _) = before_each(tap(0, &[0x30, 0x40, 0x2f]));
+        datasette.borrow_mut().play();
+        assert_eq!(edges(&datasette, &read, 0, 100), vec![0]);
+
+        clear!(motor);
+        assert_eq!(
+            edges(&datasette, &read, 100, 1000),
+            vec![],
+            "Tape should not move with the motor off"
+        );
+
+        set!(motor);
+        assert_eq!(
+            edges(&datasette, &read, 1100, 2000),
+            vec![1100 + 0x180 - 100, 1100 + 0x380 - 100],
+            "Tape should pick up partway through the pulse it stopped in"
+        );
+    }
+
+    #[test]
+    fn play_and_stop() {
+        let (datasette, _, read, sense) = before_each(tap(0, &[0x30, 0x40, 0x2f]));
+        assert!(high!(sense), "Sense should be high with PLAY released");
+        assert_eq!(edges(&datasette, &read, 0, 1000), vec![]);
+
+        datasette.borrow_mut().play();
+        assert!(low!(sense), "Sense should be low with PLAY pressed");
+        assert_eq!(
+            edges(&datasette, &read, 1000, 0x200),
+            vec![1000, 1000 + 0x180]
+        );
+
+        datasette.borrow_mut().stop();
+        assert!(high!(sense));
+        assert_eq!(edges(&datasette, &read, 0x1200, 1000), vec![]);
+
+        datasette.borrow_mut().play();
+        assert_eq!(
+            edges(&datasette, &read, 0x2000, 0x200),
+            vec![0x2000 + 0x180]
+        );
+    }
+
+    #[test]
+    fn rewind() {
+        let (datasette, _, read, _) = before_each(tap(0, &[0x30, 0x40, 0x2f]));
+        assert_eq!(datasette.borrow().len(), 3);
+        datasette.borrow_mut().play();
+        edges(&datasette, &read, 0, 1000);
+        assert_eq!(datasette.borrow().position(), 3);
+
+        datasette.borrow_mut().rewind();
+        assert_eq!(datasette.borrow().position(), 0);
+        datasette.borrow_mut().play();
+        assert_eq!(edges(&datasette, &read, 0, 0x181), vec![0, 0x180]);
+    }
+
+    #[test]
+    fn bad_images() {
+        let datasette = Datasette::new();
+        let mut datasette = datasette.borrow_mut();
+        assert_eq!(datasette.insert(vec![0; 10]), Err(TapError::Truncated));
+        assert_eq!(datasette.insert(vec![0; 30]), Err(TapError::Signature));
+        assert_eq!(datasette.insert(tap(2, &[0x30])), Err(TapError::Version(2)));
+        assert!(datasette.is_empty());
+    }
+}
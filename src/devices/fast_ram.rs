@@ -0,0 +1,477 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! A single device that stands in for the C64's eight 4164 DRAM chips.
+
+pub mod constants {
+    /// Pin assignment for address pin A0. A1-A7 follow it.
+    pub const A0: usize = 1;
+    /// Pin assignment for address pin A7.
+    pub const A7: usize = 8;
+
+    /// Pin assignment for data in pin D0 (the D pin of the chip for bit 0). D1-D7 follow
+    /// it.
+    pub const D0: usize = 9;
+    /// Pin assignment for data in pin D7.
+    pub const D7: usize = 16;
+
+    /// Pin assignment for data out pin Q0 (the Q pin of the chip for bit 0). Q1-Q7 follow
+    /// it.
+    pub const Q0: usize = 17;
+    /// Pin assignment for data out pin Q7.
+    pub const Q7: usize = 24;
+
+    /// Pin assignment for the row address strobe pin.
+    pub const RAS: usize = 25;
+    /// Pin assignment for the column address strobe pin.
+    pub const CAS: usize = 26;
+    /// Pin assignment for the write enable pin.
+    pub const WE: usize = 27;
+
+    /// Pin assignment for the +5V power supply pin.
+    pub const VCC: usize = 28;
+    /// Pin assignment for the 0V (ground) power supply pin.
+    pub const VSS: usize = 29;
+}
+
+use crate::{
+    components::{
+        device::{next_id, Device, DeviceRef, LevelChange, DUMMY},
+        pin::{
+            Mode::{Input, Output, Unconnected},
+            Pin,
+        },
+        port::Port,
+    },
+    vectors::RefVec,
+};
+
+use self::constants::*;
+
+/// Names of the address pins, in bit order.
+const ADDRESS_NAMES: [&str; 8] = ["A0", "A1", "A2", "A3", "A4", "A5", "A6", "A7"];
+
+/// Names of the data in pins, in bit order.
+const D_NAMES: [&str; 8] = ["D0", "D1", "D2", "D3", "D4", "D5", "D6", "D7"];
+
+/// Names of the data out pins, in bit order.
+const Q_NAMES: [&str; 8] = ["Q0", "Q1", "Q2", "Q3", "Q4", "Q5", "Q6", "Q7"];
+
+/// A 64k x 8 DRAM that behaves like a bank of eight 4164s sharing their address, RAS, CAS,
+/// and WE lines.
+///
+/// A pin-level 4164 does its row and column latching and its trace updates one bit at a
+/// time, so eight of them do all of that work eight times for every byte. This device has
+/// the pins of the whole bank (the shared A0-A7, RAS, CAS, and WE, plus the D and Q pins
+/// of each chip as D0-D7 and Q0-Q7) but latches the address once and stores whole bytes,
+/// so it can take the place of the bank when speed matters more than modeling each chip.
+/// Addresses are laid out the same way (the byte at a row and column is at
+/// `(row << 8) | col`), so the two hold the same data at the same addresses.
+///
+/// Everything described in the documentation of `Ic4164` works the same way here: the row
+/// is latched when RAS goes low and can be reused for any number of accesses while RAS
+/// stays low; CAS going low latches the column and reads (if WE is high) or writes (if WE
+/// is low); WE going low after CAS is read-modify-write mode, in which Q0-Q7 follow the
+/// data being written; WE going low before CAS is write mode, in which Q0-Q7 are hi-Z; and
+/// Q0-Q7 are hi-Z whenever CAS is high. Refresh and decay are not emulated.
+///
+/// `registers` returns the same four bytes as `Ic4164`, except that the last is the whole
+/// latched input byte rather than one bit.
+pub struct FastRam64k {
+    /// The unique identifier of this device.
+    id: usize,
+
+    /// The device's pins, along with a dummy pin (at index 0) to ensure that the vector
+    /// index of the others matches their pin assignments.
+    pins: RefVec<Pin>,
+
+    /// The A0-A7 pins, as a port.
+    addr: Port,
+
+    /// The D0-D7 pins, as a port.
+    d: Port,
+
+    /// The Q0-Q7 pins, as a port.
+    q: Port,
+
+    /// The stored bytes, indexed by row and column.
+    memory: Box<[u8; 0x10000]>,
+
+    /// The latched row, or `None` if RAS is high.
+    row: Option<u8>,
+
+    /// The latched column, or `None` if CAS is high.
+    col: Option<u8>,
+
+    /// The latched input data, or `None` if WE or CAS is high.
+    data: Option<u8>,
+}
+
+impl FastRam64k {
+    /// Creates a new 64k x 8 RAM, with every byte 0, and returns a shared, internally
+    /// mutable reference to it.
+    pub fn new() -> DeviceRef {
+        let mut pins = vec![];
+        for (i, name) in ADDRESS_NAMES.iter().enumerate() {
+            pins.push(pin!(A0 + i, *name, Input));
+        }
+        for (i, name) in D_NAMES.iter().enumerate() {
+            pins.push(pin!(D0 + i, *name, Input));
+        }
+        for (i, name) in Q_NAMES.iter().enumerate() {
+            pins.push(pin!(Q0 + i, *name, Output));
+        }
+        let ras = pin!(RAS, "RAS", Input);
+        let cas = pin!(CAS, "CAS", Input);
+        let we = pin!(WE, "WE", Input);
+        pins.push(clone_ref!(ras));
+        pins.push(clone_ref!(cas));
+        pins.push(clone_ref!(we));
+        pins.push(pin!(VCC, "VCC", Unconnected));
+        pins.push(pin!(VSS, "VSS", Unconnected));
+
+        let pins = RefVec::with_vec(
+            std::iter::once(pin!(0, DUMMY, Unconnected))
+                .chain(pins)
+                .collect(),
+        );
+        let addr = Port::from_pins(&pins, &(A0..=A7).collect::<Vec<usize>>());
+        let d = Port::from_pins(&pins, &(D0..=D7).collect::<Vec<usize>>());
+        let mut q = Port::from_pins(&pins, &(Q0..=Q7).collect::<Vec<usize>>());
+        q.float();
+
+        let device: DeviceRef = new_ref!(FastRam64k {
+            id: next_id(),
+            pins,
+            addr,
+            d,
+            q,
+            memory: Box::new([0; 0x10000]),
+            row: None,
+            col: None,
+            data: None,
+        });
+        attach_to!(device, ras, cas, we);
+
+        device
+    }
+
+    /// Returns the index in memory of the latched row and column.
+    fn index(&self) -> usize {
+        // As with the 4164, this is never called unless both are latched
+        (self.row.unwrap() as usize) << 8 | self.col.unwrap() as usize
+    }
+
+    /// Puts the byte at the latched address onto Q0-Q7.
+    fn read(&mut self) {
+        let value = self.memory[self.index()];
+        self.q.write(value as usize);
+    }
+
+    /// Latches D0-D7 and writes them to the latched address. In read-modify-write mode,
+    /// when Q0-Q7 aren't hi-Z, the written byte is also put onto them.
+    fn write(&mut self) {
+        let value = self.d.value() as u8;
+        self.data = Some(value);
+        let index = self.index();
+        self.memory[index] = value;
+        if !floating!(self.pins[Q0]) {
+            self.q.write(value as usize);
+        }
+    }
+}
+
+impl Device for FastRam64k {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    fn registers(&self) -> Vec<u8> {
+        let status = self.row.is_some() as u8
+            | (self.col.is_some() as u8) << 1
+            | (self.data.is_some() as u8) << 2;
+        vec![
+            status,
+            self.row.unwrap_or(0),
+            self.col.unwrap_or(0),
+            self.data.unwrap_or(0),
+        ]
+    }
+
+    fn update(&mut self, event: &LevelChange) {
+        match event {
+            LevelChange(pin, _) if number!(pin) == RAS => {
+                if high!(pin) {
+                    self.row = None;
+                } else {
+                    self.row = Some(self.addr.value() as u8);
+                }
+            }
+            LevelChange(pin, _) if number!(pin) == CAS => {
+                if high!(pin) {
+                    self.q.float();
+                    self.col = None;
+                    self.data = None;
+                } else {
+                    self.col = Some(self.addr.value() as u8);
+                    if high!(self.pins[WE]) {
+                        self.read();
+                    } else {
+                        self.write();
+                    }
+                }
+            }
+            LevelChange(pin, _) if number!(pin) == WE => {
+                if high!(pin) {
+                    self.data = None;
+                } else if high!(self.pins[CAS]) {
+                    self.q.float();
+                } else {
+                    self.write();
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Instant;
+
+    use crate::{
+        components::{
+            pin::PinRef,
+            trace::{Trace, TraceRef},
+        },
+        devices::chips::Ic4164,
+        test_utils::{traces_to_value, value_to_traces},
+    };
+
+    use super::*;
+
+    /// The traces around a 64k x 8 RAM, which is either a `FastRam64k` or eight `Ic4164`s.
+    struct Bank {
+        addr: RefVec<Trace>,
+        d: RefVec<Trace>,
+        q: RefVec<Trace>,
+        ras: TraceRef,
+        cas: TraceRef,
+        we: TraceRef,
+
+        // Keeps the devices alive, since traces only hold their pins
+        _devices: Vec<DeviceRef>,
+    }
+
+    impl Bank {
+        /// Wires up the pins of a set of devices by name. Each shared line is connected to
+        /// every device that has a pin by that name.
+        fn wire(devices: Vec<DeviceRef>, d: &[(usize, &str)], q: &[(usize, &str)]) -> Bank {
+            let line = |name: &str, only: Option<usize>| {
+                let pins: Vec<PinRef> = devices
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| only.is_none_or(|n| n == *i))
+                    .filter_map(|(_, device)| device.borrow().pin_by_name(name))
+                    .collect();
+                let trace = Trace::new(pins.clone());
+                for pin in pins {
+                    pin.borrow_mut().set_trace(clone_ref!(trace));
+                }
+                trace
+            };
+            let bank = Bank {
+                addr: RefVec::with_vec(ADDRESS_NAMES.iter().map(|n| line(n, None)).collect()),
+                d: RefVec::with_vec(d.iter().map(|(i, n)| line(n, Some(*i))).collect()),
+                q: RefVec::with_vec(q.iter().map(|(i, n)| line(n, Some(*i))).collect()),
+                ras: line("RAS", None),
+                cas: line("CAS", None),
+                we: line("WE", None),
+                _devices: devices,
+            };
+            value_to_traces(0, &bank.addr);
+            set!(bank.ras);
+            set!(bank.cas);
+            set!(bank.we);
+            bank
+        }
+
+        fn fast() -> Bank {
+            let d: Vec<(usize, &str)> = D_NAMES.iter().map(|n| (0, *n)).collect();
+            let q: Vec<(usize, &str)> = Q_NAMES.iter().map(|n| (0, *n)).collect();
+            Bank::wire(vec![FastRam64k::new()], &d, &q)
+        }
+
+        fn chips() -> Bank {
+            let devices = (0..8).map(|_| Ic4164::new()).collect();
+            let d: Vec<(usize, &str)> = (0..8).map(|i| (i, "D")).collect();
+            let q: Vec<(usize, &str)> = (0..8).map(|i| (i, "Q")).collect();
+            Bank::wire(devices, &d, &q)
+        }
+
+        fn both() -> [Bank; 2] {
+            [Bank::fast(), Bank::chips()]
+        }
+
+        /// Returns the byte on Q0-Q7, or `None` if they're hi-Z.
+        fn q(&self) -> Option<u8> {
+            if self.q.iter_ref().any(|t| floating!(t)) {
+                None
+            } else {
+                Some(traces_to_value(&self.q) as u8)
+            }
+        }
+
+        /// Writes a byte in write mode, with its own RAS cycle.
+        fn write(&self, addr: u16, value: u8) {
+            value_to_traces((addr >> 8) as usize, &self.addr);
+            clear!(self.ras);
+            value_to_traces(value as usize, &self.d);
+            clear!(self.we);
+            value_to_traces((addr & 0xff) as usize, &self.addr);
+            clear!(self.cas);
+            set!(self.cas);
+            set!(self.we);
+            set!(self.ras);
+        }
+
+        /// Reads a byte, with its own RAS cycle.
+        fn read(&self, addr: u16) -> Option<u8> {
+            value_to_traces((addr >> 8) as usize, &self.addr);
+            clear!(self.ras);
+            value_to_traces((addr & 0xff) as usize, &self.addr);
+            clear!(self.cas);
+            let value = self.q();
+            set!(self.cas);
+            set!(self.ras);
+            value
+        }
+    }
+
+    #[test]
+    fn q_hi_z_with_cas_high() {
+        for bank in Bank::both() {
+            assert_eq!(bank.q(), None, "Q should be hi-Z initially");
+            clear!(bank.ras);
+            assert_eq!(bank.q(), None, "Q should be hi-Z before CAS");
+            clear!(bank.cas);
+            assert_eq!(bank.q(), Some(0), "Q should have data during read");
+            set!(bank.cas);
+            assert_eq!(bank.q(), None, "Q should be hi-Z after CAS goes high");
+            set!(bank.ras);
+        }
+    }
+
+    #[test]
+    fn write_mode_q_hi_z() {
+        for bank in Bank::both() {
+            clear!(bank.ras);
+            value_to_traces(0xa5, &bank.d);
+            clear!(bank.we);
+            clear!(bank.cas);
+            assert_eq!(bank.q(), None, "Q should be hi-Z in write mode");
+            set!(bank.cas);
+            set!(bank.we);
+            set!(bank.ras);
+            assert_eq!(bank.read(0x0000), Some(0xa5));
+        }
+    }
+
+    #[test]
+    fn rmw_mode_q_follows_d() {
+        for bank in Bank::both() {
+            bank.write(0x1234, 0x0f);
+            value_to_traces(0x12, &bank.addr);
+            clear!(bank.ras);
+            value_to_traces(0x34, &bank.addr);
+            clear!(bank.cas);
+            assert_eq!(bank.q(), Some(0x0f), "Q should have old data before WE");
+            value_to_traces(0xf0, &bank.d);
+            clear!(bank.we);
+            assert_eq!(bank.q(), Some(0xf0), "Q should follow written data in RMW");
+            set!(bank.we);
+            set!(bank.cas);
+            set!(bank.ras);
+            assert_eq!(bank.read(0x1234), Some(0xf0));
+        }
+    }
+
+    #[test]
+    fn page_with_latched_row() {
+        for bank in Bank::both() {
+            value_to_traces(0x30, &bank.addr);
+            clear!(bank.ras);
+            for col in 0..=0xff {
+                value_to_traces(col, &bank.addr);
+                value_to_traces(col ^ 0x5a, &bank.d);
+                clear!(bank.we);
+                clear!(bank.cas);
+                set!(bank.cas);
+                set!(bank.we);
+            }
+            for col in 0..=0xff {
+                value_to_traces(col, &bank.addr);
+                clear!(bank.cas);
+                assert_eq!(bank.q(), Some((col ^ 0x5a) as u8), "column ${:02X}", col);
+                set!(bank.cas);
+            }
+            set!(bank.ras);
+            assert_eq!(bank.read(0x3012), Some(0x48));
+            assert_eq!(
+                bank.read(0x2f12),
+                Some(0x00),
+                "other rows should be untouched"
+            );
+        }
+    }
+
+    #[test]
+    fn same_contents() {
+        let [fast, chips] = Bank::both();
+        for addr in (0..=0xffffu16).step_by(0x0101) {
+            let value = (addr as u8).wrapping_mul(7) ^ (addr >> 8) as u8;
+            fast.write(addr, value);
+            chips.write(addr, value);
+        }
+        for addr in (0..=0xffffu16).step_by(0x0101) {
+            assert_eq!(fast.read(addr), chips.read(addr), "address ${:04X}", addr);
+        }
+    }
+
+    /// Times a sweep of `cycles` writes and reads through a bank, returning the elapsed
+    /// seconds.
+    fn sweep(bank: &Bank, cycles: usize) -> f64 {
+        let start = Instant::now();
+        for i in 0..cycles {
+            let addr = (i * 0x0101) as u16;
+            if i & 1 == 0 {
+                bank.write(addr, i as u8);
+            } else {
+                assert_eq!(bank.read(addr.wrapping_sub(0x0101)), Some((i - 1) as u8));
+            }
+        }
+        start.elapsed().as_secs_f64()
+    }
+
+    // Run with `cargo test --release -- --ignored --nocapture fast_ram` to see the times.
+    #[test]
+    #[ignore]
+    fn benchmark() {
+        let cycles = 100_000;
+        let fast = sweep(&Bank::fast(), cycles);
+        let chips = sweep(&Bank::chips(), cycles);
+        println!(
+            "{} cycles: FastRam64k {:.3}s, 8 x Ic4164 {:.3}s ({:.1}x)",
+            cycles,
+            fast,
+            chips,
+            chips / fast
+        );
+    }
+}
@@ -0,0 +1,485 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! The C64's keyboard, as the 8x8 matrix of switches that CIA 1 scans.
+
+pub mod constants {
+    /// The pin assignment for row 0, connected to CIA 1's PA0.
+    pub const R0: usize = 1;
+    /// The pin assignment for row 1, connected to CIA 1's PA1.
+    pub const R1: usize = 2;
+    /// The pin assignment for row 2, connected to CIA 1's PA2.
+    pub const R2: usize = 3;
+    /// The pin assignment for row 3, connected to CIA 1's PA3.
+    pub const R3: usize = 4;
+    /// The pin assignment for row 4, connected to CIA 1's PA4.
+    pub const R4: usize = 5;
+    /// The pin assignment for row 5, connected to CIA 1's PA5.
+    pub const R5: usize = 6;
+    /// The pin assignment for row 6, connected to CIA 1's PA6.
+    pub const R6: usize = 7;
+    /// The pin assignment for row 7, connected to CIA 1's PA7.
+    pub const R7: usize = 8;
+
+    /// The pin assignment for column 0, connected to CIA 1's PB0.
+    pub const C0: usize = 9;
+    /// The pin assignment for column 1, connected to CIA 1's PB1.
+    pub const C1: usize = 10;
+    /// The pin assignment for column 2, connected to CIA 1's PB2.
+    pub const C2: usize = 11;
+    /// The pin assignment for column 3, connected to CIA 1's PB3.
+    pub const C3: usize = 12;
+    /// The pin assignment for column 4, connected to CIA 1's PB4.
+    pub const C4: usize = 13;
+    /// The pin assignment for column 5, connected to CIA 1's PB5.
+    pub const C5: usize = 14;
+    /// The pin assignment for column 6, connected to CIA 1's PB6.
+    pub const C6: usize = 15;
+    /// The pin assignment for column 7, connected to CIA 1's PB7.
+    pub const C7: usize = 16;
+}
+
+use std::{cell::RefCell, rc::Rc};
+
+use crate::{
+    components::{
+        device::{next_id, Device, DeviceRef, LevelChange},
+        pin::{
+            Mode::{Input, Output, Unconnected},
+            Pin,
+        },
+    },
+    vectors::RefVec,
+};
+
+use self::constants::*;
+
+/// The number of lines (rows and columns together) in the matrix. Lines 0-7 are the rows
+/// and 8-15 are the columns, so each line's pin number is one more than its index.
+const LINES: usize = 16;
+
+/// A key on the C64's keyboard, other than RESTORE (which isn't part of the matrix; it's
+/// wired to the NMI line through a 555 timer instead).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Delete,
+    Return,
+    CursorRight,
+    F7,
+    F1,
+    F3,
+    F5,
+    CursorDown,
+    Num3,
+    W,
+    A,
+    Num4,
+    Z,
+    S,
+    E,
+    LeftShift,
+    Num5,
+    R,
+    D,
+    Num6,
+    C,
+    F,
+    T,
+    X,
+    Num7,
+    Y,
+    G,
+    Num8,
+    B,
+    H,
+    U,
+    V,
+    Num9,
+    I,
+    J,
+    Num0,
+    M,
+    K,
+    O,
+    N,
+    Plus,
+    P,
+    L,
+    Minus,
+    Period,
+    Colon,
+    At,
+    Comma,
+    Pound,
+    Asterisk,
+    Semicolon,
+    Home,
+    RightShift,
+    Equals,
+    UpArrow,
+    Slash,
+    Num1,
+    LeftArrow,
+    Control,
+    Num2,
+    Space,
+    Commodore,
+    Q,
+    RunStop,
+}
+
+/// The keys at each position of the matrix, indexed by row and then column.
+const MATRIX: [[Key; 8]; 8] = {
+    use Key::*;
+    [
+        [Delete, Return, CursorRight, F7, F1, F3, F5, CursorDown],
+        [Num3, W, A, Num4, Z, S, E, LeftShift],
+        [Num5, R, D, Num6, C, F, T, X],
+        [Num7, Y, G, Num8, B, H, U, V],
+        [Num9, I, J, Num0, M, K, O, N],
+        [Plus, P, L, Minus, Period, Colon, At, Comma],
+        [
+            Pound, Asterisk, Semicolon, Home, RightShift, Equals, UpArrow, Slash,
+        ],
+        [Num1, LeftArrow, Control, Num2, Space, Commodore, Q, RunStop],
+    ]
+};
+
+impl Key {
+    /// Returns the key's position in the matrix as a (row, column) pair.
+    pub fn position(self) -> (usize, usize) {
+        let index = MATRIX
+            .iter()
+            .flatten()
+            .position(|key| *key == self)
+            .unwrap();
+        (index / 8, index % 8)
+    }
+}
+
+/// The C64's keyboard matrix.
+///
+/// The keyboard is 64 switches arranged in an 8x8 matrix, with each key connecting one row
+/// line to one column line when it's pressed. The rows are connected to CIA 1's port A and
+/// the columns to port B. To scan the keyboard, the KERNAL drives one row low at a time
+/// (writing 0 to that bit of port A and 1 to the rest) and reads port B: each column that
+/// reads low has a pressed key in that row.
+///
+/// Since the switches are entirely passive, they conduct in both directions, and a line
+/// driven low pulls low every line that it's connected to through any chain of pressed
+/// keys, not only those connected to it directly. This is the cause of the keyboard's
+/// well-known ghosting: with three keys pressed at three corners of a rectangle, scanning
+/// the row of the fourth corner shows that key pressed as well. It also means the matrix
+/// can be scanned the other way around, by driving columns and reading rows, which is how
+/// software tells keys apart from joystick 1 (whose switches are wired to port B too).
+///
+/// All 16 lines are emulated the same way. A line that's driven low from outside is left
+/// alone. A line that's connected to one of those through pressed keys is pulled low by
+/// switching its pin to output, and every other line is an input that leaves its level to
+/// the rest of the circuit (in the C64, the CIA's port pull-ups). Levels are recalculated
+/// whenever a key changes or any line's level changes.
+///
+/// The 6526's port outputs only pull high weakly, so on real hardware a key connected to a
+/// low line wins over a port pin driving high, which is what makes ghosting happen even
+/// when the rows are outputs. Traces that connect the matrix to port pins that are outputs
+/// should use `Resolution::WiredAnd` to get the same effect.
+///
+/// One compromise is needed: a line can't be pulled low from within the level change that
+/// notified the matrix about that same line (its pin is busy delivering the notification).
+/// That only happens when something stops driving a line low while the line is still
+/// connected to another low line, and the line catches up on the next change to any line.
+///
+/// Keys are pressed and released with `set_key` or with `press` and `release`. The
+/// constructor returns a reference to the concrete type so that these can be called after
+/// the matrix is wired up.
+pub struct KeyboardMatrix {
+    /// The unique identifier of this device.
+    id: usize,
+
+    /// The row and column pins, along with a dummy pin (at index 0) to ensure that the
+    /// vector index of the others matches their pin assignments.
+    pins: RefVec<Pin>,
+
+    /// The pressed keys, as a bit for each column in each row.
+    keys: [u8; 8],
+
+    /// The lines that the matrix is pulling low, as a bit for each line.
+    pulled: u16,
+}
+
+impl KeyboardMatrix {
+    /// Creates a new keyboard matrix with no keys pressed and returns a shared, internally
+    /// mutable reference to it.
+    pub fn new() -> Rc<RefCell<KeyboardMatrix>> {
+        let r0 = pin!(R0, "R0", Input);
+        let r1 = pin!(R1, "R1", Input);
+        let r2 = pin!(R2, "R2", Input);
+        let r3 = pin!(R3, "R3", Input);
+        let r4 = pin!(R4, "R4", Input);
+        let r5 = pin!(R5, "R5", Input);
+        let r6 = pin!(R6, "R6", Input);
+        let r7 = pin!(R7, "R7", Input);
+
+        let c0 = pin!(C0, "C0", Input);
+        let c1 = pin!(C1, "C1", Input);
+        let c2 = pin!(C2, "C2", Input);
+        let c3 = pin!(C3, "C3", Input);
+        let c4 = pin!(C4, "C4", Input);
+        let c5 = pin!(C5, "C5", Input);
+        let c6 = pin!(C6, "C6", Input);
+        let c7 = pin!(C7, "C7", Input);
+
+        let keyboard = new_ref!(KeyboardMatrix {
+            id: next_id(),
+            pins: pins![r0, r1, r2, r3, r4, r5, r6, r7, c0, c1, c2, c3, c4, c5, c6, c7],
+            keys: [0; 8],
+            pulled: 0,
+        });
+
+        let device: DeviceRef = keyboard.clone();
+        attach_to!(device, r0, r1, r2, r3, r4, r5, r6, r7, c0, c1, c2, c3, c4, c5, c6, c7);
+
+        keyboard
+    }
+
+    /// Presses (if `pressed` is `true`) or releases the key at a row and column of the
+    /// matrix.
+    pub fn set_key(&mut self, row: usize, col: usize, pressed: bool) {
+        if pressed {
+            self.keys[row] |= 1 << col;
+        } else {
+            self.keys[row] &= !(1 << col);
+        }
+        self.resolve(None);
+    }
+
+    /// Presses a key.
+    pub fn press(&mut self, key: Key) {
+        let (row, col) = key.position();
+        self.set_key(row, col, true);
+    }
+
+    /// Releases a key.
+    pub fn release(&mut self, key: Key) {
+        let (row, col) = key.position();
+        self.set_key(row, col, false);
+    }
+
+    /// Returns the lines reachable from a set of lines through pressed keys, including the
+    /// lines themselves.
+    fn reach(&self, lines: u16) -> u16 {
+        let mut reached = lines;
+        loop {
+            let mut next = reached;
+            for row in 0..8 {
+                let cols = (self.keys[row] as u16) << 8;
+                if reached & (1 << row) != 0 {
+                    next |= cols;
+                }
+                if reached & cols != 0 {
+                    next |= 1 << row;
+                }
+            }
+            if next == reached {
+                return reached;
+            }
+            reached = next;
+        }
+    }
+
+    /// Recalculates which lines the matrix pulls low. `event` is the pin number and level
+    /// (`true` for low) of the line whose change caused the recalculation, if any; that
+    /// pin can't be read or changed while its change is being handled.
+    fn resolve(&mut self, event: Option<(usize, bool)>) {
+        let event_line = event.map(|(number, _)| number - 1);
+        loop {
+            let mut sources = 0;
+            for line in (0..LINES).filter(|line| self.pulled & (1 << line) == 0) {
+                let low = match event {
+                    Some((number, low)) if number == line + 1 => low,
+                    _ => low!(self.pins[line + 1]),
+                };
+                if low {
+                    sources |= 1 << line;
+                }
+            }
+            let target = self.reach(sources) & !sources;
+
+            let mut released = false;
+            for line in 0..LINES {
+                if Some(line) == event_line {
+                    continue;
+                }
+                let bit = 1 << line;
+                let pin = &self.pins[line + 1];
+                if self.pulled & bit != 0 && target & bit == 0 {
+                    // Floating the pin first lets the trace settle before the pin reads it
+                    float!(pin);
+                    set_mode!(pin, Input);
+                    self.pulled &= !bit;
+                    released = true;
+                } else if self.pulled & bit == 0 && target & bit != 0 {
+                    // Setting the level while unconnected keeps the pin from briefly
+                    // driving its old level when it becomes an output
+                    set_mode!(pin, Unconnected);
+                    clear!(pin);
+                    set_mode!(pin, Output);
+                    self.pulled |= bit;
+                }
+            }
+
+            // A released line might be driven low from outside, which makes it a source
+            if !released {
+                break;
+            }
+        }
+    }
+}
+
+impl Device for KeyboardMatrix {
+    fn id(&self) -> usize {
+        self.id
+    }
+
+    fn pins(&self) -> RefVec<Pin> {
+        self.pins.clone()
+    }
+
+    /// Returns the pressed keys, one byte per row with a bit set for each pressed column.
+    fn registers(&self) -> Vec<u8> {
+        self.keys.to_vec()
+    }
+
+    fn update(&mut self, event: &LevelChange) {
+        let LevelChange(pin, _) = event;
+        self.resolve(Some((number!(pin), low!(pin))));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{components::trace::Trace, test_utils::make_traces};
+
+    use super::*;
+
+    const ROWS: [usize; 8] = [R0, R1, R2, R3, R4, R5, R6, R7];
+    const COLS: [usize; 8] = [C0, C1, C2, C3, C4, C5, C6, C7];
+
+    fn before_each() -> (Rc<RefCell<KeyboardMatrix>>, RefVec<Trace>) {
+        let keyboard = KeyboardMatrix::new();
+        let device: DeviceRef = keyboard.clone();
+        let tr = make_traces(&device);
+        for col in COLS.iter() {
+            pull_up!(tr[*col]);
+        }
+        (keyboard, tr)
+    }
+
+    /// Drives one line of `drive` low and the rest high, and returns the levels of the
+    /// `read` lines as a byte (1 for high).
+    fn select(tr: &RefVec<Trace>, drive: &[usize; 8], read: &[usize; 8], n: usize) -> u8 {
+        for (i, line) in drive.iter().enumerate() {
+            if i == n {
+                clear!(tr[*line]);
+            } else {
+                set!(tr[*line]);
+            }
+        }
+        read.iter()
+            .enumerate()
+            .fold(0, |value, (i, line)| value | (high!(tr[*line]) as u8) << i)
+    }
+
+    /// Scans the keyboard the way the KERNAL does, returning the column byte read for each
+    /// row.
+    fn scan(tr: &RefVec<Trace>) -> [u8; 8] {
+        let mut result = [0; 8];
+        for (row, value) in result.iter_mut().enumerate() {
+            *value = select(tr, &ROWS, &COLS, row);
+        }
+        result
+    }
+
+    #[test]
+    fn no_keys() {
+        let (_, tr) = before_each();
+        assert_eq!(scan(&tr), [0xff; 8]);
+    }
+
+    #[test]
+    fn single_key() {
+        let (keyboard, tr) = before_each();
+        keyboard.borrow_mut().press(Key::A);
+        assert_eq!(Key::A.position(), (1, 2));
+
+        let mut expected = [0xff; 8];
+        expected[1] = !0x04;
+        assert_eq!(scan(&tr), expected);
+
+        keyboard.borrow_mut().release(Key::A);
+        assert_eq!(scan(&tr), [0xff; 8], "released key should not be seen");
+    }
+
+    #[test]
+    fn key_pressed_while_row_selected() {
+        let (keyboard, tr) = before_each();
+        clear!(tr[R7]);
+        assert!(high!(tr[C4]));
+        keyboard.borrow_mut().press(Key::Space);
+        assert!(
+            low!(tr[C4]),
+            "column should go low as soon as key is pressed"
+        );
+        keyboard.borrow_mut().release(Key::Space);
+        assert!(
+            high!(tr[C4]),
+            "column should go high as soon as key is released"
+        );
+    }
+
+    #[test]
+    fn two_keys_same_column() {
+        let (keyboard, tr) = before_each();
+        keyboard.borrow_mut().set_key(0, 3, true);
+        keyboard.borrow_mut().set_key(2, 3, true);
+
+        let mut expected = [0xff; 8];
+        expected[0] = !0x08;
+        expected[2] = !0x08;
+        assert_eq!(scan(&tr), expected);
+    }
+
+    #[test]
+    fn three_key_ghost() {
+        let (keyboard, tr) = before_each();
+        // Three corners of the rectangle with corners at rows 0 and 1, columns 0 and 1
+        keyboard.borrow_mut().set_key(0, 0, true);
+        keyboard.borrow_mut().set_key(0, 1, true);
+        keyboard.borrow_mut().set_key(1, 0, true);
+
+        let result = scan(&tr);
+        assert_eq!(result[0], !0x03);
+        assert_eq!(
+            result[1], !0x03,
+            "row 1 should show a phantom key at column 1"
+        );
+        assert_eq!(result[2..], [0xff; 6]);
+    }
+
+    #[test]
+    fn reverse_scan() {
+        let (keyboard, tr) = before_each();
+        for row in ROWS.iter() {
+            pull_up!(tr[*row]);
+        }
+        for col in COLS.iter() {
+            pull_off!(tr[*col]);
+        }
+        keyboard.borrow_mut().press(Key::A);
+
+        assert_eq!(select(&tr, &COLS, &ROWS, 2), !0x02);
+        assert_eq!(select(&tr, &COLS, &ROWS, 1), 0xff);
+    }
+}
@@ -3,4 +3,11 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+pub mod banking;
+pub mod cartridge;
 pub mod chips;
+pub mod control_port;
+pub mod datasette;
+pub mod fast_ram;
+pub mod keyboard;
+pub mod pla;
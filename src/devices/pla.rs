@@ -0,0 +1,400 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! The logic programmed into the C64's PLA, as plain functions of its inputs.
+//!
+//! `Ic82S100` uses `pla_output` to work out what to put on its output pins, but the same
+//! logic is useful without pins at all, for anything that needs to know which chip is
+//! selected for an access without simulating the chip. `PlaTable` goes a step further and
+//! precomputes every output, for code that needs the answer as quickly as possible.
+//!
+//! Inputs and outputs are packed into words with bit n holding the level of In or Fn. The
+//! constants in `inputs` and `outputs` name those bits after the C64 signals on each pin.
+
+pub mod inputs {
+    /// Input word bit for I0, CAS.
+    pub const CAS: u16 = 1 << 0;
+    /// Input word bit for I1, LORAM.
+    pub const LORAM: u16 = 1 << 1;
+    /// Input word bit for I2, HIRAM.
+    pub const HIRAM: u16 = 1 << 2;
+    /// Input word bit for I3, CHAREN.
+    pub const CHAREN: u16 = 1 << 3;
+    /// Input word bit for I4, VA14.
+    pub const VA14: u16 = 1 << 4;
+    /// Input word bit for I5, A15.
+    pub const A15: u16 = 1 << 5;
+    /// Input word bit for I6, A14.
+    pub const A14: u16 = 1 << 6;
+    /// Input word bit for I7, A13.
+    pub const A13: u16 = 1 << 7;
+    /// Input word bit for I8, A12.
+    pub const A12: u16 = 1 << 8;
+    /// Input word bit for I9, BA.
+    pub const BA: u16 = 1 << 9;
+    /// Input word bit for I10, AEC.
+    pub const AEC: u16 = 1 << 10;
+    /// Input word bit for I11, R_W.
+    pub const R_W: u16 = 1 << 11;
+    /// Input word bit for I12, EXROM.
+    pub const EXROM: u16 = 1 << 12;
+    /// Input word bit for I13, GAME.
+    pub const GAME: u16 = 1 << 13;
+    /// Input word bit for I14, VA13.
+    pub const VA13: u16 = 1 << 14;
+    /// Input word bit for I15, VA12.
+    pub const VA12: u16 = 1 << 15;
+}
+
+pub mod outputs {
+    /// Output word bit for F0, CASRAM.
+    pub const CASRAM: u8 = 1 << 0;
+    /// Output word bit for F1, BASIC.
+    pub const BASIC: u8 = 1 << 1;
+    /// Output word bit for F2, KERNAL.
+    pub const KERNAL: u8 = 1 << 2;
+    /// Output word bit for F3, CHAROM.
+    pub const CHAROM: u8 = 1 << 3;
+    /// Output word bit for F4, GR_W.
+    pub const GR_W: u8 = 1 << 4;
+    /// Output word bit for F5, IO.
+    pub const IO: u8 = 1 << 5;
+    /// Output word bit for F6, ROML.
+    pub const ROML: u8 = 1 << 6;
+    /// Output word bit for F7, ROMH.
+    pub const ROMH: u8 = 1 << 7;
+}
+
+use self::{inputs::*, outputs::*};
+
+/// Returns the outputs of the C64's PLA for a word of inputs.
+pub fn pla_output(inputs: u16) -> u8 {
+    // These are the product term equations programmed into the PLA for use in a
+    // C64. The names for each signal reflect the names of the pins that those
+    // signals come from, and while that is an excellent way to make long and
+    // complex code succinct, it doesn't do much for the human reader. For that
+    // reason, each term has a comment to describe in more human terms what is
+    // happening with that piece of the algorithm.
+    //
+    // Each P-term below has a comment with three lines. The first line
+    // describes the state of the three 6510 I/O port lines that are used for
+    // bank switching (LORAM, HIRAM, and CHAREN). The second line is the memory
+    // address that needs to be accessed to select that P-term (this is from
+    // either the regular address bus when the CPU is active or the VIC address
+    // bus when the VIC is active). The final line gives information about
+    // whether the CPU or the VIC is active, whether the memory access is a read
+    // or a write, and what type (if any) of cartridge must be plugged into the
+    // expansion port (the cartridge informaion takes into account the values of
+    // LORAM, HIRAM, and CHAREN already).
+    //
+    // If any piece of information is not given, its value doesn't matter to
+    // that P-term. For example, in p0, the comment says that LORAM and HIRAM
+    // must both be deselected. CHAREN isn't mentioned because whether it is
+    // selected or not doesn't change whether that P-term is selected or not.
+    //
+    // Oftentimes, the reason for multiple terms for one output selection is the
+    // limitation on what can be checked in a single logic term, given that no
+    // ORs are possible in the production of P-terms. For example, it is very
+    // common to see two terms that are identical except that one indicates "no
+    // cartridge or 8k cartridge" while the other has "16k cartridge". These two
+    // terms together really mean "anything but an Ultimax cartridge", but
+    // there's no way to do that in a single term with only AND and NOT.
+    //
+    // This information comes from the excellent paper available at
+    // skoe.de/docs/c64-dissected/pla/c64_pla_dissected_a4ds.pdf. If this sort
+    // of thing interests you, there's no better place for information about the
+    // C64 PLA.
+    let cas = inputs & CAS != 0;
+    let loram = inputs & LORAM != 0;
+    let hiram = inputs & HIRAM != 0;
+    let charen = inputs & CHAREN != 0;
+    let va14 = inputs & VA14 != 0;
+    let a15 = inputs & A15 != 0;
+    let a14 = inputs & A14 != 0;
+    let a13 = inputs & A13 != 0;
+    let a12 = inputs & A12 != 0;
+    let ba = inputs & BA != 0;
+    let aec = inputs & AEC != 0;
+    let r_w = inputs & R_W != 0;
+    let exrom = inputs & EXROM != 0;
+    let game = inputs & GAME != 0;
+    let va13 = inputs & VA13 != 0;
+    let va12 = inputs & VA12 != 0;
+
+    // LORAM deselected, HIRAM deselected
+    // $A000 - $BFFF
+    // CPU active, Read, No cartridge or 8k cartridge
+    let p0 = loram & hiram & a15 & !a14 & a13 & !aec & r_w & game;
+
+    // HIRAM deselected
+    // $E000 - $FFFF
+    // CPU active, Read, No cartridge or 8k cartridge
+    let p1 = hiram & a15 & a14 & a13 & !aec & r_w & game;
+
+    // HIRAM deselected
+    // $E000 - $FFFF
+    // CPU active, Read, 16k cartridge
+    let p2 = hiram & a15 & a14 & a13 & !aec & r_w & !exrom & !game;
+
+    // HIRAM deselected, CHAREN selected
+    // $D000 - $DFFF
+    // CPU active, Read, No cartridge or 8k cartridge
+    let p3 = hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game;
+
+    // LORAM deselected, CHAREN selected
+    // $D000 - $DFFF
+    // CPU active, Read, No cartridge or 8k cartridge
+    let p4 = loram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game;
+
+    // HIRAM deselected, CHAREN selected
+    // $D000 - $DFFF
+    // CPU active, Read, 16k cartridge
+    let p5 = hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & !exrom & !game;
+
+    //
+    // $1000 - $1FFF or $9000 - $9FFF
+    // VIC active, No cartridge or 8k cartridge
+    let p6 = va14 & !va13 & va12 & aec & game;
+
+    //
+    // $1000 - $1FFF or $9000 - $9FFF
+    // VIC active, 16k cartridge
+    let p7 = va14 & !va13 & va12 & aec & !exrom & !game;
+
+    // Unused. May be a relic from earlier design in C64 prototypes that never
+    // got removed.
+    // let p8 = cas & a15 & a14 & !a12 & a11 & !aec & !r_w;
+
+    // HIRAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Bus available, Read, No cartridge or 8k cartridge
+    let p9 = hiram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & game;
+
+    // HIRAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Write, No cartridge or 8k cartridge
+    let p10 = hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game;
+
+    // LORAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Bus available, Read, No cartridge or 8k cartridge
+    let p11 = loram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & game;
+
+    // LORAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Write, No cartridge or 8k cartridge
+    let p12 = loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game;
+
+    // HIRAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Bus available, Read, 16k cartridge
+    let p13 = hiram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & !exrom & !game;
+
+    // HIRAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Write, 16k cartridge
+    let p14 = hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game;
+
+    // LORAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Bus available, Read, 16k cartridge
+    let p15 = loram & charen & a15 & a14 & !a13 & a12 & !aec & ba & r_w & !exrom & !game;
+
+    // LORAM deselected, CHAREN deselected
+    // $D000 - $DFFF
+    // CPU active, Write, 16k cartridge
+    let p16 = loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game;
+
+    //
+    // $D000 - $DFFF
+    // CPU active, Bus available, Read, Ultimax cartridge
+    let p17 = a15 & a14 & !a13 & a12 & !aec & ba & r_w & exrom & !game;
+
+    //
+    // $D000 - $DFFF
+    // CPU active, Write, Ultimax cartridge
+    let p18 = a15 & a14 & !a13 & a12 & !aec & !r_w & exrom & !game;
+
+    // LORAM deselected, HIRAM deselected
+    // $8000 - $9FFF
+    // CPU active, Read, 8k or 16k cartridge
+    let p19 = loram & hiram & a15 & !a14 & !a13 & !aec & r_w & !exrom;
+
+    //
+    // $8000 - $9FFF
+    // CPU active, Ultimax cartridge
+    let p20 = a15 & !a14 & !a13 & !aec & exrom & !game;
+
+    // HIRAM deselected
+    // $A000 - $BFFF
+    // CPU active, Read, 16k cartridge
+    let p21 = hiram & a15 & !a14 & a13 & !aec & r_w & !exrom & !game;
+
+    //
+    // $E000 - $EFFF
+    // CPU active, Ultimax cartridge
+    let p22 = a15 & a14 & a13 & !aec & exrom & !game;
+
+    //
+    // $3000 - $3FFF, $7000 - $7FFF, $B000 - $BFFF, or $E000 - $EFFF
+    // VIC active, Ultimax cartridge
+    let p23 = va13 & va12 & aec & exrom & !game;
+
+    //
+    // $1000 - $1FFF or $3000 - $3FFF
+    // Ultimax cartridge
+    let p24 = !a15 & !a14 & a12 & exrom & !game;
+
+    //
+    // $2000 - $3FFF
+    // Ultimax cartridge
+    let p25 = !a15 & !a14 & a13 & exrom & !game;
+
+    //
+    // $4000 - $7FFF
+    // Ultimax cartridge
+    let p26 = !a15 & a14 & exrom & !game;
+
+    //
+    // $A000 - $BFFF
+    // Ultimax cartridge
+    let p27 = a15 & !a14 & a13 & exrom & !game;
+
+    //
+    // $C000 - $CFFF
+    // Ultimax cartridge
+    let p28 = a15 & a14 & !a13 & !a12 & exrom & !game;
+
+    // Unused.
+    // let p29 = !loram;
+
+    // CAS deselected
+    //
+    //
+    let p30 = cas;
+
+    // CAS selected
+    // $D000 - $DFFF
+    // CPU access, Write
+    let p31 = !cas & a15 & a14 & !a13 & a12 & !aec & !r_w;
+
+    // This is the sum-term (S-term) portion of the logic, where the P-terms
+    // calculated above are logically ORed to poroduce a single output. This is
+    // much simpler than P-term production because the P-terms handle everything
+    // about chip selection, except that each chip may be the choice of several
+    // different P-terms. That's the role of the S-term logic, to combine
+    // P-terms to come up with single outputs.
+
+    // Selects BASIC ROM.
+    let s1 = p0;
+
+    // Selects KERNAL ROM.
+    let s2 = p1 | p2;
+
+    // Selects Character ROM.
+    let s3 = p3 | p4 | p5 | p6 | p7;
+
+    // Selects I/O, color RAM, or processor registers.
+    let s4 = p9 | p10 | p11 | p12 | p13 | p14 | p15 | p16 | p17 | p18;
+
+    // Selects low cartridge ROM.
+    let s5 = p19 | p20;
+
+    // Selects high cartridge ROM.
+    let s6 = p21 | p22 | p23;
+
+    // Selects write mode for color RAM.
+    let s7 = p31;
+
+    // Deselects RAM. This is the only *de*selection, which is why it is the
+    // only one not inverted in the state assignment below.
+    let s0 = s1 | s2 | s3 | s4 | s5 | s6 | p24 | p25 | p26 | p27 | p28 | p30;
+
+    // Every output but CASRAM is active low
+    let mut outputs = 0;
+    for (bit, selected) in [
+        (CASRAM, s0),
+        (BASIC, !s1),
+        (KERNAL, !s2),
+        (CHAROM, !s3),
+        (GR_W, !s7),
+        (IO, !s4),
+        (ROML, !s5),
+        (ROMH, !s6),
+    ] {
+        if selected {
+            outputs |= bit;
+        }
+    }
+    outputs
+}
+
+/// A table of the PLA's outputs for all 65,536 possible inputs.
+///
+/// Building the table evaluates `pla_output` for every input, which takes a little time and
+/// 64k of memory, but after that each lookup is a single array index.
+pub struct PlaTable {
+    /// The outputs, indexed by input word.
+    outputs: Box<[u8]>,
+}
+
+impl PlaTable {
+    /// Creates a new table.
+    pub fn new() -> PlaTable {
+        PlaTable {
+            outputs: (0..=0xffff).map(pla_output).collect(),
+        }
+    }
+
+    /// Returns the PLA's outputs for a word of inputs.
+    pub fn lookup(&self, inputs: u16) -> u8 {
+        self.outputs[inputs as usize]
+    }
+}
+
+impl Default for PlaTable {
+    fn default() -> Self {
+        PlaTable::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::pla_reference;
+
+    #[test]
+    fn matches_reference() {
+        for value in 0..=0xffff {
+            assert_eq!(
+                pla_output(value),
+                pla_reference(value),
+                "Incorrect output for input {:016b}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn table_matches_function() {
+        let table = PlaTable::new();
+        for value in 0..=0xffff {
+            assert_eq!(
+                table.lookup(value),
+                pla_output(value),
+                "Table differs for input {:016b}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn named_bits() {
+        // Default banking, CPU reading $E000: only KERNAL is selected (low)
+        let inputs =
+            CAS | LORAM | HIRAM | CHAREN | VA14 | A15 | A14 | A13 | BA | R_W | EXROM | GAME;
+        assert_eq!(pla_output(inputs), !KERNAL);
+    }
+}
@@ -0,0 +1,332 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Storage for the records that the emulator keeps to help with debugging.
+//!
+//! Diagnostic records pile up for as long as the emulator runs, so every buffer that holds
+//! them is a `BoundedLog`. A log has a limit, either a number of entries or a number of
+//! bytes, and once it's full each new entry evicts the oldest ones. A log can be asked how
+//! many entries it has evicted, so that anything reading it can tell that it isn't seeing
+//! the whole history, and it raises a one-time warning when it starts evicting, so that
+//! whatever owns it can say so when it happens.
+
+use std::{collections::VecDeque, mem};
+
+/// How a `BoundedLog` decides that it's full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Limit {
+    /// The log holds at most this many entries.
+    Entries(usize),
+
+    /// The sizes of the log's entries add up to at most this many bytes.
+    Bytes(usize),
+}
+
+/// A first-in, first-out log that never grows past its limit.
+///
+/// A log is limited either by the number of entries it holds (`new`) or by the total size
+/// of those entries in bytes (`with_byte_budget`). In the latter case, the size of each
+/// entry is worked out by a function supplied when the log is created, which lets entries
+/// that own heap data (like a `Vec`) be counted at their real size. Either way, pushing an
+/// entry into a full log evicts the oldest entries until the new one fits. A log with a
+/// byte budget always keeps its newest entry, even if it's bigger than the whole budget on
+/// its own.
+///
+/// The log also keeps track of the number of bytes its entries use in both modes, so the
+/// memory cost of a log limited by entries can still be checked.
+#[derive(Clone, Debug)]
+pub struct BoundedLog<T> {
+    /// The entries, oldest first.
+    entries: VecDeque<T>,
+
+    /// When the log is full.
+    limit: Limit,
+
+    /// The function that works out how many bytes an entry uses.
+    size: fn(&T) -> usize,
+
+    /// The total size of the entries, in bytes.
+    bytes: usize,
+
+    /// The number of entries that have been evicted to make room for newer ones.
+    evicted: usize,
+
+    /// Whether the log has started evicting entries and `take_eviction_warning` hasn't yet
+    /// reported it.
+    warning: bool,
+}
+
+impl<T> BoundedLog<T> {
+    /// Creates a new, empty log that holds at most `capacity` entries. Each entry is counted
+    /// as `mem::size_of::<T>()` bytes.
+    pub fn new(capacity: usize) -> BoundedLog<T> {
+        BoundedLog {
+            entries: VecDeque::new(),
+            limit: Limit::Entries(capacity),
+            size: |_| mem::size_of::<T>(),
+            bytes: 0,
+            evicted: 0,
+            warning: false,
+        }
+    }
+
+    /// Creates a new, empty log whose entries use at most `budget` bytes in total, as
+    /// measured by `size`.
+    pub fn with_byte_budget(budget: usize, size: fn(&T) -> usize) -> BoundedLog<T> {
+        BoundedLog {
+            entries: VecDeque::new(),
+            limit: Limit::Bytes(budget),
+            size,
+            bytes: 0,
+            evicted: 0,
+            warning: false,
+        }
+    }
+
+    /// Adds an entry to the end of the log, evicting the oldest entries if they have to
+    /// make room for it.
+    pub fn push(&mut self, entry: T) {
+        self.bytes += (self.size)(&entry);
+        self.entries.push_back(entry);
+        self.enforce();
+    }
+
+    /// Returns the number of entries in the log.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the log has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the total size of the entries in the log, in bytes.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Returns the number of entries that have been evicted from the log since it was
+    /// created. Emptying the log with `take` or `clear` doesn't count as eviction.
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    /// Returns `true` the first time it's called after the log has started evicting entries,
+    /// and `false` every other time. This lets the log's owner warn that history is being
+    /// lost when it starts happening, without repeating itself on every push.
+    pub fn take_eviction_warning(&mut self) -> bool {
+        mem::replace(&mut self.warning, false)
+    }
+
+    /// Returns the maximum number of entries the log will hold, or `None` if it's limited
+    /// by bytes instead.
+    pub fn capacity(&self) -> Option<usize> {
+        match self.limit {
+            Limit::Entries(capacity) => Some(capacity),
+            Limit::Bytes(_) => None,
+        }
+    }
+
+    /// Returns the maximum number of bytes the log's entries will use, or `None` if it's
+    /// limited by entries instead.
+    pub fn byte_budget(&self) -> Option<usize> {
+        match self.limit {
+            Limit::Entries(_) => None,
+            Limit::Bytes(budget) => Some(budget),
+        }
+    }
+
+    /// Limits the log to `capacity` entries, evicting the oldest entries right away if it
+    /// already holds more.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.limit = Limit::Entries(capacity);
+        self.enforce();
+    }
+
+    /// Limits the log's entries to `budget` bytes, evicting the oldest entries right away
+    /// if they already use more.
+    pub fn set_byte_budget(&mut self, budget: usize) {
+        self.limit = Limit::Bytes(budget);
+        self.enforce();
+    }
+
+    /// Returns the oldest entry in the log.
+    pub fn first(&self) -> Option<&T> {
+        self.entries.front()
+    }
+
+    /// Returns the newest entry in the log.
+    pub fn last(&self) -> Option<&T> {
+        self.entries.back()
+    }
+
+    /// Returns an iterator over the entries in the log, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries.iter()
+    }
+
+    /// Removes all of the entries from the log and returns them, oldest first.
+    pub fn take(&mut self) -> Vec<T> {
+        self.bytes = 0;
+        self.entries.drain(..).collect()
+    }
+
+    /// Removes all of the entries from the log.
+    pub fn clear(&mut self) {
+        self.bytes = 0;
+        self.entries.clear();
+    }
+
+    /// Returns `true` if the log is over its limit.
+    fn over(&self) -> bool {
+        match self.limit {
+            Limit::Entries(capacity) => self.entries.len() > capacity,
+            Limit::Bytes(budget) => self.bytes > budget,
+        }
+    }
+
+    /// Evicts the oldest entries until the log is within its limit. In byte budget mode,
+    /// the newest entry is kept no matter how big it is.
+    fn enforce(&mut self) {
+        while self.over() {
+            if matches!(self.limit, Limit::Bytes(_)) && self.entries.len() == 1 {
+                break;
+            }
+            match self.entries.pop_front() {
+                Some(entry) => {
+                    self.bytes -= (self.size)(&entry);
+                    if self.evicted == 0 {
+                        self.warning = true;
+                    }
+                    self.evicted += 1;
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn holds_entries_under_capacity() {
+        let mut log = BoundedLog::new(4);
+        log.push(1u32);
+        log.push(2);
+        log.push(3);
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.evicted(), 0);
+        assert_eq!(log.iter().copied().collect::<Vec<u32>>(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn evicts_oldest_first() {
+        let mut log = BoundedLog::new(4);
+        for i in 0..10u32 {
+            log.push(i);
+        }
+        assert_eq!(log.len(), 4);
+        assert_eq!(log.evicted(), 6);
+        assert_eq!(log.first(), Some(&6));
+        assert_eq!(log.last(), Some(&9));
+        assert_eq!(log.take(), vec![6, 7, 8, 9]);
+        assert!(log.is_empty());
+        assert_eq!(
+            log.evicted(),
+            6,
+            "Taking entries should not count as eviction"
+        );
+    }
+
+    #[test]
+    fn eviction_warning_once() {
+        let mut log = BoundedLog::new(2);
+        log.push(1u8);
+        log.push(2);
+        assert!(!log.take_eviction_warning());
+
+        log.push(3);
+        log.push(4);
+        assert!(log.take_eviction_warning());
+        assert!(!log.take_eviction_warning(), "warning should be taken once");
+
+        log.push(5);
+        assert!(
+            !log.take_eviction_warning(),
+            "later evictions should not warn again"
+        );
+        assert_eq!(log.evicted(), 3);
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let mut log = BoundedLog::new(0);
+        log.push(1u8);
+        assert!(log.is_empty());
+        assert_eq!(log.evicted(), 1);
+    }
+
+    #[test]
+    fn entry_bytes() {
+        let mut log = BoundedLog::new(8);
+        log.push(1u32);
+        log.push(2);
+        assert_eq!(log.bytes(), 8);
+        log.clear();
+        assert_eq!(log.bytes(), 0);
+    }
+
+    #[test]
+    fn byte_budget() {
+        let mut log = BoundedLog::with_byte_budget(10, |s: &String| s.len());
+        log.push(String::from("abcd"));
+        log.push(String::from("efgh"));
+        assert_eq!(log.bytes(), 8);
+        assert_eq!(log.evicted(), 0);
+
+        log.push(String::from("ijk"));
+        assert_eq!(log.bytes(), 7);
+        assert_eq!(log.evicted(), 1);
+        assert_eq!(log.first().map(String::as_str), Some("efgh"));
+
+        log.push(String::from("lmnopq"));
+        assert_eq!(log.bytes(), 9);
+        assert_eq!(log.evicted(), 2);
+        assert_eq!(
+            log.iter().map(String::as_str).collect::<Vec<&str>>(),
+            vec!["ijk", "lmnopq"]
+        );
+    }
+
+    #[test]
+    fn oversized_entry_kept() {
+        let mut log = BoundedLog::with_byte_budget(4, |s: &String| s.len());
+        log.push(String::from("ab"));
+        log.push(String::from("abcdefgh"));
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.bytes(), 8);
+        assert_eq!(log.last().map(String::as_str), Some("abcdefgh"));
+    }
+
+    #[test]
+    fn shrinking_limit_evicts() {
+        let mut log = BoundedLog::new(8);
+        for i in 0..8u16 {
+            log.push(i);
+        }
+        log.set_capacity(3);
+        assert_eq!(log.capacity(), Some(3));
+        assert_eq!(log.iter().copied().collect::<Vec<u16>>(), vec![5, 6, 7]);
+
+        log.set_byte_budget(4);
+        assert_eq!(log.capacity(), None);
+        assert_eq!(log.byte_budget(), Some(4));
+        assert_eq!(log.iter().copied().collect::<Vec<u16>>(), vec![6, 7]);
+        assert_eq!(log.evicted(), 6);
+    }
+}
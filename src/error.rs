@@ -0,0 +1,341 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! The crate-level error type.
+//!
+//! Each part of the emulator that can fail defines its own error enum describing what went
+//! wrong in its own terms (like `PortError` or `BusError`). Those are gathered here into a
+//! single `Error` type, with `From` conversions so that `?` can carry a module's error up
+//! through code that deals with several modules at once. The original error is kept as the
+//! `source` of the wrapping one, so nothing is lost along the way.
+//!
+//! Both enums are `#[non_exhaustive]`, since new kinds of errors will be added as more of
+//! the system is emulated. `Error` is neither `Copy` nor `PartialEq`, since some of the
+//! errors it wraps carry an `io::Error`; match on it instead of comparing it.
+
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    components::{bus::BusError, port::PortError, trace::ConnectError},
+    devices::{cartridge::CartridgeError, chips::CiaConfigError, datasette::TapError},
+    roms::RomError,
+    utils::WidthError,
+};
+
+/// An error from anywhere in the emulator.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An error in connecting devices together.
+    Wiring(WiringError),
+
+    /// An error in building a cartridge from an image.
+    Cartridge(CartridgeError),
+
+    /// An error in reading a tape image.
+    Tape(TapError),
+
+    /// An error in loading or patching a ROM image.
+    Rom(RomError),
+
+    /// An error in the configuration of a chip.
+    Config(CiaConfigError),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Error::Wiring(e) => write!(f, "wiring error: {}", e),
+            Error::Cartridge(e) => write!(f, "cartridge error: {}", e),
+            Error::Tape(e) => write!(f, "tape error: {}", e),
+            Error::Rom(e) => write!(f, "ROM error: {}", e),
+            Error::Config(e) => write!(f, "configuration error: {}", e),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Wiring(e) => Some(e),
+            Error::Cartridge(e) => Some(e),
+            Error::Tape(e) => Some(e),
+            Error::Rom(e) => Some(e),
+            Error::Config(e) => Some(e),
+        }
+    }
+}
+
+impl From<WiringError> for Error {
+    fn from(e: WiringError) -> Self {
+        Error::Wiring(e)
+    }
+}
+
+impl From<CartridgeError> for Error {
+    fn from(e: CartridgeError) -> Self {
+        Error::Cartridge(e)
+    }
+}
+
+impl From<TapError> for Error {
+    fn from(e: TapError) -> Self {
+        Error::Tape(e)
+    }
+}
+
+impl From<RomError> for Error {
+    fn from(e: RomError) -> Self {
+        Error::Rom(e)
+    }
+}
+
+impl From<CiaConfigError> for Error {
+    fn from(e: CiaConfigError) -> Self {
+        Error::Config(e)
+    }
+}
+
+impl From<PortError> for Error {
+    fn from(e: PortError) -> Self {
+        Error::Wiring(e.into())
+    }
+}
+
+impl From<BusError> for Error {
+    fn from(e: BusError) -> Self {
+        Error::Wiring(e.into())
+    }
+}
+
+impl From<ConnectError> for Error {
+    fn from(e: ConnectError) -> Self {
+        Error::Wiring(e.into())
+    }
+}
+
+impl From<WidthError> for Error {
+    fn from(e: WidthError) -> Self {
+        Error::Wiring(e.into())
+    }
+}
+
+/// An error in connecting devices together, whether through ports, buses, or pins named
+/// in `connect!`, or in putting a value on the pins once they're connected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum WiringError {
+    /// An error from connecting one port to another.
+    Port(PortError),
+
+    /// An error from connecting pins to a bus.
+    Bus(BusError),
+
+    /// An error from connecting pins by name.
+    Connect(ConnectError),
+
+    /// An error from putting a value on too few pins.
+    Width(WidthError),
+}
+
+impl Display for WiringError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            WiringError::Port(e) => write!(f, "port: {}", e),
+            WiringError::Bus(e) => write!(f, "bus: {}", e),
+            WiringError::Connect(e) => write!(f, "connect: {}", e),
+            WiringError::Width(e) => write!(f, "width: {}", e),
+        }
+    }
+}
+
+impl error::Error for WiringError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            WiringError::Port(e) => Some(e),
+            WiringError::Bus(e) => Some(e),
+            WiringError::Connect(e) => Some(e),
+            WiringError::Width(e) => Some(e),
+        }
+    }
+}
+
+impl From<PortError> for WiringError {
+    fn from(e: PortError) -> Self {
+        WiringError::Port(e)
+    }
+}
+
+impl From<BusError> for WiringError {
+    fn from(e: BusError) -> Self {
+        WiringError::Bus(e)
+    }
+}
+
+impl From<ConnectError> for WiringError {
+    fn from(e: ConnectError) -> Self {
+        WiringError::Connect(e)
+    }
+}
+
+impl From<WidthError> for WiringError {
+    fn from(e: WidthError) -> Self {
+        WiringError::Width(e)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{error::Error as _, io};
+
+    use crate::{
+        components::{
+            bus::Bus,
+            pin::{Mode::Input, Pin},
+            port::Port,
+        },
+        devices::chips::Quirk,
+        vectors::RefVec,
+    };
+
+    use super::*;
+
+    fn make_pins(width: usize) -> RefVec<Pin> {
+        RefVec::with_vec((0..width).map(|i| pin!(i, "P", Input)).collect())
+    }
+
+    fn connect_all(bus: &mut Bus, a: &Port, b: &Port) -> Result<(), Error> {
+        a.connect(b)?;
+        bus.connect(&a.pins())?;
+        Ok(())
+    }
+
+    #[test]
+    fn display() {
+        let e = Error::from(PortError::WidthMismatch(8, 4));
+        assert_eq!(
+            e.to_string(),
+            "wiring error: port: cannot connect a 8-pin port to a 4-pin port"
+        );
+        let e = Error::from(BusError::AlreadyConnected(3));
+        assert_eq!(
+            e.to_string(),
+            "wiring error: bus: pin at index 3 is already connected to a trace"
+        );
+    }
+
+    #[test]
+    fn source_chain() {
+        let e = Error::from(BusError::WidthMismatch(16, 8));
+        let wiring = e.source().unwrap();
+        assert_eq!(
+            wiring.to_string(),
+            "bus: cannot connect 8 pins to a 16-line bus"
+        );
+        let bus = wiring.source().unwrap();
+        assert_eq!(bus.to_string(), "cannot connect 8 pins to a 16-line bus");
+        assert!(bus.source().is_none());
+    }
+
+    #[test]
+    fn connect_source_chain() {
+        let e = Error::from(ConnectError::NoSuchPin("CIA1".into(), "PX".into()));
+        assert_eq!(
+            e.to_string(),
+            "wiring error: connect: device CIA1 has no pin named PX"
+        );
+        let wiring = e.source().unwrap();
+        assert_eq!(
+            wiring.to_string(),
+            "connect: device CIA1 has no pin named PX"
+        );
+        let connect = wiring.source().unwrap();
+        assert_eq!(connect.to_string(), "device CIA1 has no pin named PX");
+        assert!(connect.source().is_none());
+    }
+
+    #[test]
+    fn width_source_chain() {
+        let e = Error::from(WidthError {
+            value: 0x100,
+            width: 8,
+        });
+        assert_eq!(
+            e.to_string(),
+            "wiring error: width: value $100 does not fit in 8 pins"
+        );
+        let wiring = e.source().unwrap();
+        assert_eq!(
+            wiring.to_string(),
+            "width: value $100 does not fit in 8 pins"
+        );
+        let width = wiring.source().unwrap();
+        assert_eq!(width.to_string(), "value $100 does not fit in 8 pins");
+        assert!(width.source().is_none());
+    }
+
+    #[test]
+    fn config_source_chain() {
+        let e = Error::from(CiaConfigError::Requires(
+            Quirk::TimerBBug,
+            Quirk::DelayedIrq,
+        ));
+        assert_eq!(
+            e.to_string(),
+            "configuration error: quirk \"timer B flag lost when ICR is read on underflow\" \
+             requires quirk \"IRQ delayed by one cycle\""
+        );
+        let config = e.source().unwrap();
+        assert!(config.to_string().starts_with("quirk \"timer B flag"));
+        assert!(config.source().is_none());
+    }
+
+    #[test]
+    fn question_mark() {
+        let a = Port::new(make_pins(4));
+        let b = Port::new(make_pins(2));
+        let mut bus = Bus::new(4, "D");
+        assert!(matches!(
+            connect_all(&mut bus, &a, &b),
+            Err(Error::Wiring(WiringError::Port(PortError::WidthMismatch(
+                4, 2
+            ))))
+        ));
+
+        // The ports connect, but then the pins already have traces for the bus
+        let b = Port::new(make_pins(4));
+        let mut bus = Bus::new(4, "D");
+        assert!(matches!(
+            connect_all(&mut bus, &a, &b),
+            Err(Error::Wiring(WiringError::Bus(BusError::AlreadyConnected(
+                0
+            ))))
+        ));
+    }
+
+    #[test]
+    fn rom_source_chain() {
+        let io = io::Error::new(io::ErrorKind::NotFound, "no such file");
+        let e = Error::from(RomError::from(io));
+        assert_eq!(
+            e.to_string(),
+            "ROM error: ROM image could not be read: no such file"
+        );
+        let rom = e.source().unwrap();
+        assert_eq!(rom.to_string(), "ROM image could not be read: no such file");
+        let io = rom.source().unwrap();
+        assert_eq!(io.to_string(), "no such file");
+
+        let e = Error::from(RomError::Size(0x2000, 0x1000));
+        assert_eq!(
+            e.to_string(),
+            "ROM error: ROM image is 4096 bytes, but it must be 8192 bytes"
+        );
+        assert!(e.source().unwrap().source().is_none());
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! The .CRT cartridge image format.
+//!
+//! A .CRT file is a 64-byte header followed by any number of CHIP packets, each holding one
+//! ROM image along with the address it's mapped to. All numbers in the file are
+//! big-endian. The header gives the hardware type, which identifies any bank-switching
+//! logic on the cartridge, and the levels of the EXROM and GAME lines, which determine
+//! whether the cartridge is an 8k, 16k, or Ultimax cartridge.
+
+use crate::devices::cartridge::CartridgeError;
+
+/// The signature at the start of every .CRT file.
+const SIGNATURE: &[u8; 16] = b"C64 CARTRIDGE   ";
+
+/// The signature at the start of every CHIP packet.
+const CHIP_SIGNATURE: &[u8; 4] = b"CHIP";
+
+/// The size of the .CRT header. The header records its own length, but some files get it
+/// wrong, so anything shorter than this is taken to mean this.
+const HEADER_SIZE: usize = 0x40;
+
+/// The size of the header at the start of each CHIP packet.
+const CHIP_HEADER_SIZE: usize = 0x10;
+
+/// The size of each of the ROML and ROMH windows.
+const WINDOW_SIZE: usize = 0x2000;
+
+/// The base addresses of the ROML window and the two places the ROMH window can be.
+const ROML_BASE: u16 = 0x8000;
+const ROMH_BASE: u16 = 0xa000;
+const ULTIMAX_ROMH_BASE: u16 = 0xe000;
+
+/// The memory configuration that a cartridge selects with its EXROM and GAME lines.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Configuration {
+    /// EXROM low, GAME high: 8k of ROM read through ROML at $8000-$9FFF.
+    Normal8k,
+
+    /// EXROM and GAME low: 16k of ROM read through ROML at $8000-$9FFF and ROMH at
+    /// $A000-$BFFF.
+    Normal16k,
+
+    /// EXROM high, GAME low: ROM read through ROMH at $E000-$FFFF and, optionally, ROML at
+    /// $8000-$9FFF.
+    Ultimax,
+}
+
+impl Configuration {
+    /// Returns the configuration selected by levels of the EXROM and GAME lines, or
+    /// `None` if both are high (which means that no cartridge is plugged in).
+    pub fn from_lines(exrom: bool, game: bool) -> Option<Configuration> {
+        match (exrom, game) {
+            (false, true) => Some(Configuration::Normal8k),
+            (false, false) => Some(Configuration::Normal16k),
+            (true, false) => Some(Configuration::Ultimax),
+            (true, true) => None,
+        }
+    }
+
+    /// Returns the address that ROMH starts at in this configuration, or `None` if ROMH
+    /// isn't used.
+    pub fn romh_base(self) -> Option<u16> {
+        match self {
+            Configuration::Normal8k => None,
+            Configuration::Normal16k => Some(ROMH_BASE),
+            Configuration::Ultimax => Some(ULTIMAX_ROMH_BASE),
+        }
+    }
+}
+
+/// A ROM image from a .CRT file, along with the address that it's loaded at.
+///
+/// A bank is never bigger than the 8k window it's read through, but it can be smaller, in
+/// which case its load address is a multiple of its size (a power of two).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bank {
+    /// The address of the first byte of the ROM.
+    pub load: u16,
+
+    /// The ROM image.
+    pub data: Vec<u8>,
+}
+
+/// The contents of a .CRT file.
+///
+/// The EXROM and GAME levels are exactly the levels that the cartridge puts on the PLA's
+/// EXROM and GAME inputs (both are active low), and the ROML and ROMH banks are the ROMs
+/// that are read when the PLA selects its ROML and ROMH outputs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrtImage {
+    /// The cartridge's name, from the header.
+    pub name: String,
+
+    /// The hardware type, which identifies the cartridge's banking logic.
+    pub hardware: u16,
+
+    /// The level of the EXROM line.
+    pub exrom: bool,
+
+    /// The level of the GAME line.
+    pub game: bool,
+
+    /// The ROM read through ROML, if the cartridge has one.
+    pub roml: Option<Bank>,
+
+    /// The ROM read through ROMH, if the cartridge has one.
+    pub romh: Option<Bank>,
+}
+
+impl CrtImage {
+    /// Returns the memory configuration selected by the cartridge's EXROM and GAME lines.
+    /// This is always `Some` for an image that was parsed successfully.
+    pub fn configuration(&self) -> Option<Configuration> {
+        Configuration::from_lines(self.exrom, self.game)
+    }
+}
+
+/// A CHIP packet.
+struct Chip {
+    /// The offset of the packet within the file.
+    offset: usize,
+
+    /// The address that the ROM is mapped to.
+    load: u16,
+
+    /// The ROM image.
+    data: Vec<u8>,
+}
+
+/// Reads a big-endian number of `len` bytes starting at `at`.
+fn read_be(bytes: &[u8], at: usize, len: usize) -> usize {
+    bytes[at..at + len]
+        .iter()
+        .fold(0, |value, &b| (value << 8) | b as usize)
+}
+
+/// Parses the contents of a .CRT file.
+///
+/// Only hardware type 0 (a normal cartridge with no banking) is supported. Its ROMs can be
+/// given as one CHIP packet per 8k window or, for a 16k cartridge, a single 16k packet at
+/// $8000, which is split between ROML and ROMH.
+pub fn parse(bytes: &[u8]) -> Result<CrtImage, CartridgeError> {
+    if bytes.len() < HEADER_SIZE {
+        return Err(CartridgeError::Truncated(0));
+    }
+    if &bytes[..SIGNATURE.len()] != SIGNATURE {
+        return Err(CartridgeError::Signature);
+    }
+    let hardware = read_be(bytes, 0x16, 2) as u16;
+    if hardware != 0 {
+        return Err(CartridgeError::Hardware(hardware));
+    }
+    let exrom = bytes[0x18] != 0;
+    let game = bytes[0x19] != 0;
+    let configuration =
+        Configuration::from_lines(exrom, game).ok_or(CartridgeError::Lines(exrom, game))?;
+
+    let name = bytes[0x20..0x40]
+        .iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| b as char)
+        .collect();
+
+    let chips = chips(bytes, read_be(bytes, 0x10, 4).max(HEADER_SIZE))?;
+    let (roml, romh) = banks(chips, configuration.romh_base())?;
+
+    Ok(CrtImage {
+        name,
+        hardware,
+        exrom,
+        game,
+        roml,
+        romh,
+    })
+}
+
+/// Reads the CHIP packets that start at `offset` and run to the end of the file.
+fn chips(bytes: &[u8], mut offset: usize) -> Result<Vec<Chip>, CartridgeError> {
+    let mut chips = vec![];
+    while offset < bytes.len() {
+        if offset + CHIP_HEADER_SIZE > bytes.len() {
+            return Err(CartridgeError::Truncated(offset));
+        }
+        if &bytes[offset..offset + CHIP_SIGNATURE.len()] != CHIP_SIGNATURE {
+            return Err(CartridgeError::Chip(offset));
+        }
+        let length = read_be(bytes, offset + 0x04, 4);
+        let load = read_be(bytes, offset + 0x0c, 2) as u16;
+        let size = read_be(bytes, offset + 0x0e, 2);
+        if length < CHIP_HEADER_SIZE + size {
+            return Err(CartridgeError::Chip(offset));
+        }
+        let start = offset + CHIP_HEADER_SIZE;
+        if start + size > bytes.len() {
+            return Err(CartridgeError::Truncated(offset));
+        }
+        chips.push(Chip {
+            offset,
+            load,
+            data: bytes[start..start + size].to_vec(),
+        });
+        offset += length;
+    }
+    Ok(chips)
+}
+
+/// Sorts ROM images into the ROML window (at $8000) and the ROMH window (at `romh_base`,
+/// if the configuration has one). A 16k image at the start of ROML fills both windows if
+/// they're contiguous.
+fn banks(
+    chips: Vec<Chip>,
+    romh_base: Option<u16>,
+) -> Result<(Option<Bank>, Option<Bank>), CartridgeError> {
+    let mut roml = None;
+    let mut romh = None;
+
+    for mut chip in chips {
+        let bad = CartridgeError::LoadAddress(chip.offset);
+        if chip.data.len() == WINDOW_SIZE * 2
+            && chip.load == ROML_BASE
+            && romh_base == Some(ROMH_BASE)
+        {
+            if roml.is_some() || romh.is_some() {
+                return Err(bad);
+            }
+            let high = chip.data.split_off(WINDOW_SIZE);
+            roml = Some(Bank {
+                load: ROML_BASE,
+                data: chip.data,
+            });
+            romh = Some(Bank {
+                load: ROMH_BASE,
+                data: high,
+            });
+            continue;
+        }
+
+        let window = if in_window(chip.load, ROML_BASE) {
+            &mut roml
+        } else {
+            match romh_base {
+                Some(base) if in_window(chip.load, base) => &mut romh,
+                _ => return Err(bad),
+            }
+        };
+        if window.is_some() || !fits(&chip) {
+            return Err(bad);
+        }
+        *window = Some(Bank {
+            load: chip.load,
+            data: chip.data,
+        });
+    }
+    Ok((roml, romh))
+}
+
+/// Returns `true` if an address is in the 8k window starting at `base`.
+fn in_window(addr: u16, base: u16) -> bool {
+    (base as usize..base as usize + WINDOW_SIZE).contains(&(addr as usize))
+}
+
+/// Returns `true` if a ROM image can be mirrored through its window, which it can if it's a
+/// power of two in size, no bigger than the window, and starts at a multiple of its size.
+fn fits(chip: &Chip) -> bool {
+    let size = chip.data.len();
+    size.is_power_of_two() && size <= WINDOW_SIZE && chip.load as usize & (size - 1) == 0
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        components::trace::Trace,
+        devices::chips::{ic82s100::constants::*, Ic82S100},
+        test_utils::{make_crt, make_traces},
+        vectors::RefVec,
+    };
+
+    use super::*;
+
+    #[test]
+    fn parse_minimal() {
+        // Built by hand rather than with `make_crt`, so that the layout is checked against
+        // the format rather than against the builder
+        let mut bytes = b"C64 CARTRIDGE   ".to_vec();
+        bytes.extend([0x00, 0x00, 0x00, 0x40, 0x01, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        bytes.extend([0; 6]);
+        let mut name = b"MINIMAL".to_vec();
+        name.resize(32, 0);
+        bytes.extend(name);
+        bytes.extend(b"CHIP");
+        bytes.extend([0x00, 0x00, 0x20, 0x10, 0x00, 0x00, 0x00, 0x00]);
+        bytes.extend([0x80, 0x00, 0x20, 0x00]);
+        bytes.extend((0..0x2000).map(|i| i as u8));
+
+        let image = parse(&bytes).unwrap();
+        assert_eq!(image.name, "MINIMAL");
+        assert_eq!(image.hardware, 0);
+        assert!(!image.exrom);
+        assert!(image.game);
+        assert_eq!(image.configuration(), Some(Configuration::Normal8k));
+
+        let roml = image.roml.unwrap();
+        assert_eq!(roml.load, 0x8000);
+        assert_eq!(roml.data.len(), 0x2000);
+        assert_eq!(roml.data[0x1234], 0x34);
+        assert_eq!(image.romh, None);
+    }
+
+    #[test]
+    fn parse_16k_split() {
+        let data: Vec<u8> = (0..0x4000).map(|i| (i >> 8) as u8).collect();
+        let image = parse(&make_crt(0, 0, 0, &[(0x8000, data)])).unwrap();
+        assert_eq!(image.configuration(), Some(Configuration::Normal16k));
+        let roml = image.roml.unwrap();
+        let romh = image.romh.unwrap();
+        assert_eq!((roml.load, roml.data[0]), (0x8000, 0x00));
+        assert_eq!((romh.load, romh.data[0]), (0xa000, 0x20));
+    }
+
+    #[test]
+    fn parse_ultimax() {
+        let image = parse(&make_crt(0, 1, 0, &[(0xf000, vec![0xea; 0x1000])])).unwrap();
+        assert_eq!(image.configuration(), Some(Configuration::Ultimax));
+        assert_eq!(image.roml, None);
+        assert_eq!(image.romh.unwrap().load, 0xf000);
+    }
+
+    #[test]
+    fn misaligned_bank() {
+        let bytes = make_crt(0, 0, 1, &[(0x8800, vec![0; 0x1000])]);
+        assert_eq!(parse(&bytes).err(), Some(CartridgeError::LoadAddress(0x40)));
+    }
+
+    /// Sets up a PLA for a CPU read of `addr` with all three banking bits high, puts the
+    /// image's EXROM and GAME levels on its inputs, and returns its traces.
+    fn pla_read(image: &CrtImage, addr: u16) -> RefVec<Trace> {
+        let pla = Ic82S100::new();
+        let tr = make_traces(&pla);
+        clear!(tr[OE]);
+        clear!(tr[AEC]);
+        for input in [CAS, LORAM, HIRAM, CHAREN, VA14, BA, R_W, VA13, VA12] {
+            set!(tr[input]);
+        }
+        let lines: [(usize, bool); 6] = [
+            (A15, addr & 0x8000 != 0),
+            (A14, addr & 0x4000 != 0),
+            (A13, addr & 0x2000 != 0),
+            (A12, addr & 0x1000 != 0),
+            (EXROM, image.exrom),
+            (GAME, image.game),
+        ];
+        for (input, level) in lines {
+            set_level!(tr[input], Some(level as u8 as f64));
+        }
+        tr
+    }
+
+    /// Returns `true` if a PLA output is selected (low).
+    fn selected(tr: &RefVec<Trace>, output: usize) -> bool {
+        low!(tr[output])
+    }
+
+    #[test]
+    fn configurations_select_pla_outputs() {
+        let image = parse(&make_crt(0, 0, 1, &[(0x8000, vec![0; 0x2000])])).unwrap();
+        assert!(selected(&pla_read(&image, 0x8000), ROML));
+        assert!(!selected(&pla_read(&image, 0xa000), ROMH));
+        assert!(selected(&pla_read(&image, 0xa000), BASIC));
+
+        let image = parse(&make_crt(0, 0, 0, &[(0x8000, vec![0; 0x4000])])).unwrap();
+        assert!(selected(&pla_read(&image, 0x8000), ROML));
+        assert!(selected(&pla_read(&image, 0xa000), ROMH));
+
+        let image = parse(&make_crt(0, 1, 0, &[(0xe000, vec![0; 0x2000])])).unwrap();
+        assert!(selected(&pla_read(&image, 0xe000), ROMH));
+        assert!(!selected(&pla_read(&image, 0xe000), KERNAL));
+    }
+}
@@ -0,0 +1,8 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Parsers for the file formats that C64 software is distributed in.
+
+pub mod crt;
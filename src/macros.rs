@@ -33,12 +33,18 @@ macro_rules! pins {
                 $(std::rc::Rc::clone(&$pin)),*
             ];
             v.sort_by(|a, b| a.borrow().number().cmp(&b.borrow().number()));
+            debug_assert!(
+                v.iter().enumerate().all(|(i, a)| v
+                    .iter()
+                    .skip(i + 1)
+                    .all(|b| a.borrow().name() != b.borrow().name())),
+                "pin names must be unique within a device"
+            );
             v
         }
     );
 }
 
-#[cfg(test)]
 macro_rules! trace {
     ($($pin:expr),* $(,)?) => (
         {
@@ -86,7 +92,8 @@ macro_rules! level {
 
 macro_rules! set_level {
     ($pt:expr, $level:expr $(,)?) => {
-        $pt.borrow_mut().set_level($level)
+        $pt.borrow_mut()
+            .drive($crate::components::level::Level::from($level))
     };
 }
 
@@ -133,6 +140,12 @@ macro_rules! toggle {
     };
 }
 
+macro_rules! open_collector {
+    ($($pin:expr),* $(,)?) => (
+        $($pin.borrow_mut().set_open_collector(true);)*
+    );
+}
+
 macro_rules! mode {
     ($pin:expr $(,)?) => {
         $pin.borrow().mode()
@@ -174,13 +187,36 @@ macro_rules! attach {
 
 #[cfg(test)]
 macro_rules! detach {
-    ($pin:expr $(,)?) => {
-        $pin.borrow_mut().detach()
+    ($pin:expr, $id:expr $(,)?) => {
+        $pin.borrow_mut().detach($id)
     };
 }
 
+// Connects pins of several devices, looked up by name, with a new trace. Each argument is
+// a device variable, a dot, and a pin name, as in `connect!(pla."CAS", ram."CAS")`. This
+// evaluates to a `Result` that is an error naming the device and pin if a pin doesn't exist
+// or is already connected.
+#[allow(unused_macros)]
+macro_rules! connect {
+    ($($device:ident . $name:literal),+ $(,)?) => (
+        $crate::components::trace::connect(&[$((stringify!($device), &$device, $name)),+])
+    );
+}
+
 macro_rules! attach_to {
     ($device:expr, $($pin:expr),+ $(,)?) => (
         $(attach!($pin, clone_ref!($device)));+
     );
 }
+
+// Evaluates to the ROMs that tests should use (see `test_utils::test_roms`), or returns
+// from the enclosing test if there aren't any, skipping it.
+#[cfg(test)]
+macro_rules! test_roms {
+    () => {
+        match crate::test_utils::test_roms() {
+            Some(roms) => roms,
+            None => return,
+        }
+    };
+}
@@ -3,14 +3,24 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+// Devices are always handed out as shared, internally mutable references (`DeviceRef`)
+// because their pins need references back to them, so their `new` functions can't return
+// `Self`.
+#![allow(clippy::new_ret_no_self)]
+
 #[macro_use]
 mod macros;
 
 pub mod components;
 pub mod devices;
+pub mod diagnostics;
+pub mod error;
+pub mod loaders;
+pub mod memory;
 pub mod roms;
 pub mod utils;
 pub mod vectors;
+pub mod video;
 
 #[cfg(test)]
 pub mod test_utils;
@@ -0,0 +1,259 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    components::addressable::Addressable,
+    devices::banking::{BankMode, Resolver, Selected},
+    roms::RomSet,
+};
+
+/// Address of the 6510's I/O port data direction register.
+const PORT_DDR: u16 = 0x0000;
+/// Address of the 6510's I/O port data register.
+const PORT_DATA: u16 = 0x0001;
+
+/// The C64's memory map, as seen by the CPU.
+///
+/// This puts the 64k of RAM, the BASIC, KERNAL, and character ROMs, and the 1k of color RAM
+/// together behind a single `Addressable` interface. Every access is run through an
+/// emulated 82S100 PLA (by way of a `Resolver`), which selects the device that answers it
+/// exactly as it would in the real machine, so all of the usual bank switching works:
+/// writes to ROM addresses go to the RAM underneath, and the ROMs and I/O are switched in
+/// and out by the LORAM, HIRAM, and CHAREN lines.
+///
+/// Those three lines come from the 6510's built-in I/O port, which is at addresses $0000
+/// (data direction) and $0001 (data). Since that port is part of the CPU rather than a
+/// separate chip, it's emulated here. Bits that are inputs read high, which (since the
+/// port starts with every bit as an input) means the map starts out in the default mode
+/// with BASIC, KERNAL, and I/O all visible. As on the real machine, writes to $0000 and
+/// $0001 also go to the RAM underneath, though reads always come from the port.
+///
+/// The ROM images are supplied as a `RomSet` when the map is created, so they can be
+/// either the embedded images or ones loaded from files.
+///
+/// Color RAM is the only I/O device in the map so far, at $D800-$DBFF. It stores 4 bits at
+/// each address, and the upper 4 bits of a read are 0. The rest of the I/O area (the VIC,
+/// SID, CIAs, and the cartridge I/O areas) reads 0 and ignores writes. There's no
+/// cartridge port either, so GAME and EXROM are always high.
+pub struct C64Memory {
+    /// The PLA that decides which device answers each access.
+    pla: Resolver,
+
+    /// The BASIC, KERNAL, and character ROMs.
+    roms: RomSet,
+
+    /// The 64k of system RAM.
+    ram: Box<[u8; 0x10000]>,
+
+    /// The 1k of color RAM. Only the low 4 bits of each byte are used.
+    color: Box<[u8; 0x400]>,
+
+    /// The 6510's I/O port data direction register.
+    port_ddr: u8,
+
+    /// The 6510's I/O port data register.
+    port_data: u8,
+}
+
+impl C64Memory {
+    /// Creates a new memory map with the embedded ROMs, cleared RAM, and the 6510's I/O
+    /// port in its reset state (all bits inputs, so the default banking mode is in effect).
+    #[cfg(feature = "embedded-roms")]
+    pub fn new() -> C64Memory {
+        C64Memory::with_roms(RomSet::embedded())
+    }
+
+    /// Creates a new memory map that's the same as the one from `new`, except that it uses
+    /// the given ROM images.
+    pub fn with_roms(roms: RomSet) -> C64Memory {
+        C64Memory {
+            pla: Resolver::new(),
+            roms,
+            ram: Box::new([0; 0x10000]),
+            color: Box::new([0; 0x400]),
+            port_ddr: 0,
+            port_data: 0,
+        }
+    }
+
+    /// Returns the value that the 6510's I/O port presents on its pins. Bits that are
+    /// inputs read high.
+    fn port(&self) -> u8 {
+        self.port_data | !self.port_ddr
+    }
+
+    /// Returns the current banking mode, taken from the 6510's I/O port.
+    fn mode(&self) -> BankMode {
+        let port = self.port();
+        BankMode {
+            loram: port & 0x01 != 0,
+            hiram: port & 0x02 != 0,
+            charen: port & 0x04 != 0,
+            game: true,
+            exrom: true,
+        }
+    }
+
+    /// Determines whether an I/O address is in color RAM.
+    fn is_color(addr: u16) -> bool {
+        (0xd800..0xdc00).contains(&addr)
+    }
+}
+
+#[cfg(feature = "embedded-roms")]
+impl Default for C64Memory {
+    fn default() -> Self {
+        C64Memory::new()
+    }
+}
+
+impl Addressable for C64Memory {
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            PORT_DDR => return self.port_ddr,
+            PORT_DATA => return self.port(),
+            _ => {}
+        }
+
+        let index = addr as usize;
+        match self.pla.resolve(self.mode(), addr, true, true) {
+            Selected::Ram => self.ram[index],
+            Selected::Basic => self.roms.basic[index & 0x1fff],
+            Selected::Kernal => self.roms.kernal[index & 0x1fff],
+            Selected::CharRom => self.roms.character[index & 0x0fff],
+            Selected::Io if C64Memory::is_color(addr) => self.color[index & 0x03ff] & 0x0f,
+            _ => 0,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        match addr {
+            PORT_DDR => self.port_ddr = value,
+            PORT_DATA => self.port_data = value,
+            _ => {}
+        }
+
+        let index = addr as usize;
+        match self.pla.resolve(self.mode(), addr, true, false) {
+            Selected::Ram => self.ram[index] = value,
+            Selected::Io if C64Memory::is_color(addr) => self.color[index & 0x03ff] = value & 0x0f,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::testing::{check_addressable, AddressableSpec};
+
+    /// ROMs that are all zeros, for tests that don't care what's in them.
+    fn blank_roms() -> RomSet {
+        RomSet {
+            basic: Box::new([0; 0x2000]),
+            kernal: Box::new([0; 0x2000]),
+            character: Box::new([0; 0x1000]),
+        }
+    }
+
+    fn read_word(memory: &mut C64Memory, addr: u16) -> u16 {
+        memory.read(addr) as u16 | (memory.read(addr + 1) as u16) << 8
+    }
+
+    #[test]
+    fn kernal_reset_vector() {
+        let roms = test_roms!();
+        let expected = roms.kernal[0x1ffc] as u16 | (roms.kernal[0x1ffd] as u16) << 8;
+        let mut memory = C64Memory::with_roms(roms);
+        assert_eq!(read_word(&mut memory, 0xfffc), expected);
+        assert_eq!(expected, 0xfce2, "the KERNAL reset routine is at $FCE2");
+    }
+
+    #[test]
+    fn hiram_low_banks_out_kernal() {
+        let mut memory = C64Memory::with_roms(test_roms!());
+        memory.write(0xfffc, 0x34);
+        memory.write(0xfffd, 0x12);
+        assert_eq!(
+            read_word(&mut memory, 0xfffc),
+            0xfce2,
+            "writes under ROM should not change what's read"
+        );
+
+        // Make the port all outputs, with LORAM and HIRAM low
+        memory.write(PORT_DDR, 0x07);
+        memory.write(PORT_DATA, 0x04);
+        assert_eq!(read_word(&mut memory, 0xfffc), 0x1234);
+        assert_eq!(memory.read(0xa000), 0, "BASIC should be banked out");
+    }
+
+    #[test]
+    fn basic_and_character_rom() {
+        let roms = test_roms!();
+        let mut memory = C64Memory::with_roms(roms.clone());
+        assert_eq!(memory.read(0xa000), roms.basic[0]);
+        assert_eq!(memory.read(0xbfff), roms.basic[0x1fff]);
+
+        memory.write(PORT_DDR, 0x07);
+        memory.write(PORT_DATA, 0x03);
+        assert_eq!(memory.read(0xd008), roms.character[8]);
+        assert_eq!(memory.read(0xdfff), roms.character[0xfff]);
+    }
+
+    #[test]
+    fn color_ram() {
+        let mut memory = C64Memory::with_roms(blank_roms());
+        memory.write(0xd800, 0xfe);
+        assert_eq!(memory.read(0xd800), 0x0e, "color RAM should hold 4 bits");
+        assert_eq!(
+            memory.read(0x5800),
+            0,
+            "color RAM writes should not reach RAM"
+        );
+
+        // With I/O banked out, the same addresses are RAM
+        memory.write(PORT_DDR, 0x07);
+        memory.write(PORT_DATA, 0x00);
+        assert_eq!(memory.read(0xd800), 0);
+        memory.write(0xd800, 0xfe);
+        assert_eq!(memory.read(0xd800), 0xfe);
+    }
+
+    #[test]
+    fn processor_port() {
+        let mut memory = C64Memory::with_roms(blank_roms());
+        assert_eq!(memory.read(PORT_DDR), 0);
+        assert_eq!(memory.read(PORT_DATA), 0xff, "inputs should read high");
+
+        memory.write(PORT_DDR, 0x2f);
+        memory.write(PORT_DATA, 0x37);
+        assert_eq!(memory.read(PORT_DDR), 0x2f);
+        assert_eq!(memory.read(PORT_DATA), 0xf7);
+
+        // The RAM under the port is written too, and shows up with the port banked out
+        // (which can't be done with a real 6510, but can be checked through the RAM array)
+        assert_eq!(memory.ram[0], 0x2f);
+        assert_eq!(memory.ram[1], 0x37);
+    }
+
+    #[test]
+    fn addressable_contract() {
+        // All RAM except the processor port, which reads from the port rather than RAM
+        let all_ram = || {
+            let mut memory = C64Memory::with_roms(blank_roms());
+            memory.write(PORT_DDR, 0x07);
+            memory.write(PORT_DATA, 0x00);
+            Box::new(memory) as Box<dyn Addressable>
+        };
+        check_addressable(all_ram, AddressableSpec::stored(0x0002..=0xffff));
+
+        // The default map, where only the RAM that isn't under ROM or I/O reads back
+        let spec = AddressableSpec {
+            stored: vec![0x0002..=0x9fff, 0xc000..=0xcfff],
+            mirror: None,
+        };
+        check_addressable(|| Box::new(C64Memory::with_roms(blank_roms())), spec);
+    }
+}
@@ -0,0 +1,194 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{
+    components::{addressable::Addressable, trace::TraceRef},
+    devices::{
+        banking::{BankMode, Resolver, Selected},
+        chips::{ic2114::constants::*, Ic2114},
+    },
+};
+
+/// The 2114's address pins, in bit order.
+const ADDRESS: [usize; 10] = [A0, A1, A2, A3, A4, A5, A6, A7, A8, A9];
+
+/// The 2114's data pins, in bit order.
+const DATA: [usize; 4] = [D0, D1, D2, D3];
+
+/// The C64's color RAM, a 2114 at $D800-$DBFF as the CPU sees it.
+///
+/// The 2114 stores 4 bits at each of its 1024 addresses. The screen uses the first 1000 of
+/// them (one for each of the 40 x 25 character cells), and the last 24 are free for
+/// software to use. Only D0-D3 of the data bus are connected to the chip, so when the CPU
+/// reads color RAM, D4-D7 aren't driven by anything and just keep whatever was last on the
+/// bus. Reads here do the same: the upper 4 bits of the value read are the upper 4 bits of
+/// the last value on the bus, which is the last value written unless `set_bus` says
+/// otherwise. (On a real machine it's usually whatever the VIC fetched in the half-cycle
+/// before, so software can't rely on it.)
+///
+/// The chip is wired up as it is in the C64. Its CS pin is driven by the 74139, which
+/// selects it when the PLA selects the I/O block and A10-A11 are 0 and 1; its WE pin is
+/// driven by the PLA's GR_W output, which is low whenever the CPU writes to $D000-$DFFF.
+/// The chip only stores a value when both are low, so writes to $D800-$DBFF are ignored
+/// when the banking mode (set with `set_mode`) maps something other than I/O there. Reads
+/// in that case return the bus value, since nothing is selected.
+pub struct ColorRam {
+    /// Traces connected to each of the 2114's pins, indexed by pin number. The chip itself
+    /// is kept alive by its pins.
+    traces: Vec<TraceRef>,
+
+    /// The PLA that decides whether the I/O block is selected.
+    pla: Resolver,
+
+    /// The current banking mode.
+    mode: BankMode,
+
+    /// The last value on the data bus.
+    bus: u8,
+}
+
+impl ColorRam {
+    /// Creates new color RAM, with every nibble 0, in the default banking mode.
+    pub fn new() -> ColorRam {
+        let chip = Ic2114::new();
+        let traces: Vec<TraceRef> = chip
+            .borrow()
+            .pins()
+            .iter()
+            .map(|pin| trace!(clone_ref!(pin)))
+            .collect();
+        set!(traces[CS], traces[WE]);
+        ColorRam {
+            traces,
+            pla: Resolver::new(),
+            mode: BankMode::default(),
+            bus: 0,
+        }
+    }
+
+    /// Sets the banking mode, which decides whether color RAM is visible at $D800-$DBFF.
+    pub fn set_mode(&mut self, mode: BankMode) {
+        self.mode = mode;
+    }
+
+    /// Sets the value on the data bus, which the upper 4 bits of later reads come from.
+    pub fn set_bus(&mut self, value: u8) {
+        self.bus = value;
+    }
+
+    /// Determines whether the 74139 selects color RAM for a CPU access to an address.
+    fn selected(&self, addr: u16, read: bool) -> bool {
+        addr & 0x0c00 == 0x0800 && self.pla.resolve(self.mode, addr, true, read) == Selected::Io
+    }
+
+    /// Puts A0-A9 of an address onto the chip's address pins.
+    fn address(&self, addr: u16) {
+        for (bit, &n) in ADDRESS.iter().enumerate() {
+            set_level!(self.traces[n], Some(((addr >> bit) & 1) as f64));
+        }
+    }
+}
+
+impl Default for ColorRam {
+    fn default() -> Self {
+        ColorRam::new()
+    }
+}
+
+impl Addressable for ColorRam {
+    fn read(&mut self, addr: u16) -> u8 {
+        if !self.selected(addr, true) {
+            return self.bus;
+        }
+        self.address(addr);
+        clear!(self.traces[CS]);
+        let nibble = DATA.iter().enumerate().fold(0, |value, (bit, &n)| {
+            value | (high!(self.traces[n]) as u8) << bit
+        });
+        set!(self.traces[CS]);
+
+        self.bus = (self.bus & 0xf0) | nibble;
+        self.bus
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        self.bus = value;
+        // GR_W is low for every CPU write to $D000-$DFFF, but the chip only stores the
+        // value if the 74139 selects it too
+        if (0xd000..0xe000).contains(&addr) && self.selected(addr, false) {
+            self.address(addr);
+            for (bit, &n) in DATA.iter().enumerate() {
+                set_level!(self.traces[n], Some(((value >> bit) & 1) as f64));
+            }
+            clear!(self.traces[WE]);
+            clear!(self.traces[CS]);
+            set!(self.traces[CS]);
+            set!(self.traces[WE]);
+            for &n in DATA.iter() {
+                float!(self.traces[n]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn upper_nibble_from_bus() {
+        let mut ram = ColorRam::new();
+        ram.write(0xd800, 0xfe);
+        assert_eq!(
+            ram.read(0xd800),
+            0xfe,
+            "upper nibble should be the written value still on the bus"
+        );
+        ram.set_bus(0x30);
+        assert_eq!(
+            ram.read(0xd800),
+            0x3e,
+            "upper nibble should come from the bus"
+        );
+        ram.write(0xdbe7, 0x05);
+        ram.set_bus(0x00);
+        assert_eq!(ram.read(0xdbe7), 0x05);
+        assert_eq!(ram.read(0xd800), 0x0e, "other nibbles should be untouched");
+    }
+
+    #[test]
+    fn write_needs_io() {
+        let mut ram = ColorRam::new();
+        ram.write(0xd810, 0x07);
+
+        // All RAM, so the PLA doesn't select I/O and the 74139 doesn't select the 2114
+        ram.set_mode(BankMode::from_mode(0));
+        ram.write(0xd810, 0x0c);
+        ram.set_bus(0xa0);
+        assert_eq!(ram.read(0xd810), 0xa0, "nothing should drive the bus");
+
+        ram.set_mode(BankMode::default());
+        ram.set_bus(0x00);
+        assert_eq!(
+            ram.read(0xd810),
+            0x07,
+            "write with I/O switched out was stored"
+        );
+    }
+
+    #[test]
+    fn other_io_not_selected() {
+        let mut ram = ColorRam::new();
+        ram.write(0xd400, 0x0f);
+        ram.write(0xdc00, 0x0f);
+        ram.set_bus(0x00);
+        assert_eq!(
+            ram.read(0xd800),
+            0x00,
+            "SID and CIA writes should not reach the 2114"
+        );
+        assert_eq!(ram.read(0xd000), 0x00);
+    }
+}
@@ -0,0 +1,130 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::components::addressable::Addressable;
+
+/// An `Addressable` that decodes only some of the address lines before passing accesses on
+/// to another one.
+///
+/// Most of the C64's I/O chips only see the low few address lines, so their registers repeat
+/// all the way through the block of addresses that they're selected for. The VIC, for
+/// example, sees only A0-A5, so its 47 registers (and 17 unused addresses) appear at $D000,
+/// again at $D040, and so on 16 times through to $D3FF. (See `Ic74139` for how those blocks
+/// are selected.) This wrapper models that for any `Addressable`: each address has `mask`
+/// applied to it, then `base` is ORed in, and the result is what the wrapped device sees.
+///
+/// `base` lets a device that expects its full addresses (one that decodes A0-A15 itself)
+/// be mirrored as well. A mask of $003F and a base of $D000 turn every address in $D000-
+/// $D3FF into one in $D000-$D03F. For a device that only looks at the low bits anyway,
+/// `base` can be 0.
+pub struct Mirrored {
+    /// The device that accesses are passed on to.
+    inner: Box<dyn Addressable>,
+
+    /// The address lines that are decoded. Address bits that are 0 here are ignored.
+    mask: u16,
+
+    /// The bits that are ORed into every address after it's masked.
+    base: u16,
+}
+
+impl Mirrored {
+    /// Creates a wrapper that passes each access on to `inner` at `(addr & mask) | base`.
+    pub fn new(inner: Box<dyn Addressable>, mask: u16, base: u16) -> Mirrored {
+        Mirrored { inner, mask, base }
+    }
+
+    /// Returns the address that the wrapped device sees for an address.
+    fn translate(&self, addr: u16) -> u16 {
+        (addr & self.mask) | self.base
+    }
+}
+
+impl Addressable for Mirrored {
+    fn read(&mut self, addr: u16) -> u8 {
+        let addr = self.translate(addr);
+        self.inner.read(addr)
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let addr = self.translate(addr);
+        self.inner.write(addr, value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::{
+        devices::chips::{Ic6567, VideoStandard},
+        memory::testing::{check_addressable, AddressableSpec},
+    };
+
+    /// Registers at $D000-$D03F that record every address they're accessed at and fail on
+    /// any address outside of that range.
+    struct Registers {
+        regs: [u8; 0x40],
+        accesses: Rc<RefCell<Vec<u16>>>,
+    }
+
+    impl Addressable for Registers {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.accesses.borrow_mut().push(addr);
+            self.regs[(addr - 0xd000) as usize]
+        }
+
+        fn write(&mut self, addr: u16, value: u8) {
+            self.accesses.borrow_mut().push(addr);
+            self.regs[(addr - 0xd000) as usize] = value;
+        }
+    }
+
+    fn registers() -> (Mirrored, Rc<RefCell<Vec<u16>>>) {
+        let accesses = Rc::new(RefCell::new(vec![]));
+        let regs = Registers {
+            regs: [0; 0x40],
+            accesses: Rc::clone(&accesses),
+        };
+        (Mirrored::new(Box::new(regs), 0x003f, 0xd000), accesses)
+    }
+
+    #[test]
+    fn translates_addresses() {
+        let (mut mirrored, accesses) = registers();
+        mirrored.write(0xd000, 0x12);
+        assert_eq!(mirrored.read(0xd040), 0x12);
+        mirrored.write(0xd3ff, 0x34);
+        assert_eq!(mirrored.read(0xd03f), 0x34);
+        assert_eq!(*accesses.borrow(), vec![0xd000, 0xd000, 0xd03f, 0xd03f]);
+    }
+
+    #[test]
+    fn vic_registers_mirrored() {
+        let mut vic = Mirrored::new(Box::new(Ic6567::new(VideoStandard::Ntsc)), 0x003f, 0xd000);
+        vic.write(0xd000, 0xa5);
+        assert_eq!(
+            vic.read(0xd040),
+            0xa5,
+            "$D040 should be sprite 0's X register"
+        );
+        vic.write(0xd3c1, 0x5a);
+        assert_eq!(
+            vic.read(0xd001),
+            0x5a,
+            "$D3C1 should be sprite 0's Y register"
+        );
+    }
+
+    #[test]
+    fn addressable_contract() {
+        let spec = AddressableSpec {
+            stored: vec![0x00..=0x3f],
+            mirror: Some(0x40),
+        };
+        check_addressable(|| Box::new(registers().0), spec);
+    }
+}
@@ -0,0 +1,21 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Memory maps: `Addressable` devices that put other memory and devices together into a
+//! single address space, along with the plain memory they're built from.
+
+mod c64;
+mod color_ram;
+mod mirrored;
+mod ram;
+mod read_only;
+#[cfg(test)]
+pub mod testing;
+
+pub use self::c64::C64Memory;
+pub use self::color_ram::ColorRam;
+pub use self::mirrored::Mirrored;
+pub use self::ram::{FillPattern, Ram};
+pub use self::read_only::ReadOnly;
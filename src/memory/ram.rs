@@ -0,0 +1,247 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::components::addressable::Addressable;
+
+/// The values that a `Ram` holds before anything is written to it.
+#[derive(Clone, Copy, Debug)]
+pub enum FillPattern {
+    /// Every byte is $00.
+    Zeros,
+
+    /// Every byte is $FF.
+    Ones,
+
+    /// The pattern that a C64's DRAM comes up with at power-on: 64 bytes of $00, then 64
+    /// bytes of $FF, repeating from address $0000 on. (So $0000-$003F are $00, $0040-$007F
+    /// are $FF, $0080-$00BF are $00, and so on.) Real chips aren't quite this tidy, but they
+    /// come close enough that software which reads memory it never wrote (to seed a random
+    /// number generator, for example) depends on it.
+    C64PowerOn,
+
+    /// Each byte is the value that the function returns for its address.
+    Custom(fn(u16) -> u8),
+}
+
+impl FillPattern {
+    /// Returns the value that this pattern puts at an address.
+    pub fn value(&self, addr: u16) -> u8 {
+        match self {
+            FillPattern::Zeros => 0x00,
+            FillPattern::Ones => 0xff,
+            FillPattern::C64PowerOn => {
+                if addr & 0x40 == 0 {
+                    0x00
+                } else {
+                    0xff
+                }
+            }
+            FillPattern::Custom(f) => f(addr),
+        }
+    }
+}
+
+/// A block of plain read/write memory, up to 64k in size.
+///
+/// The memory decodes only as many address lines as it needs, so a block smaller than 64k
+/// repeats throughout the address space (a 1k block at $0000 is also at $0400, $0800, and
+/// so on), as a RAM chip with its upper address lines unconnected would. The size doesn't
+/// have to be a power of two, but if it isn't, the block repeats every `size` addresses.
+///
+/// Wrapped in a `ReadOnly`, a block made with `from_bytes` serves as ROM.
+pub struct Ram {
+    /// The stored bytes.
+    data: Vec<u8>,
+
+    /// The address lines that are decoded, if the size is a power of two. Masking an
+    /// address gives the same result as taking it modulo the size, only faster.
+    mask: Option<usize>,
+}
+
+impl Ram {
+    /// Creates a new block of memory of `size` bytes, all of them 0. `size` must be between
+    /// 1 and 65,536.
+    pub fn new(size: usize) -> Ram {
+        Ram::with_fill(size, FillPattern::Zeros)
+    }
+
+    /// Creates a new block of memory of `size` bytes, filled with `pattern`. `size` must be
+    /// between 1 and 65,536.
+    pub fn with_fill(size: usize, pattern: FillPattern) -> Ram {
+        assert!(
+            (1..=0x10000).contains(&size),
+            "RAM size {} must be between 1 and 65536",
+            size
+        );
+        Ram::with_data((0..size).map(|addr| pattern.value(addr as u16)).collect())
+    }
+
+    /// Creates a new block of memory holding a copy of `data`, which must be between 1 and
+    /// 65,536 bytes long. This is the way to turn a ROM image into an `Addressable`.
+    pub fn from_bytes(data: &[u8]) -> Ram {
+        assert!(
+            (1..=0x10000).contains(&data.len()),
+            "RAM size {} must be between 1 and 65536",
+            data.len()
+        );
+        Ram::with_data(data.to_vec())
+    }
+
+    /// Creates a block of memory around bytes whose length has already been checked.
+    fn with_data(data: Vec<u8>) -> Ram {
+        let len = data.len();
+        Ram {
+            data,
+            mask: len.is_power_of_two().then(|| len - 1),
+        }
+    }
+
+    /// Returns the index into `data` of the byte that an address selects.
+    fn index(&self, addr: u16) -> usize {
+        match self.mask {
+            Some(mask) => addr as usize & mask,
+            None => addr as usize % self.data.len(),
+        }
+    }
+
+    /// Returns the size of the memory in bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    /// Determines whether the memory has no bytes. This is always false, since a `Ram`
+    /// can't be created without any.
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// Returns the contents of the memory, with the byte at address 0 first.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Replaces the contents of the memory with `data`, which must be exactly as long as
+    /// the memory is.
+    pub fn load_bytes(&mut self, data: &[u8]) {
+        assert_eq!(
+            data.len(),
+            self.data.len(),
+            "{} bytes can't be loaded into {} bytes of RAM",
+            data.len(),
+            self.data.len()
+        );
+        self.data.copy_from_slice(data);
+    }
+}
+
+impl Addressable for Ram {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.data[self.index(addr)]
+    }
+
+    fn write(&mut self, addr: u16, value: u8) {
+        let index = self.index(addr);
+        self.data[index] = value;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::memory::testing::{check_addressable, AddressableSpec};
+
+    #[test]
+    fn zero_filled() {
+        let mut ram = Ram::new(0x10000);
+        assert!((0..=0xffff).all(|addr| ram.read(addr) == 0));
+    }
+
+    #[test]
+    fn c64_power_on() {
+        let mut ram = Ram::with_fill(0x10000, FillPattern::C64PowerOn);
+        for (addr, expected) in [
+            (0x0000, 0x00),
+            (0x003f, 0x00),
+            (0x0040, 0xff),
+            (0x007f, 0xff),
+            (0x0080, 0x00),
+            (0x0400, 0x00),
+            (0x07e8, 0xff),
+            (0x1234, 0x00),
+            (0xc0c0, 0xff),
+            (0xffc0, 0xff),
+            (0xffff, 0xff),
+        ] {
+            assert_eq!(
+                ram.read(addr),
+                expected,
+                "Incorrect power-on value at ${:04X}",
+                addr
+            );
+        }
+    }
+
+    #[test]
+    fn other_patterns() {
+        let mut ram = Ram::with_fill(0x100, FillPattern::Ones);
+        assert_eq!(ram.read(0x42), 0xff);
+
+        let mut ram = Ram::with_fill(0x100, FillPattern::Custom(|addr| addr as u8 ^ 0x5a));
+        assert_eq!(ram.read(0x00), 0x5a);
+        assert_eq!(ram.read(0xa5), 0xff);
+    }
+
+    #[test]
+    fn mirrored() {
+        let mut ram = Ram::new(0x400);
+        assert_eq!(ram.len(), 0x400);
+        ram.write(0x0801, 0x37);
+        assert_eq!(ram.read(0x0001), 0x37);
+        assert_eq!(ram.read(0xfc01), 0x37);
+
+        // 3000 bytes isn't a power of two, so this block repeats every 3000 addresses
+        let mut ram = Ram::new(3000);
+        ram.write(0x0010, 0x5a);
+        assert_eq!(ram.read(3000 + 0x0010), 0x5a);
+        assert_eq!(ram.read(0x0010 + 0x0800), 0x00);
+        ram.write(6000 + 0x0011, 0xa5);
+        assert_eq!(ram.read(0x0011), 0xa5);
+    }
+
+    #[test]
+    fn dump_and_restore() {
+        let mut ram = Ram::new(0x10000);
+        for addr in 0..=0xffff {
+            ram.write(addr, (addr ^ (addr >> 8)) as u8);
+        }
+        let dump = ram.as_bytes().to_vec();
+
+        ram.load_bytes(&[0; 0x10000]);
+        assert!((0..=0xffff).all(|addr| ram.read(addr) == 0));
+
+        ram.load_bytes(&dump);
+        assert_eq!(ram.as_bytes(), &dump[..]);
+        assert_eq!(ram.read(0x1234), 0x26);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't be loaded")]
+    fn restore_wrong_length() {
+        Ram::new(0x400).load_bytes(&[0; 0x800]);
+    }
+
+    #[test]
+    fn addressable_contract() {
+        check_addressable(
+            || Box::new(Ram::new(0x10000)),
+            AddressableSpec::stored(0x0000..=0xffff),
+        );
+        let spec = AddressableSpec {
+            stored: vec![0x000..=0x3ff],
+            mirror: Some(0x400),
+        };
+        check_addressable(|| Box::new(Ram::new(0x400)), spec);
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+use crate::{components::addressable::Addressable, memory::Ram};
+
+/// An `Addressable` that passes reads on to another one and ignores writes.
+///
+/// This is what a ROM looks like from the bus: it answers reads, but a write changes
+/// nothing. (In the C64, writes to ROM addresses go to the RAM underneath, but that's the
+/// PLA's doing, not the ROM's; see `C64Memory`.) Wrapping a `Ram` made from a ROM image is
+/// the easiest way to put that image on a bus, and `rom` does exactly that. Since a `Ram`
+/// repeats throughout the address space, so does the ROM, just as a 2364 with its A13-A15
+/// unconnected would.
+pub struct ReadOnly {
+    /// The device that reads are passed on to.
+    inner: Box<dyn Addressable>,
+}
+
+impl ReadOnly {
+    /// Creates a wrapper that passes reads on to `inner` and drops writes.
+    pub fn new(inner: Box<dyn Addressable>) -> ReadOnly {
+        ReadOnly { inner }
+    }
+
+    /// Creates a read-only block of memory holding a ROM image, which must be between 1
+    /// and 65,536 bytes long.
+    pub fn rom(image: &[u8]) -> ReadOnly {
+        ReadOnly::new(Box::new(Ram::from_bytes(image)))
+    }
+}
+
+impl Addressable for ReadOnly {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.inner.read(addr)
+    }
+
+    fn write(&mut self, _addr: u16, _value: u8) {}
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn drops_writes() {
+        let mut ram = Ram::new(0x100);
+        ram.write(0x42, 0x99);
+        let mut rom = ReadOnly::new(Box::new(ram));
+
+        assert_eq!(rom.read(0x42), 0x99);
+        rom.write(0x42, 0x00);
+        rom.write(0x43, 0x11);
+        assert_eq!(rom.read(0x42), 0x99, "write should be ignored");
+        assert_eq!(rom.read(0x43), 0x00, "write should be ignored");
+    }
+
+    #[test]
+    fn mounted_2364() {
+        let roms = test_roms!();
+        let mut kernal = ReadOnly::rom(&roms.kernal[..]);
+
+        for offset in [0x0000, 0x0001, 0x1234, 0x1ffc, 0x1fff] {
+            let expected = roms.kernal[offset as usize];
+            for base in [0x0000u16, 0x2000, 0xe000] {
+                assert_eq!(
+                    kernal.read(base + offset),
+                    expected,
+                    "Incorrect byte at ${:04X}",
+                    base + offset
+                );
+            }
+        }
+        kernal.write(0xfffc, 0x00);
+        assert_eq!(kernal.read(0xfffc), roms.kernal[0x1ffc]);
+    }
+}
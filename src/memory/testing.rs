@@ -0,0 +1,175 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Contract tests for `Addressable` implementations.
+//!
+//! `Addressable` promises very little on its own, since some implementations are plain
+//! memory and others are banks of chip registers with side effects. What an implementation
+//! does promise is described with an `AddressableSpec`, and `check_addressable` runs the
+//! standard set of checks against it. A new implementation gets coverage with a test like
+//! this:
+//!
+//! ```ignore
+//! #[test]
+//! fn addressable_contract() {
+//!     check_addressable(|| Box::new(Thing::new()), AddressableSpec::stored(0..=0xff));
+//! }
+//! ```
+
+use std::ops::RangeInclusive;
+
+use crate::components::addressable::Addressable;
+
+/// The values that each stored address is checked with. Between them, every bit is both
+/// set and cleared, and each bit is checked next to both a set and a cleared neighbor.
+const PATTERNS: [u8; 4] = [0x00, 0xff, 0x55, 0xaa];
+
+/// What an `Addressable` implementation promises about its addresses.
+#[derive(Clone, Debug)]
+pub struct AddressableSpec {
+    /// The addresses that hold the last value written to them, the way RAM does, and that
+    /// can be read without side effects. Addresses outside of these ranges aren't checked.
+    pub stored: Vec<RangeInclusive<u16>>,
+
+    /// The size of the block of addresses that repeats throughout the address space, if
+    /// the implementation decodes only some of the address lines. For example, a chip with
+    /// 16 registers that ignores A4-A15 would have a mirror size of 16, and its stored
+    /// ranges should then all be within the first 16 addresses.
+    pub mirror: Option<u32>,
+}
+
+impl AddressableSpec {
+    /// Creates a spec for an implementation that stores values at the given addresses and
+    /// decodes the entire address.
+    pub fn stored(range: RangeInclusive<u16>) -> AddressableSpec {
+        AddressableSpec {
+            stored: vec![range],
+            mirror: None,
+        }
+    }
+
+    /// Returns every stored address, in order.
+    fn addresses(&self) -> Vec<u16> {
+        self.stored.iter().flat_map(|range| range.clone()).collect()
+    }
+}
+
+/// Runs the standard contract checks against an `Addressable` implementation. `make` must
+/// return a new instance each time it's called, already set up so that it does what
+/// `spec` says it does; each check gets a fresh instance so that they can't interfere with
+/// one another.
+///
+/// The checks are:
+///
+/// * Each stored address reads back every value written to it, and reads it back the same
+///   way twice in a row (so reads of it have no side effects).
+/// * Every stored address can hold a different value at the same time. This catches
+///   addresses that are wrongly decoded into the same location, including the first and
+///   last addresses of the address space wrapping onto each other.
+/// * If the spec has a mirror size, a write to any mirror of a stored address (up to and
+///   including the last one, which ends at $FFFF) can be read at every other mirror.
+pub fn check_addressable<F>(make: F, spec: AddressableSpec)
+where
+    F: Fn() -> Box<dyn Addressable>,
+{
+    let addresses = spec.addresses();
+    assert!(!addresses.is_empty(), "Spec has no stored addresses");
+
+    check_read_back(make(), &addresses);
+    check_independent(make(), &addresses);
+    if let Some(size) = spec.mirror {
+        check_mirrors(make(), &addresses, size);
+    }
+}
+
+/// Checks that each address reads back each pattern written to it, consistently.
+fn check_read_back(mut device: Box<dyn Addressable>, addresses: &[u16]) {
+    for &addr in addresses {
+        for &value in PATTERNS.iter() {
+            device.write(addr, value);
+            assert_eq!(
+                device.read(addr),
+                value,
+                "Incorrect value read back at ${:04X}",
+                addr
+            );
+            assert_eq!(
+                device.read(addr),
+                value,
+                "Incorrect value on second read at ${:04X}",
+                addr
+            );
+        }
+    }
+}
+
+/// Checks that all addresses can hold different values at once, by writing a value
+/// derived from each address to it and then reading them all back.
+fn check_independent(mut device: Box<dyn Addressable>, addresses: &[u16]) {
+    let value = |addr: u16| (addr as u8) ^ ((addr >> 8) as u8) ^ 0xa5;
+
+    for &addr in addresses {
+        device.write(addr, value(addr));
+    }
+    for &addr in addresses {
+        assert_eq!(
+            device.read(addr),
+            value(addr),
+            "Value at ${:04X} changed by a write to another address",
+            addr
+        );
+    }
+    // Backwards too, so that aliasing is caught whichever of the two is written last
+    for &addr in addresses.iter().rev() {
+        device.write(addr, !value(addr));
+    }
+    for &addr in addresses {
+        assert_eq!(
+            device.read(addr),
+            !value(addr),
+            "Value at ${:04X} changed by a write to another address",
+            addr
+        );
+    }
+}
+
+/// Checks that writes to each mirror of an address can be read through every mirror.
+fn check_mirrors(mut device: Box<dyn Addressable>, addresses: &[u16], size: u32) {
+    assert!(
+        size > 0 && 0x10000 % size == 0,
+        "Mirror size {} does not evenly divide the address space",
+        size
+    );
+
+    let count = 0x10000 / size;
+    for &addr in addresses {
+        assert!(
+            (addr as u32) < size,
+            "Stored address ${:04X} is outside the first mirror",
+            addr
+        );
+        for n in 1..count {
+            let mirror = (addr as u32 + n * size) as u16;
+            let value = n as u8 ^ 0x5a;
+
+            device.write(mirror, value);
+            assert_eq!(
+                device.read(addr),
+                value,
+                "Write to mirror ${:04X} not seen at ${:04X}",
+                mirror,
+                addr
+            );
+            device.write(addr, !value);
+            assert_eq!(
+                device.read(mirror),
+                !value,
+                "Write to ${:04X} not seen at mirror ${:04X}",
+                addr,
+                mirror
+            );
+        }
+    }
+}
@@ -0,0 +1,91 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+#[cfg(feature = "embedded-roms")]
+use super::ROM_CHARACTER;
+
+/// One of the two character sets in the character ROM.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Charset {
+    /// The uppercase and graphics set, in the first 2k of the ROM. This is the set that the
+    /// C64 starts up with.
+    Uppercase,
+
+    /// The lowercase and uppercase set, in the second 2k of the ROM. It's switched to by
+    /// pressing Commodore and SHIFT together.
+    Lowercase,
+}
+
+/// Returns the bitmap of a character from a character ROM image.
+///
+/// `code` is a screen code (the value stored in screen memory), not a PETSCII code. Screen
+/// codes 128-255 are the reverse-video versions of 0-127, which the ROM stores as separate
+/// bitmaps, so every code has its own glyph.
+///
+/// The bitmap is eight bytes, one for each row of pixels from top to bottom. In each row,
+/// bit 7 is the leftmost pixel and bit 0 is the rightmost, and a 1 bit is a pixel in the
+/// foreground color.
+pub fn glyph_in(rom: &[u8; 0x1000], charset: Charset, code: u8) -> [u8; 8] {
+    let base = match charset {
+        Charset::Uppercase => 0x000,
+        Charset::Lowercase => 0x800,
+    };
+    let start = base + code as usize * 8;
+    let mut bitmap = [0; 8];
+    bitmap.copy_from_slice(&rom[start..start + 8]);
+    bitmap
+}
+
+/// Returns the bitmap of a character from the embedded character ROM. See `glyph_in`.
+#[cfg(feature = "embedded-roms")]
+pub fn glyph(charset: Charset, code: u8) -> [u8; 8] {
+    glyph_in(&ROM_CHARACTER, charset, code)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn space_is_blank() {
+        let roms = test_roms!();
+        let glyph = |charset, code| glyph_in(&roms.character, charset, code);
+        assert_eq!(glyph(Charset::Uppercase, 32), [0; 8]);
+        assert_eq!(glyph(Charset::Lowercase, 32), [0; 8]);
+    }
+
+    #[test]
+    fn letters() {
+        let roms = test_roms!();
+        let glyph = |charset, code| glyph_in(&roms.character, charset, code);
+        let at = glyph(Charset::Uppercase, 0);
+        assert_eq!(at, [0x3c, 0x66, 0x6e, 0x6e, 0x60, 0x62, 0x3c, 0x00]);
+
+        let a = glyph(Charset::Uppercase, 1);
+        assert!(a[..7].iter().all(|row| *row != 0), "A should fill 7 rows");
+        assert_eq!(a[7], 0, "bottom row should be left for spacing");
+        assert_ne!(
+            glyph(Charset::Lowercase, 1),
+            a,
+            "code 1 should be lowercase a in the lowercase set"
+        );
+        assert_eq!(
+            glyph(Charset::Lowercase, 65),
+            a,
+            "code 65 should be uppercase A in the lowercase set"
+        );
+    }
+
+    #[test]
+    fn reverse_video() {
+        let roms = test_roms!();
+        let glyph = |charset, code| glyph_in(&roms.character, charset, code);
+        let a = glyph(Charset::Uppercase, 1);
+        let reversed = glyph(Charset::Uppercase, 129);
+        for (row, reversed_row) in a.iter().zip(reversed.iter()) {
+            assert_eq!(*reversed_row, !row);
+        }
+    }
+}
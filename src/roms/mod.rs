@@ -3,10 +3,276 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+//! The C64's ROM images and the means to load them.
+//!
+//! Copies of the BASIC, KERNAL, and character ROMs are compiled in as `ROM_BASIC`,
+//! `ROM_KERNAL`, and `ROM_CHARACTER` when the `embedded-roms` feature is enabled (it is by
+//! default). Those images are Commodore's, and not everyone can distribute them, so with
+//! the feature disabled they're left out and the ROMs have to be loaded from files at run
+//! time instead. Either way, they end up in a `RomSet`, which is what everything that needs
+//! the ROMs takes.
+//!
+//! Replacement images (a KERNAL that skips the RAM test, say) can be loaded with
+//! `load_rom_file` or changed in place with `apply_patches`, and `identify` tells whether
+//! an image is one of the stock ones.
+
+#[cfg(feature = "embedded-roms")]
 mod basic;
+#[cfg(feature = "embedded-roms")]
 mod character;
+#[cfg(feature = "embedded-roms")]
 mod kernal;
 
+mod chargen;
+
+#[cfg(feature = "embedded-roms")]
 pub use self::basic::ROM_BASIC;
+#[cfg(feature = "embedded-roms")]
 pub use self::character::ROM_CHARACTER;
+#[cfg(feature = "embedded-roms")]
+pub use self::chargen::glyph;
+pub use self::chargen::{glyph_in, Charset};
+#[cfg(feature = "embedded-roms")]
 pub use self::kernal::ROM_KERNAL;
+
+use std::{
+    convert::TryInto,
+    error,
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::Path,
+};
+
+/// An error in loading or patching a ROM image.
+#[derive(Debug)]
+pub enum RomError {
+    /// The image couldn't be read.
+    Io(io::Error),
+
+    /// The image was the wrong size. The first value is the size that was expected and the
+    /// second is the size of the image.
+    Size(usize, usize),
+
+    /// A patch at this offset runs past the end of the image.
+    Patch(u16),
+}
+
+impl Display for RomError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            RomError::Io(e) => write!(f, "ROM image could not be read: {}", e),
+            RomError::Size(expected, actual) => write!(
+                f,
+                "ROM image is {} bytes, but it must be {} bytes",
+                actual, expected
+            ),
+            RomError::Patch(offset) => {
+                write!(
+                    f,
+                    "patch at offset ${:04X} runs past the end of the ROM",
+                    offset
+                )
+            }
+        }
+    }
+}
+
+impl error::Error for RomError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            RomError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RomError {
+    fn from(e: io::Error) -> Self {
+        RomError::Io(e)
+    }
+}
+
+/// Reads a ROM image of `expected_len` bytes from a file. A file of any other length is a
+/// `RomError::Size`, since a ROM image that's too short or too long is almost certainly the
+/// wrong file.
+pub fn load_rom_file(path: &Path, expected_len: usize) -> Result<Vec<u8>, RomError> {
+    let bytes = fs::read(path)?;
+    if bytes.len() != expected_len {
+        return Err(RomError::Size(expected_len, bytes.len()));
+    }
+    Ok(bytes)
+}
+
+/// Writes patches into a ROM image. Each patch is an offset into the image and the bytes
+/// that replace the ones starting there. Patches are applied in order, and if one doesn't
+/// fit in the image, neither it nor any of the ones after it are applied.
+pub fn apply_patches(rom: &mut [u8], patches: &[(u16, &[u8])]) -> Result<(), RomError> {
+    for &(offset, bytes) in patches {
+        let start = offset as usize;
+        match rom.get_mut(start..start + bytes.len()) {
+            Some(target) => target.copy_from_slice(bytes),
+            None => return Err(RomError::Patch(offset)),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the CRC-32 checksum of some data. This is the common CRC-32 (the one used by zip
+/// and PNG), so the checksums of ROM images can be compared with those published elsewhere.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+/// The CRC-32 checksums of the ROM images that Commodore shipped in the C64, along with
+/// their part numbers.
+const KNOWN_IMAGES: [(u32, &str); 5] = [
+    (0xf833_d117, "901226-01 BASIC"),
+    (0xdce7_82fa, "901227-01 KERNAL"),
+    (0xa5c6_87b3, "901227-02 KERNAL"),
+    (0xdbe3_e7c7, "901227-03 KERNAL"),
+    (0xec42_72ee, "901225-01 character"),
+];
+
+/// Returns the part number and name of a ROM image if it's one that Commodore shipped in
+/// the C64, or `None` if it isn't (it's been patched, perhaps). A board builder can use this
+/// to warn about images that aren't what they claim to be.
+pub fn identify(image: &[u8]) -> Option<&'static str> {
+    let crc = crc32(image);
+    KNOWN_IMAGES
+        .iter()
+        .find(|&&(known, _)| known == crc)
+        .map(|&(_, name)| name)
+}
+
+/// Reads a ROM image of exactly `N` bytes from a file into a fixed-size array.
+fn load_boxed<const N: usize>(path: &Path) -> Result<Box<[u8; N]>, RomError> {
+    load_rom_file(path, N)?
+        .into_boxed_slice()
+        .try_into()
+        .map_err(|bytes: Box<[u8]>| RomError::Size(N, bytes.len()))
+}
+
+/// The name of the BASIC ROM image file in a directory passed to `RomSet::from_dir`.
+pub const BASIC_FILE: &str = "basic.bin";
+/// The name of the KERNAL ROM image file in a directory passed to `RomSet::from_dir`.
+pub const KERNAL_FILE: &str = "kernal.bin";
+/// The name of the character ROM image file in a directory passed to `RomSet::from_dir`.
+pub const CHARACTER_FILE: &str = "chargen.bin";
+
+/// The three ROM images that a C64 needs.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RomSet {
+    /// The 8k BASIC ROM, which appears at $A000-$BFFF.
+    pub basic: Box<[u8; 0x2000]>,
+
+    /// The 8k KERNAL ROM, which appears at $E000-$FFFF.
+    pub kernal: Box<[u8; 0x2000]>,
+
+    /// The 4k character ROM, which appears at $D000-$DFFF when I/O is switched out.
+    pub character: Box<[u8; 0x1000]>,
+}
+
+impl RomSet {
+    /// Loads the ROM images from a directory, where they must be in files named
+    /// `BASIC_FILE`, `KERNAL_FILE`, and `CHARACTER_FILE`. A missing file or one that's the
+    /// wrong length is an error.
+    pub fn from_dir(dir: &Path) -> Result<RomSet, RomError> {
+        Ok(RomSet {
+            basic: load_boxed(&dir.join(BASIC_FILE))?,
+            kernal: load_boxed(&dir.join(KERNAL_FILE))?,
+            character: load_boxed(&dir.join(CHARACTER_FILE))?,
+        })
+    }
+
+    /// Returns a set made of the ROM images compiled into the crate.
+    #[cfg(feature = "embedded-roms")]
+    pub fn embedded() -> RomSet {
+        RomSet {
+            basic: Box::new(ROM_BASIC),
+            kernal: Box::new(ROM_KERNAL),
+            character: Box::new(ROM_CHARACTER),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+
+    use super::*;
+    use crate::{components::addressable::Addressable, memory::C64Memory};
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn identify_images() {
+        let roms = test_roms!();
+        assert_eq!(identify(&roms.basic[..]), Some("901226-01 BASIC"));
+        assert_eq!(identify(&roms.kernal[..]), Some("901227-03 KERNAL"));
+        assert_eq!(identify(&roms.character[..]), Some("901225-01 character"));
+        assert_eq!(identify(&[0; 0x2000]), None);
+    }
+
+    #[test]
+    fn patches() {
+        let mut rom = vec![0; 0x10];
+        apply_patches(&mut rom, &[(0x00, &[1, 2]), (0x0e, &[3, 4])]).unwrap();
+        assert_eq!(rom[..3], [1, 2, 0]);
+        assert_eq!(rom[0x0d..], [0, 3, 4]);
+
+        let result = apply_patches(&mut rom, &[(0x0f, &[5, 6]), (0x00, &[7])]);
+        assert!(matches!(result, Err(RomError::Patch(0x0f))));
+        assert_eq!(rom[0x0f], 4, "patch that doesn't fit should not be applied");
+        assert_eq!(
+            rom[0x00], 1,
+            "patches after a failed one should not be applied"
+        );
+    }
+
+    #[test]
+    fn load_wrong_size() {
+        let path = env::temp_dir().join(format!("c64-rom-{}.bin", std::process::id()));
+        fs::write(&path, [0; 0x1000]).unwrap();
+        let result = load_rom_file(&path, 0x2000);
+        let loaded = load_rom_file(&path, 0x1000);
+        fs::remove_file(&path).unwrap();
+
+        let err = result.unwrap_err();
+        assert!(matches!(err, RomError::Size(0x2000, 0x1000)));
+        assert_eq!(
+            err.to_string(),
+            "ROM image is 4096 bytes, but it must be 8192 bytes"
+        );
+        assert_eq!(loaded.unwrap(), vec![0; 0x1000]);
+
+        let err = load_rom_file(&path, 0x1000).unwrap_err();
+        assert!(matches!(err, RomError::Io(_)));
+    }
+
+    #[test]
+    fn patched_reset_vector() {
+        let mut roms = test_roms!();
+        apply_patches(&mut roms.kernal[..], &[(0x1ffc, &[0x34, 0x12])]).unwrap();
+        assert_eq!(
+            identify(&roms.kernal[..]),
+            None,
+            "patched image is not stock"
+        );
+
+        let mut memory = C64Memory::with_roms(roms);
+        assert_eq!(memory.read(0xfffc), 0x34);
+        assert_eq!(memory.read(0xfffd), 0x12);
+    }
+}
@@ -3,14 +3,23 @@
 // This software is released under the MIT License.
 // https://opensource.org/licenses/MIT
 
+use std::{env, path::Path};
+
 use crate::{
-    components::{device::DeviceRef, trace::Trace},
+    components::{device::DeviceRef, pin::Pin, trace::Trace},
+    roms::RomSet,
     vectors::RefVec,
 };
 
 pub fn make_traces(device: &DeviceRef) -> RefVec<Trace> {
+    trace_each(&device.borrow().pins())
+}
+
+/// Connects each of a vector of pins to a trace of its own, returning the traces in the
+/// same order as the pins.
+pub fn trace_each(pins: &RefVec<Pin>) -> RefVec<Trace> {
     let mut v = vec![];
-    for pin in device.borrow().pins().iter() {
+    for pin in pins.iter() {
         v.push(trace!(clone_ref!(pin)));
     }
     RefVec::with_vec(v)
@@ -25,10 +34,167 @@ pub fn value_to_traces(value: usize, traces: &RefVec<Trace>) {
 pub fn traces_to_value(traces: &RefVec<Trace>) -> usize {
     let mut value = 0;
     for (i, trace) in traces.iter_ref().enumerate() {
-        value |= (match level!(trace) {
-            Some(v) if v >= 0.5 => 1,
-            _ => 0,
-        }) << i;
+        value |= (high!(trace) as usize) << i;
     }
     value
 }
+
+/// Returns the outputs of the C64's PLA for a word of inputs, computed independently of
+/// `pla_output` to serve as a check on it. It's adapted from a C program that provides a 64k
+/// table of outputs for the PLA based on all of the possible inputs. The original is located
+/// at http://www.zimmers.net/anonftp/pub/cbm/firmware/computers/c64/pla.c.
+pub fn pla_reference(input: u16) -> u8 {
+    let cas = input & (1 << 0) != 0;
+    let loram = input & (1 << 1) != 0;
+    let hiram = input & (1 << 2) != 0;
+    let charen = input & (1 << 3) != 0;
+    let va14 = input & (1 << 4) != 0;
+    let a15 = input & (1 << 5) != 0;
+    let a14 = input & (1 << 6) != 0;
+    let a13 = input & (1 << 7) != 0;
+    let a12 = input & (1 << 8) != 0;
+    let ba = input & (1 << 9) != 0;
+    let aec = input & (1 << 10) != 0;
+    let r_w = input & (1 << 11) != 0;
+    let exrom = input & (1 << 12) != 0;
+    let game = input & (1 << 13) != 0;
+    let va13 = input & (1 << 14) != 0;
+    let va12 = input & (1 << 15) != 0;
+
+    let f0 = (loram & hiram & a15 & !a14 & a13 & !aec & r_w & game)
+        | (hiram & a15 & a14 & a13 & !aec & r_w & game)
+        | (hiram & a15 & a14 & a13 & !aec & r_w & !exrom & !game)
+        | (hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game)
+        | (loram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & game)
+        | (hiram & !charen & a15 & a14 & !a13 & a12 & !aec & r_w & !exrom & !game)
+        | (va14 & aec & game & !va13 & va12)
+        | (va14 & aec & !exrom & !game & !va13 & va12)
+        | (hiram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & game)
+        | (hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game)
+        | (loram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & game)
+        | (loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & game)
+        | (hiram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & !exrom & !game)
+        | (hiram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game)
+        | (loram & charen & a15 & a14 & !a13 & a12 & ba & !aec & r_w & !exrom & !game)
+        | (loram & charen & a15 & a14 & !a13 & a12 & !aec & !r_w & !exrom & !game)
+        | (a15 & a14 & !a13 & a12 & ba & !aec & r_w & exrom & !game)
+        | (a15 & a14 & !a13 & a12 & !aec & !r_w & exrom & !game)
+        | (loram & hiram & a15 & !a14 & !a13 & !aec & r_w & !exrom)
+        | (a15 & !a14 & !a13 & !aec & exrom & !game)
+        | (hiram & a15 & !a14 & a13 & !aec & r_w & !exrom & !game)
+        | (a15 & a14 & a13 & !aec & exrom & !game)
+        | (aec & exrom & !game & va13 & va12)
+        | (!a15 & !a14 & a12 & exrom & !game)
+        | (!a15 & !a14 & a13 & exrom & !game)
+        | (!a15 & a14 & exrom & !game)
+        | (a15 & !a14 & a13 & exrom & !game)
+        | (a15 & a14 & !a13 & !a12 & exrom & !game)
+        | cas;
+    let f1 = !loram | !hiram | !a15 | a14 | !a13 | aec | !r_w | !game;
+    let f2 = (!hiram | !a15 | !a14 | !a13 | aec | !r_w | !game)
+        & (!hiram | !a15 | !a14 | !a13 | aec | !r_w | exrom | game);
+    let f3 = (!hiram | charen | !a15 | !a14 | a13 | !a12 | aec | !r_w | !game)
+        & (!loram | charen | !a15 | !a14 | a13 | !a12 | aec | !r_w | !game)
+        & (!hiram | charen | !a15 | !a14 | a13 | !a12 | aec | !r_w | exrom | game)
+        & (!va14 | !aec | !game | va13 | !va12)
+        & (!va14 | !aec | exrom | game | va13 | !va12);
+    let f4 = cas | !a15 | !a14 | a13 | !a12 | aec | r_w;
+    let f5 = (!hiram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | !game)
+        & (!hiram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | !game)
+        & (!loram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | !game)
+        & (!loram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | !game)
+        & (!hiram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | exrom | game)
+        & (!hiram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | exrom | game)
+        & (!loram | !charen | !a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | exrom | game)
+        & (!loram | !charen | !a15 | !a14 | a13 | !a12 | aec | r_w | exrom | game)
+        & (!a15 | !a14 | a13 | !a12 | !ba | aec | !r_w | !exrom | game)
+        & (!a15 | !a14 | a13 | !a12 | aec | r_w | !exrom | game);
+    let f6 = (!loram | !hiram | !a15 | a14 | a13 | aec | !r_w | exrom)
+        & (!a15 | a14 | a13 | aec | !exrom | game);
+    let f7 = (!hiram | !a15 | a14 | !a13 | aec | !r_w | exrom | game)
+        & (!a15 | !a14 | !a13 | aec | !exrom | game)
+        & (!aec | !exrom | game | !va13 | !va12);
+
+    let mut output = 0;
+    if f0 {
+        output |= 1 << 0;
+    }
+    if f1 {
+        output |= 1 << 1;
+    }
+    if f2 {
+        output |= 1 << 2;
+    }
+    if f3 {
+        output |= 1 << 3;
+    }
+    if f4 {
+        output |= 1 << 4;
+    }
+    if f5 {
+        output |= 1 << 5;
+    }
+    if f6 {
+        output |= 1 << 6;
+    }
+    if f7 {
+        output |= 1 << 7;
+    }
+
+    output
+}
+
+/// Builds a .CRT image with the given header values and CHIP packets, each of which is a
+/// load address and ROM image.
+pub fn make_crt(hardware: u16, exrom: u8, game: u8, chips: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let mut bytes = b"C64 CARTRIDGE   ".to_vec();
+    bytes.extend([0x00, 0x00, 0x00, 0x40]);
+    bytes.extend([0x01, 0x00]);
+    bytes.extend(hardware.to_be_bytes());
+    bytes.extend([exrom, game]);
+    bytes.extend([0; 6]);
+    let mut name = b"TEST".to_vec();
+    name.resize(32, 0);
+    bytes.extend(name);
+
+    for (load, data) in chips {
+        bytes.extend(b"CHIP");
+        bytes.extend(((0x10 + data.len()) as u32).to_be_bytes());
+        bytes.extend([0x00, 0x00, 0x00, 0x00]);
+        bytes.extend(load.to_be_bytes());
+        bytes.extend((data.len() as u16).to_be_bytes());
+        bytes.extend(data);
+    }
+    bytes
+}
+
+/// The environment variable that names a directory to load the test ROMs from.
+pub const ROM_DIR_VAR: &str = "C64_ROM_DIR";
+
+/// Returns the ROM images that tests that need the real ROMs should use. If the directory
+/// named by `ROM_DIR_VAR` is set, the ROMs are loaded from there; otherwise the embedded
+/// ROMs are used if they're compiled in. If there are no ROMs available at all, this
+/// returns `None` and the test should be skipped by returning early.
+pub fn test_roms() -> Option<RomSet> {
+    match env::var_os(ROM_DIR_VAR) {
+        Some(dir) => Some(
+            RomSet::from_dir(Path::new(&dir))
+                .unwrap_or_else(|e| panic!("could not load ROMs from {}: {}", ROM_DIR_VAR, e)),
+        ),
+        None => embedded_roms(),
+    }
+}
+
+#[cfg(feature = "embedded-roms")]
+fn embedded_roms() -> Option<RomSet> {
+    Some(RomSet::embedded())
+}
+
+#[cfg(not(feature = "embedded-roms"))]
+fn embedded_roms() -> Option<RomSet> {
+    eprintln!(
+        "skipping test: no embedded ROMs and {} is not set",
+        ROM_DIR_VAR
+    );
+    None
+}
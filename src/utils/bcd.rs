@@ -0,0 +1,252 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Helpers for working with binary-coded decimal (BCD) values.
+//!
+//! Both the 6510's decimal mode and the 6526's time-of-day clock store numbers as packed
+//! BCD, where each nibble of a byte holds one decimal digit (so decimal 42 is stored as
+//! `$42`). These helpers do the conversions and arithmetic in one place so that chip
+//! emulations and the tests that validate them don't each need their own version.
+//!
+//! A byte is only *valid* BCD if both of its nibbles are in the range 0-9. Real hardware
+//! happily accepts invalid values, though, and different chips mangle them in different
+//! ways. The arithmetic functions here process invalid input exactly as the NMOS 6502 (and
+//! therefore the 6510) does in decimal mode, and they report whether the inputs were valid
+//! so that callers emulating other chips (like the 6526, whose TOD counters simply count up
+//! from whatever invalid value is in them) can do their own thing instead.
+
+/// The result of a BCD addition or subtraction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BcdResult {
+    /// The two-digit BCD result of the operation.
+    pub value: u8,
+
+    /// The carry out of the operation. For addition, this is set if the result exceeded 99.
+    /// For subtraction it follows the 6502 convention, being *clear* if a borrow was
+    /// needed.
+    pub carry: bool,
+
+    /// Whether both operands were valid BCD. If this is `false`, `value` and `carry` are
+    /// what an NMOS 6502 would produce for the same operands.
+    pub valid: bool,
+}
+
+/// Determines whether a byte is valid packed BCD (both nibbles in the range 0-9).
+pub fn is_bcd(value: u8) -> bool {
+    value & 0x0f <= 0x09 && value & 0xf0 <= 0x90
+}
+
+/// Converts a binary value into packed BCD. Since a byte can only hold two decimal digits,
+/// values over 99 wrap around (so 123 becomes `$23`).
+pub fn to_bcd(value: u8) -> u8 {
+    let value = value % 100;
+    ((value / 10) << 4) | (value % 10)
+}
+
+/// Converts a packed BCD value into binary. Invalid nibbles are not rejected; each nibble
+/// is simply weighted by its position, so `$1A` converts to 20 (1 * 10 + 10) and `$FF`
+/// converts to 165.
+pub fn from_bcd(value: u8) -> u8 {
+    (value >> 4) * 10 + (value & 0x0f)
+}
+
+/// Adds two packed BCD values and a carry, returning the BCD result and the carry out.
+///
+/// This uses the NMOS 6502 decimal adjustment: each nibble has 6 added to it if it exceeds
+/// 9 after the binary addition. For valid BCD inputs this produces the correct decimal sum;
+/// for invalid inputs it produces the same (not particularly meaningful) result that the
+/// 6510 does.
+pub fn bcd_add(a: u8, b: u8, carry: bool) -> BcdResult {
+    let mut low = (a & 0x0f) as u16 + (b & 0x0f) as u16 + carry as u16;
+    if low >= 0x0a {
+        low = ((low + 0x06) & 0x0f) + 0x10;
+    }
+    let mut result = (a & 0xf0) as u16 + (b & 0xf0) as u16 + low;
+    if result >= 0xa0 {
+        result += 0x60;
+    }
+
+    BcdResult {
+        value: result as u8,
+        carry: result >= 0x100,
+        valid: is_bcd(a) && is_bcd(b),
+    }
+}
+
+/// Subtracts one packed BCD value from another, returning the BCD result and the carry
+/// out. As with the 6502's `SBC`, the carry going in should be set unless there is a
+/// borrow from a previous subtraction, and the carry coming out is cleared if this
+/// subtraction needed a borrow.
+///
+/// This uses the NMOS 6502 decimal adjustment: each nibble has 6 subtracted from it if the
+/// binary subtraction borrowed from it. For valid BCD inputs this produces the correct
+/// decimal difference (wrapping around from 0 to 99); for invalid inputs it produces the
+/// same result that the 6510 does.
+pub fn bcd_sub(a: u8, b: u8, carry: bool) -> BcdResult {
+    let borrow = !carry as i16;
+    let low = (a & 0x0f) as i16 - (b & 0x0f) as i16 - borrow;
+    let mut result = a as i16 - b as i16 - borrow;
+    let no_borrow = result >= 0;
+    if result < 0 {
+        result -= 0x60;
+    }
+    if low < 0 {
+        result -= 0x06;
+    }
+
+    BcdResult {
+        value: result as u8,
+        carry: no_borrow,
+        valid: is_bcd(a) && is_bcd(b),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn validity() {
+        for value in 0..=0xff {
+            let expected = value >> 4 <= 9 && value & 0x0f <= 9;
+            assert_eq!(
+                is_bcd(value),
+                expected,
+                "${:02X} should {}be valid BCD",
+                value,
+                if expected { "" } else { "not " }
+            );
+        }
+    }
+
+    #[test]
+    fn to_bcd_all() {
+        for value in 0..=0xff {
+            let bcd = to_bcd(value);
+            assert!(
+                is_bcd(bcd),
+                "to_bcd({}) produced invalid BCD ${:02X}",
+                value,
+                bcd
+            );
+            assert_eq!(
+                from_bcd(bcd),
+                value % 100,
+                "to_bcd({}) should wrap to {}",
+                value,
+                value % 100
+            );
+        }
+        assert_eq!(to_bcd(42), 0x42);
+        assert_eq!(to_bcd(99), 0x99);
+        assert_eq!(to_bcd(100), 0x00);
+    }
+
+    #[test]
+    fn from_bcd_all() {
+        for value in 0..=0xff {
+            let expected = (value >> 4) as u16 * 10 + (value & 0x0f) as u16;
+            let actual = from_bcd(value);
+            assert_eq!(
+                actual as u16, expected,
+                "from_bcd(${:02X}) should weight each nibble by position",
+                value
+            );
+            if is_bcd(value) {
+                assert_eq!(
+                    to_bcd(actual),
+                    value,
+                    "${:02X} should survive a round trip",
+                    value
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn add_valid() {
+        for a in 0..100 {
+            for b in 0..100 {
+                for c in [false, true] {
+                    let sum = a + b + c as u8;
+                    let result = bcd_add(to_bcd(a), to_bcd(b), c);
+                    assert_eq!(
+                        result,
+                        BcdResult {
+                            value: to_bcd(sum),
+                            carry: sum > 99,
+                            valid: true,
+                        },
+                        "Incorrect result for {} + {} + {}",
+                        a,
+                        b,
+                        c as u8
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sub_valid() {
+        for a in 0..100 {
+            for b in 0..100 {
+                for c in [false, true] {
+                    let diff = a as i16 - b as i16 - !c as i16;
+                    let result = bcd_sub(to_bcd(a), to_bcd(b), c);
+                    assert_eq!(
+                        result,
+                        BcdResult {
+                            value: to_bcd(diff.rem_euclid(100) as u8),
+                            carry: diff >= 0,
+                            valid: true,
+                        },
+                        "Incorrect result for {} - {} - {}",
+                        a,
+                        b,
+                        !c as u8
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_flagged() {
+        for a in 0..=0xff {
+            for b in 0..=0xff {
+                let valid = is_bcd(a) && is_bcd(b);
+                assert_eq!(bcd_add(a, b, false).valid, valid);
+                assert_eq!(bcd_sub(a, b, true).valid, valid);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_nmos_results() {
+        // Results of the NMOS decimal adjustment for operands with invalid nibbles.
+        assert_eq!(bcd_add(0x0f, 0x01, false).value, 0x16);
+        assert_eq!(bcd_add(0x1a, 0x00, false).value, 0x20);
+        assert_eq!(bcd_add(0xff, 0xff, true).value, 0x55);
+        assert!(bcd_add(0xff, 0xff, true).carry);
+        assert_eq!(bcd_sub(0x00, 0x01, true).value, 0x99);
+        assert_eq!(bcd_sub(0x1a, 0x00, true).value, 0x1a);
+        assert_eq!(bcd_sub(0x20, 0x0f, true).value, 0x0b);
+    }
+
+    #[test]
+    fn carry_matches_binary() {
+        // The carry out of a 6502 subtraction is the same in decimal and binary mode, even
+        // for invalid BCD.
+        for a in 0..=0xff {
+            for b in 0..=0xff {
+                for c in [false, true] {
+                    let binary = a as i16 - b as i16 - !c as i16 >= 0;
+                    assert_eq!(bcd_sub(a, b, c).carry, binary);
+                }
+            }
+        }
+    }
+}
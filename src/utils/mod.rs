@@ -0,0 +1,277 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+pub mod bcd;
+pub mod petscii;
+
+use std::{
+    error,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::{
+    components::{
+        addressable::Addressable,
+        pin::{with_batch, Mode, Pin},
+    },
+    vectors::RefVec,
+};
+
+/// A vector of pins that holds a number *LSB-first*: the pin at index 0 holds bit 0 of the
+/// value, the pin at index 1 holds bit 1, and so on. Chips build these vectors from their
+/// `PA_*` arrays, which must therefore list pins in bit order (A0, A1, A2...) rather than
+/// in physical pin order.
+///
+/// The multi-pin conversions below take their pins as this type, so the order they expect
+/// is part of their signatures. A `&RefVec<Pin>` converts into it, so callers that have
+/// built their vectors in bit order can pass them as they are.
+#[derive(Clone, Copy)]
+pub struct LsbFirst<'a>(pub &'a RefVec<Pin>);
+
+impl<'a> From<&'a RefVec<Pin>> for LsbFirst<'a> {
+    fn from(pins: &'a RefVec<Pin>) -> Self {
+        LsbFirst(pins)
+    }
+}
+
+/// Reads the levels of a vector of pins and returns them as a number. The pins are taken
+/// LSB-first, so the first pin in the vector is bit 0. Pins that are high contribute a 1
+/// bit; pins that are low or floating contribute a 0 bit.
+#[inline]
+pub fn pins_to_value<'a>(pins: impl Into<LsbFirst<'a>>) -> usize {
+    let LsbFirst(pins) = pins.into();
+    debug_assert!(
+        pins.len() <= usize::BITS as usize,
+        "Cannot read {} pins into a usize",
+        pins.len()
+    );
+    let mut value = 0;
+    for (i, pin) in pins.iter_ref().enumerate() {
+        value |= (high!(pin) as usize) << i;
+    }
+    value
+}
+
+/// Sets the levels of a vector of pins to represent a number. The pins are taken LSB-first,
+/// so the first pin in the vector is set to bit 0 of the value. The value must fit in the
+/// number of pins supplied.
+///
+/// The pins are set in a batch (see `pin::with_batch`), so a device watching them is
+/// notified once per pin that changed, after all of them have their new levels, rather
+/// than seeing the value change one bit at a time.
+#[inline]
+pub fn value_to_pins<'a>(value: usize, pins: impl Into<LsbFirst<'a>>) {
+    let LsbFirst(pins) = pins.into();
+    debug_assert!(
+        pins.len() >= usize::BITS as usize || value >> pins.len() == 0,
+        "Value ${:X} does not fit in {} pins",
+        value,
+        pins.len()
+    );
+    with_batch(|| {
+        for (i, pin) in pins.iter_ref().enumerate() {
+            set_level!(pin, Some(((value >> i) & 1) as f64));
+        }
+    });
+}
+
+/// An error from trying to put a value on fewer pins than it needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WidthError {
+    /// The value that didn't fit.
+    pub value: usize,
+
+    /// The number of pins it was supposed to fit in.
+    pub width: usize,
+}
+
+impl Display for WidthError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "value ${:X} does not fit in {} pins",
+            self.value, self.width
+        )
+    }
+}
+
+impl error::Error for WidthError {}
+
+/// Sets the levels of a vector of pins to represent a number, like `value_to_pins`, but
+/// returns an error instead of setting anything if the value doesn't fit in the number of
+/// pins supplied. This is meant for values that come from outside the emulator (a file, or
+/// a user) rather than from a chip's own registers.
+pub fn try_value_to_pins<'a>(
+    value: usize,
+    pins: impl Into<LsbFirst<'a>>,
+) -> Result<(), WidthError> {
+    let LsbFirst(pins) = pins.into();
+    if pins.len() < usize::BITS as usize && value >> pins.len() != 0 {
+        return Err(WidthError {
+            value,
+            width: pins.len(),
+        });
+    }
+    value_to_pins(value, pins);
+    Ok(())
+}
+
+/// Sets the levels of all of the pins in a vector to `None`.
+#[inline]
+pub fn none_to_pins(pins: &RefVec<Pin>) {
+    for pin in pins.iter_ref() {
+        float!(pin);
+    }
+}
+
+/// Sets the mode of all of the pins in a vector.
+#[inline]
+pub fn mode_to_pins(mode: Mode, pins: &RefVec<Pin>) {
+    for pin in pins.iter_ref() {
+        set_mode!(pin, mode);
+    }
+}
+
+/// Writes a chip's register values as rows of 16 in hex, each row starting with the
+/// address of its first register (counting from `base`). This is the layout that machine
+/// language monitors use for register dumps.
+pub fn write_registers(f: &mut Formatter, base: u16, regs: &[u8]) -> fmt::Result {
+    for (row, values) in regs.chunks(16).enumerate() {
+        write!(f, "{:04X}:", base as usize + row * 16)?;
+        for value in values {
+            write!(f, " {:02X}", value)?;
+        }
+        writeln!(f)?;
+    }
+    Ok(())
+}
+
+/// Returns a dump of `len` bytes of memory starting at `start`, in the layout of `xxd`.
+///
+/// Each line has the address of its first byte, up to 16 bytes in hex, and the same bytes
+/// as ASCII, with a `.` for each byte that isn't a printable ASCII character. The last line
+/// is padded so that its ASCII lines up with the lines above it if the range doesn't end on
+/// a multiple of 16 bytes. Addresses past $FFFF wrap around to $0000, so a dump of more than
+/// 64k shows some of the memory more than once.
+///
+/// The memory is read with `Addressable::read`, which takes `&mut` because reads can have
+/// side effects. Dumping the registers of a chip like the 6526 can therefore change them.
+pub fn hex_dump(mem: &mut dyn Addressable, start: u16, len: usize) -> String {
+    let bytes: Vec<u8> = (0..len)
+        .map(|offset| mem.read(start.wrapping_add(offset as u16)))
+        .collect();
+
+    let mut dump = String::new();
+    for (line, values) in bytes.chunks(16).enumerate() {
+        let addr = start.wrapping_add((line * 16) as u16);
+        let hex: Vec<String> = values
+            .iter()
+            .map(|value| format!("{:02X}", value))
+            .collect();
+        let ascii: String = values
+            .iter()
+            .map(|&value| match value {
+                0x20..=0x7e => value as char,
+                _ => '.',
+            })
+            .collect();
+        dump.push_str(&format!("{:04X}: {:47}  {}\n", addr, hex.join(" "), ascii));
+    }
+    dump
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{components::pin::Mode::Output, memory::Ram};
+
+    fn bus(width: usize) -> RefVec<Pin> {
+        RefVec::with_vec((0..width).map(|i| pin!(i + 1, "D", Output)).collect())
+    }
+
+    #[test]
+    fn lsb_first() {
+        let pins = bus(4);
+        value_to_pins(0b0001, LsbFirst(&pins));
+        assert!(high!(pins[0]), "bit 0 should be on the first pin");
+        assert!(low!(pins[3]));
+        assert_eq!(pins_to_value(LsbFirst(&pins)), 0b0001);
+    }
+
+    #[test]
+    fn value_fits() {
+        let pins = bus(8);
+        try_value_to_pins(0xff, &pins).unwrap();
+        assert_eq!(pins_to_value(&pins), 0xff);
+        try_value_to_pins(0x00, &pins).unwrap();
+        assert_eq!(pins_to_value(&pins), 0x00);
+    }
+
+    #[test]
+    fn value_too_wide() {
+        let pins = bus(8);
+        value_to_pins(0x5a, &pins);
+
+        let err = try_value_to_pins(0x1234, &pins).unwrap_err();
+        assert_eq!(
+            err,
+            WidthError {
+                value: 0x1234,
+                width: 8
+            }
+        );
+        assert_eq!(err.to_string(), "value $1234 does not fit in 8 pins");
+        assert_eq!(
+            pins_to_value(&pins),
+            0x5a,
+            "pins should be untouched by a value that doesn't fit"
+        );
+        assert!(try_value_to_pins(0x100, &pins).is_err());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "does not fit in 8 pins")]
+    fn value_truncation_caught() {
+        value_to_pins(0x1234, &bus(8));
+    }
+
+    #[test]
+    fn hex_dump_lines() {
+        let mut ram = Ram::new(0x10000);
+        for (i, &value) in b"Hello, world!\x00\x01\x7fC64 rules\xff\x80"
+            .iter()
+            .enumerate()
+        {
+            ram.write(0xfff8u16.wrapping_add(i as u16), value);
+        }
+
+        assert_eq!(
+            hex_dump(&mut ram, 0xfff8, 32),
+            "FFF8: 48 65 6C 6C 6F 2C 20 77 6F 72 6C 64 21 00 01 7F  Hello, world!...\n\
+             0008: 43 36 34 20 72 75 6C 65 73 FF 80 00 00 00 00 00  C64 rules.......\n"
+        );
+        assert_eq!(
+            hex_dump(&mut ram, 0x0008, 11),
+            "0008: 43 36 34 20 72 75 6C 65 73 FF 80                 C64 rules..\n"
+        );
+    }
+
+    #[test]
+    fn hex_dump_over_64k() {
+        let mut ram = Ram::new(0x10000);
+        ram.write(0x0000, b'A');
+        ram.write(0xfff0, b'Z');
+
+        let dump = hex_dump(&mut ram, 0x0000, 0x10010);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines.len(), 0x1001);
+        assert!(lines[0xfff].starts_with("FFF0: 5A "));
+        assert!(
+            lines[0x1000].starts_with("0000: 41 "),
+            "line past $FFFF should wrap to $0000"
+        );
+    }
+}
@@ -0,0 +1,145 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Conversions between ASCII, PETSCII, and screen codes.
+//!
+//! The C64 has two ways of representing text. PETSCII is what the KERNAL and BASIC work
+//! with: it's what the keyboard produces, what `PRINT` takes, and what's stored in strings
+//! and files. Screen codes are what's stored in screen memory, and each one is simply the
+//! index of a glyph in the character ROM. Neither is ASCII, although PETSCII is close to the
+//! 1963 version of it.
+//!
+//! How PETSCII letters look depends on which of the two character sets is in use. In the
+//! uppercase set (the one the C64 starts up with), $41-$5A are uppercase letters and
+//! $C1-$DA are graphics characters. In the lowercase set, $41-$5A are lowercase letters and
+//! $C1-$DA are uppercase. These conversions assume the lowercase set, since it's the only
+//! one in which both cases of ASCII letters can be shown.
+//!
+//! Characters that have no equivalent on the other side are replaced with a question mark
+//! (`PLACEHOLDER` in PETSCII and screen codes, `'?'` in ASCII). On the PETSCII side, that's
+//! the control codes (colors, cursor movement, and so on), the graphics characters, and
+//! the pound sign. On the ASCII side, it's `\`, `` ` ``, `{`, `|`, `}`, `~`, control
+//! characters other than newline, and anything that isn't ASCII at all.
+
+/// The PETSCII code and screen code that replace characters that can't be converted. Both
+/// are a question mark.
+pub const PLACEHOLDER: u8 = 0x3f;
+
+/// The PETSCII code for RETURN, which is converted to and from a newline.
+const RETURN: u8 = 0x0d;
+
+/// Converts an ASCII string to PETSCII.
+///
+/// Lowercase letters become $41-$5A and uppercase letters $C1-$DA. `^` and `_` become the
+/// up arrow and left arrow, which occupy the same positions in PETSCII, and a newline
+/// becomes RETURN.
+pub fn ascii_to_petscii(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            '\n' => RETURN,
+            'a'..='z' => c as u8 - 0x20,
+            'A'..='Z' => c as u8 + 0x80,
+            ' '..='[' | ']' | '^' | '_' => c as u8,
+            _ => PLACEHOLDER,
+        })
+        .collect()
+}
+
+/// Converts PETSCII to an ASCII string.
+///
+/// This is the reverse of `ascii_to_petscii`, except that $61-$7A are also converted to
+/// uppercase letters (they show the same glyphs as $C1-$DA).
+pub fn petscii_to_ascii(petscii: &[u8]) -> String {
+    petscii
+        .iter()
+        .map(|&code| match code {
+            RETURN => '\n',
+            0x41..=0x5a => (code + 0x20) as char,
+            0xc1..=0xda => (code - 0x80) as char,
+            0x61..=0x7a => (code - 0x20) as char,
+            0x20..=0x5b | 0x5d..=0x5f => code as char,
+            _ => '?',
+        })
+        .collect()
+}
+
+/// Converts PETSCII to screen codes.
+///
+/// Every printable PETSCII code has a screen code that shows the same glyph in either
+/// character set. The control codes ($00-$1F and $80-$9F) don't, so they're replaced with
+/// `PLACEHOLDER`.
+pub fn petscii_to_screen(petscii: &[u8]) -> Vec<u8> {
+    petscii
+        .iter()
+        .map(|&code| match code {
+            0x20..=0x3f => code,
+            0x40..=0x5f => code - 0x40,
+            0x60..=0x7f => code - 0x20,
+            0xa0..=0xbf => code - 0x40,
+            0xc0..=0xdf => code - 0x80,
+            0xe0..=0xfe => code - 0x80,
+            0xff => 0x5e,
+            _ => PLACEHOLDER,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// The printable ASCII characters that PETSCII has.
+    fn printable() -> String {
+        (' '..='~').filter(|c| !"\\`{|}~".contains(*c)).collect()
+    }
+
+    #[test]
+    fn ascii_round_trip() {
+        let text = printable();
+        assert_eq!(text.len(), 89);
+        assert_eq!(petscii_to_ascii(&ascii_to_petscii(&text)), text);
+        assert_eq!(
+            petscii_to_ascii(&ascii_to_petscii("HELLO\nworld")),
+            "HELLO\nworld"
+        );
+    }
+
+    #[test]
+    fn petscii_round_trip() {
+        let codes: Vec<u8> = (0x20..=0x5f)
+            .filter(|code| *code != 0x5c)
+            .chain(0xc1..=0xda)
+            .collect();
+        assert_eq!(ascii_to_petscii(&petscii_to_ascii(&codes)), codes);
+    }
+
+    #[test]
+    fn letter_cases() {
+        assert_eq!(ascii_to_petscii("Az"), vec![0xc1, 0x5a]);
+        assert_eq!(petscii_to_ascii(&[0x41, 0xc1, 0x61]), "aAA");
+    }
+
+    #[test]
+    fn unmappable() {
+        assert_eq!(
+            ascii_to_petscii("a\\b{é}"),
+            vec![0x41, 0x3f, 0x42, 0x3f, 0x3f, 0x3f]
+        );
+        assert_eq!(petscii_to_ascii(&[0x05, 0x5c, 0x93, 0xa9]), "????");
+        assert_eq!(petscii_to_screen(&[0x05, 0x0d, 0x93]), vec![PLACEHOLDER; 3]);
+    }
+
+    #[test]
+    fn screen_codes() {
+        assert_eq!(
+            petscii_to_screen(&ascii_to_petscii("@Ab 1!")),
+            vec![0x00, 0x41, 0x02, 0x20, 0x31, 0x21]
+        );
+        assert_eq!(
+            petscii_to_screen(&[0x60, 0xa0, 0xe0, 0xff]),
+            [0x40, 0x60, 0x60, 0x5e]
+        );
+    }
+}
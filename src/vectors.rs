@@ -6,6 +6,8 @@
 use std::ops::{Deref, DerefMut};
 use std::{cell::RefCell, rc::Rc};
 
+use crate::components::pin::Pin;
+
 /// A vector with three extra operations on it dealing with shared, internally mutable
 /// references.
 ///
@@ -103,9 +105,11 @@ use std::{cell::RefCell, rc::Rc};
 /// return a `Vec` to be used in any context that requires a `Vec` and not a `RefVec`) that
 /// has an additional type of iterator that internally clones references, so the simple act
 /// of creating an iterator doesn't mess everything up. It has a couple other new methods -
-/// `get_ref()` is like `get` except it returns a cloned reference, and a `clone()`
+/// `get_ref()` is like `get` except it returns a cloned reference, `map_indices()` picks out
+/// some of the items (a chip's address pins, say) as a new `RefVec`, and a `clone()`
 /// implementation that will return a new `RefVec` of cloned references to all of the
-/// original's items.
+/// original's items. Everything else that a `Vec` does, like indexing, `len()`, and
+/// `iter()`, works on a `RefVec` as well.
 pub struct RefVec<T>(Vec<Rc<RefCell<T>>>);
 
 /// Here is the iterator itself. It calls `Rc::clone()` on each item referenced in the
@@ -130,6 +134,17 @@ impl<T> RefVec<T> {
         Rc::clone(&self[index])
     }
 
+    /// Returns a new `RefVec` of cloned references to the items at the given indices, in the
+    /// order that the indices are given. This is how chips gather up pins that are used
+    /// together, like the pins of an address bus in bit order:
+    ///
+    /// ```ignore
+    /// let addr_pins = pins.map_indices(&PA_ADDRESS);
+    /// ```
+    pub fn map_indices(&self, indices: &[usize]) -> RefVec<T> {
+        RefVec(indices.iter().map(|&i| self.get_ref(i)).collect())
+    }
+
     /// Returns an iterator that itself returns cloned references to all of the underlying
     /// items.
     pub fn iter_ref(&self) -> RefIter<'_, T> {
@@ -137,11 +152,30 @@ impl<T> RefVec<T> {
     }
 }
 
+impl RefVec<Pin> {
+    /// Returns the levels of all of the pins, without risking a panic if any of them is
+    /// already borrowed. A pin that can't be borrowed (because it's in the middle of
+    /// changing, for example, which is exactly when a device's `update` runs) gives `None`,
+    /// the same as a floating pin. This is meant for logging and debugging code that wants
+    /// a snapshot of a bus from anywhere; chips should read their pins normally.
+    pub fn try_levels(&self) -> Vec<Option<f64>> {
+        self.iter()
+            .map(|pin| pin.try_borrow().ok().and_then(|pin| pin.level()))
+            .collect()
+    }
+}
+
+impl<T> Default for RefVec<T> {
+    fn default() -> Self {
+        RefVec::new()
+    }
+}
+
 impl<'a, T> Iterator for RefIter<'a, T> {
     type Item = Rc<RefCell<T>>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.0.get(0) {
+        match self.0.first() {
             Some(item) => {
                 self.0 = &self.0[1..];
                 Some(Rc::clone(item))
@@ -158,7 +192,7 @@ impl<T> Clone for RefVec<T> {
         RefVec(
             self.0
                 .iter()
-                .map(|pin| Rc::clone(pin))
+                .map(Rc::clone)
                 .collect::<Vec<Rc<RefCell<T>>>>(),
         )
     }
@@ -177,3 +211,69 @@ impl<T> DerefMut for RefVec<T> {
         &mut self.0
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{components::pin::Mode::Output, utils::value_to_pins};
+
+    fn numbers() -> RefVec<usize> {
+        RefVec::with_vec((0..8).map(|n| Rc::new(RefCell::new(n * 10))).collect())
+    }
+
+    #[test]
+    fn indexing() {
+        let v = numbers();
+        assert_eq!(v.len(), 8);
+        assert_eq!(*v[3].borrow(), 30);
+        assert_eq!(
+            v.iter().map(|n| *n.borrow()).collect::<Vec<usize>>(),
+            vec![0, 10, 20, 30, 40, 50, 60, 70]
+        );
+    }
+
+    #[test]
+    fn subset() {
+        let v = numbers();
+        let subset = v.map_indices(&[6, 1, 3]);
+        assert_eq!(
+            subset.iter().map(|n| *n.borrow()).collect::<Vec<usize>>(),
+            vec![60, 10, 30]
+        );
+
+        // The subset shares its items with the original
+        *subset[0].borrow_mut() = 65;
+        assert_eq!(*v[6].borrow(), 65);
+        assert!(Rc::ptr_eq(&subset[2], &v[3]));
+        assert!(v.map_indices(&[]).is_empty());
+    }
+
+    fn data_bus() -> RefVec<Pin> {
+        RefVec::with_vec((0..8).map(|i| pin!(i + 1, "D", Output)).collect())
+    }
+
+    #[test]
+    fn levels_snapshot() {
+        let bus = data_bus();
+        value_to_pins(0xa5, &bus);
+        assert_eq!(
+            bus.try_levels(),
+            [1.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 1.0]
+                .iter()
+                .map(|&level| Some(level))
+                .collect::<Vec<Option<f64>>>()
+        );
+    }
+
+    #[test]
+    fn levels_while_borrowed() {
+        let bus = data_bus();
+        value_to_pins(0xff, &bus);
+
+        let _borrowed = bus[3].borrow_mut();
+        let levels = bus.try_levels();
+        assert_eq!(levels[3], None, "borrowed pin should read as None");
+        assert_eq!(levels[2], Some(1.0));
+        assert_eq!(levels[4], Some(1.0));
+    }
+}
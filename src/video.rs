@@ -0,0 +1,308 @@
+// Copyright (c) 2021 Thomas J. Otterson
+//
+// This software is released under the MIT License.
+// https://opensource.org/licenses/MIT
+
+//! Rendering of the C64's text screen straight from memory.
+//!
+//! This doesn't emulate the VIC. It takes the contents of screen memory, color RAM, and a
+//! character set and draws what the VIC would display from them in text mode, which is
+//! enough to see what's on the screen (the READY prompt, say) without timing any of it.
+//! Raster effects, sprites, and bitmap modes aren't drawn.
+//!
+//! Pixels are color numbers from 0 to 15, the same numbers that go into color RAM and the
+//! VIC's color registers. `PALETTE` gives the RGB color for each one, and `Frame` can be
+//! written out as a PPM image with those colors.
+
+use std::io::{self, Write};
+
+/// The width of the text screen in characters.
+pub const COLUMNS: usize = 40;
+/// The height of the text screen in characters.
+pub const ROWS: usize = 25;
+
+/// The width of the display window (the part of the screen inside the border) in pixels.
+pub const WIDTH: usize = COLUMNS * 8;
+/// The height of the display window in pixels.
+pub const HEIGHT: usize = ROWS * 8;
+
+/// The width of the border to each side of the display window, as much of it as a PAL
+/// monitor usually shows.
+pub const BORDER_WIDTH: usize = 32;
+/// The height of the border above and below the display window, as much of it as a PAL
+/// monitor usually shows.
+pub const BORDER_HEIGHT: usize = 36;
+
+/// The RGB values of the C64's 16 colors, indexed by color number. These are the values
+/// measured by Philip "Pepto" Timmermann, which are the ones most emulators use.
+pub const PALETTE: [[u8; 3]; 16] = [
+    [0x00, 0x00, 0x00], // black
+    [0xff, 0xff, 0xff], // white
+    [0x68, 0x37, 0x2b], // red
+    [0x70, 0xa4, 0xb2], // cyan
+    [0x6f, 0x3d, 0x86], // purple
+    [0x58, 0x8d, 0x43], // green
+    [0x35, 0x28, 0x79], // blue
+    [0xb8, 0xc7, 0x6f], // yellow
+    [0x6f, 0x4f, 0x25], // orange
+    [0x43, 0x39, 0x00], // brown
+    [0x9a, 0x67, 0x59], // light red
+    [0x44, 0x44, 0x44], // dark grey
+    [0x6c, 0x6c, 0x6c], // grey
+    [0x9a, 0xd2, 0x84], // light green
+    [0x6c, 0x5e, 0xb5], // light blue
+    [0x95, 0x95, 0x95], // light grey
+];
+
+/// An image made of color numbers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The width of the image in pixels.
+    width: usize,
+
+    /// The height of the image in pixels.
+    height: usize,
+
+    /// The color number of each pixel, row by row from the top left.
+    pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Creates a new frame with every pixel set to `color`.
+    pub fn new(width: usize, height: usize, color: u8) -> Frame {
+        Frame {
+            width,
+            height,
+            pixels: vec![color & 0x0f; width * height],
+        }
+    }
+
+    /// Returns the width of the frame in pixels.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the height of the frame in pixels.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the color number of the pixel at (`x`, `y`), counting from the top left.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Sets the color number of the pixel at (`x`, `y`). Only the low 4 bits of `color`
+    /// are used, just as the VIC only uses the low 4 bits of its color registers.
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: u8) {
+        self.pixels[y * self.width + x] = color & 0x0f;
+    }
+
+    /// Writes the frame as a binary PPM (P6) image, with colors from `PALETTE`.
+    pub fn write_ppm(&self, w: &mut dyn Write) -> io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let data: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|&color| PALETTE[color as usize])
+            .collect();
+        w.write_all(&data)
+    }
+}
+
+/// Returns the bitmap of a character from a 2k character set.
+///
+/// A character set has 256 characters, one for each screen code, and the second half
+/// (codes 128-255, starting at offset $400) holds the reverse-video versions of the first.
+/// The VIC doesn't invert anything itself; it just draws whatever is in the set. Of the
+/// two sets in the character ROM, the uppercase one is the first 2k and the lowercase one
+/// is the second. The bitmap is laid out as described in `roms::glyph_in`.
+pub fn glyph(charset: &[u8; 0x800], code: u8) -> [u8; 8] {
+    let start = code as usize * 8;
+    let mut bitmap = [0; 8];
+    bitmap.copy_from_slice(&charset[start..start + 8]);
+    bitmap
+}
+
+/// The settings of the VIC that affect how the text screen is drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TextOptions {
+    /// The border color ($D020), or `None` to render only the display window.
+    pub border: Option<u8>,
+
+    /// The background colors 0-2 ($D021-$D023). Only background color 0 is used outside of
+    /// multicolor mode.
+    pub background: [u8; 3],
+
+    /// Whether multicolor text mode is on (bit 4 of $D016).
+    pub multicolor: bool,
+}
+
+/// Renders a text screen in standard (hires) text mode, with a border.
+///
+/// Each byte of `screen_ram` is the screen code of one character cell, from left to right
+/// and top to bottom, and the same byte of `color_ram` is its foreground color. Set pixels
+/// in a glyph are drawn in the foreground color and clear ones in `background`.
+pub fn render_text_screen(
+    screen_ram: &[u8; 1000],
+    color_ram: &[u8; 1000],
+    charset: &[u8; 0x800],
+    border: u8,
+    background: u8,
+) -> Frame {
+    let options = TextOptions {
+        border: Some(border),
+        background: [background, 0, 0],
+        multicolor: false,
+    };
+    render_text_screen_with(screen_ram, color_ram, charset, &options)
+}
+
+/// Renders a text screen with the given VIC settings.
+///
+/// In multicolor mode, a cell whose color RAM value has bit 3 clear is still drawn in hires
+/// with a foreground color of that value. A cell with bit 3 set is drawn with pixels twice
+/// as wide, each pair of bits in a glyph row picking a color: %00 is background color 0,
+/// %01 is background color 1, %10 is background color 2, and %11 is the low 3 bits of the
+/// color RAM value.
+pub fn render_text_screen_with(
+    screen_ram: &[u8; 1000],
+    color_ram: &[u8; 1000],
+    charset: &[u8; 0x800],
+    options: &TextOptions,
+) -> Frame {
+    let (left, top, mut frame) = match options.border {
+        Some(border) => (
+            BORDER_WIDTH,
+            BORDER_HEIGHT,
+            Frame::new(WIDTH + BORDER_WIDTH * 2, HEIGHT + BORDER_HEIGHT * 2, border),
+        ),
+        None => (0, 0, Frame::new(WIDTH, HEIGHT, 0)),
+    };
+    let [bg0, bg1, bg2] = options.background;
+
+    for cell in 0..COLUMNS * ROWS {
+        let x0 = left + cell % COLUMNS * 8;
+        let y0 = top + cell / COLUMNS * 8;
+        let color = color_ram[cell] & 0x0f;
+        let bitmap = glyph(charset, screen_ram[cell]);
+
+        for (row, bits) in bitmap.iter().enumerate() {
+            for col in 0..8 {
+                let pixel = if options.multicolor && color & 0x08 != 0 {
+                    match (bits >> (6 - col / 2 * 2)) & 0x03 {
+                        0 => bg0,
+                        1 => bg1,
+                        2 => bg2,
+                        _ => color & 0x07,
+                    }
+                } else if bits & (0x80 >> col) != 0 {
+                    color
+                } else {
+                    bg0
+                };
+                frame.set_pixel(x0 + col, y0 + row, pixel);
+            }
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A character set where code 1 is a solid block, code 2 has only its top left pixel
+    /// set, code 3 is a row of multicolor bit pairs %00, %01, %10, %11, and every other
+    /// character is blank.
+    fn charset() -> [u8; 0x800] {
+        let mut charset = [0; 0x800];
+        charset[8..16].copy_from_slice(&[0xff; 8]);
+        charset[16] = 0x80;
+        charset[24] = 0x1b;
+        charset
+    }
+
+    #[test]
+    fn glyphs() {
+        let charset = charset();
+        let frame = render_text_screen(
+            &{
+                let mut screen = [0x20; 1000];
+                screen[0] = 1;
+                screen[41] = 2;
+                screen
+            },
+            &[5; 1000],
+            &charset,
+            14,
+            6,
+        );
+        assert_eq!(frame.width(), 384);
+        assert_eq!(frame.height(), 272);
+
+        assert_eq!(frame.pixel(0, 0), 14, "border");
+        assert_eq!(frame.pixel(31, 35), 14, "border");
+        assert_eq!(frame.pixel(32, 36), 5, "solid block in the first cell");
+        assert_eq!(frame.pixel(39, 43), 5, "solid block in the first cell");
+        assert_eq!(frame.pixel(40, 36), 6, "blank second cell");
+        assert_eq!(frame.pixel(40, 44), 5, "top left pixel of cell 41");
+        assert_eq!(frame.pixel(41, 44), 6, "rest of cell 41");
+        assert_eq!(frame.pixel(40, 45), 6, "rest of cell 41");
+    }
+
+    #[test]
+    fn multicolor() {
+        let charset = charset();
+        let mut screen = [0x20; 1000];
+        screen[0] = 3;
+        screen[1] = 3;
+        let mut color = [0; 1000];
+        color[0] = 0x0a;
+        color[1] = 0x02;
+        let options = TextOptions {
+            border: None,
+            background: [0, 1, 7],
+            multicolor: true,
+        };
+        let frame = render_text_screen_with(&screen, &color, &charset, &options);
+        assert_eq!(frame.width(), WIDTH);
+        assert_eq!(frame.height(), HEIGHT);
+
+        let row: Vec<u8> = (0..8).map(|x| frame.pixel(x, 0)).collect();
+        assert_eq!(row, [0, 0, 1, 1, 7, 7, 2, 2], "multicolor cell");
+
+        let row: Vec<u8> = (8..16).map(|x| frame.pixel(x, 0)).collect();
+        assert_eq!(row, [0, 0, 0, 2, 2, 0, 2, 2], "hires cell with bit 3 clear");
+    }
+
+    #[test]
+    fn ready_prompt() {
+        let roms = test_roms!();
+        let mut charset = [0; 0x800];
+        charset.copy_from_slice(&roms.character[..0x800]);
+        assert_eq!(
+            glyph(&charset, 0x12),
+            [0x7c, 0x66, 0x66, 0x7c, 0x78, 0x6c, 0x66, 0x00]
+        );
+        assert_eq!(glyph(&charset, 0x92), glyph(&charset, 0x12).map(|b| !b));
+
+        // "READY." in light blue on blue, the way the C64 shows it
+        let mut screen = [0x20; 1000];
+        screen[..6].copy_from_slice(&[0x12, 0x05, 0x01, 0x04, 0x19, 0x2e]);
+        let frame = render_text_screen(&screen, &[14; 1000], &charset, 14, 6);
+        assert_eq!(frame.pixel(32, 36), 6, "R has a blank left column");
+        assert_eq!(
+            frame.pixel(33, 36),
+            14,
+            "R's top row starts in the second column"
+        );
+
+        let mut ppm = vec![];
+        frame.write_ppm(&mut ppm).unwrap();
+        let header = b"P6\n384 272\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 384 * 272 * 3);
+        assert_eq!(&ppm[header.len()..header.len() + 3], &PALETTE[14]);
+    }
+}